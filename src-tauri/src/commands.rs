@@ -1,6 +1,21 @@
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
 use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::file_ids::{FileIdStore, FILE_IDS_FILE};
+use crate::file_ops::{self, get_file_name};
+use crate::markdown::{trim_trailing_whitespace, MarkdownOptions};
+use crate::trash_index::{TrashedItem, TRASH_INDEX_FILE};
+use crate::workspace::{
+    load_workspace_config_in, save_workspace_config_in, set_last_opened_in, workspace_config_path_in, WorkspaceConfig,
+};
 
 #[derive(Error, Debug)]
 pub enum MikuError {
@@ -10,14 +25,82 @@ pub enum MikuError {
     Json(#[from] serde_json::Error),
     #[error("Path error: {0}")]
     Path(String),
+    #[error("File is read-only: {0}")]
+    ReadOnly(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Outside workspace: {0}")]
+    OutsideWorkspace(String),
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("Operation cancelled: {0}")]
+    Cancelled(String),
+    #[error("Timed out reading: {0}")]
+    Timeout(String),
+    #[error("App data directory unavailable: {0}")]
+    DataDirUnavailable(String),
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+    #[error("Not a git repository: {0}")]
+    NotARepository(String),
+    #[error("Git error: {0}")]
+    Git(String),
+}
+
+impl MikuError {
+    /// Stable identifier the frontend can branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            MikuError::Io(_) => "Io",
+            MikuError::Json(_) => "Json",
+            MikuError::Path(_) => "Path",
+            MikuError::ReadOnly(_) => "ReadOnly",
+            MikuError::NotFound(_) => "NotFound",
+            MikuError::PermissionDenied(_) => "PermissionDenied",
+            MikuError::AlreadyExists(_) => "AlreadyExists",
+            MikuError::Conflict(_) => "Conflict",
+            MikuError::OutsideWorkspace(_) => "OutsideWorkspace",
+            MikuError::InvalidPattern(_) => "InvalidPattern",
+            MikuError::Cancelled(_) => "Cancelled",
+            MikuError::Timeout(_) => "Timeout",
+            MikuError::DataDirUnavailable(_) => "DataDirUnavailable",
+            MikuError::Unsupported(_) => "Unsupported",
+            MikuError::NotARepository(_) => "NotARepository",
+            MikuError::Git(_) => "Git",
+        }
+    }
+
+    /// Convert an IO error for `path`, turning not-found and permission
+    /// problems into their own variants carrying the offending path
+    pub(crate) fn from_io(err: std::io::Error, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_string_lossy().to_string();
+        match err.kind() {
+            std::io::ErrorKind::NotFound => MikuError::NotFound(path),
+            std::io::ErrorKind::PermissionDenied => MikuError::PermissionDenied(path),
+            _ => MikuError::Io(err),
+        }
+    }
 }
 
+/// Serialized as `{ code, message }` so the frontend can branch on `code`
+/// while still having a readable `message` to show
 impl Serialize for MikuError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("MikuError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
     }
 }
 
@@ -120,12 +203,198 @@ pub struct EditorSettings {
     pub sound_enabled: bool,
     #[serde(default)]
     pub keyboard_sounds: KeyboardSoundSettings,
+    /// How often the frontend triggers an auto-save. `None` disables it.
+    #[serde(default)]
+    pub autosave_interval_secs: Option<u32>,
+    /// Where auto-saves are written
+    #[serde(default)]
+    pub autosave_mode: AutosaveMode,
+    /// Markdown extensions used by every command that parses markdown
+    #[serde(default)]
+    pub markdown_options: MarkdownOptions,
+    /// File extensions treated as markdown documents, without the dot
+    #[serde(default = "default_markdown_extensions")]
+    pub markdown_extensions: Vec<String>,
+    /// Recent files kept per workspace, capped at `MAX_RECENT_LIMIT`
+    #[serde(default = "default_recent_limit")]
+    pub max_recent_files: usize,
+    /// Unpinned recent workspaces kept, capped at `MAX_RECENT_LIMIT`
+    #[serde(default = "default_recent_limit")]
+    pub max_recent_workspaces: usize,
+    /// Strip trailing whitespace on save, leaving fenced code untouched
+    #[serde(default)]
+    pub trim_trailing_whitespace: bool,
+    /// When trimming, keep two-space markdown hard line breaks
+    #[serde(default = "default_preserve_hard_breaks")]
+    pub preserve_hard_breaks: bool,
+    /// Files bulk operations open at once. Lower it for network drives or
+    /// tight file descriptor limits; the minimum is 1.
+    #[serde(default = "default_io_concurrency")]
+    pub io_concurrency: usize,
+    /// Seconds a file read or folder listing may take before failing with a
+    /// timeout instead of hanging the UI, e.g. on a flaky network drive.
+    /// 0 waits forever.
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Folder nesting below which the file tree stops descending, as a
+    /// backstop against pathological or symlink-looped trees. The minimum
+    /// is 1.
+    #[serde(default = "default_max_tree_depth")]
+    pub max_tree_depth: usize,
+    /// How long config backups and auto-save drafts are kept
+    #[serde(default)]
+    pub retention: RetentionPolicy,
 }
 
 fn default_sound_enabled() -> bool {
     true
 }
 
+fn default_preserve_hard_breaks() -> bool {
+    true
+}
+
+fn default_markdown_extensions() -> Vec<String> {
+    file_ops::DEFAULT_MARKDOWN_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+}
+
+/// Default length of the recent files and recent workspaces lists
+const DEFAULT_RECENT_LIMIT: usize = 10;
+/// Upper bound for the configurable recent list lengths
+const MAX_RECENT_LIMIT: usize = 100;
+
+fn default_recent_limit() -> usize {
+    DEFAULT_RECENT_LIMIT
+}
+
+/// Default number of files bulk operations open at once
+const DEFAULT_IO_CONCURRENCY: usize = 32;
+
+fn default_io_concurrency() -> usize {
+    DEFAULT_IO_CONCURRENCY
+}
+
+/// Default for `read_timeout_secs`
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
+
+fn default_read_timeout_secs() -> u64 {
+    DEFAULT_READ_TIMEOUT_SECS
+}
+
+/// Process-wide copy of `read_timeout_secs`, so reads don't have to load
+/// the settings file first
+static READ_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_READ_TIMEOUT_SECS);
+
+/// Default for `max_tree_depth`
+pub(crate) const DEFAULT_MAX_TREE_DEPTH: usize = 64;
+
+fn default_max_tree_depth() -> usize {
+    DEFAULT_MAX_TREE_DEPTH
+}
+
+/// Process-wide copy of `max_tree_depth`
+static MAX_TREE_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_TREE_DEPTH);
+
+/// The configured `max_tree_depth`
+pub(crate) fn max_tree_depth() -> usize {
+    MAX_TREE_DEPTH.load(Ordering::Relaxed).max(1)
+}
+
+/// Limits on the backups and drafts Miku leaves behind. A limit of 0 turns
+/// that rule off.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Backups kept for each file, newest first
+    pub max_backups_per_file: usize,
+    /// Backups and auto-save drafts older than this are removed
+    pub max_snapshot_age_days: u32,
+    /// Combined size of all backups; the oldest go first beyond it
+    pub max_total_backup_bytes: u64,
+}
+
+const DEFAULT_RETENTION_POLICY: RetentionPolicy = RetentionPolicy {
+    max_backups_per_file: 5,
+    max_snapshot_age_days: 30,
+    max_total_backup_bytes: 50 * 1024 * 1024,
+};
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        DEFAULT_RETENTION_POLICY
+    }
+}
+
+/// Process-wide copy of `retention`, so backups can be pruned as they're
+/// written without loading the settings, which may be the file being
+/// backed up
+static RETENTION_POLICY: std::sync::RwLock<RetentionPolicy> = std::sync::RwLock::new(DEFAULT_RETENTION_POLICY);
+
+/// The configured `retention`
+pub(crate) fn retention_policy() -> RetentionPolicy {
+    *RETENTION_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `read`, failing with `Timeout` for `path` once it takes longer than
+/// `limit`.
+///
+/// The timeout only stops waiting: `read` is dropped, but a `tokio::fs`
+/// call it was awaiting keeps running on tokio's blocking pool until the OS
+/// returns, and a read stuck on a dead network mount holds that thread
+/// indefinitely. The pool is bounded (512 threads by default), so repeated
+/// hangs degrade to queued blocking work rather than unbounded threads, and
+/// the caller gets its error back either way.
+pub(crate) async fn with_timeout<T>(
+    limit: Option<Duration>,
+    path: impl AsRef<Path>,
+    read: impl std::future::Future<Output = Result<T, MikuError>>,
+) -> Result<T, MikuError> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, read)
+            .await
+            .map_err(|_| MikuError::Timeout(path.as_ref().to_string_lossy().to_string()))?,
+        None => read.await,
+    }
+}
+
+/// Run `read` under the configured `read_timeout_secs`. See `with_timeout`
+/// for what happens to a read that never returns.
+pub(crate) async fn with_read_timeout<T>(
+    path: impl AsRef<Path>,
+    read: impl std::future::Future<Output = Result<T, MikuError>>,
+) -> Result<T, MikuError> {
+    let secs = READ_TIMEOUT_SECS.load(Ordering::Relaxed);
+    with_timeout((secs > 0).then(|| Duration::from_secs(secs)), path, read).await
+}
+
+/// Apply the settings that are read from process-wide state rather than
+/// loaded on each use
+pub(crate) fn apply_global_settings(settings: &EditorSettings) {
+    file_ops::set_markdown_extensions(&settings.markdown_extensions);
+    READ_TIMEOUT_SECS.store(settings.read_timeout_secs, Ordering::Relaxed);
+    MAX_TREE_DEPTH.store(settings.max_tree_depth, Ordering::Relaxed);
+    *RETENTION_POLICY.write().unwrap_or_else(|e| e.into_inner()) = settings.retention;
+}
+
+impl EditorSettings {
+    pub(crate) fn recent_files_limit(&self) -> usize {
+        self.max_recent_files.clamp(1, MAX_RECENT_LIMIT)
+    }
+
+    pub(crate) fn recent_workspaces_limit(&self) -> usize {
+        self.max_recent_workspaces.clamp(1, MAX_RECENT_LIMIT)
+    }
+
+    pub(crate) fn io_concurrency_limit(&self) -> usize {
+        self.io_concurrency.max(1)
+    }
+}
+
+/// The saved settings, or the defaults if they can't be read
+pub(crate) async fn settings_or_default(data_dir: &Path) -> EditorSettings {
+    load_settings_in(data_dir).await.unwrap_or_default()
+}
+
 impl Default for EditorSettings {
     fn default() -> Self {
         Self {
@@ -140,6 +409,18 @@ impl Default for EditorSettings {
             writing_context: String::new(),
             sound_enabled: true,
             keyboard_sounds: KeyboardSoundSettings::default(),
+            autosave_interval_secs: None,
+            autosave_mode: AutosaveMode::default(),
+            markdown_options: MarkdownOptions::default(),
+            markdown_extensions: default_markdown_extensions(),
+            max_recent_files: DEFAULT_RECENT_LIMIT,
+            max_recent_workspaces: DEFAULT_RECENT_LIMIT,
+            trim_trailing_whitespace: false,
+            preserve_hard_breaks: true,
+            io_concurrency: DEFAULT_IO_CONCURRENCY,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            max_tree_depth: DEFAULT_MAX_TREE_DEPTH,
+            retention: RetentionPolicy::default(),
         }
     }
 }
@@ -149,6 +430,34 @@ pub struct Document {
     pub path: Option<String>,
     pub content: String,
     pub is_modified: bool,
+    /// Opened in preview mode; the UI disables editing and saves are refused
+    #[serde(default)]
+    pub readonly: bool,
+    /// The file starts with a UTF-8 byte order mark, kept in `content`
+    #[serde(default)]
+    pub has_bom: bool,
+    /// Line endings the file was read with; `content` keeps them as-is
+    #[serde(default)]
+    pub line_ending: LineEnding,
+    /// `content_hash` of `content` in hex, to tell later edits or writes
+    /// by other programs apart from what was loaded
+    #[serde(default)]
+    pub content_hash: String,
+}
+
+impl Document {
+    /// A document matching what's on disk at `path`
+    pub(crate) fn saved(path: String, content: String, readonly: bool) -> Self {
+        Document {
+            path: Some(path),
+            has_bom: content.starts_with('\u{feff}'),
+            line_ending: LineEnding::detect(&content),
+            content_hash: content_hash_hex(&content),
+            content,
+            is_modified: false,
+            readonly,
+        }
+    }
 }
 
 impl Default for Document {
@@ -157,302 +466,3701 @@ impl Default for Document {
             path: None,
             content: String::new(),
             is_modified: false,
+            readonly: false,
+            has_bom: false,
+            line_ending: LineEnding::Lf,
+            content_hash: content_hash_hex(""),
         }
     }
 }
 
-/// Get the app data directory for Miku
-fn get_app_data_dir() -> Result<PathBuf, MikuError> {
-    dirs::data_dir()
-        .map(|p| p.join("miku"))
-        .ok_or_else(|| MikuError::Path("Could not determine app data directory".to_string()))
+/// Line ending style of a document
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// `\n` only, also used for text without any line breaks
+    #[default]
+    Lf,
+    /// `\r\n` only
+    CrLf,
+    /// Both styles appear
+    Mixed,
 }
 
-#[tauri::command]
-pub async fn load_settings() -> Result<EditorSettings, MikuError> {
-    let settings_path = get_app_data_dir()?.join("settings.json");
+impl LineEnding {
+    /// The line ending style used throughout `content`
+    pub fn detect(content: &str) -> Self {
+        let crlf = content.matches("\r\n").count();
+        let lf = content.matches('\n').count() - crlf;
+        match (lf, crlf) {
+            (_, 0) => LineEnding::Lf,
+            (0, _) => LineEnding::CrLf,
+            _ => LineEnding::Mixed,
+        }
+    }
 
-    if settings_path.exists() {
-        let content = tokio::fs::read_to_string(&settings_path).await?;
-        let settings: EditorSettings = serde_json::from_str(&content)?;
-        Ok(settings)
-    } else {
-        Ok(EditorSettings::default())
+    /// Rewrite every line break in `content` in this style. `Mixed` leaves
+    /// the content untouched.
+    pub fn apply(self, content: String) -> String {
+        match self {
+            LineEnding::Lf if content.contains("\r\n") => content.replace("\r\n", "\n"),
+            LineEnding::CrLf if LineEnding::detect(&content) != LineEnding::CrLf => {
+                content.replace("\r\n", "\n").replace('\n', "\r\n")
+            }
+            _ => content,
+        }
+    }
+}
+
+/// How often each kind of line break appears in a document
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndingCounts {
+    pub lf: usize,
+    pub crlf: usize,
+    /// Lone `\r`, as written by classic Mac OS
+    pub cr: usize,
+    /// The more common of `lf` and `crlf`, `Lf` on a tie
+    pub dominant: LineEnding,
+}
+
+/// Count the line breaks of each kind in `content`
+#[tauri::command]
+pub fn detect_line_endings(content: String) -> LineEndingCounts {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count() - crlf;
+    let cr = content.matches('\r').count() - crlf;
+    LineEndingCounts {
+        lf,
+        crlf,
+        cr,
+        dominant: if crlf > lf { LineEnding::CrLf } else { LineEnding::Lf },
     }
 }
 
+/// Rewrite every line break in `content`, lone `\r` included, in `style`,
+/// so the editor can fix mixed endings in the open buffer. `Mixed` uses
+/// whichever style dominates.
 #[tauri::command]
-pub async fn save_settings(settings: EditorSettings) -> Result<(), MikuError> {
-    let app_dir = get_app_data_dir()?;
-    tokio::fs::create_dir_all(&app_dir).await?;
+pub fn normalize_content_line_endings(content: String, style: LineEnding) -> String {
+    let style = match style {
+        LineEnding::Mixed => detect_line_endings(content.clone()).dominant,
+        style => style,
+    };
+    let unified = content.replace("\r\n", "\n").replace('\r', "\n");
+    match style {
+        LineEnding::CrLf => unified.replace('\n', "\r\n"),
+        _ => unified,
+    }
+}
 
-    let settings_path = app_dir.join("settings.json");
-    let content = serde_json::to_string_pretty(&settings)?;
-    tokio::fs::write(&settings_path, content).await?;
+/// Paths the app currently has open in read-only preview mode. Independent
+/// of OS-level file permissions. Paths are kept canonicalized, so a save
+/// through a symlink or a differently spelled path is still refused.
+#[derive(Default)]
+pub struct ReadonlyDocuments(std::sync::Mutex<HashSet<PathBuf>>);
 
-    Ok(())
+impl ReadonlyDocuments {
+    /// `path` canonicalized, or with only its folder canonicalized when the
+    /// file itself is gone, e.g. after a rename
+    pub(crate) fn key(path: &str) -> PathBuf {
+        let path = Path::new(path);
+        if let Ok(canonical) = std::fs::canonicalize(path) {
+            return canonical;
+        }
+        match (path.parent().map(std::fs::canonicalize), path.file_name()) {
+            (Some(Ok(parent)), Some(name)) => parent.join(name),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn set(&self, path: &str, readonly: bool) {
+        let key = Self::key(path);
+        let mut paths = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if readonly {
+            paths.insert(key);
+        } else {
+            paths.remove(&key);
+        }
+    }
+
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        let key = Self::key(path);
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).contains(&key)
+    }
+
+    /// Carry read-only paths at or under `old_key` (from `key`, taken before
+    /// the move) over to `new_path`
+    pub(crate) fn moved(&self, old_key: &Path, new_path: &str) {
+        let new_key = Self::key(new_path);
+        let mut paths = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let moved: Vec<PathBuf> = paths.iter().filter(|path| path.starts_with(old_key)).cloned().collect();
+        for path in moved {
+            paths.remove(&path);
+            // `join("")` would add a trailing separator
+            match path.strip_prefix(old_key) {
+                Ok(rest) if !rest.as_os_str().is_empty() => paths.insert(new_key.join(rest)),
+                _ => paths.insert(new_key.clone()),
+            };
+        }
+    }
 }
 
-#[tauri::command]
-pub async fn open_file(path: String) -> Result<Document, MikuError> {
-    let content = tokio::fs::read_to_string(&path).await?;
-    Ok(Document {
-        path: Some(path),
-        content,
-        is_modified: false,
-    })
+/// Paths of open documents with unsaved changes, as reported by the
+/// frontend through `mark_dirty`. Untitled documents are marked under a key
+/// that isn't an absolute path, such as their tab id.
+#[derive(Default)]
+pub struct DirtyDocuments(std::sync::Mutex<HashSet<String>>);
+
+impl DirtyDocuments {
+    pub(crate) fn set(&self, path: &str, dirty: bool) {
+        let mut paths = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if dirty {
+            paths.insert(path.to_string());
+        } else {
+            paths.remove(path);
+        }
+    }
+
+    /// Dirty paths, sorted
+    pub(crate) fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.0.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    pub(crate) fn summary(&self) -> DirtySummary {
+        let (dirty_paths, untitled): (Vec<String>, Vec<String>) =
+            self.paths().into_iter().partition(|path| Path::new(path).is_absolute());
+        DirtySummary {
+            dirty_paths,
+            untitled_count: untitled.len(),
+        }
+    }
+}
+
+/// Unsaved work across every window
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct DirtySummary {
+    /// Files with unsaved changes, sorted
+    pub dirty_paths: Vec<String>,
+    /// Untitled documents with unsaved content
+    pub untitled_count: usize,
 }
 
+/// What would be lost by quitting now, for a confirmation prompt
 #[tauri::command]
-pub async fn save_file(path: String, content: String) -> Result<(), MikuError> {
-    tokio::fs::write(&path, &content).await?;
-    Ok(())
+pub fn dirty_summary(dirty_docs: State<'_, DirtyDocuments>) -> DirtySummary {
+    dirty_docs.summary()
 }
 
+/// Record whether an open document has unsaved changes
 #[tauri::command]
-pub fn new_document() -> Document {
-    Document::default()
+pub fn mark_dirty(path: String, dirty: bool, dirty_docs: State<'_, DirtyDocuments>) {
+    dirty_docs.set(&path, dirty);
 }
 
+/// Forget a document the frontend closed, so a later open of the same path
+/// doesn't inherit its read-only or unsaved state
 #[tauri::command]
-pub async fn get_recent_files() -> Result<Vec<String>, MikuError> {
-    let recent_path = get_app_data_dir()?.join("recent_files.json");
+pub fn close_file(
+    path: String,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    dirty_docs: State<'_, DirtyDocuments>,
+) {
+    readonly_docs.set(&path, false);
+    dirty_docs.set(&path, false);
+}
 
-    if recent_path.exists() {
-        let content = tokio::fs::read_to_string(&recent_path).await?;
-        let files: Vec<String> = serde_json::from_str(&content)?;
-        Ok(files)
-    } else {
-        Ok(Vec::new())
+/// Serializes read-modify-write cycles on the JSON files in the app data
+/// directory (settings, recent files, workspace config) across windows
+#[derive(Default)]
+pub struct ConfigLock(tokio::sync::Mutex<()>);
+
+impl ConfigLock {
+    pub async fn acquire(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.0.lock().await
     }
 }
 
-#[tauri::command]
-pub async fn add_recent_file(path: String) -> Result<(), MikuError> {
-    let app_dir = get_app_data_dir()?;
-    tokio::fs::create_dir_all(&app_dir).await?;
+/// Write `content` to a temp file next to `path` and rename it into place,
+/// so readers never observe a partially written file
+pub(crate) async fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> Result<(), MikuError> {
+    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
-    let recent_path = app_dir.join("recent_files.json");
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| MikuError::Path("Invalid file path".to_string()))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
 
-    let mut files: Vec<String> = if recent_path.exists() {
-        let content = tokio::fs::read_to_string(&recent_path).await?;
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Vec::new()
+    let result = match tokio::fs::write(&temp_path, content).await {
+        Ok(()) => tokio::fs::rename(&temp_path, path).await,
+        Err(e) => Err(e),
     };
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e.into());
+    }
 
-    // Remove if already exists and add to front
-    files.retain(|f| f != &path);
-    files.insert(0, path);
+    Ok(())
+}
 
-    // Keep only last 10 files
-    files.truncate(10);
+/// File names inside the app data directory
+const SETTINGS_FILE: &str = "settings.json";
+const RECENT_FILES_FILE: &str = "recent_files.json";
+const SESSION_FILE: &str = "session.json";
+const LAYOUT_FILE: &str = "layout.json";
+const PREFERENCES_FILE: &str = "preferences.json";
 
-    let content = serde_json::to_string_pretty(&files)?;
-    tokio::fs::write(&recent_path, content).await?;
+/// Environment variable that relocates the app data directory, e.g. for
+/// portable installs or hermetic testing
+const DATA_DIR_ENV: &str = "MIKU_DATA_DIR";
+
+/// Where the platform data folder is expected to come from, for errors
+/// when it can't be resolved
+#[cfg(target_os = "macos")]
+const PLATFORM_DATA_DIR_HINT: &str = "$HOME/Library/Application Support";
+#[cfg(target_os = "windows")]
+const PLATFORM_DATA_DIR_HINT: &str = "%APPDATA% (the user's Roaming profile folder)";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const PLATFORM_DATA_DIR_HINT: &str = "$XDG_DATA_HOME or $HOME/.local/share";
+
+/// The app data directory, resolved and created once by `init_data_dir`
+static DATA_DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+/// Resolve the app data directory from the `MIKU_DATA_DIR` override or the
+/// platform data folder, and create it if needed
+fn resolve_data_dir(env_dir: Option<std::ffi::OsString>, platform_dir: Option<PathBuf>) -> Result<PathBuf, MikuError> {
+    if let Some(dir) = env_dir.filter(|d| !d.is_empty()) {
+        let dir = PathBuf::from(dir);
+        let dir = if dir.is_absolute() { dir } else { std::env::current_dir()?.join(dir) };
+        return ensure_data_dir(dir, |dir, e| {
+            MikuError::Path(format!(
+                "{} points to {} which cannot be created: {}",
+                DATA_DIR_ENV,
+                dir.to_string_lossy(),
+                e
+            ))
+        });
+    }
+
+    let Some(platform_dir) = platform_dir else {
+        return Err(MikuError::DataDirUnavailable(format!(
+            "the platform data folder ({}) could not be determined; set {} to choose where Miku keeps its data",
+            PLATFORM_DATA_DIR_HINT, DATA_DIR_ENV
+        )));
+    };
+    ensure_data_dir(platform_dir.join("miku"), |dir, e| {
+        MikuError::DataDirUnavailable(format!(
+            "{} cannot be created ({}); set {} to choose another location",
+            dir.to_string_lossy(),
+            e,
+            DATA_DIR_ENV
+        ))
+    })
+}
+
+/// Create `dir`, describing a failure with `error`
+fn ensure_data_dir(
+    dir: PathBuf,
+    error: impl FnOnce(&Path, std::io::Error) -> MikuError,
+) -> Result<PathBuf, MikuError> {
+    std::fs::create_dir_all(&dir).map_err(|e| error(&dir, e))?;
+    Ok(dir)
+}
+
+/// Resolve and create the app data directory, honoring `MIKU_DATA_DIR`.
+/// Called once at startup; a failure isn't remembered, so a later call
+/// tries again.
+pub(crate) fn init_data_dir() -> Result<PathBuf, MikuError> {
+    if let Some(dir) = DATA_DIR.get() {
+        return Ok(dir.clone());
+    }
+    let dir = resolve_data_dir(std::env::var_os(DATA_DIR_ENV), dirs::data_dir())?;
+    Ok(DATA_DIR.get_or_init(|| dir).clone())
+}
+
+/// Get the app data directory for Miku. Every config loader goes through
+/// here, so the directory exists by the time they read or write it; it's
+/// only created on the first call.
+pub(crate) fn app_data_dir() -> Result<PathBuf, MikuError> {
+    init_data_dir()
+}
+
+/// A config file that couldn't be parsed and was reset to defaults
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct RecoveredConfig {
+    pub path: String,
+    /// Where the unreadable file was moved
+    pub backup_path: String,
+    pub error: String,
+}
+
+/// Recoveries not yet reported to the frontend
+fn recovered_configs() -> &'static std::sync::Mutex<Vec<RecoveredConfig>> {
+    static RECOVERED: std::sync::OnceLock<std::sync::Mutex<Vec<RecoveredConfig>>> = std::sync::OnceLock::new();
+    RECOVERED.get_or_init(Default::default)
+}
+
+/// Where recoveries are reported as they happen, once the frontend has
+/// asked for a config and so is listening
+static RECOVERY_EMITTER: std::sync::OnceLock<AppHandle> = std::sync::OnceLock::new();
+
+/// Move a corrupt config file aside as `<name>.corrupt-<timestamp>` so the
+/// caller can fall back to defaults without losing what was there.
+/// `content` is what the caller read and failed to parse; when the file no
+/// longer holds it, another loader got there first and nothing is moved.
+///
+/// Quarantines are serialized by their own lock rather than `ConfigLock`:
+/// loaders run both with and without that lock held, and it isn't
+/// reentrant.
+pub(crate) async fn quarantine_config(path: &Path, content: &str, error: &serde_json::Error) -> Result<(), MikuError> {
+    static QUARANTINE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _guard = QUARANTINE_LOCK.lock().await;
+    if read_config_file(path).await?.as_deref() != Some(content) {
+        return Ok(());
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup = path.with_file_name(format!("{}.corrupt-{}", file_name, now_ms()));
+    tokio::fs::rename(path, &backup)
+        .await
+        .map_err(|e| MikuError::from_io(e, path))?;
+
+    recovered_configs()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(RecoveredConfig {
+            path: path.to_string_lossy().to_string(),
+            backup_path: backup.to_string_lossy().to_string(),
+            error: error.to_string(),
+        });
+    if let Some(app) = RECOVERY_EMITTER.get() {
+        emit_recovered_configs(app);
+    }
 
+    if let Some(dir) = path.parent() {
+        if let Err(err) = prune_backups_in(dir, &retention_policy(), now_ms()).await {
+            log::warn!("failed to prune backups in {}: {}", dir.to_string_lossy(), err);
+        }
+    }
     Ok(())
 }
 
-#[tauri::command]
-pub fn get_app_version() -> String {
-    env!("CARGO_PKG_VERSION").to_string()
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Files removed to enforce the retention policy
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct RetentionReport {
+    pub removed: Vec<String>,
+    pub freed_bytes: u64,
 }
 
-// ============================================
-// Session restore
-// ============================================
+impl RetentionReport {
+    async fn remove(&mut self, path: &Path, size: u64) -> Result<(), MikuError> {
+        tokio::fs::remove_file(path)
+            .await
+            .map_err(|e| MikuError::from_io(e, path))?;
+        self.removed.push(path.to_string_lossy().to_string());
+        self.freed_bytes += size;
+        Ok(())
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SessionTab {
-    pub path: Option<String>,
-    pub content: String,
+/// A `<name>.corrupt-<timestamp>` backup written by `quarantine_config`
+struct Backup {
+    path: PathBuf,
+    original: String,
+    timestamp_ms: u64,
+    size: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SessionState {
-    pub tabs: Vec<SessionTab>,
-    pub active_index: usize,
+/// The config backups in `dir`, newest first
+async fn list_backups_in(dir: &Path) -> Result<Vec<Backup>, MikuError> {
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| MikuError::from_io(e, dir))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some((original, timestamp)) = name.rsplit_once(".corrupt-") else {
+            continue;
+        };
+        let (Ok(timestamp_ms), Ok(metadata)) = (timestamp.parse(), entry.metadata().await) else {
+            continue;
+        };
+        backups.push(Backup {
+            path: entry.path(),
+            original: original.to_string(),
+            timestamp_ms,
+            size: metadata.len(),
+        });
+    }
+    backups.sort_by(|a, b| b.timestamp_ms.cmp(&a.timestamp_ms));
+    Ok(backups)
+}
+
+/// Apply `policy` to the config backups in `dir`. The newest backup of
+/// each file is always kept, however old or large, so a file that was just
+/// quarantined can still be recovered. Going from newest to oldest, any
+/// other backup is removed once its file has `max_backups_per_file` newer
+/// ones, once it's past `max_snapshot_age_days`, or once it would push the
+/// kept total over `max_total_backup_bytes`.
+pub(crate) async fn prune_backups_in(
+    dir: &Path,
+    policy: &RetentionPolicy,
+    now_ms: u64,
+) -> Result<RetentionReport, MikuError> {
+    let mut per_file: HashMap<String, usize> = HashMap::new();
+    let (newest, older): (Vec<_>, Vec<_>) = list_backups_in(dir)
+        .await?
+        .into_iter()
+        .partition(|backup| per_file.insert(backup.original.clone(), 1).is_none());
+
+    let mut report = RetentionReport::default();
+    let max_age_ms = u64::from(policy.max_snapshot_age_days) * DAY_MS;
+    let mut kept_bytes: u64 = newest.iter().map(|backup| backup.size).sum();
+    for backup in older {
+        let count = per_file.entry(backup.original.clone()).or_insert(1);
+        *count += 1;
+        let too_many = policy.max_backups_per_file > 0 && *count > policy.max_backups_per_file;
+        let too_old = max_age_ms > 0 && now_ms.saturating_sub(backup.timestamp_ms) > max_age_ms;
+        let too_big = policy.max_total_backup_bytes > 0 && kept_bytes + backup.size > policy.max_total_backup_bytes;
+        if too_many || too_old || too_big {
+            report.remove(&backup.path, backup.size).await?;
+        } else {
+            kept_bytes += backup.size;
+        }
+    }
+    Ok(report)
 }
 
+/// Remove config backups older than `max_age_days`, and the oldest ones
+/// beyond `max_total_bytes` in total, keeping the newest backup of every
+/// file. Either limit can be 0 to skip it. Returns what was removed and
+/// the space freed.
 #[tauri::command]
-pub async fn save_session(session: SessionState) -> Result<(), MikuError> {
-    let app_dir = get_app_data_dir()?;
-    tokio::fs::create_dir_all(&app_dir).await?;
-    let session_path = app_dir.join("session.json");
-    let content = serde_json::to_string_pretty(&session)?;
-    tokio::fs::write(&session_path, content).await?;
+pub async fn prune_backups(
+    max_age_days: u32,
+    max_total_bytes: u64,
+    lock: State<'_, ConfigLock>,
+) -> Result<RetentionReport, MikuError> {
+    let _guard = lock.acquire().await;
+    let policy = RetentionPolicy {
+        max_backups_per_file: 0,
+        max_snapshot_age_days: max_age_days,
+        max_total_backup_bytes: max_total_bytes,
+    };
+    prune_backups_in(&app_data_dir()?, &policy, now_ms()).await
+}
+
+/// Remove auto-save sidecars in `workspace` that haven't been written for
+/// longer than `max_snapshot_age_days` and whose document has been saved
+/// since. A draft newer than its document holds unsaved work and is kept
+/// however old it is.
+pub(crate) async fn prune_autosave_sidecars_in(
+    workspace: &Path,
+    policy: &RetentionPolicy,
+    now_ms: u64,
+    report: &mut RetentionReport,
+) -> Result<(), MikuError> {
+    let max_age_ms = u64::from(policy.max_snapshot_age_days) * DAY_MS;
+    if max_age_ms == 0 {
+        return Ok(());
+    }
+    for sidecar in list_autosave_sidecars_in(workspace).await? {
+        let Some(modified_ms) = sidecar.modified_ms else {
+            continue;
+        };
+        let superseded = tokio::fs::metadata(&sidecar.path)
+            .await
+            .ok()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .is_some_and(|saved| saved.as_millis() as u64 >= modified_ms);
+        if superseded && now_ms.saturating_sub(modified_ms) > max_age_ms {
+            let path = Path::new(&sidecar.sidecar_path);
+            let size = tokio::fs::metadata(path).await.map_or(0, |m| m.len());
+            report.remove(path, size).await?;
+        }
+    }
     Ok(())
 }
 
+/// Enforce the retention policy from settings now: prune config backups,
+/// and stale auto-save drafts in `workspace_path` or else the saved current
+/// workspace. Backups are also pruned whenever a new one is written.
 #[tauri::command]
-pub async fn load_session() -> Result<Option<SessionState>, MikuError> {
-    let session_path = get_app_data_dir()?.join("session.json");
-    if session_path.exists() {
-        let content = tokio::fs::read_to_string(&session_path).await?;
-        let session: SessionState = serde_json::from_str(&content)?;
-        Ok(Some(session))
-    } else {
-        Ok(None)
+pub async fn apply_retention(
+    workspace_path: Option<String>,
+    lock: State<'_, ConfigLock>,
+) -> Result<RetentionReport, MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    let policy = settings_or_default(&data_dir).await.retention;
+    let mut report = prune_backups_in(&data_dir, &policy, now_ms()).await?;
+    let workspace = match workspace_path {
+        Some(workspace) => Some(workspace),
+        None => load_workspace_config_in(&data_dir).await?.current_workspace,
+    };
+    if let Some(workspace) = workspace {
+        prune_autosave_sidecars_in(Path::new(&workspace), &policy, now_ms(), &mut report).await?;
     }
+    Ok(report)
 }
 
-// ============================================
-// Image assets
-// ============================================
+/// Emit `config://recovered` for each config file reset since the last
+/// call. The config commands the frontend runs on startup call this, after
+/// which recoveries are also emitted as soon as they happen.
+pub(crate) fn emit_recovered_configs(app: &AppHandle) {
+    let _ = RECOVERY_EMITTER.set(app.clone());
+    let recovered = std::mem::take(&mut *recovered_configs().lock().unwrap_or_else(|e| e.into_inner()));
+    for config in recovered {
+        let _ = app.emit("config://recovered", config);
+    }
+}
 
-/// Result returned after saving an image asset.
+/// Locations of Miku's config files
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SavedImageAsset {
-    /// Absolute path on disk where the image was written.
-    pub absolute_path: String,
-    /// Markdown-friendly path to embed in the document. Relative when a
-    /// document path is known, otherwise the absolute path.
-    pub markdown_path: String,
+pub struct AppPaths {
+    pub data_dir: String,
+    pub settings_path: String,
+    pub recent_files_path: String,
+    pub workspace_config_path: String,
 }
 
-/// Save raw image bytes to disk.
-///
-/// When `document_path` is provided, the image is written to a sibling
-/// `assets/` folder next to the document and the returned `markdown_path`
-/// is a forward-slash relative path. When no document path is provided,
-/// the image is written inside the app data directory under `assets/`
-/// and the absolute path is returned.
+fn app_paths_in(data_dir: &Path) -> AppPaths {
+    AppPaths {
+        data_dir: data_dir.to_string_lossy().to_string(),
+        settings_path: data_dir.join(SETTINGS_FILE).to_string_lossy().to_string(),
+        recent_files_path: data_dir.join(RECENT_FILES_FILE).to_string_lossy().to_string(),
+        workspace_config_path: workspace_config_path_in(data_dir).to_string_lossy().to_string(),
+    }
+}
+
+/// Get the directory where Miku stores its settings and state
 #[tauri::command]
-pub async fn save_image_asset(
-    document_path: Option<String>,
-    data: Vec<u8>,
-    extension: String,
-) -> Result<SavedImageAsset, MikuError> {
-    // Sanitize extension - strip leading dot, limit chars
-    let ext = extension
-        .trim()
-        .trim_start_matches('.')
-        .chars()
-        .filter(|c| c.is_ascii_alphanumeric())
-        .take(8)
-        .collect::<String>()
-        .to_lowercase();
-    let ext = if ext.is_empty() { "png".to_string() } else { ext };
+pub fn get_app_data_dir() -> Result<String, MikuError> {
+    Ok(app_data_dir()?.to_string_lossy().to_string())
+}
 
-    // Generate unique filename using millis timestamp
-    let ts = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_millis())
-        .unwrap_or(0);
-    let filename = format!("image-{}.{}", ts, ext);
+/// Get the resolved locations of Miku's config files
+#[tauri::command]
+pub fn get_paths() -> Result<AppPaths, MikuError> {
+    Ok(app_paths_in(&app_data_dir()?))
+}
 
-    // Determine target directory
-    let (assets_dir, markdown_path) = match document_path.as_deref() {
-        Some(doc_path) if !doc_path.is_empty() => {
-            let doc = std::path::Path::new(doc_path);
-            let parent = doc
-                .parent()
-                .ok_or_else(|| MikuError::Path("Document has no parent directory".to_string()))?;
-            let assets = parent.join("assets");
-            let md = format!("assets/{}", filename);
-            (assets, md)
-        }
-        _ => {
-            let assets = get_app_data_dir()?.join("assets");
-            let full = assets.join(&filename);
-            let md = full.to_string_lossy().to_string();
-            (assets, md)
+pub(crate) async fn load_settings_in(data_dir: &Path) -> Result<EditorSettings, MikuError> {
+    let settings_path = data_dir.join(SETTINGS_FILE);
+
+    let settings = if settings_path.exists() {
+        let content = tokio::fs::read_to_string(&settings_path).await?;
+        match serde_json::from_str(&content) {
+            Ok(settings) => settings,
+            Err(err) => {
+                quarantine_config(&settings_path, &content, &err).await?;
+                EditorSettings::default()
+            }
         }
+    } else {
+        EditorSettings::default()
     };
+    Ok(settings)
+}
 
-    tokio::fs::create_dir_all(&assets_dir).await?;
+#[tauri::command]
+pub async fn load_settings(app: AppHandle) -> Result<EditorSettings, MikuError> {
+    let data_dir = app_data_dir()?;
+    let settings = with_read_timeout(data_dir.join(SETTINGS_FILE), load_settings_in(&data_dir)).await?;
+    emit_recovered_configs(&app);
+    apply_global_settings(&settings);
+    Ok(settings)
+}
+
+/// `options` if given, otherwise the markdown extensions from the saved
+/// settings, falling back to the defaults when those can't be read
+pub(crate) async fn markdown_options_or_saved(options: Option<MarkdownOptions>) -> MarkdownOptions {
+    if let Some(options) = options {
+        return options;
+    }
+    match app_data_dir() {
+        Ok(data_dir) => settings_or_default(&data_dir).await.markdown_options,
+        Err(_) => MarkdownOptions::default(),
+    }
+}
+
+/// How many files bulk operations may open at once, from the saved
+/// settings or the default when those can't be read
+pub(crate) async fn io_concurrency_or_saved() -> usize {
+    match app_data_dir() {
+        Ok(data_dir) => settings_or_default(&data_dir).await.io_concurrency_limit(),
+        Err(_) => DEFAULT_IO_CONCURRENCY,
+    }
+}
+
+pub(crate) async fn save_settings_in(data_dir: &Path, settings: &EditorSettings) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
+
+    let content = serde_json::to_string_pretty(settings)?;
+    write_atomic(&data_dir.join(SETTINGS_FILE), content).await
+}
+
+/// Lay the fields in `patch` over the stored settings and save the result.
+/// Fields `patch` leaves out keep their saved values.
+pub(crate) async fn merge_settings_in(
+    data_dir: &Path,
+    patch: serde_json::Map<String, serde_json::Value>,
+) -> Result<EditorSettings, MikuError> {
+    let mut merged = match serde_json::to_value(load_settings_in(data_dir).await?)? {
+        serde_json::Value::Object(fields) => fields,
+        _ => Default::default(),
+    };
+    merged.extend(patch);
+    let settings: EditorSettings = serde_json::from_value(serde_json::Value::Object(merged))?;
+    save_settings_in(data_dir, &settings).await?;
+    Ok(settings)
+}
+
+/// Save the settings the frontend knows about. Backend-only settings (recent
+/// file limits, retention, ...) aren't part of its payload and are kept.
+#[tauri::command]
+pub async fn save_settings(
+    settings: serde_json::Map<String, serde_json::Value>,
+    lock: State<'_, ConfigLock>,
+) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let settings = merge_settings_in(&app_data_dir()?, settings).await?;
+    apply_global_settings(&settings);
+    Ok(())
+}
+
+pub(crate) async fn set_markdown_extensions_in(
+    data_dir: &Path,
+    extensions: &[String],
+) -> Result<Vec<String>, MikuError> {
+    let mut settings = load_settings_in(data_dir).await?;
+    settings.markdown_extensions = file_ops::normalize_extensions(extensions);
+    save_settings_in(data_dir, &settings).await?;
+    Ok(settings.markdown_extensions)
+}
+
+/// Set which file extensions are treated as markdown everywhere: the file
+/// tree, search, links and recent files. Returns the cleaned-up list.
+#[tauri::command]
+pub async fn set_markdown_extensions(
+    extensions: Vec<String>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Vec<String>, MikuError> {
+    let _guard = lock.acquire().await;
+    let extensions = set_markdown_extensions_in(&app_data_dir()?, &extensions).await?;
+    file_ops::set_markdown_extensions(&extensions);
+    Ok(extensions)
+}
+
+pub(crate) async fn read_document(path: String, readonly: bool) -> Result<Document, MikuError> {
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    Ok(Document::saved(path, content, readonly))
+}
+
+#[tauri::command]
+pub async fn open_file(path: String, readonly_docs: State<'_, ReadonlyDocuments>) -> Result<Document, MikuError> {
+    let document = with_read_timeout(path.clone(), read_document(path, false)).await?;
+    readonly_docs.set(&document.path.clone().unwrap_or_default(), false);
+    Ok(document)
+}
+
+/// Open the file at `path`, or when it's missing and `create_if_missing` is
+/// set, create it empty and remember it as a recent file. Returns the
+/// document and whether it was created.
+pub(crate) async fn open_file_or_create_in(
+    data_dir: &Path,
+    path: String,
+    create_if_missing: bool,
+) -> Result<(Document, bool), MikuError> {
+    if Path::new(&path).exists() {
+        return Ok((read_document(path, false).await?, false));
+    }
+    if !create_if_missing {
+        return Err(MikuError::NotFound(path));
+    }
+
+    if let Some(parent) = Path::new(&path).parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| MikuError::from_io(e, parent))?;
+    }
+    // `create_new` so a file that appeared in the meantime isn't truncated
+    tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    add_recent_file_in(data_dir, path.clone()).await?;
+    Ok((read_document(path, false).await?, true))
+}
+
+/// Open a file, recreating it empty when it's missing and
+/// `create_if_missing` is set, e.g. for a recent file that was deleted.
+/// Otherwise a missing file fails with `NotFound`.
+#[tauri::command]
+pub async fn open_file_or_create(
+    app: AppHandle,
+    path: String,
+    create_if_missing: bool,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Document, MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    let (document, created) =
+        with_read_timeout(path.clone(), open_file_or_create_in(&data_dir, path, create_if_missing)).await?;
+    readonly_docs.set(&document.path.clone().unwrap_or_default(), false);
+    if created {
+        emit_recents_changed(&app, &data_dir).await?;
+    }
+    Ok(document)
+}
+
+/// Open a file in preview mode. Saves to this path are refused until it's
+/// opened normally again.
+#[tauri::command]
+pub async fn open_file_readonly(
+    path: String,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+) -> Result<Document, MikuError> {
+    let document = with_read_timeout(path.clone(), read_document(path, true)).await?;
+    readonly_docs.set(&document.path.clone().unwrap_or_default(), true);
+    Ok(document)
+}
+
+/// Whether `current_content` differs from what's saved at `path`. Comparing
+/// hashes means typing and then undoing back to the saved text isn't dirty.
+/// Unsaved documents (no path) are dirty once they have any content.
+#[tauri::command]
+pub async fn document_is_dirty(path: Option<String>, current_content: String) -> Result<bool, MikuError> {
+    let Some(path) = path else {
+        return Ok(!current_content.is_empty());
+    };
+
+    let saved = match tokio::fs::read_to_string(&path).await {
+        Ok(saved) => saved,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(MikuError::from_io(e, &path)),
+    };
+    Ok(content_hash(&saved) != content_hash(&current_content))
+}
+
+/// The beginning of a file, for hover previews
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FilePreview {
+    pub preview: String,
+    /// True when the file continues past the returned preview
+    pub truncated: bool,
+}
+
+/// Read at most `max_lines` lines and `max_bytes` bytes from the start of a
+/// file, stopping as soon as either limit is hit.
+#[tauri::command]
+pub async fn preview_file(path: String, max_lines: usize, max_bytes: usize) -> Result<FilePreview, MikuError> {
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    let mut reader = tokio::io::BufReader::new(file);
+
+    let mut buf = Vec::new();
+    let mut lines = 0;
+    let mut truncated = false;
+
+    while lines < max_lines {
+        // Never buffer more than one byte past the limit, even for huge lines
+        let remaining = (max_bytes - buf.len()) as u64 + 1;
+        let read = (&mut reader).take(remaining).read_until(b'\n', &mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        if buf.len() > max_bytes {
+            buf.truncate(max_bytes);
+            truncated = true;
+            break;
+        }
+        lines += 1;
+    }
+
+    if !truncated && lines == max_lines {
+        truncated = !reader.fill_buf().await?.is_empty();
+    }
+
+    // Don't split a multi-byte character at the cut point
+    if let Err(e) = std::str::from_utf8(&buf) {
+        if e.error_len().is_none() {
+            buf.truncate(e.valid_up_to());
+        }
+    }
+
+    Ok(FilePreview {
+        preview: String::from_utf8_lossy(&buf).into_owned(),
+        truncated,
+    })
+}
+
+/// Byte limit `preview_files` uses when none is given
+const DEFAULT_PREVIEW_BYTES: usize = 4096;
+
+/// One file's preview from `preview_files`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileItemPreview {
+    pub path: String,
+    pub preview: String,
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub(crate) async fn preview_files_in(
+    paths: Vec<String>,
+    max_lines: usize,
+    max_bytes: usize,
+    concurrency: usize,
+) -> Vec<FileItemPreview> {
+    stream::iter(paths)
+        .map(|path| async move {
+            match preview_file(path.clone(), max_lines, max_bytes).await {
+                Ok(FilePreview { preview, truncated }) => FileItemPreview {
+                    path,
+                    preview,
+                    truncated,
+                    error: None,
+                },
+                Err(e) => FileItemPreview {
+                    path,
+                    preview: String::new(),
+                    truncated: false,
+                    error: Some(e.to_string()),
+                },
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Preview many files in one call, e.g. for a grid of note cards. Reads run
+/// concurrently up to the `io_concurrency` setting; a file that can't be
+/// read gets an `error` instead of failing the batch. Results keep the
+/// order of `paths`.
+#[tauri::command]
+pub async fn preview_files(paths: Vec<String>, max_lines: usize, max_bytes: Option<usize>) -> Vec<FileItemPreview> {
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_PREVIEW_BYTES);
+    preview_files_in(paths, max_lines, max_bytes, io_concurrency_or_saved().await).await
+}
+
+/// Write a document to disk, refusing files that are read-only on disk
+pub(crate) async fn write_document(path: String, content: String) -> Result<(), MikuError> {
+    if let Ok(metadata) = tokio::fs::metadata(&path).await {
+        if metadata.permissions().readonly() {
+            return Err(MikuError::ReadOnly(path));
+        }
+    }
+
+    tokio::fs::write(&path, &content)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    crate::watcher::record_write(Path::new(&path), content_hash(&content)).await;
+    Ok(())
+}
+
+/// Make sure the folder `path` goes in exists, creating it when
+/// `create_parents` is set. A missing folder is otherwise `NotFound` naming
+/// it, rather than a bare IO error from the write.
+async fn ensure_parent_dir(path: &str, create_parents: bool) -> Result<(), MikuError> {
+    let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if parent.is_dir() {
+        return Ok(());
+    }
+    if !create_parents {
+        return Err(MikuError::NotFound(parent.to_string_lossy().to_string()));
+    }
+    tokio::fs::create_dir_all(parent)
+        .await
+        .map_err(|e| MikuError::from_io(e, parent))
+}
+
+/// Save `content` to `path` with the cleanups from `settings`. Returns the
+/// content as written.
+pub(crate) async fn save_file_in(
+    readonly_docs: &ReadonlyDocuments,
+    settings: &EditorSettings,
+    path: String,
+    content: String,
+    line_ending: Option<LineEnding>,
+    create_parents: bool,
+) -> Result<String, MikuError> {
+    if readonly_docs.contains(&path) {
+        return Err(MikuError::ReadOnly(path));
+    }
+    ensure_parent_dir(&path, create_parents).await?;
+    let content = if settings.trim_trailing_whitespace {
+        trim_trailing_whitespace(&content, settings.preserve_hard_breaks)
+    } else {
+        content
+    };
+    let content = match line_ending {
+        Some(line_ending) => line_ending.apply(content),
+        None => content,
+    };
+    write_document(path.clone(), content.clone()).await?;
+    remove_autosave_sidecar(&path).await?;
+    Ok(content)
+}
+
+/// Save a document. Pass the `line_ending` the document was opened with to
+/// keep the file's original line break style. If the file's folder was
+/// removed in the meantime this fails with `NotFound` for the folder,
+/// unless `create_parents` is set to recreate it. Returns the content as
+/// written, which differs from `content` when trailing whitespace is
+/// trimmed on save.
+#[tauri::command]
+pub async fn save_file(
+    path: String,
+    content: String,
+    line_ending: Option<LineEnding>,
+    create_parents: Option<bool>,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+) -> Result<String, MikuError> {
+    let settings = settings_or_default(&app_data_dir()?).await;
+    let create_parents = create_parents.unwrap_or(false);
+    save_file_in(&readonly_docs, &settings, path, content, line_ending, create_parents).await
+}
+
+pub(crate) async fn save_file_as_in(
+    data_dir: &Path,
+    readonly_docs: &ReadonlyDocuments,
+    new_path: String,
+    content: String,
+    line_ending: Option<LineEnding>,
+    overwrite: bool,
+    source_path: Option<&str>,
+) -> Result<Document, MikuError> {
+    if Path::new(&new_path).exists() && !overwrite {
+        return Err(MikuError::AlreadyExists(new_path));
+    }
+
+    let settings = settings_or_default(data_dir).await;
+    let written = save_file_in(readonly_docs, &settings, new_path.clone(), content, line_ending, false).await?;
+    add_recent_file_in(data_dir, new_path.clone()).await?;
+    if let Some(source_path) = source_path {
+        crate::file_ids::move_file_ids_in(data_dir, source_path, &new_path).await?;
+    }
+    Ok(Document::saved(new_path, written, false))
+}
+
+/// Save a document under a new path, add it to recent files and return it
+/// as the document to keep editing. An existing file at `new_path` is only
+/// replaced with `overwrite`; otherwise this fails with `AlreadyExists`.
+/// Data kept by file id for `source_path`, the document it was saved from,
+/// moves to the new path.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_file_as(
+    app: AppHandle,
+    new_path: String,
+    content: String,
+    line_ending: Option<LineEnding>,
+    overwrite: Option<bool>,
+    source_path: Option<String>,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Document, MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    let document = save_file_as_in(
+        &data_dir,
+        &readonly_docs,
+        new_path,
+        content,
+        line_ending,
+        overwrite.unwrap_or(false),
+        source_path.as_deref(),
+    )
+    .await?;
+    emit_recents_changed(&app, &data_dir).await?;
+    Ok(document)
+}
+
+/// Whether the file at `path` is non-empty and doesn't end with a newline
+async fn missing_trailing_newline(path: &str) -> Result<bool, MikuError> {
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(MikuError::from_io(e, path)),
+    };
+
+    if file.metadata().await?.len() == 0 {
+        return Ok(false);
+    }
+
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1)).await?;
+    file.read_exact(&mut last).await?;
+    Ok(last[0] != b'\n')
+}
+
+/// Append text to a file without rewriting it, creating the file if needed.
+/// With `ensure_leading_newline`, a newline is inserted first when the file
+/// doesn't already end with one.
+#[tauri::command]
+pub async fn append_to_file(path: String, text: String, ensure_leading_newline: bool) -> Result<(), MikuError> {
+    let mut chunk = String::with_capacity(text.len() + 1);
+    if ensure_leading_newline && missing_trailing_newline(&path).await? {
+        chunk.push('\n');
+    }
+    chunk.push_str(&text);
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    file.write_all(chunk.as_bytes()).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn new_document() -> Document {
+    Document::default()
+}
+
+/// An entry in the recent files list
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecentFile {
+    pub path: String,
+    /// Workspace that was active when the file was opened, or `None` for
+    /// files opened outside of any workspace (the global bucket)
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// When the file was last opened, in milliseconds since the Unix epoch
+    #[serde(default)]
+    pub last_opened_ms: u64,
+}
+
+/// A recent file as shown in the UI
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecentFileEntry {
+    pub path: String,
+    pub name: String,
+    pub last_opened_ms: u64,
+    /// Whether the file still exists on disk. Missing files are kept so the
+    /// UI can grey them out rather than silently dropping them.
+    pub exists: bool,
+}
+
+impl From<RecentFile> for RecentFileEntry {
+    fn from(file: RecentFile) -> Self {
+        RecentFileEntry {
+            name: get_file_name(&file.path).unwrap_or_else(|| file.path.clone()),
+            exists: Path::new(&file.path).exists(),
+            last_opened_ms: file.last_opened_ms,
+            path: file.path,
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// On-disk shape of a recent file entry. Older versions stored a bare path.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRecentFile {
+    Entry(RecentFile),
+    Legacy(String),
+}
+
+fn parse_recent_files(content: &str) -> Result<Vec<RecentFile>, serde_json::Error> {
+    let stored: Vec<StoredRecentFile> = serde_json::from_str(content)?;
+    Ok(stored
+        .into_iter()
+        .map(|entry| match entry {
+            StoredRecentFile::Entry(file) => file,
+            StoredRecentFile::Legacy(path) => RecentFile {
+                path,
+                workspace: None,
+                last_opened_ms: 0,
+            },
+        })
+        .collect())
+}
+
+/// Load all recent file entries from `data_dir`
+pub(crate) async fn load_recent_files_in(data_dir: &Path) -> Result<Vec<RecentFile>, MikuError> {
+    let recent_path = data_dir.join(RECENT_FILES_FILE);
+
+    if recent_path.exists() {
+        let content = tokio::fs::read_to_string(&recent_path).await?;
+        match parse_recent_files(&content) {
+            Ok(files) => Ok(files),
+            Err(err) => {
+                quarantine_config(&recent_path, &content, &err).await?;
+                Ok(Vec::new())
+            }
+        }
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Recent files for `workspace`, followed by files from the global bucket
+/// in the order they were opened. `None` returns only the global bucket.
+pub(crate) async fn recent_files_for_workspace_in(
+    data_dir: &Path,
+    workspace: Option<&str>,
+) -> Result<Vec<RecentFileEntry>, MikuError> {
+    let files = load_recent_files_in(data_dir).await?;
+
+    Ok(files
+        .into_iter()
+        .filter(|f| f.workspace.is_none() || f.workspace.as_deref() == workspace)
+        .map(RecentFileEntry::from)
+        .collect())
+}
+
+/// Recent files split by whether they're still on disk
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct PartitionedRecentFiles {
+    pub existing: Vec<RecentFileEntry>,
+    /// Moved or deleted since they were opened
+    pub missing: Vec<RecentFileEntry>,
+}
+
+/// `recent_files_for_workspace_in`, split into files that exist and files
+/// that don't. The stored list is left as is.
+pub(crate) async fn recent_files_partitioned_in(
+    data_dir: &Path,
+    workspace: Option<&str>,
+) -> Result<PartitionedRecentFiles, MikuError> {
+    let (existing, missing) = recent_files_for_workspace_in(data_dir, workspace)
+        .await?
+        .into_iter()
+        .partition(|entry| entry.exists);
+    Ok(PartitionedRecentFiles { existing, missing })
+}
+
+pub(crate) async fn add_recent_file_in(data_dir: &Path, path: String) -> Result<(), MikuError> {
+    // Associate the file with the active workspace when it lives inside it
+    let workspace = load_workspace_config_in(data_dir)
+        .await?
+        .current_workspace
+        .filter(|ws| Path::new(&path).starts_with(ws));
+
+    tokio::fs::create_dir_all(data_dir).await?;
+
+    let recent_path = data_dir.join(RECENT_FILES_FILE);
+    let mut files = load_recent_files_in(data_dir).await?;
+
+    // Remove if already exists and add to front
+    files.retain(|f| !file_ops::same_path(&f.path, &path));
+    files.insert(
+        0,
+        RecentFile {
+            path,
+            workspace: workspace.clone(),
+            last_opened_ms: now_ms(),
+        },
+    );
+
+    // Keep only the most recent files of this workspace's bucket
+    let limit = settings_or_default(data_dir).await.recent_files_limit();
+    let mut kept = 0;
+    files.retain(|f| {
+        if f.workspace != workspace {
+            return true;
+        }
+        kept += 1;
+        kept <= limit
+    });
+
+    let content = serde_json::to_string_pretty(&files)?;
+    write_atomic(&recent_path, content).await?;
+
+    if let Some(workspace) = &workspace {
+        set_last_opened_in(data_dir, workspace, &files[0].path).await?;
+    }
+
+    Ok(())
+}
+
+/// Point recent entries for `old_path` at `new_path`, which now belongs to
+/// `workspace`. Other entries are left untouched.
+pub(crate) async fn move_recent_file_in(
+    data_dir: &Path,
+    old_path: &str,
+    new_path: &str,
+    workspace: Option<&str>,
+) -> Result<(), MikuError> {
+    let mut files = load_recent_files_in(data_dir).await?;
+    if !files.iter().any(|f| file_ops::same_path(&f.path, old_path)) {
+        return Ok(());
+    }
+
+    for file in files.iter_mut().filter(|f| file_ops::same_path(&f.path, old_path)) {
+        file.path = new_path.to_string();
+        file.workspace = workspace.map(|w| w.to_string());
+    }
+
+    let content = serde_json::to_string_pretty(&files)?;
+    write_atomic(&data_dir.join(RECENT_FILES_FILE), content).await
+}
+
+/// Point the recent entry for `old_path`, typically one that went missing,
+/// at the existing file `new_path`, keeping its position, bucket and open
+/// time. Data stored for the file by id follows it too.
+pub(crate) async fn relocate_recent_file_in(data_dir: &Path, old_path: &str, new_path: &str) -> Result<(), MikuError> {
+    if !Path::new(new_path).is_file() {
+        return Err(MikuError::NotFound(new_path.to_string()));
+    }
+
+    let mut files = load_recent_files_in(data_dir).await?;
+    let Some(index) = files.iter().position(|f| file_ops::same_path(&f.path, old_path)) else {
+        return Err(MikuError::NotFound(old_path.to_string()));
+    };
+    files[index].path = new_path.to_string();
+    // Drop any other entry already pointing at `new_path`
+    let mut position = 0;
+    files.retain(|f| {
+        position += 1;
+        position - 1 == index || !file_ops::same_path(&f.path, new_path)
+    });
+
+    let content = serde_json::to_string_pretty(&files)?;
+    write_atomic(&data_dir.join(RECENT_FILES_FILE), content).await?;
+    crate::file_ids::move_file_ids_in(data_dir, old_path, new_path).await
+}
+
+/// Drop `path` from the recent files, in whichever bucket it is
+pub(crate) async fn remove_recent_file_in(data_dir: &Path, path: &str) -> Result<(), MikuError> {
+    let mut files = load_recent_files_in(data_dir).await?;
+    let before = files.len();
+    files.retain(|f| !file_ops::same_path(&f.path, path));
+    if files.len() == before {
+        return Ok(());
+    }
+
+    let content = serde_json::to_string_pretty(&files)?;
+    write_atomic(&data_dir.join(RECENT_FILES_FILE), content).await
+}
+
+/// Forget the recent files shown for the active workspace: its own bucket
+/// and the global one. Other workspaces keep their history.
+pub(crate) async fn clear_recent_files_in(data_dir: &Path) -> Result<(), MikuError> {
+    let workspace = load_workspace_config_in(data_dir).await?.current_workspace;
+    let mut files = load_recent_files_in(data_dir).await?;
+    files.retain(|f| f.workspace.is_some() && f.workspace != workspace);
+
+    let content = serde_json::to_string_pretty(&files)?;
+    write_atomic(&data_dir.join(RECENT_FILES_FILE), content).await
+}
+
+/// Event sent to every window after the recent files change
+pub const RECENTS_CHANGED_EVENT: &str = "recents://changed";
+
+/// Hand the active workspace's recent files to `emit`, so other windows can
+/// refresh their menus without polling
+pub(crate) async fn emit_recents_changed_in(
+    data_dir: &Path,
+    emit: impl Fn(Vec<RecentFileEntry>),
+) -> Result<(), MikuError> {
+    let workspace = load_workspace_config_in(data_dir).await?.current_workspace;
+    emit(recent_files_for_workspace_in(data_dir, workspace.as_deref()).await?);
+    Ok(())
+}
+
+pub(crate) async fn emit_recents_changed(app: &AppHandle, data_dir: &Path) -> Result<(), MikuError> {
+    emit_recents_changed_in(data_dir, |files| {
+        let _ = app.emit(RECENTS_CHANGED_EVENT, files);
+    })
+    .await
+}
+
+/// Get recent files for the active workspace (plus files opened outside any workspace)
+#[tauri::command]
+pub async fn get_recent_files(app: AppHandle) -> Result<Vec<RecentFileEntry>, MikuError> {
+    let data_dir = app_data_dir()?;
+    let workspace = load_workspace_config_in(&data_dir).await?.current_workspace;
+    let files = recent_files_for_workspace_in(&data_dir, workspace.as_deref()).await;
+    emit_recovered_configs(&app);
+    files
+}
+
+/// Get recent files for a specific workspace (plus files opened outside any workspace)
+#[tauri::command]
+pub async fn get_recent_files_for_workspace(workspace_path: String) -> Result<Vec<RecentFileEntry>, MikuError> {
+    recent_files_for_workspace_in(&app_data_dir()?, Some(&workspace_path)).await
+}
+
+/// Recent files for the active workspace, split into existing and missing
+/// ones so the UI can offer to relocate the missing ones
+#[tauri::command]
+pub async fn get_recent_files_partitioned() -> Result<PartitionedRecentFiles, MikuError> {
+    let data_dir = app_data_dir()?;
+    let workspace = load_workspace_config_in(&data_dir).await?.current_workspace;
+    recent_files_partitioned_in(&data_dir, workspace.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn add_recent_file(app: AppHandle, path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    add_recent_file_in(&data_dir, path).await?;
+    emit_recents_changed(&app, &data_dir).await
+}
+
+/// Remove one file from the recent files list
+#[tauri::command]
+pub async fn remove_recent_file(app: AppHandle, path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    remove_recent_file_in(&data_dir, &path).await?;
+    emit_recents_changed(&app, &data_dir).await
+}
+
+/// Fix up a recent file that was moved outside the app by pointing its
+/// entry at `new_path`, which must exist
+#[tauri::command]
+pub async fn relocate_recent_file(
+    app: AppHandle,
+    old_path: String,
+    new_path: String,
+    lock: State<'_, ConfigLock>,
+) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    relocate_recent_file_in(&data_dir, &old_path, &new_path).await?;
+    emit_recents_changed(&app, &data_dir).await
+}
+
+/// Clear the recent files shown for the active workspace
+#[tauri::command]
+pub async fn clear_recent_files(app: AppHandle, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    clear_recent_files_in(&data_dir).await?;
+    emit_recents_changed(&app, &data_dir).await
+}
+
+#[tauri::command]
+pub fn get_app_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Summary of the app's state for support requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub os: String,
+    pub data_dir: String,
+    /// Whether the settings file is missing or parses cleanly
+    pub settings_ok: bool,
+    /// Whether the workspace config is missing or parses cleanly
+    pub workspace_config_ok: bool,
+    pub current_workspace: Option<String>,
+    pub recent_file_count: usize,
+}
+
+/// Contents of a config file, `None` when it doesn't exist
+pub(crate) async fn read_config_file(path: &Path) -> Result<Option<String>, MikuError> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(MikuError::from_io(e, path)),
+    }
+}
+
+pub(crate) async fn diagnostics_in(data_dir: &Path) -> Diagnostics {
+    // Parse the files directly: the regular loaders would repair them
+    let settings = read_config_file(&data_dir.join(SETTINGS_FILE)).await;
+    let workspace_config = read_config_file(&workspace_config_path_in(data_dir)).await;
+    let recent_files = read_config_file(&data_dir.join(RECENT_FILES_FILE)).await;
+
+    let settings_ok = match settings {
+        Ok(Some(content)) => serde_json::from_str::<EditorSettings>(&content).is_ok(),
+        Ok(None) => true,
+        Err(_) => false,
+    };
+    let workspace_config = match workspace_config {
+        Ok(Some(content)) => serde_json::from_str::<WorkspaceConfig>(&content).ok(),
+        Ok(None) => Some(WorkspaceConfig::default()),
+        Err(_) => None,
+    };
+    let recent_file_count = match recent_files {
+        Ok(Some(content)) => parse_recent_files(&content).map_or(0, |files| files.len()),
+        _ => 0,
+    };
+
+    Diagnostics {
+        app_version: get_app_version(),
+        os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        data_dir: data_dir.to_string_lossy().to_string(),
+        settings_ok,
+        workspace_config_ok: workspace_config.is_some(),
+        current_workspace: workspace_config.and_then(|config| config.current_workspace),
+        recent_file_count,
+    }
+}
+
+/// Collect version, platform, and config health in one report. Never fails
+/// on broken config; that's what it reports.
+#[tauri::command]
+pub async fn diagnostics() -> Result<Diagnostics, MikuError> {
+    Ok(diagnostics_in(&app_data_dir()?).await)
+}
+
+/// Something wrong with the app data directory or a file in it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AppDataIssue {
+    /// The file, or the data directory itself
+    pub file: String,
+    pub problem: String,
+}
+
+/// Result of `verify_app_data`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AppDataReport {
+    pub writable: bool,
+    pub issues: Vec<AppDataIssue>,
+}
+
+/// Why a config file's content doesn't parse, if it doesn't
+type ConfigCheck = fn(&str) -> Option<String>;
+
+/// Why `content` isn't a valid `T`, if it isn't
+fn parse_error<T: serde::de::DeserializeOwned>(content: &str) -> Option<String> {
+    serde_json::from_str::<T>(content).err().map(|err| err.to_string())
+}
+
+/// Create `data_dir` if needed and write and remove a scratch file in it
+async fn probe_writable(data_dir: &Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    let probe = data_dir.join(format!(".write-test-{}", now_ms()));
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+pub(crate) async fn verify_app_data_in(data_dir: &Path) -> AppDataReport {
+    let mut issues = Vec::new();
+    let writable = match probe_writable(data_dir).await {
+        Ok(()) => true,
+        Err(err) => {
+            issues.push(AppDataIssue {
+                file: data_dir.to_string_lossy().to_string(),
+                problem: format!("Not writable: {}", err),
+            });
+            false
+        }
+    };
+
+    // Parse the files directly: the regular loaders would repair them
+    let checks: [(PathBuf, ConfigCheck); 8] = [
+        (data_dir.join(SETTINGS_FILE), parse_error::<EditorSettings>),
+        (data_dir.join(RECENT_FILES_FILE), |content| parse_recent_files(content).err().map(|err| err.to_string())),
+        (workspace_config_path_in(data_dir), parse_error::<WorkspaceConfig>),
+        (data_dir.join(PREFERENCES_FILE), parse_error::<serde_json::Map<String, serde_json::Value>>),
+        (data_dir.join(FILE_IDS_FILE), parse_error::<FileIdStore>),
+        (data_dir.join(SESSION_FILE), parse_error::<SessionState>),
+        (data_dir.join(LAYOUT_FILE), parse_error::<serde_json::Value>),
+        (data_dir.join(TRASH_INDEX_FILE), parse_error::<Vec<TrashedItem>>),
+    ];
+    for (path, check) in checks {
+        let problem = match read_config_file(&path).await {
+            Ok(Some(content)) => check(&content).map(|err| format!("Doesn't parse: {}", err)),
+            Ok(None) => None,
+            Err(err) => Some(format!("Can't be read: {}", err)),
+        };
+        if let Some(problem) = problem {
+            issues.push(AppDataIssue {
+                file: path.to_string_lossy().to_string(),
+                problem,
+            });
+        }
+    }
+
+    AppDataReport { writable, issues }
+}
+
+/// Check on startup that the data directory can be written and that every
+/// config file in it parses. Nothing is changed; loading a corrupt file
+/// through its regular command sets it aside and starts fresh.
+#[tauri::command]
+pub async fn verify_app_data() -> Result<AppDataReport, MikuError> {
+    Ok(verify_app_data_in(&app_data_dir()?).await)
+}
+
+// ============================================
+// Path helpers
+// ============================================
+
+#[tauri::command]
+pub fn path_exists(path: String) -> bool {
+    file_ops::file_exists(&path)
+}
+
+#[tauri::command]
+pub fn is_markdown_file(path: String) -> bool {
+    file_ops::is_markdown_file(&path)
+}
+
+#[tauri::command]
+pub fn file_name(path: String) -> Option<String> {
+    file_ops::get_file_name(&path)
+}
+
+#[tauri::command]
+pub fn parent_dir(path: String) -> Option<String> {
+    file_ops::get_parent_dir(&path)
+}
+
+/// `path` relative to `base` (usually the workspace root) for display.
+/// Paths outside `base` are returned unchanged.
+#[tauri::command]
+pub fn relativize_path(path: String, base: String) -> String {
+    file_ops::relativize_path(&path, &base)
+}
+
+pub(crate) async fn open_external_in(path: &str) -> Result<(), MikuError> {
+    if !Path::new(path).exists() {
+        return Err(MikuError::NotFound(path.to_string()));
+    }
+
+    // The opener hands the path to the OS directly (ShellExecute, `open`,
+    // `xdg-open`), so shell metacharacters in file names aren't interpreted
+    let target = path.to_string();
+    tokio::task::spawn_blocking(move || tauri_plugin_opener::open_path(&target, None::<&str>))
+        .await
+        .map_err(std::io::Error::other)?
+        .map_err(|e| MikuError::Path(format!("No default application could open {}: {}", path, e)))
+}
+
+/// Hand a file the editor can't display (PDFs, images, ...) to the
+/// system's default application for it
+#[tauri::command]
+pub async fn open_external(path: String) -> Result<(), MikuError> {
+    open_external_in(&path).await
+}
+
+// ============================================
+// Session restore
+// ============================================
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionTab {
+    pub path: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionState {
+    pub tabs: Vec<SessionTab>,
+    pub active_index: usize,
+}
+
+pub(crate) async fn save_session_in(data_dir: &Path, session: &SessionState) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    let content = serde_json::to_string_pretty(session)?;
+    write_atomic(&data_dir.join(SESSION_FILE), content).await
+}
+
+/// The saved session, or `None` when there is none. A corrupt file is set
+/// aside like other config files.
+pub(crate) async fn load_session_in(data_dir: &Path) -> Result<Option<SessionState>, MikuError> {
+    let session_path = data_dir.join(SESSION_FILE);
+    let Some(content) = read_config_file(&session_path).await? else {
+        return Ok(None);
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(session) => Ok(Some(session)),
+        Err(err) => {
+            quarantine_config(&session_path, &content, &err).await?;
+            Ok(None)
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn save_session(session: SessionState) -> Result<(), MikuError> {
+    save_session_in(&app_data_dir()?, &session).await
+}
+
+#[tauri::command]
+pub async fn load_session(app: AppHandle) -> Result<Option<SessionState>, MikuError> {
+    let session = load_session_in(&app_data_dir()?).await;
+    emit_recovered_configs(&app);
+    session
+}
+
+// ============================================
+// Window layout
+// ============================================
+
+pub(crate) async fn save_layout_in(data_dir: &Path, layout: &serde_json::Value) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    let content = serde_json::to_string_pretty(layout)?;
+    write_atomic(&data_dir.join(LAYOUT_FILE), content).await
+}
+
+/// The saved layout, or an empty object when there is none. A corrupt
+/// file is set aside like other config files.
+pub(crate) async fn load_layout_in(data_dir: &Path) -> Result<serde_json::Value, MikuError> {
+    let empty = || serde_json::Value::Object(Default::default());
+    let layout_path = data_dir.join(LAYOUT_FILE);
+    let Some(content) = read_config_file(&layout_path).await? else {
+        return Ok(empty());
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(layout) => Ok(layout),
+        Err(err) => {
+            quarantine_config(&layout_path, &content, &err).await?;
+            Ok(empty())
+        }
+    }
+}
+
+/// Save window and panel layout (sizes, sidebar width, ...). The frontend
+/// owns the shape of `layout`; it's stored as-is.
+#[tauri::command]
+pub async fn save_layout(layout: serde_json::Value) -> Result<(), MikuError> {
+    save_layout_in(&app_data_dir()?, &layout).await
+}
+
+/// Load the layout saved by `save_layout`, or `{}` when there is none
+#[tauri::command]
+pub async fn load_layout(app: AppHandle) -> Result<serde_json::Value, MikuError> {
+    let layout = load_layout_in(&app_data_dir()?).await;
+    emit_recovered_configs(&app);
+    layout
+}
+
+// ============================================
+// Preferences
+// ============================================
+
+/// Split a `namespace.name` preference key
+fn preference_key(key: &str) -> Result<(&str, &str), MikuError> {
+    match key.split_once('.') {
+        Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => Ok((namespace, name)),
+        _ => Err(MikuError::Path(format!("Preference key must look like namespace.name: {}", key))),
+    }
+}
+
+/// Every stored preference, grouped by namespace
+async fn load_preferences_in(data_dir: &Path) -> Result<serde_json::Map<String, serde_json::Value>, MikuError> {
+    let preferences_path = data_dir.join(PREFERENCES_FILE);
+    let Some(content) = read_config_file(&preferences_path).await? else {
+        return Ok(Default::default());
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(preferences) => Ok(preferences),
+        Err(err) => {
+            quarantine_config(&preferences_path, &content, &err).await?;
+            Ok(Default::default())
+        }
+    }
+}
+
+pub(crate) async fn get_preference_in(data_dir: &Path, key: &str) -> Result<Option<serde_json::Value>, MikuError> {
+    let (namespace, name) = preference_key(key)?;
+    let mut preferences = load_preferences_in(data_dir).await?;
+    Ok(preferences
+        .get_mut(namespace)
+        .and_then(serde_json::Value::as_object_mut)
+        .and_then(|values| values.remove(name)))
+}
+
+/// Store `value` under `key`, or remove the key when `value` is null
+pub(crate) async fn set_preference_in(data_dir: &Path, key: &str, value: serde_json::Value) -> Result<(), MikuError> {
+    let (namespace, name) = preference_key(key)?;
+    let mut preferences = load_preferences_in(data_dir).await?;
+    let mut values = match preferences.remove(namespace) {
+        Some(serde_json::Value::Object(values)) => values,
+        _ => Default::default(),
+    };
+    if value.is_null() {
+        values.remove(name);
+    } else {
+        values.insert(name.to_string(), value);
+    }
+    if !values.is_empty() {
+        preferences.insert(namespace.to_string(), serde_json::Value::Object(values));
+    }
+
+    tokio::fs::create_dir_all(data_dir).await?;
+    write_atomic(&data_dir.join(PREFERENCES_FILE), serde_json::to_string_pretty(&preferences)?).await
+}
+
+/// A small frontend preference that has no place in `EditorSettings`.
+/// Keys are namespaced as `namespace.name`, e.g. `templates.lastUsed`.
+/// Returns null when the key was never set.
+#[tauri::command]
+pub async fn get_preference(key: String) -> Result<Option<serde_json::Value>, MikuError> {
+    get_preference_in(&app_data_dir()?, &key).await
+}
+
+/// Store any JSON `value` under a `namespace.name` key; null removes it
+#[tauri::command]
+pub async fn set_preference(
+    key: String,
+    value: serde_json::Value,
+    lock: State<'_, ConfigLock>,
+) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    set_preference_in(&app_data_dir()?, &key, value).await
+}
+
+// ============================================
+// App state bundle
+// ============================================
+
+/// Format version of exported app state bundles. Version 2 added
+/// `workspace_roots`.
+const APP_STATE_BUNDLE_VERSION: u32 = 2;
+
+/// Settings, recent files, workspaces (including pinned ones) and session
+/// in one portable file. Components are kept as raw JSON so each one is
+/// validated through its own loader on import.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppStateBundle {
+    pub version: u32,
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub recent_files: Option<serde_json::Value>,
+    #[serde(default)]
+    pub workspace_config: Option<serde_json::Value>,
+    #[serde(default)]
+    pub session: Option<serde_json::Value>,
+    /// Workspace folders of a portable bundle by the token that stands in
+    /// for them, e.g. `${WORKSPACE_1}` for `${HOME}/notes`. Editing an entry
+    /// relocates every path in that workspace on import.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub workspace_roots: HashMap<String, String>,
+}
+
+/// Which bundle components were restored
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    /// Components present in the bundle that failed validation
+    pub skipped: Vec<String>,
+}
+
+/// Stands in for the home directory in bundles exported with portable
+/// paths, so they can be imported under a different user name
+const HOME_TOKEN: &str = "${HOME}";
+
+/// Token standing in for the `index`th workspace folder of a portable
+/// bundle
+fn workspace_token(index: usize) -> String {
+    format!("${{WORKSPACE_{}}}", index)
+}
+
+/// `s` with `from` swapped for `to` when `s` is `from` or a path below it.
+/// The separators after the prefix become `separator`.
+fn replaced_path_prefix(s: &str, from: &str, to: &str, separator: char) -> Option<String> {
+    let rest = s.strip_prefix(from)?;
+    let rest = rest.replace(['/', '\\'], &separator.to_string());
+    (rest.is_empty() || rest.starts_with(separator)).then(|| format!("{}{}", to, rest))
+}
+
+/// Rewrite every string in `value`, object keys included, that is `from`
+/// or a path below it to start with `to` instead, using `separator` in the
+/// rest of the path
+fn replace_path_prefix(value: &mut serde_json::Value, from: &str, to: &str, separator: char) {
+    let replace = |s: &str| replaced_path_prefix(s, from, to, separator);
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some(replaced) = replace(s) {
+                *s = replaced;
+            }
+        }
+        serde_json::Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| replace_path_prefix(item, from, to, separator)),
+        serde_json::Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut item)| {
+                    replace_path_prefix(&mut item, from, to, separator);
+                    (replace(&key).unwrap_or(key), item)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+/// Folders of the workspaces in a raw workspace config, deepest first so a
+/// nested workspace claims its paths before its parent
+fn workspace_roots(config: Option<&serde_json::Value>) -> Vec<String> {
+    let Some(config) = config.and_then(|config| serde_json::from_value::<WorkspaceConfig>(config.clone()).ok()) else {
+        return Vec::new();
+    };
+    let mut roots: Vec<String> = config
+        .current_workspace
+        .into_iter()
+        .chain(config.recent_workspaces.into_iter().map(|workspace| workspace.path))
+        .filter_map(|root| home_prefix(Path::new(&root)))
+        .collect();
+    roots.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    roots.dedup();
+    roots
+}
+
+/// `home` without a trailing separator, or `None` for an unusable one
+fn home_prefix(home: &Path) -> Option<String> {
+    let home = home.to_string_lossy();
+    let home = home.trim_end_matches(['/', '\\']);
+    (!home.is_empty()).then(|| home.to_string())
+}
+
+/// Raw JSON of a config file, `None` when it's missing or unreadable
+async fn config_value(path: &Path) -> Option<serde_json::Value> {
+    let content = tokio::fs::read_to_string(path).await.ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Write the bundle to `out_path`. With `portable_home`, paths inside a
+/// workspace are stored relative to a `${WORKSPACE_n}` token, and other
+/// paths inside that home directory relative to `${HOME}`, all with `/`
+/// separators.
+pub(crate) async fn export_app_state_in(
+    data_dir: &Path,
+    out_path: &Path,
+    portable_home: Option<&Path>,
+) -> Result<(), MikuError> {
+    let mut bundle = AppStateBundle {
+        version: APP_STATE_BUNDLE_VERSION,
+        settings: config_value(&data_dir.join(SETTINGS_FILE)).await,
+        recent_files: config_value(&data_dir.join(RECENT_FILES_FILE)).await,
+        workspace_config: config_value(&workspace_config_path_in(data_dir)).await,
+        session: config_value(&data_dir.join(SESSION_FILE)).await,
+        workspace_roots: HashMap::new(),
+    };
+    if let Some(home) = portable_home.and_then(home_prefix) {
+        let roots = workspace_roots(bundle.workspace_config.as_ref());
+        let components = [
+            &mut bundle.settings,
+            &mut bundle.recent_files,
+            &mut bundle.workspace_config,
+            &mut bundle.session,
+        ];
+        for value in components.into_iter().flatten() {
+            for (index, root) in roots.iter().enumerate() {
+                replace_path_prefix(value, root, &workspace_token(index + 1), '/');
+            }
+            replace_path_prefix(value, &home, HOME_TOKEN, '/');
+        }
+        for (index, root) in roots.into_iter().enumerate() {
+            let root = replaced_path_prefix(&root, &home, HOME_TOKEN, '/').unwrap_or(root);
+            bundle.workspace_roots.insert(workspace_token(index + 1), root);
+        }
+    }
+    write_atomic(out_path, serde_json::to_string_pretty(&bundle)?).await
+}
+
+/// Restore one component: parse it the way its loader would, then save it
+async fn import_component(data_dir: &Path, name: &str, value: serde_json::Value) -> Result<(), MikuError> {
+    match name {
+        "settings" => save_settings_in(data_dir, &serde_json::from_value(value)?).await,
+        "recent_files" => {
+            let files = parse_recent_files(&value.to_string())?;
+            write_atomic(&data_dir.join(RECENT_FILES_FILE), serde_json::to_string_pretty(&files)?).await
+        }
+        "workspace_config" => save_workspace_config_in(data_dir, &serde_json::from_value(value)?).await,
+        "session" => save_session_in(data_dir, &serde_json::from_value(value)?).await,
+        _ => Err(MikuError::Path(format!("Unknown app state component: {}", name))),
+    }
+}
+
+/// Restore the bundle at `in_path`, expanding `${WORKSPACE_n}` tokens to
+/// their folders and `${HOME}` in portable paths to `home`
+pub(crate) async fn import_app_state_in(
+    data_dir: &Path,
+    in_path: &Path,
+    home: Option<&Path>,
+) -> Result<ImportReport, MikuError> {
+    let content = tokio::fs::read_to_string(in_path)
+        .await
+        .map_err(|e| MikuError::from_io(e, in_path))?;
+    let bundle: AppStateBundle = serde_json::from_str(&content)?;
+    if bundle.version > APP_STATE_BUNDLE_VERSION {
+        return Err(MikuError::Path(format!(
+            "App state bundle version {} is newer than this version of Miku supports",
+            bundle.version
+        )));
+    }
+    tokio::fs::create_dir_all(data_dir).await?;
+
+    let components = [
+        ("settings", bundle.settings),
+        ("recent_files", bundle.recent_files),
+        ("workspace_config", bundle.workspace_config),
+        ("session", bundle.session),
+    ];
+    let home = home.and_then(home_prefix);
+    let mut report = ImportReport::default();
+    for (name, value) in components {
+        let Some(mut value) = value else {
+            continue;
+        };
+        for (token, root) in &bundle.workspace_roots {
+            replace_path_prefix(&mut value, token, root, std::path::MAIN_SEPARATOR);
+        }
+        if let Some(home) = &home {
+            replace_path_prefix(&mut value, HOME_TOKEN, home, std::path::MAIN_SEPARATOR);
+        }
+        match import_component(data_dir, name, value).await {
+            Ok(()) => report.imported.push(name.to_string()),
+            Err(_) => report.skipped.push(name.to_string()),
+        }
+    }
+    Ok(report)
+}
+
+/// Write settings, recent files, workspaces and session to one JSON file.
+/// `portable_paths` stores paths in the home directory as `${HOME}/...` so
+/// the bundle works on a machine with a different user name.
+#[tauri::command]
+pub async fn export_app_state(
+    out_path: String,
+    portable_paths: Option<bool>,
+    lock: State<'_, ConfigLock>,
+) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    let home = portable_paths.unwrap_or(false).then(dirs::home_dir).flatten();
+    export_app_state_in(&app_data_dir()?, Path::new(&out_path), home.as_deref()).await
+}
+
+/// Restore an app state bundle, expanding portable `${HOME}` paths to this
+/// machine's home directory. Components that fail validation are skipped
+/// and reported rather than failing the whole import.
+#[tauri::command]
+pub async fn import_app_state(in_path: String, lock: State<'_, ConfigLock>) -> Result<ImportReport, MikuError> {
+    let _guard = lock.acquire().await;
+    import_app_state_in(&app_data_dir()?, Path::new(&in_path), dirs::home_dir().as_deref()).await
+}
+
+// ============================================
+// Auto-save
+// ============================================
+
+/// Stable 64-bit FNV-1a hash of document content, used to detect changes
+pub(crate) fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// `content_hash` as 16 hex digits, for the frontend where a 64-bit number
+/// wouldn't survive the trip through JSON
+pub(crate) fn content_hash_hex(content: &str) -> String {
+    format!("{:016x}", content_hash(content))
+}
+
+/// Content hash and time of the last auto-save, per path
+#[derive(Default)]
+pub struct AutosaveState(std::sync::Mutex<HashMap<String, (u64, Instant)>>);
+
+/// Where auto-saves are written
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AutosaveMode {
+    /// Overwrite the document itself
+    #[default]
+    InPlace,
+    /// Write `<path>.autosave` next to the document, leaving it untouched
+    /// until an explicit save
+    Sidecar,
+}
+
+/// How early a timer tick may arrive and still count as a full interval
+const AUTOSAVE_TIMER_SLACK: Duration = Duration::from_millis(500);
+
+/// Minimum gap between auto-saves for an `interval_secs` frontend timer.
+/// Slightly shorter than the interval, so a tick that fires a little early
+/// isn't skipped, pushing the save to the tick after.
+fn autosave_debounce(interval_secs: u32) -> Duration {
+    Duration::from_secs(interval_secs.into()).saturating_sub(AUTOSAVE_TIMER_SLACK)
+}
+
+/// Path of the sidecar file auto-saves of `path` go to in `Sidecar` mode
+pub(crate) fn autosave_sidecar_path(path: &str) -> String {
+    format!("{}.autosave", path)
+}
+
+/// Delete the auto-save sidecar of `path`, if there is one
+async fn remove_autosave_sidecar(path: &str) -> Result<(), MikuError> {
+    let sidecar = autosave_sidecar_path(path);
+    match tokio::fs::remove_file(&sidecar).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(MikuError::from_io(e, &sidecar)),
+        _ => Ok(()),
+    }
+}
+
+/// Payload of the `autosave://saved` event
+#[derive(Debug, Serialize, Clone)]
+pub struct AutosaveEvent {
+    pub path: String,
+    pub timestamp_ms: u64,
+}
+
+/// Write `content` unless it matches what was last auto-saved to `path`, or
+/// the previous auto-save was less than `debounce` ago. Returns the save
+/// time when the file was actually written.
+pub(crate) async fn autosave_in(
+    state: &AutosaveState,
+    path: &str,
+    content: String,
+    debounce: Duration,
+    mode: AutosaveMode,
+) -> Result<Option<u64>, MikuError> {
+    let hash = content_hash(&content);
+    {
+        let last = state.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((last_hash, saved_at)) = last.get(path) {
+            if *last_hash == hash || saved_at.elapsed() < debounce {
+                return Ok(None);
+            }
+        }
+    }
+
+    let target = match mode {
+        AutosaveMode::InPlace => path.to_string(),
+        AutosaveMode::Sidecar => autosave_sidecar_path(path),
+    };
+    write_document(target, content).await?;
+
+    state
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.to_string(), (hash, Instant::now()));
+    Ok(Some(now_ms()))
+}
+
+/// Auto-save a document on the frontend's timer. Does nothing when auto-save
+/// is disabled in settings or the document was opened read-only. Emits
+/// `autosave://saved` after a real write.
+#[tauri::command]
+pub async fn autosave(
+    app: AppHandle,
+    path: String,
+    content: String,
+    state: State<'_, AutosaveState>,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+) -> Result<bool, MikuError> {
+    if readonly_docs.contains(&path) {
+        return Ok(false);
+    }
+
+    let settings = load_settings_in(&app_data_dir()?).await?;
+    let Some(interval) = settings.autosave_interval_secs else {
+        return Ok(false);
+    };
+
+    let saved = autosave_in(&state, &path, content, autosave_debounce(interval), settings.autosave_mode).await?;
+    if let Some(timestamp_ms) = saved {
+        let _ = app.emit("autosave://saved", AutosaveEvent { path, timestamp_ms });
+    }
+    Ok(saved.is_some())
+}
+
+/// A sidecar auto-save left behind without an explicit save
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AutosaveSidecar {
+    /// The document the sidecar belongs to
+    pub path: String,
+    pub sidecar_path: String,
+    pub modified_ms: Option<u64>,
+}
+
+pub(crate) async fn list_autosave_sidecars_in(workspace: &Path) -> Result<Vec<AutosaveSidecar>, MikuError> {
+    let mut sidecars = Vec::new();
+    for file in crate::search::markdown_files(workspace).await? {
+        let path = file.to_string_lossy().to_string();
+        let sidecar_path = autosave_sidecar_path(&path);
+        let Ok(metadata) = tokio::fs::metadata(&sidecar_path).await else {
+            continue;
+        };
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+        sidecars.push(AutosaveSidecar {
+            path,
+            sidecar_path,
+            modified_ms,
+        });
+    }
+    Ok(sidecars)
+}
+
+/// Sidecar auto-saves in the workspace that were never followed by an
+/// explicit save, so the UI can offer to recover them on startup
+#[tauri::command]
+pub async fn list_autosave_sidecars(workspace_path: String) -> Result<Vec<AutosaveSidecar>, MikuError> {
+    list_autosave_sidecars_in(Path::new(&workspace_path)).await
+}
+
+// ============================================
+// Image assets
+// ============================================
+
+/// Result returned after saving an image asset.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavedImageAsset {
+    /// Absolute path on disk where the image was written.
+    pub absolute_path: String,
+    /// Markdown-friendly path to embed in the document. Relative when a
+    /// document path is known, otherwise the absolute path.
+    pub markdown_path: String,
+}
+
+/// Save raw image bytes to disk.
+///
+/// When `document_path` is provided, the image is written to a sibling
+/// `assets/` folder next to the document and the returned `markdown_path`
+/// is a forward-slash relative path. When no document path is provided,
+/// the image is written inside the app data directory under `assets/`
+/// and the absolute path is returned.
+#[tauri::command]
+pub async fn save_image_asset(
+    document_path: Option<String>,
+    data: Vec<u8>,
+    extension: String,
+) -> Result<SavedImageAsset, MikuError> {
+    // Sanitize extension - strip leading dot, limit chars
+    let ext = extension
+        .trim()
+        .trim_start_matches('.')
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(8)
+        .collect::<String>()
+        .to_lowercase();
+    let ext = if ext.is_empty() { "png".to_string() } else { ext };
+
+    // Generate unique filename using millis timestamp
+    let ts = now_ms();
+    let filename = format!("image-{}.{}", ts, ext);
+
+    // Determine target directory
+    let (assets_dir, markdown_path) = match document_path.as_deref() {
+        Some(doc_path) if !doc_path.is_empty() => {
+            let doc = std::path::Path::new(doc_path);
+            let parent = doc
+                .parent()
+                .ok_or_else(|| MikuError::Path("Document has no parent directory".to_string()))?;
+            let assets = parent.join("assets");
+            let md = format!("assets/{}", filename);
+            (assets, md)
+        }
+        _ => {
+            let assets = app_data_dir()?.join("assets");
+            let full = assets.join(&filename);
+            let md = full.to_string_lossy().to_string();
+            (assets, md)
+        }
+    };
+
+    tokio::fs::create_dir_all(&assets_dir).await?;
+
+    let full_path = assets_dir.join(&filename);
+    tokio::fs::write(&full_path, &data).await?;
+
+    Ok(SavedImageAsset {
+        absolute_path: full_path.to_string_lossy().to_string(),
+        markdown_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{save_workspace_config_in, WorkspaceConfig};
+
+    async fn activate_workspace(data_dir: &Path, workspace: Option<&str>) {
+        let config = WorkspaceConfig {
+            current_workspace: workspace.map(|w| w.to_string()),
+            ..Default::default()
+        };
+        save_workspace_config_in(data_dir, &config).await.unwrap();
+    }
+
+    fn paths(entries: Vec<RecentFileEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn test_default_settings() {
+        let settings = EditorSettings::default();
+        assert!(settings.theme.is_none()); // Deprecated field
+        assert_eq!(settings.theme_preference.selected, "system");
+        assert_eq!(settings.theme_preference.light_fallback, "light");
+        assert_eq!(settings.theme_preference.dark_fallback, "dark");
+        assert_eq!(settings.font_size, 16);
+        assert_eq!(settings.line_height, 1.6);
+        assert_eq!(settings.editor_width, 720);
+        assert_eq!(settings.font_family, "mono");
+        assert_eq!(settings.review_mode, "manual");
+        assert_eq!(settings.aggressiveness, "balanced");
+        assert!(settings.writing_context.is_empty());
+        assert!(settings.sound_enabled);
+        assert!(settings.autosave_interval_secs.is_none());
+        // Keyboard sounds defaults
+        assert!(!settings.keyboard_sounds.enabled);
+        assert_eq!(settings.keyboard_sounds.profile_id, "cherry-mx-blue");
+        assert_eq!(settings.keyboard_sounds.volume, 0.5);
+        assert!(!settings.keyboard_sounds.play_keyup_sounds);
+        assert_eq!(settings.keyboard_sounds.pitch_variation, 0.02);
+    }
+
+    #[test]
+    fn test_default_document() {
+        let doc = Document::default();
+        assert!(doc.path.is_none());
+        assert!(doc.content.is_empty());
+        assert!(!doc.is_modified);
+    }
+
+    #[test]
+    fn test_new_document_command() {
+        let doc = new_document();
+        assert!(doc.path.is_none());
+        assert!(doc.content.is_empty());
+        assert!(!doc.is_modified);
+    }
+
+    #[test]
+    fn test_get_app_version() {
+        let version = get_app_version();
+        assert!(!version.is_empty());
+        assert_eq!(version, "0.0.9");
+    }
+
+    #[test]
+    fn test_path_helper_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(path_exists(dir.path().to_string_lossy().to_string()));
+        assert!(!path_exists(dir.path().join("missing.md").to_string_lossy().to_string()));
+
+        assert!(is_markdown_file("/path/to/file.md".to_string()));
+        assert!(is_markdown_file("file.MD".to_string()));
+        assert!(!is_markdown_file("/path/to/file.txt".to_string()));
+
+        assert_eq!(file_name("/path/to/file.md".to_string()), Some("file.md".to_string()));
+        assert_eq!(file_name("/".to_string()), None);
+
+        assert_eq!(parent_dir("/path/to/file.md".to_string()), Some("/path/to".to_string()));
+        assert_eq!(parent_dir("file.md".to_string()), Some("".to_string()));
+    }
+
+    #[test]
+    fn test_settings_serialization() {
+        let settings = EditorSettings::default();
+        let json = serde_json::to_string(&settings).unwrap();
+        let deserialized: EditorSettings = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(settings.theme_preference.selected, deserialized.theme_preference.selected);
+        assert_eq!(settings.font_size, deserialized.font_size);
+        assert_eq!(settings.line_height, deserialized.line_height);
+    }
+
+    #[test]
+    fn test_document_serialization() {
+        let doc = Document {
+            path: Some("/test/path.md".to_string()),
+            content: "# Test Content".to_string(),
+            is_modified: true,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&doc).unwrap();
+        let deserialized: Document = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(doc.path, deserialized.path);
+        assert_eq!(doc.content, deserialized.content);
+        assert_eq!(doc.is_modified, deserialized.is_modified);
+    }
+
+    #[test]
+    fn test_error_serialization() {
+        let error = MikuError::Path("test error".to_string());
+        let json = serde_json::to_string(&error).unwrap();
+        assert!(json.contains("test error"));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let io = std::io::Error::other("disk on fire");
+        let json_err = serde_json::from_str::<EditorSettings>("{").unwrap_err();
+        let cases = vec![
+            (MikuError::Io(io), "Io"),
+            (MikuError::Json(json_err), "Json"),
+            (MikuError::Path("bad".to_string()), "Path"),
+            (MikuError::ReadOnly("/a.md".to_string()), "ReadOnly"),
+            (MikuError::NotFound("/a.md".to_string()), "NotFound"),
+            (MikuError::PermissionDenied("/a.md".to_string()), "PermissionDenied"),
+            (MikuError::AlreadyExists("/a.md".to_string()), "AlreadyExists"),
+            (MikuError::Conflict("/a.md".to_string()), "Conflict"),
+            (MikuError::OutsideWorkspace("/a.md".to_string()), "OutsideWorkspace"),
+            (MikuError::InvalidPattern("(".to_string()), "InvalidPattern"),
+            (MikuError::Cancelled("export-1".to_string()), "Cancelled"),
+            (MikuError::Timeout("/a.md".to_string()), "Timeout"),
+            (MikuError::DataDirUnavailable("no home".to_string()), "DataDirUnavailable"),
+            (MikuError::Unsupported("pdf".to_string()), "Unsupported"),
+            (MikuError::NotARepository("/notes".to_string()), "NotARepository"),
+            (MikuError::Git("corrupt index".to_string()), "Git"),
+        ];
+
+        for (error, code) in cases {
+            let message = error.to_string();
+            let value = serde_json::to_value(&error).unwrap();
+            assert_eq!(value["code"], code);
+            assert_eq!(value["message"], message.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_settings_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let loaded = load_settings_in(dir.path()).await.unwrap();
+        assert_eq!(loaded.font_size, EditorSettings::default().font_size);
+
+        let settings = EditorSettings {
+            font_size: 20,
+            font_family: "serif".to_string(),
+            ..Default::default()
+        };
+        save_settings_in(dir.path(), &settings).await.unwrap();
+
+        let loaded = load_settings_in(dir.path()).await.unwrap();
+        assert_eq!(loaded.font_size, 20);
+        assert_eq!(loaded.font_family, "serif");
+    }
+
+    #[tokio::test]
+    async fn test_save_settings_keeps_backend_only_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = EditorSettings {
+            max_recent_files: 3,
+            trim_trailing_whitespace: true,
+            max_tree_depth: 7,
+            ..Default::default()
+        };
+        save_settings_in(dir.path(), &settings).await.unwrap();
+
+        // What `toBackendSettings` sends: only the fields the UI edits
+        let frontend = serde_json::json!({
+            "theme": null,
+            "theme_preference": { "selected": "dark", "light_fallback": "light", "dark_fallback": "dark" },
+            "font_size": 18,
+            "line_height": 1.6,
+            "editor_width": 720,
+            "font_family": "serif",
+            "review_mode": "manual",
+            "aggressiveness": "balanced",
+            "writing_context": "",
+            "sound_enabled": false,
+            "keyboard_sounds": { "enabled": false, "profile_id": "cream", "volume": 0.5,
+                "play_keyup_sounds": false, "pitch_variation": 0.0 }
+        });
+        let serde_json::Value::Object(patch) = frontend else { unreachable!() };
+        merge_settings_in(dir.path(), patch).await.unwrap();
+
+        let loaded = load_settings_in(dir.path()).await.unwrap();
+        assert_eq!(loaded.font_size, 18);
+        assert_eq!(loaded.font_family, "serif");
+        assert_eq!(loaded.max_recent_files, 3);
+        assert!(loaded.trim_trailing_whitespace);
+        assert_eq!(loaded.max_tree_depth, 7);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_dedupe_and_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path();
+
+        for i in 0..DEFAULT_RECENT_LIMIT + 2 {
+            add_recent_file_in(data_dir, format!("/notes/{}.md", i)).await.unwrap();
+        }
+        add_recent_file_in(data_dir, "/notes/5.md".to_string()).await.unwrap();
+
+        let files = paths(recent_files_for_workspace_in(data_dir, None).await.unwrap());
+        assert_eq!(files.len(), DEFAULT_RECENT_LIMIT);
+        assert_eq!(files[0], "/notes/5.md");
+        assert_eq!(files.iter().filter(|f| *f == "/notes/5.md").count(), 1);
+        assert!(!files.contains(&"/notes/0.md".to_string()));
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[tokio::test]
+    async fn test_recent_files_dedupe_ignores_case() {
+        let dir = tempfile::tempdir().unwrap();
+        add_recent_file_in(dir.path(), "/Notes/a.md".to_string()).await.unwrap();
+        add_recent_file_in(dir.path(), "/notes/A.md".to_string()).await.unwrap();
+
+        let files = paths(recent_files_for_workspace_in(dir.path(), None).await.unwrap());
+        assert_eq!(files, vec!["/notes/A.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_custom_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = EditorSettings {
+            max_recent_files: 3,
+            ..Default::default()
+        };
+        save_settings_in(dir.path(), &settings).await.unwrap();
+
+        for name in ["a", "b", "c", "d"] {
+            add_recent_file_in(dir.path(), format!("/notes/{}.md", name)).await.unwrap();
+        }
+
+        let files = paths(recent_files_for_workspace_in(dir.path(), None).await.unwrap());
+        assert_eq!(files, vec!["/notes/d.md", "/notes/c.md", "/notes/b.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_recents_changed_event_after_add() {
+        let dir = tempfile::tempdir().unwrap();
+        add_recent_file_in(dir.path(), "/notes/a.md".to_string()).await.unwrap();
+        add_recent_file_in(dir.path(), "/notes/b.md".to_string()).await.unwrap();
+
+        let events = std::sync::Mutex::new(Vec::new());
+        emit_recents_changed_in(dir.path(), |files| events.lock().unwrap().push(paths(files)))
+            .await
+            .unwrap();
+        assert_eq!(events.into_inner().unwrap(), vec![vec!["/notes/b.md", "/notes/a.md"]]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_and_clear_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        activate_workspace(dir.path(), Some("/vaults/notes")).await;
+        add_recent_file_in(dir.path(), "/vaults/notes/a.md".to_string()).await.unwrap();
+        add_recent_file_in(dir.path(), "/tmp/b.md".to_string()).await.unwrap();
+        activate_workspace(dir.path(), Some("/vaults/work")).await;
+        add_recent_file_in(dir.path(), "/vaults/work/c.md".to_string()).await.unwrap();
+
+        remove_recent_file_in(dir.path(), "/tmp/b.md").await.unwrap();
+        let files = paths(recent_files_for_workspace_in(dir.path(), Some("/vaults/work")).await.unwrap());
+        assert_eq!(files, vec!["/vaults/work/c.md"]);
+
+        clear_recent_files_in(dir.path()).await.unwrap();
+        assert!(recent_files_for_workspace_in(dir.path(), Some("/vaults/work")).await.unwrap().is_empty());
+        let files = paths(recent_files_for_workspace_in(dir.path(), Some("/vaults/notes")).await.unwrap());
+        assert_eq!(files, vec!["/vaults/notes/a.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_move_recent_file_matches_other_normalization() {
+        let dir = tempfile::tempdir().unwrap();
+        add_recent_file_in(dir.path(), "/notes/caf\u{e9}.md".to_string()).await.unwrap();
+
+        move_recent_file_in(dir.path(), "/notes/cafe\u{301}.md", "/work/cafe.md", Some("/work"))
+            .await
+            .unwrap();
+        let files: Vec<String> = load_recent_files_in(dir.path()).await.unwrap().into_iter().map(|f| f.path).collect();
+        assert_eq!(files, vec!["/work/cafe.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_reports_config_health() {
+        let dir = tempfile::tempdir().unwrap();
+        activate_workspace(dir.path(), Some("/vaults/notes")).await;
+        add_recent_file_in(dir.path(), "/tmp/a.md".to_string()).await.unwrap();
+        std::fs::write(dir.path().join(SETTINGS_FILE), "{ \"font_size\": ").unwrap();
+
+        let report = diagnostics_in(dir.path()).await;
+        assert_eq!(report.app_version, get_app_version());
+        assert!(report.os.starts_with(std::env::consts::OS));
+        assert_eq!(report.data_dir, dir.path().to_string_lossy());
+        assert!(!report.settings_ok);
+        assert!(report.workspace_config_ok);
+        assert_eq!(report.current_workspace.as_deref(), Some("/vaults/notes"));
+        assert_eq!(report.recent_file_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_verify_app_data() {
+        let dir = tempfile::tempdir().unwrap();
+        add_recent_file_in(dir.path(), "/tmp/a.md".to_string()).await.unwrap();
+        set_preference_in(dir.path(), "ui.zoom", serde_json::json!(1.5)).await.unwrap();
+        let report = verify_app_data_in(dir.path()).await;
+        assert_eq!(report, AppDataReport { writable: true, issues: Vec::new() });
+        // The probe cleans up after itself
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let preferences = dir.path().join(PREFERENCES_FILE);
+        std::fs::write(&preferences, "[1, 2").unwrap();
+        let report = verify_app_data_in(dir.path()).await;
+        assert!(report.writable);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].file, preferences.to_string_lossy());
+        assert!(report.issues[0].problem.starts_with("Doesn't parse"));
+        // Reported, not repaired
+        assert!(preferences.exists());
+
+        // A data directory that can't be created under a plain file
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, "").unwrap();
+        let unwritable = blocker.join("miku");
+        let report = verify_app_data_in(&unwritable).await;
+        assert!(!report.writable);
+        assert_eq!(report.issues[0].file, unwritable.to_string_lossy());
+        assert!(report.issues[0].problem.starts_with("Not writable"));
+    }
+
+    /// Backups left next to `path` by `quarantine_config`
+    fn corrupt_backups(path: &Path) -> Vec<PathBuf> {
+        let prefix = format!("{}.corrupt-", path.file_name().unwrap().to_string_lossy());
+        std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p.file_name().unwrap().to_string_lossy().starts_with(&prefix))
+            .collect()
+    }
+
+    fn was_reported(path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        recovered_configs().lock().unwrap().iter().any(|r| r.path == path)
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_configs_are_backed_up_and_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings_path = dir.path().join(SETTINGS_FILE);
+        let recent_path = dir.path().join(RECENT_FILES_FILE);
+        let workspace_path = workspace_config_path_in(dir.path());
+        std::fs::write(&settings_path, "{ \"font_size\": 1").unwrap();
+        std::fs::write(&recent_path, "[\"/a.md\", ").unwrap();
+        std::fs::write(&workspace_path, "not json").unwrap();
+
+        let settings = load_settings_in(dir.path()).await.unwrap();
+        assert_eq!(settings.font_size, EditorSettings::default().font_size);
+        assert!(load_recent_files_in(dir.path()).await.unwrap().is_empty());
+        assert!(load_workspace_config_in(dir.path()).await.unwrap().current_workspace.is_none());
+
+        for (path, original) in [
+            (&settings_path, "{ \"font_size\": 1"),
+            (&recent_path, "[\"/a.md\", "),
+            (&workspace_path, "not json"),
+        ] {
+            assert!(!path.exists());
+            let backups = corrupt_backups(path);
+            assert_eq!(backups.len(), 1);
+            assert_eq!(std::fs::read_to_string(&backups[0]).unwrap(), original);
+            assert!(was_reported(path));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_loaders_quarantine_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings_path = dir.path().join(SETTINGS_FILE);
+        std::fs::write(&settings_path, "{ \"font_size\": ").unwrap();
+
+        let loads = (0..4).map(|_| load_settings_in(dir.path()));
+        for settings in futures::future::join_all(loads).await {
+            assert_eq!(settings.unwrap().font_size, EditorSettings::default().font_size);
+        }
+        assert_eq!(corrupt_backups(&settings_path).len(), 1);
+
+        // A file rewritten since a loader read it is left alone
+        std::fs::write(&settings_path, "{}").unwrap();
+        let err = serde_json::from_str::<EditorSettings>("{").unwrap_err();
+        quarantine_config(&settings_path, "{", &err).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn test_backup_retention_prunes_oldest_and_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = 100 * DAY_MS;
+        let backup = |name: &str, age_days: u64| {
+            let path = dir.path().join(format!("{}.corrupt-{}", name, now - age_days * DAY_MS));
+            std::fs::write(&path, "{}").unwrap();
+            path
+        };
+        let settings: Vec<PathBuf> = (0..4).map(|age| backup(SETTINGS_FILE, age)).collect();
+        let old_recent = backup(RECENT_FILES_FILE, 40);
+        let recent = backup(RECENT_FILES_FILE, 2);
+        std::fs::write(dir.path().join(SETTINGS_FILE), "{}").unwrap();
+
+        let policy = RetentionPolicy {
+            max_backups_per_file: 2,
+            max_snapshot_age_days: 30,
+            max_total_backup_bytes: 0,
+        };
+        let report = prune_backups_in(dir.path(), &policy, now).await.unwrap();
+
+        // The two newest settings backups survive, the older two go
+        assert!(settings[0].exists() && settings[1].exists());
+        assert!(!settings[2].exists() && !settings[3].exists());
+        // Past the age limit, even as the only other backup of its file
+        assert!(!old_recent.exists());
+        assert!(recent.exists());
+        assert!(dir.path().join(SETTINGS_FILE).exists());
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(report.freed_bytes, 6);
+
+        // The size limit drops the oldest of what's left, but the newest
+        // backup of each file stays even when they alone are over it
+        let policy = RetentionPolicy {
+            max_total_backup_bytes: 1,
+            ..policy
+        };
+        prune_backups_in(dir.path(), &policy, now).await.unwrap();
+        assert!(settings[0].exists() && !settings[1].exists());
+        assert!(recent.exists());
+
+        // Nor is the newest backup removed for its age
+        let policy = RetentionPolicy {
+            max_snapshot_age_days: 1,
+            ..policy
+        };
+        prune_backups_in(dir.path(), &policy, now + 10 * DAY_MS).await.unwrap();
+        assert!(settings[0].exists() && recent.exists());
+    }
+
+    #[tokio::test]
+    async fn test_prune_backups_keeps_newest_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = 100 * DAY_MS;
+        let backup = |name: &str, age_days: u64, size: usize| {
+            let path = dir.path().join(format!("{}.corrupt-{}", name, now - age_days * DAY_MS));
+            std::fs::write(&path, "x".repeat(size)).unwrap();
+            path
+        };
+        let settings = [backup(SETTINGS_FILE, 1, 10), backup(SETTINGS_FILE, 5, 10), backup(SETTINGS_FILE, 60, 10)];
+        // Only backup of its file, so it stays however old
+        let lone = backup(RECENT_FILES_FILE, 90, 10);
+
+        let limits = |max_age_days, max_total_backup_bytes| RetentionPolicy {
+            max_backups_per_file: 0,
+            max_snapshot_age_days: max_age_days,
+            max_total_backup_bytes,
+        };
+        let report = prune_backups_in(dir.path(), &limits(30, 0), now).await.unwrap();
+        assert_eq!(report.removed, vec![settings[2].to_string_lossy().to_string()]);
+        assert_eq!(report.freed_bytes, 10);
+        assert!(settings[0].exists() && settings[1].exists() && lone.exists());
+
+        // Over the budget, the oldest non-newest backups go first
+        let older = backup(SETTINGS_FILE, 10, 10);
+        let report = prune_backups_in(dir.path(), &limits(0, 30), now).await.unwrap();
+        assert_eq!(report.removed, vec![older.to_string_lossy().to_string()]);
+        assert!(settings[1].exists());
+
+        // The newest of each file survives even when they alone are over
+        let report = prune_backups_in(dir.path(), &limits(0, 1), now).await.unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(settings[0].exists() && !settings[1].exists() && lone.exists());
+    }
+
+    #[tokio::test]
+    async fn test_stale_autosave_sidecars_are_pruned() {
+        let vault = tempfile::tempdir().unwrap();
+        for name in ["fresh.md", "stale.md", "unsaved.md"] {
+            let path = vault.path().join(name);
+            std::fs::write(&path, "").unwrap();
+            std::fs::write(autosave_sidecar_path(&path.to_string_lossy()), "draft").unwrap();
+        }
+        let day = Duration::from_secs(24 * 60 * 60);
+        let days_ago = |days: u32| filetime::FileTime::from_system_time(std::time::SystemTime::now() - day * days);
+        // Saved after the draft was written, so the draft is obsolete
+        let stale = vault.path().join("stale.md.autosave");
+        filetime::set_file_mtime(&stale, days_ago(40)).unwrap();
+        // Never saved since: the draft is the only copy of the work
+        let unsaved = vault.path().join("unsaved.md.autosave");
+        filetime::set_file_mtime(vault.path().join("unsaved.md"), days_ago(50)).unwrap();
+        filetime::set_file_mtime(&unsaved, days_ago(40)).unwrap();
+
+        let mut report = RetentionReport::default();
+        prune_autosave_sidecars_in(vault.path(), &RetentionPolicy::default(), now_ms(), &mut report)
+            .await
+            .unwrap();
+        assert!(!stale.exists());
+        assert!(unsaved.exists());
+        assert!(vault.path().join("fresh.md.autosave").exists());
+        assert_eq!(report.removed, vec![stale.to_string_lossy().to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_app_state_round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        let settings = EditorSettings {
+            font_size: 21,
+            ..Default::default()
+        };
+        save_settings_in(source.path(), &settings).await.unwrap();
+        activate_workspace(source.path(), Some("/vaults/notes")).await;
+        add_recent_file_in(source.path(), "/vaults/notes/a.md".to_string()).await.unwrap();
+        let session = SessionState {
+            tabs: vec![SessionTab { path: None, content: "draft".to_string() }],
+            active_index: 0,
+        };
+        save_session_in(source.path(), &session).await.unwrap();
 
-    let full_path = assets_dir.join(&filename);
-    tokio::fs::write(&full_path, &data).await?;
+        let bundle_path = source.path().join("miku-state.json");
+        export_app_state_in(source.path(), &bundle_path, None).await.unwrap();
 
-    Ok(SavedImageAsset {
-        absolute_path: full_path.to_string_lossy().to_string(),
-        markdown_path,
-    })
-}
+        let target = tempfile::tempdir().unwrap();
+        let report = import_app_state_in(target.path(), &bundle_path, None).await.unwrap();
+        assert_eq!(report.imported, vec!["settings", "recent_files", "workspace_config", "session"]);
+        assert!(report.skipped.is_empty());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(load_settings_in(target.path()).await.unwrap().font_size, 21);
+        let config = load_workspace_config_in(target.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some("/vaults/notes"));
+        let recent = paths(recent_files_for_workspace_in(target.path(), Some("/vaults/notes")).await.unwrap());
+        assert_eq!(recent, vec!["/vaults/notes/a.md"]);
+        assert_eq!(load_session_in(target.path()).await.unwrap().unwrap().tabs[0].content, "draft");
+    }
+
+    #[tokio::test]
+    async fn test_portable_app_state_moves_between_homes() {
+        let source = tempfile::tempdir().unwrap();
+        activate_workspace(source.path(), Some("/home/alice/notes")).await;
+        add_recent_file_in(source.path(), "/home/alice/notes/a.md".to_string()).await.unwrap();
+        add_recent_file_in(source.path(), "/home/alicesmith/b.md".to_string()).await.unwrap();
+
+        let bundle_path = source.path().join("miku-state.json");
+        export_app_state_in(source.path(), &bundle_path, Some(Path::new("/home/alice/")))
+            .await
+            .unwrap();
+        let bundle = std::fs::read_to_string(&bundle_path).unwrap();
+        assert!(bundle.contains("${WORKSPACE_1}/a.md"));
+        assert!(bundle.contains("\"${WORKSPACE_1}\": \"${HOME}/notes\""));
+        assert!(!bundle.contains("/home/alice/"));
+
+        let target = tempfile::tempdir().unwrap();
+        let report = import_app_state_in(target.path(), &bundle_path, Some(Path::new("/Users/bob")))
+            .await
+            .unwrap();
+        assert!(report.skipped.is_empty());
+        let config = load_workspace_config_in(target.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some("/Users/bob/notes"));
+        let files = load_recent_files_in(target.path()).await.unwrap();
+        let recent: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        // Only paths inside the home directory were made portable
+        assert_eq!(recent, vec!["/home/alicesmith/b.md", "/Users/bob/notes/a.md"]);
+        assert_eq!(files[1].workspace.as_deref(), Some("/Users/bob/notes"));
+    }
+
+    #[tokio::test]
+    async fn test_portable_app_state_tokenizes_workspaces() {
+        let source = tempfile::tempdir().unwrap();
+        activate_workspace(source.path(), Some("D:\\vaults\\work")).await;
+        add_recent_file_in(source.path(), "D:\\vaults\\work\\plans\\q3.md".to_string()).await.unwrap();
+        add_recent_file_in(source.path(), "C:\\Users\\alice\\inbox.md".to_string()).await.unwrap();
+
+        let bundle_path = source.path().join("miku-state.json");
+        export_app_state_in(source.path(), &bundle_path, Some(Path::new("C:\\Users\\alice")))
+            .await
+            .unwrap();
+        let mut bundle: AppStateBundle = serde_json::from_str(&std::fs::read_to_string(&bundle_path).unwrap()).unwrap();
+        let recent = bundle.recent_files.as_ref().unwrap().to_string();
+        assert!(recent.contains("${WORKSPACE_1}/plans/q3.md"));
+        assert!(recent.contains("${HOME}/inbox.md"));
+        assert_eq!(bundle.workspace_roots["${WORKSPACE_1}"], "D:\\vaults\\work");
+
+        // Moving the vault means editing one entry
+        bundle.workspace_roots.insert("${WORKSPACE_1}".to_string(), "/srv/work".to_string());
+        std::fs::write(&bundle_path, serde_json::to_string(&bundle).unwrap()).unwrap();
+        let target = tempfile::tempdir().unwrap();
+        import_app_state_in(target.path(), &bundle_path, Some(Path::new("/home/alice"))).await.unwrap();
+        let config = load_workspace_config_in(target.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some("/srv/work"));
+        let files = load_recent_files_in(target.path()).await.unwrap();
+        let recent: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert!(recent.contains(&"/srv/work/plans/q3.md"));
+        assert!(recent.contains(&"/home/alice/inbox.md"));
+    }
+
+    #[tokio::test]
+    async fn test_import_app_state_skips_invalid_components() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_path = dir.path().join("bundle.json");
+        let bundle = serde_json::json!({
+            "version": 1,
+            "settings": { "font_size": "huge" },
+            "session": { "tabs": [], "active_index": 0 }
+        });
+        std::fs::write(&bundle_path, bundle.to_string()).unwrap();
+
+        let report = import_app_state_in(dir.path(), &bundle_path, None).await.unwrap();
+        assert_eq!(report.imported, vec!["session"]);
+        assert_eq!(report.skipped, vec!["settings"]);
+        assert!(!dir.path().join(SETTINGS_FILE).exists());
+    }
 
     #[test]
-    fn test_default_settings() {
+    fn test_recent_limits_are_clamped() {
+        let settings: EditorSettings = serde_json::from_value(serde_json::json!({
+            "font_size": 16,
+            "line_height": 1.6,
+            "editor_width": 720,
+            "font_family": "mono",
+            "review_mode": "manual",
+            "aggressiveness": "balanced",
+            "writing_context": "",
+            "max_recent_files": 5000,
+            "max_recent_workspaces": 0
+        }))
+        .unwrap();
+        assert_eq!(settings.recent_files_limit(), MAX_RECENT_LIMIT);
+        assert_eq!(settings.recent_workspaces_limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_layout_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load_layout_in(dir.path()).await.unwrap(), serde_json::json!({}));
+
+        let layout = serde_json::json!({
+            "window": { "width": 1280, "height": 800, "maximized": false },
+            "sidebar": { "width": 264.5, "collapsed": true },
+            "panels": ["outline", "backlinks"]
+        });
+        save_layout_in(dir.path(), &layout).await.unwrap();
+        assert_eq!(load_layout_in(dir.path()).await.unwrap(), layout);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_layout_loads_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(LAYOUT_FILE), "{ \"sidebar\": ").unwrap();
+
+        assert_eq!(load_layout_in(dir.path()).await.unwrap(), serde_json::json!({}));
+        assert!(!dir.path().join(LAYOUT_FILE).exists());
+        let backups = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(backups, 1);
+    }
+
+    #[tokio::test]
+    async fn test_preferences_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(get_preference_in(dir.path(), "templates.lastUsed").await.unwrap(), None);
+
+        let values = [
+            ("templates.lastUsed", serde_json::json!("daily.md")),
+            ("ui.showMinimap", serde_json::json!(true)),
+            ("ui.zoom", serde_json::json!(1.25)),
+            ("ui.hiddenPanels", serde_json::json!(["outline", "backlinks"])),
+            ("export.pdf", serde_json::json!({ "margins": 12, "toc": false })),
+        ];
+        for (key, value) in &values {
+            set_preference_in(dir.path(), key, value.clone()).await.unwrap();
+        }
+        for (key, value) in &values {
+            assert_eq!(get_preference_in(dir.path(), key).await.unwrap().as_ref(), Some(value));
+        }
+
+        // Overwriting leaves the rest of the namespace alone
+        set_preference_in(dir.path(), "ui.zoom", serde_json::json!(2)).await.unwrap();
+        assert_eq!(get_preference_in(dir.path(), "ui.zoom").await.unwrap(), Some(serde_json::json!(2)));
+        assert_eq!(
+            get_preference_in(dir.path(), "ui.showMinimap").await.unwrap(),
+            Some(serde_json::json!(true))
+        );
+
+        // Same name, different namespaces
+        set_preference_in(dir.path(), "editor.zoom", serde_json::json!(3)).await.unwrap();
+        assert_eq!(get_preference_in(dir.path(), "ui.zoom").await.unwrap(), Some(serde_json::json!(2)));
+
+        set_preference_in(dir.path(), "templates.lastUsed", serde_json::Value::Null).await.unwrap();
+        assert_eq!(get_preference_in(dir.path(), "templates.lastUsed").await.unwrap(), None);
+
+        for key in ["zoom", ".zoom", "ui."] {
+            assert!(matches!(get_preference_in(dir.path(), key).await, Err(MikuError::Path(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_session_in(dir.path()).await.unwrap().is_none());
+
+        let session = SessionState {
+            tabs: vec![
+                SessionTab { path: Some("/notes/a.md".to_string()), content: "# A".to_string() },
+                SessionTab { path: None, content: "draft".to_string() },
+            ],
+            active_index: 1,
+        };
+        save_session_in(dir.path(), &session).await.unwrap();
+
+        let loaded = load_session_in(dir.path()).await.unwrap().unwrap();
+        assert_eq!(loaded.active_index, 1);
+        assert_eq!(loaded.tabs.len(), 2);
+        assert_eq!(loaded.tabs[0].path.as_deref(), Some("/notes/a.md"));
+        assert_eq!(loaded.tabs[1].content, "draft");
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_session_loads_none() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(SESSION_FILE), "{ \"tabs\": [").unwrap();
+
+        assert!(load_session_in(dir.path()).await.unwrap().is_none());
+        assert!(!dir.path().join(SESSION_FILE).exists());
+        let backups = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(backups, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_scoped_per_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path();
+
+        activate_workspace(data_dir, Some("/vaults/work")).await;
+        add_recent_file_in(data_dir, "/vaults/work/plan.md".to_string()).await.unwrap();
+
+        activate_workspace(data_dir, Some("/vaults/home")).await;
+        add_recent_file_in(data_dir, "/vaults/home/diary.md".to_string()).await.unwrap();
+
+        let home = paths(recent_files_for_workspace_in(data_dir, Some("/vaults/home")).await.unwrap());
+        assert_eq!(home, vec!["/vaults/home/diary.md".to_string()]);
+
+        let work = paths(recent_files_for_workspace_in(data_dir, Some("/vaults/work")).await.unwrap());
+        assert_eq!(work, vec!["/vaults/work/plan.md".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_outside_workspace_are_global() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path();
+
+        activate_workspace(data_dir, Some("/vaults/work")).await;
+        add_recent_file_in(data_dir, "/tmp/scratch.md".to_string()).await.unwrap();
+        add_recent_file_in(data_dir, "/vaults/work/plan.md".to_string()).await.unwrap();
+
+        let files = load_recent_files_in(data_dir).await.unwrap();
+        assert_eq!(files[0].workspace.as_deref(), Some("/vaults/work"));
+        assert_eq!(files[1].workspace, None);
+
+        // Global entries show up regardless of the active workspace
+        let other = paths(recent_files_for_workspace_in(data_dir, Some("/vaults/home")).await.unwrap());
+        assert_eq!(other, vec!["/tmp/scratch.md".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_legacy_recent_files() {
+        let files = parse_recent_files(r#"["/a.md", "/b.md"]"#).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "/a.md");
+        assert!(files.iter().all(|f| f.workspace.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_recent_files_migrate_to_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "# Note").unwrap();
+        let note_path = note.to_string_lossy().to_string();
+
+        let legacy = serde_json::to_string(&vec![note_path.clone()]).unwrap();
+        std::fs::write(dir.path().join(RECENT_FILES_FILE), legacy).unwrap();
+
+        let entries = recent_files_for_workspace_in(dir.path(), None).await.unwrap();
+        assert_eq!(
+            entries,
+            vec![RecentFileEntry {
+                path: note_path.clone(),
+                name: "note.md".to_string(),
+                last_opened_ms: 0,
+                exists: true,
+            }]
+        );
+
+        // Re-adding stamps the open time and rewrites in the new format
+        add_recent_file_in(dir.path(), note_path).await.unwrap();
+        let entries = recent_files_for_workspace_in(dir.path(), None).await.unwrap();
+        assert!(entries[0].last_opened_ms > 0);
+    }
+
+    #[tokio::test]
+    async fn test_recent_file_exists_reflects_deleted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let note = dir.path().join("gone.md");
+        std::fs::write(&note, "bye").unwrap();
+
+        add_recent_file_in(&data_dir, note.to_string_lossy().to_string()).await.unwrap();
+        let entries = recent_files_for_workspace_in(&data_dir, None).await.unwrap();
+        assert!(entries[0].exists);
+
+        std::fs::remove_file(&note).unwrap();
+        let entries = recent_files_for_workspace_in(&data_dir, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "gone.md");
+        assert!(!entries[0].exists);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_partitioned() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        for name in ["kept.md", "moved.md", "also-kept.md", "deleted.md"] {
+            let note = dir.path().join(name);
+            std::fs::write(&note, "").unwrap();
+            add_recent_file_in(&data_dir, note.to_string_lossy().to_string()).await.unwrap();
+        }
+        std::fs::rename(dir.path().join("moved.md"), dir.path().join("elsewhere.md")).unwrap();
+        std::fs::remove_file(dir.path().join("deleted.md")).unwrap();
+
+        let partitioned = recent_files_partitioned_in(&data_dir, None).await.unwrap();
+        let names = |entries: &[RecentFileEntry]| entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&partitioned.existing), vec!["also-kept.md", "kept.md"]);
+        assert_eq!(names(&partitioned.missing), vec!["deleted.md", "moved.md"]);
+
+        // Nothing is dropped from the stored list
+        assert_eq!(load_recent_files_in(&data_dir).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_relocate_recent_file_keeps_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        for name in ["first.md", "lost.md", "last.md"] {
+            std::fs::write(path(name), "").unwrap();
+            add_recent_file_in(&data_dir, path(name)).await.unwrap();
+        }
+        crate::file_ids::set_file_data_in(&data_dir, &path("lost.md"), "cursor", serde_json::json!(7))
+            .await
+            .unwrap();
+        std::fs::rename(path("lost.md"), path("found.md")).unwrap();
+
+        let missing = relocate_recent_file_in(&data_dir, &path("lost.md"), &path("nowhere.md")).await;
+        assert!(matches!(missing, Err(MikuError::NotFound(p)) if p == path("nowhere.md")));
+
+        let before = load_recent_files_in(&data_dir).await.unwrap();
+        relocate_recent_file_in(&data_dir, &path("lost.md"), &path("found.md")).await.unwrap();
+        let after = load_recent_files_in(&data_dir).await.unwrap();
+        let paths: Vec<&str> = after.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec![path("last.md"), path("found.md"), path("first.md")]);
+        assert_eq!(after[1].last_opened_ms, before[1].last_opened_ms);
+
+        let data = crate::file_ids::get_file_data_in(&data_dir, &path("found.md")).await.unwrap();
+        assert_eq!(data.get("cursor"), Some(&serde_json::json!(7)));
+    }
+
+    #[tokio::test]
+    async fn test_open_external_requires_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("scan.pdf").to_string_lossy().to_string();
+
+        let result = open_external_in(&missing).await;
+        assert!(matches!(result, Err(MikuError::NotFound(path)) if path == missing));
+    }
+
+    #[test]
+    fn test_dirty_summary_tracks_current_state() {
+        let dirty_docs = DirtyDocuments::default();
+        assert_eq!(dirty_docs.summary(), DirtySummary::default());
+
+        let draft = std::env::temp_dir().join("draft.md").to_string_lossy().to_string();
+        let notes = std::env::temp_dir().join("notes.md").to_string_lossy().to_string();
+        dirty_docs.set(&notes, true);
+        dirty_docs.set(&draft, true);
+        dirty_docs.set("untitled-1", true);
+        dirty_docs.set("untitled-2", true);
+        dirty_docs.set("untitled-2", false);
+        let summary = dirty_docs.summary();
+        assert_eq!(summary.dirty_paths, vec![draft.clone(), notes]);
+        assert_eq!(summary.untitled_count, 1);
+
+        dirty_docs.set(&draft, false);
+        dirty_docs.set("untitled-1", false);
+        assert_eq!(dirty_docs.summary().dirty_paths.len(), 1);
+        assert_eq!(dirty_docs.summary().untitled_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_readonly_opened_document_refuses_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peek.md");
+        std::fs::write(&path, "# Peek").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let readonly_docs = ReadonlyDocuments::default();
+
+        let doc = read_document(path.clone(), true).await.unwrap();
+        assert!(doc.readonly);
+        assert_eq!(doc.content, "# Peek");
+        readonly_docs.set(&path, true);
+
         let settings = EditorSettings::default();
-        assert!(settings.theme.is_none()); // Deprecated field
-        assert_eq!(settings.theme_preference.selected, "system");
-        assert_eq!(settings.theme_preference.light_fallback, "light");
-        assert_eq!(settings.theme_preference.dark_fallback, "dark");
-        assert_eq!(settings.font_size, 16);
-        assert_eq!(settings.line_height, 1.6);
-        assert_eq!(settings.editor_width, 720);
-        assert_eq!(settings.font_family, "mono");
-        assert_eq!(settings.review_mode, "manual");
-        assert_eq!(settings.aggressiveness, "balanced");
-        assert!(settings.writing_context.is_empty());
-        assert!(settings.sound_enabled);
-        // Keyboard sounds defaults
-        assert!(!settings.keyboard_sounds.enabled);
-        assert_eq!(settings.keyboard_sounds.profile_id, "cherry-mx-blue");
-        assert_eq!(settings.keyboard_sounds.volume, 0.5);
-        assert!(!settings.keyboard_sounds.play_keyup_sounds);
-        assert_eq!(settings.keyboard_sounds.pitch_variation, 0.02);
+        let result = save_file_in(&readonly_docs, &settings, path.clone(), "edited".to_string(), None, false).await;
+        assert!(matches!(result, Err(MikuError::ReadOnly(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# Peek");
+
+        // Opening normally again makes it editable
+        readonly_docs.set(&path, false);
+        save_file_in(&readonly_docs, &settings, path.clone(), "edited".to_string(), None, false).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "edited");
     }
 
     #[test]
-    fn test_default_document() {
-        let doc = Document::default();
-        assert!(doc.path.is_none());
-        assert!(doc.content.is_empty());
+    fn test_readonly_paths_are_canonical_and_follow_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+        std::fs::write(dir.path().join("notes/peek.md"), "# Peek").unwrap();
+        let path = dir.path().join("notes/peek.md").to_string_lossy().to_string();
+        let spelled = dir.path().join("notes/../notes/./peek.md").to_string_lossy().to_string();
+        let readonly_docs = ReadonlyDocuments::default();
+
+        readonly_docs.set(&spelled, true);
+        assert!(readonly_docs.contains(&path));
+
+        // Renaming the folder carries the entry to the new path
+        let old_key = ReadonlyDocuments::key(&dir.path().join("notes").to_string_lossy());
+        std::fs::rename(dir.path().join("notes"), dir.path().join("drafts")).unwrap();
+        let new_folder = dir.path().join("drafts").to_string_lossy().to_string();
+        readonly_docs.moved(&old_key, &new_folder);
+        assert!(!readonly_docs.contains(&path));
+        let moved = dir.path().join("drafts/peek.md").to_string_lossy().to_string();
+        assert!(readonly_docs.contains(&moved));
+
+        readonly_docs.set(&moved, false);
+        assert!(!readonly_docs.contains(&moved));
+    }
+
+    #[tokio::test]
+    async fn test_save_into_missing_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("removed").join("drafts");
+        let path = folder.join("note.md").to_string_lossy().to_string();
+        let (readonly_docs, settings) = (ReadonlyDocuments::default(), EditorSettings::default());
+
+        let result = save_file_in(&readonly_docs, &settings, path.clone(), "text".to_string(), None, false).await;
+        match result {
+            Err(MikuError::NotFound(missing)) => assert_eq!(Path::new(&missing), folder),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+        assert!(!folder.exists());
+
+        save_file_in(&readonly_docs, &settings, path.clone(), "text".to_string(), None, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "text");
+    }
+
+    #[tokio::test]
+    async fn test_open_crlf_file_keeps_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("windows.md");
+        std::fs::write(&path, "# Title\r\n\r\nBody\r\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let doc = read_document(path.clone(), false).await.unwrap();
+        assert_eq!(doc.line_ending, LineEnding::CrLf);
+        assert!(!doc.has_bom);
+        assert_eq!(doc.content, "# Title\r\n\r\nBody\r\n");
+
+        // An editor working in LF saves back in the file's own style
+        let settings = EditorSettings::default();
+        let edited = "# Title\n\nNew body\n".to_string();
+        save_file_in(&ReadonlyDocuments::default(), &settings, path.clone(), edited, Some(doc.line_ending), false)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# Title\r\n\r\nNew body\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_open_file_or_create_missing_path() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let path = vault.path().join("moved").join("gone.md");
+        let path_str = path.to_string_lossy().to_string();
+
+        let refused = open_file_or_create_in(data.path(), path_str.clone(), false).await;
+        assert!(matches!(refused, Err(MikuError::NotFound(p)) if p == path_str));
+        assert!(!path.exists());
+        assert!(load_recent_files_in(data.path()).await.unwrap().is_empty());
+
+        let (doc, created) = open_file_or_create_in(data.path(), path_str.clone(), true).await.unwrap();
+        assert!(created);
+        assert_eq!(doc.path.as_deref(), Some(path_str.as_str()));
+        assert_eq!(doc.content, "");
         assert!(!doc.is_modified);
+        assert!(path.is_file());
+        let recents = load_recent_files_in(data.path()).await.unwrap();
+        assert_eq!(recents[0].path, path_str);
+
+        // Once it exists, it's simply opened
+        std::fs::write(&path, "kept").unwrap();
+        let (doc, created) = open_file_or_create_in(data.path(), path_str, true).await.unwrap();
+        assert!(!created);
+        assert_eq!(doc.content, "kept");
     }
 
-    #[test]
-    fn test_new_document_command() {
-        let doc = new_document();
-        assert!(doc.path.is_none());
-        assert!(doc.content.is_empty());
+    #[tokio::test]
+    async fn test_save_file_as_updates_recents_and_returns_document() {
+        let data = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let readonly_docs = ReadonlyDocuments::default();
+        let path = dir.path().join("copy.md").to_string_lossy().to_string();
+
+        let source = dir.path().join("draft.md");
+        std::fs::write(&source, "").unwrap();
+        let source = source.to_string_lossy().to_string();
+        crate::file_ids::set_file_data_in(data.path(), &source, "cursor", serde_json::json!(7)).await.unwrap();
+
+        let copy = "# Copy\n".to_string();
+        let doc = save_file_as_in(data.path(), &readonly_docs, path.clone(), copy, None, false, Some(&source))
+            .await
+            .unwrap();
+        assert_eq!(doc.path.as_deref(), Some(path.as_str()));
         assert!(!doc.is_modified);
+        assert_eq!(doc.content, "# Copy\n");
+        assert_eq!(doc.content_hash, content_hash_hex("# Copy\n"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# Copy\n");
+        assert_eq!(load_recent_files_in(data.path()).await.unwrap()[0].path, path);
+        let data_for_copy = crate::file_ids::get_file_data_in(data.path(), &path).await.unwrap();
+        assert_eq!(data_for_copy["cursor"], serde_json::json!(7));
+    }
+
+    #[tokio::test]
+    async fn test_save_file_as_refuses_to_overwrite_without_flag() {
+        let data = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let readonly_docs = ReadonlyDocuments::default();
+        let path = dir.path().join("taken.md");
+        std::fs::write(&path, "original").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let refused =
+            save_file_as_in(data.path(), &readonly_docs, path.clone(), "new".to_string(), None, false, None).await;
+        assert!(matches!(refused, Err(MikuError::AlreadyExists(p)) if p == path));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+        assert!(load_recent_files_in(data.path()).await.unwrap().is_empty());
+
+        let doc = save_file_as_in(data.path(), &readonly_docs, path.clone(), "new".to_string(), None, true, None)
+            .await
+            .unwrap();
+        assert_eq!(doc.content, "new");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    }
+    #[tokio::test]
+    async fn test_open_bom_file_reports_bom() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bom.md");
+        std::fs::write(&path, "\u{feff}# Title\nBody\r\n").unwrap();
+
+        let doc = read_document(path.to_string_lossy().to_string(), false).await.unwrap();
+        assert!(doc.has_bom);
+        assert_eq!(doc.line_ending, LineEnding::Mixed);
+        assert_eq!(doc.content, "\u{feff}# Title\nBody\r\n");
     }
 
     #[test]
-    fn test_get_app_version() {
-        let version = get_app_version();
-        assert!(!version.is_empty());
-        assert_eq!(version, "0.0.9");
+    fn test_line_ending_detect_and_apply() {
+        assert_eq!(LineEnding::detect("no breaks"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb"), LineEnding::CrLf);
+        assert_eq!(LineEnding::apply(LineEnding::Lf, "a\r\nb\n".to_string()), "a\nb\n");
+        assert_eq!(LineEnding::apply(LineEnding::CrLf, "a\r\nb\n".to_string()), "a\r\nb\r\n");
+        assert_eq!(LineEnding::apply(LineEnding::Mixed, "a\r\nb\n".to_string()), "a\r\nb\n");
     }
 
     #[test]
-    fn test_settings_serialization() {
+    fn test_mixed_line_endings_in_content() {
+        let mixed = "one\r\ntwo\nthree\r\nfour\rfive\r\n";
+        let counts = detect_line_endings(mixed.to_string());
+        assert_eq!((counts.lf, counts.crlf, counts.cr), (1, 3, 1));
+        assert_eq!(counts.dominant, LineEnding::CrLf);
+
+        assert_eq!(
+            normalize_content_line_endings(mixed.to_string(), LineEnding::Lf),
+            "one\ntwo\nthree\nfour\nfive\n"
+        );
+        let fixed = normalize_content_line_endings(mixed.to_string(), LineEnding::Mixed);
+        assert_eq!(fixed, "one\r\ntwo\r\nthree\r\nfour\r\nfive\r\n");
+        assert_eq!(LineEnding::detect(&fixed), LineEnding::CrLf);
+    }
+
+    #[tokio::test]
+    async fn test_slow_read_times_out() {
+        let limit = Some(Duration::from_millis(20));
+        let result: Result<(), _> = with_timeout(limit, "/slow/share/a.md", futures::future::pending()).await;
+        assert!(matches!(result, Err(MikuError::Timeout(path)) if path == "/slow/share/a.md"));
+
+        let result = with_timeout(limit, "/fast.md", async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_blocked_fifo_read_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("stuck.md");
+        let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+        let path = fifo.to_string_lossy().to_string();
+
+        // Opening a fifo for reading blocks until a writer shows up
+        let result = with_timeout(Some(Duration::from_millis(50)), &path, read_document(path.clone(), false)).await;
+        assert!(matches!(result, Err(MikuError::Timeout(_))));
+
+        // Unblock the abandoned read so the runtime can shut down
+        drop(std::fs::OpenOptions::new().write(true).open(&fifo).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_document_is_dirty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.md");
+        std::fs::write(&path, "saved text").unwrap();
+        let path = Some(path.to_string_lossy().to_string());
+
+        // Typed and then reverted back to the saved state
+        assert!(!document_is_dirty(path.clone(), "saved text".to_string()).await.unwrap());
+        assert!(document_is_dirty(path, "saved text!".to_string()).await.unwrap());
+
+        let missing = Some(dir.path().join("gone.md").to_string_lossy().to_string());
+        assert!(document_is_dirty(missing, String::new()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unsaved_document_is_dirty_when_non_empty() {
+        assert!(!document_is_dirty(None, String::new()).await.unwrap());
+        assert!(document_is_dirty(None, "draft".to_string()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_preview_file_stops_at_line_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.md");
+        let content: String = (0..10_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, content).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let preview = preview_file(path.clone(), 3, 4096).await.unwrap();
+        assert_eq!(preview.preview, "line 0\nline 1\nline 2\n");
+        assert!(preview.truncated);
+
+        let preview = preview_file(path, 100, 10).await.unwrap();
+        assert_eq!(preview.preview, "line 0\nlin");
+        assert!(preview.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_preview_file_small_file_is_not_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("small.md");
+        std::fs::write(&path, "one\ntwo").unwrap();
+
+        let preview = preview_file(path.to_string_lossy().to_string(), 2, 4096).await.unwrap();
+        assert_eq!(preview.preview, "one\ntwo");
+        assert!(!preview.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_preview_files_keeps_going_past_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        std::fs::write(path("a.md"), "# A\nfirst\nsecond\n").unwrap();
+        std::fs::write(path("b.md"), "just one line").unwrap();
+
+        let paths = vec![path("a.md"), path("missing.md"), path("b.md")];
+        let previews = preview_files_in(paths, 2, 4096, 2).await;
+
+        let summary: Vec<(&str, bool, bool)> = previews
+            .iter()
+            .map(|p| (p.preview.as_str(), p.truncated, p.error.is_some()))
+            .collect();
+        assert_eq!(summary, vec![("# A\nfirst\n", true, false), ("", false, true), ("just one line", false, false)]);
+        assert_eq!(previews[1].path, path("missing.md"));
+    }
+
+    #[tokio::test]
+    async fn test_autosave_skips_unchanged_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("draft.md");
+        let path_str = path.to_string_lossy().to_string();
+        let state = AutosaveState::default();
+        let mode = AutosaveMode::InPlace;
+
+        let saved = autosave_in(&state, &path_str, "v1".to_string(), Duration::ZERO, mode).await.unwrap();
+        assert!(saved.is_some());
+
+        // Tamper with the file; an unchanged autosave must not rewrite it
+        std::fs::write(&path, "external").unwrap();
+        let saved = autosave_in(&state, &path_str, "v1".to_string(), Duration::ZERO, mode).await.unwrap();
+        assert!(saved.is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "external");
+
+        let saved = autosave_in(&state, &path_str, "v2".to_string(), Duration::ZERO, mode).await.unwrap();
+        assert!(saved.is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "v2");
+    }
+
+    #[tokio::test]
+    async fn test_autosave_debounces_rapid_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("draft.md");
+        let path_str = path.to_string_lossy().to_string();
+        let state = AutosaveState::default();
+        let debounce = Duration::from_secs(60);
+
+        let mode = AutosaveMode::InPlace;
+        assert!(autosave_in(&state, &path_str, "v1".to_string(), debounce, mode).await.unwrap().is_some());
+        assert!(autosave_in(&state, &path_str, "v2".to_string(), debounce, mode).await.unwrap().is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "v1");
+    }
+
+    #[tokio::test]
+    async fn test_autosave_on_a_slightly_early_tick() {
+        let dir = tempfile::tempdir().unwrap();
+        let path_str = dir.path().join("draft.md").to_string_lossy().to_string();
+        let state = AutosaveState::default();
+        let debounce = autosave_debounce(1);
+        let mode = AutosaveMode::InPlace;
+
+        assert!(autosave_in(&state, &path_str, "v1".to_string(), debounce, mode).await.unwrap().is_some());
+        // The next tick of a one-second timer, arriving a little early
+        tokio::time::sleep(Duration::from_millis(900)).await;
+        assert!(autosave_in(&state, &path_str, "v2".to_string(), debounce, mode).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sidecar_autosave_until_explicit_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("draft.md");
+        std::fs::write(&path, "saved").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+        let sidecar = dir.path().join("draft.md.autosave");
+        let state = AutosaveState::default();
+
+        let saved = autosave_in(&state, &path_str, "typing".to_string(), Duration::ZERO, AutosaveMode::Sidecar)
+            .await
+            .unwrap();
+        assert!(saved.is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "saved");
+        assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), "typing");
+
+        let stale = list_autosave_sidecars_in(dir.path()).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].path, path_str);
+        assert_eq!(Path::new(&stale[0].sidecar_path), sidecar);
+
         let settings = EditorSettings::default();
-        let json = serde_json::to_string(&settings).unwrap();
-        let deserialized: EditorSettings = serde_json::from_str(&json).unwrap();
+        save_file_in(&ReadonlyDocuments::default(), &settings, path_str.clone(), "typing".to_string(), None, false)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "typing");
+        assert!(!sidecar.exists());
+        assert!(list_autosave_sidecars_in(dir.path()).await.unwrap().is_empty());
+    }
 
-        assert_eq!(settings.theme_preference.selected, deserialized.theme_preference.selected);
-        assert_eq!(settings.font_size, deserialized.font_size);
-        assert_eq!(settings.line_height, deserialized.line_height);
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(""), 0xcbf29ce484222325);
+        assert_eq!(content_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(content_hash("draft"), content_hash("draft "));
+    }
+
+    #[tokio::test]
+    async fn test_append_to_file_adds_missing_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.md");
+        std::fs::write(&path, "first").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        append_to_file(path_str.clone(), "second\n".to_string(), true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+
+        // Already ends with a newline, so nothing extra is inserted
+        append_to_file(path_str, "third".to_string(), true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\nthird");
+    }
+
+    #[tokio::test]
+    async fn test_append_to_file_without_leading_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.md");
+        std::fs::write(&path, "first").unwrap();
+
+        append_to_file(path.to_string_lossy().to_string(), " more".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first more");
+    }
+
+    #[tokio::test]
+    async fn test_append_to_file_creates_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.md");
+
+        append_to_file(path.to_string_lossy().to_string(), "hello".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_settings_saves_leave_valid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = std::sync::Arc::new(dir.path().to_path_buf());
+        let lock = std::sync::Arc::new(ConfigLock::default());
+
+        let handles: Vec<_> = (0..32)
+            .map(|i| {
+                let data_dir = data_dir.clone();
+                let lock = lock.clone();
+                tokio::spawn(async move {
+                    let settings = EditorSettings {
+                        font_size: 10 + i,
+                        writing_context: "x".repeat(4096),
+                        ..Default::default()
+                    };
+                    let _guard = lock.acquire().await;
+                    save_settings_in(&data_dir, &settings).await
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+
+        let content = std::fs::read_to_string(data_dir.join("settings.json")).unwrap();
+        let settings: EditorSettings = serde_json::from_str(&content).unwrap();
+        assert!((10..42).contains(&settings.font_size));
+        assert_eq!(settings.writing_context.len(), 4096);
+
+        // No temp files are left behind
+        let leftovers = std::fs::read_dir(data_dir.as_path()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_missing_file_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.md").to_string_lossy().to_string();
+
+        match read_document(path.clone(), false).await {
+            Err(MikuError::NotFound(missing)) => assert_eq!(missing, path),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
     }
 
     #[test]
-    fn test_document_serialization() {
-        let doc = Document {
-            path: Some("/test/path.md".to_string()),
-            content: "# Test Content".to_string(),
-            is_modified: true,
-        };
+    fn test_io_error_kinds_are_mapped() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            MikuError::from_io(denied, "/locked.md"),
+            MikuError::PermissionDenied(path) if path == "/locked.md"
+        ));
 
-        let json = serde_json::to_string(&doc).unwrap();
-        let deserialized: Document = serde_json::from_str(&json).unwrap();
+        let other = std::io::Error::other("disk on fire");
+        assert!(matches!(MikuError::from_io(other, "/a.md"), MikuError::Io(_)));
+    }
 
-        assert_eq!(doc.path, deserialized.path);
-        assert_eq!(doc.content, deserialized.content);
-        assert_eq!(doc.is_modified, deserialized.is_modified);
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_open_unreadable_file_is_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.md");
+        std::fs::write(&path, "secret").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Privileged users can read regardless of permission bits
+        if std::fs::read(&path).is_ok() {
+            return;
+        }
+        let result = read_document(path.to_string_lossy().to_string(), false).await;
+        assert!(matches!(result, Err(MikuError::PermissionDenied(_))));
     }
 
     #[test]
-    fn test_error_serialization() {
-        let error = MikuError::Path("test error".to_string());
-        let json = serde_json::to_string(&error).unwrap();
-        assert!(json.contains("test error"));
+    fn test_get_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolved = resolve_data_dir(None, Some(dir.path().to_path_buf())).unwrap();
+        let paths = app_paths_in(&resolved);
+        let data_dir = Path::new(&paths.data_dir);
+        assert_eq!(data_dir, dir.path().join("miku"));
+
+        for (path, file) in [
+            (&paths.settings_path, "settings.json"),
+            (&paths.recent_files_path, "recent_files.json"),
+            (&paths.workspace_config_path, "workspace_config.json"),
+        ] {
+            assert_eq!(Path::new(path), data_dir.join(file));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_dir_env_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let override_dir = dir.path().join("portable");
+
+        let platform_dir = dir.path().join("platform");
+        let resolved = resolve_data_dir(Some(override_dir.clone().into()), Some(platform_dir.clone())).unwrap();
+        assert_eq!(resolved, override_dir);
+        assert!(!platform_dir.exists());
+        assert!(override_dir.is_dir());
+
+        save_settings_in(&resolved, &EditorSettings::default()).await.unwrap();
+        assert!(override_dir.join("settings.json").exists());
+    }
+
+    #[test]
+    fn test_data_dir_env_override_must_be_creatable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("not-a-dir");
+        std::fs::write(&file, "").unwrap();
+
+        let resolved = resolve_data_dir(Some(file.join("miku").into()), None);
+
+        assert!(matches!(resolved, Err(MikuError::Path(message)) if message.contains(DATA_DIR_ENV)));
+    }
+
+    #[test]
+    fn test_missing_platform_data_dir_is_descriptive() {
+        let error = resolve_data_dir(None, None).unwrap_err();
+        assert_eq!(error.code(), "DataDirUnavailable");
+        let message = error.to_string();
+        assert!(message.contains(PLATFORM_DATA_DIR_HINT));
+        assert!(message.contains(DATA_DIR_ENV));
+
+        // An empty override falls through to the platform folder, which is
+        // created on first use
+        let dir = tempfile::tempdir().unwrap();
+        let resolved = resolve_data_dir(Some("".into()), Some(dir.path().to_path_buf())).unwrap();
+        assert_eq!(resolved, dir.path().join("miku"));
+        assert!(resolved.is_dir());
     }
 }
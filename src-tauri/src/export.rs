@@ -0,0 +1,945 @@
+// Export module for Miku
+// Renders markdown documents to standalone HTML files
+
+use base64::Engine;
+use pulldown_cmark::{html, Event, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tauri::{AppHandle, State};
+
+use crate::commands::{content_hash, markdown_options_or_saved, write_atomic, MikuError};
+use crate::file_ops::is_markdown_file;
+use crate::markdown::{
+    document_links, percent_decode, slugify_text, split_frontmatter, strip_frontmatter, DocumentLink,
+    MarkdownOptions,
+};
+use crate::progress::{Operations, Progress};
+use crate::workspace::{relative_link, resolve_link_within, unique_path, ActiveWorkspace};
+
+/// Output format for exports
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Html,
+    Pdf,
+}
+
+/// Result of writing an export to disk
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportResult {
+    pub output_path: String,
+    /// Number of source documents included
+    pub section_count: usize,
+}
+
+/// How local images referenced by exported documents are handled
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageHandling {
+    /// Leave `src` as written
+    #[default]
+    Link,
+    /// Embed as base64 `data:` URIs, keeping the export a single file
+    Inline,
+    /// Copy next to the output file and point `src` at the copy
+    Copy,
+}
+
+/// URLs that don't refer to a local file
+fn is_remote(url: &str) -> bool {
+    url.contains("://") || url.starts_with("//") || url.starts_with("data:")
+}
+
+fn image_mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Rewrites local image sources while an export is rendered
+pub(crate) struct ImageBundler<'a> {
+    mode: ImageHandling,
+    out_dir: &'a Path,
+    /// Images may only be read from inside this folder. Documents outside
+    /// it are limited to their own folder.
+    workspace: Option<&'a Path>,
+    /// Source image -> `src` it was already rewritten to
+    bundled: HashMap<PathBuf, String>,
+}
+
+impl<'a> ImageBundler<'a> {
+    pub(crate) fn new(mode: ImageHandling, out_dir: &'a Path, workspace: Option<&'a Path>) -> Self {
+        ImageBundler {
+            mode,
+            out_dir,
+            workspace,
+            bundled: HashMap::new(),
+        }
+    }
+
+    /// The image at `url` in a document inside `base_dir`, if it's a file
+    /// inside the folder the export may read from
+    async fn local_source(&self, base_dir: &Path, url: &str) -> Option<PathBuf> {
+        let allowed = match self.workspace {
+            Some(workspace) if base_dir.starts_with(workspace) => workspace,
+            _ => base_dir,
+        };
+        let allowed = tokio::fs::canonicalize(allowed).await.ok()?;
+        let path = percent_decode(url.split(['?', '#']).next().unwrap_or_default());
+        let source = tokio::fs::canonicalize(base_dir.join(path)).await.ok()?;
+        (source.starts_with(&allowed) && source.is_file()).then_some(source)
+    }
+
+    /// Where a copy of `source` goes in the output folder: an identical
+    /// file already there from an earlier export, or else a free name
+    async fn copy_destination(&self, source: &Path) -> Result<(PathBuf, bool), MikuError> {
+        let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let bytes = tokio::fs::read(source)
+            .await
+            .map_err(|e| MikuError::from_io(e, source))?;
+        let stem = Path::new(&name).file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let extension = Path::new(&name)
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+
+        for n in 0.. {
+            let candidate = match n {
+                0 => self.out_dir.join(&name),
+                n => self.out_dir.join(format!("{} ({}){}", stem, n, extension)),
+            };
+            match tokio::fs::read(&candidate).await {
+                Ok(existing) if existing == bytes => return Ok((candidate, false)),
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+        Ok((unique_path(self.out_dir, &name), true))
+    }
+
+    /// New `src` for an image at `url` in a document inside `base_dir`, or
+    /// `None` to keep it. Remote and missing images are left alone, as are
+    /// images outside the workspace.
+    async fn rewrite(&mut self, base_dir: &Path, url: &str) -> Result<Option<String>, MikuError> {
+        if self.mode == ImageHandling::Link || is_remote(url) {
+            return Ok(None);
+        }
+        let Some(source) = self.local_source(base_dir, url).await else {
+            return Ok(None);
+        };
+        if let Some(src) = self.bundled.get(&source) {
+            return Ok(Some(src.clone()));
+        }
+
+        let src = match self.mode {
+            ImageHandling::Link => return Ok(None),
+            ImageHandling::Inline => {
+                let bytes = tokio::fs::read(&source)
+                    .await
+                    .map_err(|e| MikuError::from_io(e, &source))?;
+                format!(
+                    "data:{};base64,{}",
+                    image_mime(&source),
+                    base64::engine::general_purpose::STANDARD.encode(bytes)
+                )
+            }
+            ImageHandling::Copy => {
+                let out_dir = tokio::fs::canonicalize(self.out_dir).await.ok();
+                let dest = if source.parent() == out_dir.as_deref() {
+                    source.clone()
+                } else {
+                    let (dest, is_new) = self.copy_destination(&source).await?;
+                    if is_new {
+                        tokio::fs::copy(&source, &dest)
+                            .await
+                            .map_err(|e| MikuError::from_io(e, &source))?;
+                    }
+                    dest
+                };
+                dest.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .replace(' ', "%20")
+            }
+        };
+
+        self.bundled.insert(source, src.clone());
+        Ok(Some(src))
+    }
+}
+
+/// Render markdown from a document in `base_dir` to an HTML fragment,
+/// bundling its local images as `images` asks
+pub(crate) async fn render_html(
+    markdown: &str,
+    options: &MarkdownOptions,
+    base_dir: &Path,
+    images: &mut ImageBundler<'_>,
+) -> Result<String, MikuError> {
+    let mut events: Vec<Event> = Parser::new_ext(markdown, options.parser_options()).collect();
+    for event in events.iter_mut() {
+        match event {
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                if let Some(src) = images.rewrite(base_dir, dest_url).await? {
+                    *dest_url = src.into();
+                }
+            }
+            Event::Html(html) | Event::InlineHtml(html) if !options.html => {
+                *event = Event::Text(html.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut out, events.into_iter());
+    Ok(out)
+}
+
+/// Most documents whose rendered HTML is kept; the least recently used
+/// render is dropped past this
+const RENDER_CACHE_CAPACITY: usize = 32;
+
+/// Last render of a document, reused while its content and the markdown
+/// options stay the same
+struct CachedRender {
+    content_hash: u64,
+    options: MarkdownOptions,
+    html: String,
+    /// Value of `RenderEntries::clock` when the render was last served
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct RenderEntries {
+    renders: HashMap<String, CachedRender>,
+    clock: u64,
+}
+
+/// Rendered HTML of the documents being previewed, one entry per path and
+/// at most `RENDER_CACHE_CAPACITY` entries
+#[derive(Default)]
+pub struct RenderCache {
+    entries: std::sync::Mutex<RenderEntries>,
+    /// Number of renders that missed the cache
+    renders: AtomicUsize,
+}
+
+impl RenderCache {
+    /// HTML for the document at `path`, or for `content` when given. The
+    /// content is hashed here and only rendered when it differs from the
+    /// last render of `path`; without it the file on disk is read.
+    pub(crate) async fn render(
+        &self,
+        path: &str,
+        content: Option<String>,
+        options: MarkdownOptions,
+    ) -> Result<String, MikuError> {
+        let content = match content {
+            Some(content) => content,
+            None => tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| MikuError::from_io(e, path))?,
+        };
+        let hash = content_hash(&content);
+
+        {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.clock += 1;
+            let now = entries.clock;
+            if let Some(cached) = entries.renders.get_mut(path) {
+                if cached.content_hash == hash && cached.options == options {
+                    cached.last_used = now;
+                    return Ok(cached.html.clone());
+                }
+            }
+        }
+
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        let mut images = ImageBundler::new(ImageHandling::Link, base_dir, None);
+        let html = render_html(&content, &options, base_dir, &mut images).await?;
+        self.renders.fetch_add(1, Ordering::Relaxed);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if !entries.renders.contains_key(path) && entries.renders.len() >= RENDER_CACHE_CAPACITY {
+            let oldest = entries
+                .renders
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                entries.renders.remove(&oldest);
+            }
+        }
+        let last_used = entries.clock;
+        entries.renders.insert(
+            path.to_string(),
+            CachedRender {
+                content_hash: hash,
+                options,
+                html: html.clone(),
+                last_used,
+            },
+        );
+        Ok(html)
+    }
+}
+
+/// Render a document for preview, returning the cached HTML when its
+/// content hashes the same as the last render of `path` so unchanged
+/// documents aren't parsed again. Without `content` the file on disk is
+/// rendered.
+#[tauri::command]
+pub async fn render_markdown_cached(
+    path: String,
+    content: Option<String>,
+    cache: State<'_, RenderCache>,
+) -> Result<String, MikuError> {
+    let options = markdown_options_or_saved(None).await;
+    cache.render(&path, content, options).await
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wrap a rendered fragment in a minimal standalone HTML page
+pub(crate) fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+/// Markdown for one document's section: a heading named after its file
+/// followed by its body, with its frontmatter dropped when
+/// `strip_frontmatter` is set
+fn section_markdown(title: &str, content: &str, strip_frontmatter: bool) -> String {
+    let body = match split_frontmatter(content) {
+        Some((_, body)) if strip_frontmatter => body,
+        _ => content,
+    };
+    format!("# {}\n\n{}", title, body.trim())
+}
+
+/// Concatenate documents in order as sections separated by horizontal
+/// rules, also returning the 1-based line of each section's heading
+pub(crate) fn merge_documents_with_offsets(documents: &[(String, String)]) -> (String, Vec<usize>) {
+    let mut merged = String::new();
+    let mut offsets = Vec::with_capacity(documents.len());
+
+    for (index, (title, content)) in documents.iter().enumerate() {
+        if index > 0 {
+            merged.push_str("\n\n---\n\n");
+        }
+        offsets.push(merged.matches('\n').count() + 1);
+        merged.push_str(&section_markdown(title, content, true));
+    }
+
+    (merged, offsets)
+}
+
+/// Title for a file's section: its name without the extension
+pub(crate) fn section_title(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Merge the given files in order and export them as a single document.
+/// Images are only bundled from inside `workspace`, or from each
+/// document's own folder when it's outside the workspace.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn export_documents(
+    paths: Vec<String>,
+    out_path: String,
+    format: ExportFormat,
+    images: ImageHandling,
+    strip_frontmatter: bool,
+    workspace: Option<&Path>,
+    options: &MarkdownOptions,
+    progress: &Progress,
+) -> Result<ExportResult, MikuError> {
+    if format == ExportFormat::Pdf {
+        return Err(MikuError::Unsupported("PDF export is not supported yet".to_string()));
+    }
+    if paths.is_empty() {
+        return Err(MikuError::Path("No files selected for export".to_string()));
+    }
+
+    let out_dir = Path::new(&out_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut bundler = ImageBundler::new(images, out_dir, workspace);
+
+    // Each document is rendered on its own so its images resolve against
+    // its own folder
+    let mut sections = Vec::with_capacity(paths.len());
+    for (index, path) in paths.iter().enumerate() {
+        progress.step(index, paths.len(), path)?;
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| MikuError::from_io(e, path))?;
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        // File names can hold markup, which the heading would pass through
+        let markdown = section_markdown(&escape_html(&section_title(path)), &content, strip_frontmatter);
+        sections.push(render_html(&markdown, options, base_dir, &mut bundler).await?);
+    }
+
+    let title = section_title(&out_path);
+    let page = html_page(&title, &sections.join("<hr />\n"));
+    write_atomic(Path::new(&out_path), page).await?;
+    progress.step(paths.len(), paths.len(), &out_path)?;
+
+    Ok(ExportResult {
+        output_path: out_path,
+        section_count: sections.len(),
+    })
+}
+
+/// Merge the given files in order and export them as a single document.
+/// With an `operation_id`, reports `progress://<operation_id>` events and
+/// can be stopped with `cancel_operation`. Markdown is rendered with the
+/// extensions from the saved settings; `images` picks whether local images
+/// are linked as written (the default), inlined, or copied beside the output.
+/// Only images inside the open workspace are inlined or copied. Frontmatter
+/// is left out unless `strip_frontmatter` is false.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_selection(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    active: State<'_, ActiveWorkspace>,
+    paths: Vec<String>,
+    out_path: String,
+    format: ExportFormat,
+    images: Option<ImageHandling>,
+    strip_frontmatter: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<ExportResult, MikuError> {
+    let options = markdown_options_or_saved(None).await;
+    let progress = Progress::for_operation(&app, &operations, operation_id)?;
+    let workspace = active.get();
+    let images = images.unwrap_or_default();
+    let strip = strip_frontmatter.unwrap_or(true);
+    export_documents(paths, out_path, format, images, strip, workspace.as_deref(), &options, &progress).await
+}
+
+/// A link from one note of a cluster to another note of the workspace
+struct NoteLink {
+    link: DocumentLink,
+    /// Canonical path of the linked note
+    target: PathBuf,
+    /// `#fragment` to keep on the rewritten link
+    fragment: Option<String>,
+}
+
+/// Links in `content` of the note at `source` that point to markdown notes
+/// inside `workspace`. Remote links, images and missing notes are left out.
+async fn note_links(workspace: &Path, source: &Path, content: &str, options: &MarkdownOptions) -> Vec<NoteLink> {
+    let mut links = Vec::new();
+    for link in document_links(content, options) {
+        if is_remote(&link.target) {
+            continue;
+        }
+        let source = source.to_string_lossy();
+        let Ok(resolved) = resolve_link_within(workspace, &source, &link.target, options).await else {
+            continue;
+        };
+        if !resolved.exists || !is_markdown_file(&resolved.path) {
+            continue;
+        }
+        let Ok(target) = std::fs::canonicalize(&resolved.path) else {
+            continue;
+        };
+
+        let fragment = match link.target.strip_prefix("[[") {
+            // Wikilinks name the heading; markdown links need its anchor
+            Some(wiki) => wiki
+                .trim_end_matches("]]")
+                .split_once('#')
+                .map(|(_, heading)| slugify_text(heading)),
+            None => link.target.split_once('#').map(|(_, fragment)| fragment.to_string()),
+        };
+        links.push(NoteLink { link, target, fragment });
+    }
+    links
+}
+
+pub(crate) async fn export_linked_cluster_in(
+    workspace: &Path,
+    root: &Path,
+    out_dir: &Path,
+    depth: usize,
+    strip: bool,
+    options: &MarkdownOptions,
+    progress: &Progress,
+) -> Result<Vec<String>, MikuError> {
+    let workspace = std::fs::canonicalize(workspace).map_err(|e| MikuError::from_io(e, workspace))?;
+    let root = std::fs::canonicalize(root).map_err(|e| MikuError::from_io(e, root))?;
+    if !root.starts_with(&workspace) {
+        return Err(MikuError::OutsideWorkspace(root.to_string_lossy().to_string()));
+    }
+    tokio::fs::create_dir_all(out_dir)
+        .await
+        .map_err(|e| MikuError::from_io(e, out_dir))?;
+    if std::fs::canonicalize(out_dir).map_err(|e| MikuError::from_io(e, out_dir))? == workspace {
+        // The bundle mirrors the workspace layout, so this would overwrite the notes
+        return Err(MikuError::Conflict(out_dir.to_string_lossy().to_string()));
+    }
+
+    // Breadth-first so every note is reached by its shortest path, and
+    // `included` doubles as the visited set that stops cycles
+    let mut included = HashSet::from([root.clone()]);
+    let mut frontier = VecDeque::from([(root, 0)]);
+    let mut notes = Vec::new();
+    while let Some((path, hops)) = frontier.pop_front() {
+        progress.check()?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| MikuError::from_io(e, &path))?;
+        let links = note_links(&workspace, &path, &content, options).await;
+        if hops < depth {
+            for link in &links {
+                if included.insert(link.target.clone()) {
+                    frontier.push_back((link.target.clone(), hops + 1));
+                }
+            }
+        }
+        notes.push((path, content, links));
+    }
+
+    // The bundle keeps the notes' relative layout; links to notes left out
+    // of it become plain text
+    for (index, (path, content, links)) in notes.iter().enumerate() {
+        progress.step(index, notes.len(), &path.to_string_lossy())?;
+        let mut rewritten = content.clone();
+        for NoteLink { link, target, fragment } in links.iter().rev() {
+            let text = if link.text.is_empty() { section_title(&target.to_string_lossy()) } else { link.text.clone() };
+            let replacement = if included.contains(target) {
+                let mut href = relative_link(path.parent().unwrap_or(&workspace), target);
+                if let Some(fragment) = fragment {
+                    href = format!("{}#{}", href, fragment);
+                }
+                format!("[{}]({})", text, href)
+            } else {
+                text
+            };
+            rewritten.replace_range(link.range.clone(), &replacement);
+        }
+        if strip {
+            rewritten = strip_frontmatter(rewritten);
+        }
+
+        let dest = out_dir.join(path.strip_prefix(&workspace).unwrap_or(path));
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        write_atomic(&dest, rewritten).await?;
+    }
+
+    Ok(notes.iter().map(|(path, _, _)| path.to_string_lossy().to_string()).collect())
+}
+
+/// Export the note at `root_path` together with every note reachable from
+/// it through at most `depth` internal links, markdown or `[[wikilink]]`.
+/// The notes are copied into `out_dir` keeping their folder layout, with
+/// links rewritten so they only point inside the bundle. Returns the
+/// included notes, starting with `root_path`. With an `operation_id`,
+/// reports progress while writing and can be cancelled. Frontmatter is
+/// left out of the copies unless `strip_frontmatter` is false.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_linked_cluster(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    root_path: String,
+    out_dir: String,
+    depth: usize,
+    strip_frontmatter: Option<bool>,
+    operation_id: Option<String>,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<Vec<String>, MikuError> {
+    let progress = Progress::for_operation(&app, &operations, operation_id)?;
+    let root = Path::new(&root_path);
+    // Links resolve within the open workspace; a note outside it only sees
+    // its own folder
+    let workspace = match active.get() {
+        Some(workspace) if root.starts_with(&workspace) => workspace,
+        _ => root.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+    let options = markdown_options_or_saved(None).await;
+    let strip = strip_frontmatter.unwrap_or(true);
+    export_linked_cluster_in(&workspace, root, Path::new(&out_dir), depth, strip, &options, &progress).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_selection_html_keeps_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("intro.md");
+        let second = dir.path().join("details.md");
+        std::fs::write(&first, "---\ntitle: Intro\n---\nHello **world**").unwrap();
+        std::fs::write(&second, "Some | Table\n--- | ---\na | b").unwrap();
+        let out = dir.path().join("bundle.html");
+
+        let result = export_documents(
+            vec![
+                first.to_string_lossy().to_string(),
+                second.to_string_lossy().to_string(),
+            ],
+            out.to_string_lossy().to_string(),
+            ExportFormat::Html,
+            ImageHandling::Link,
+            true,
+            None,
+            &MarkdownOptions::default(),
+            &Progress::none(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.section_count, 2);
+
+        let html = std::fs::read_to_string(&out).unwrap();
+        let intro = html.find("<h1>intro</h1>").unwrap();
+        let details = html.find("<h1>details</h1>").unwrap();
+        assert!(intro < details);
+        assert!(html.contains("<strong>world</strong>"));
+        assert!(html.contains("<table>"));
+        assert!(!html.contains("title: Intro"));
+    }
+
+    #[tokio::test]
+    async fn test_export_selection_can_keep_frontmatter() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("intro.md");
+        std::fs::write(&note, "---\ntitle: Intro\n---\nHello").unwrap();
+        let out = dir.path().join("intro.html");
+
+        export_documents(
+            vec![note.to_string_lossy().to_string()],
+            out.to_string_lossy().to_string(),
+            ExportFormat::Html,
+            ImageHandling::Link,
+            false,
+            None,
+            &MarkdownOptions::default(),
+            &Progress::none(),
+        )
+        .await
+        .unwrap();
+
+        let html = std::fs::read_to_string(&out).unwrap();
+        assert!(html.contains("title: Intro"));
+        assert!(html.contains("Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_export_selection_pdf_is_rejected() {
+        let result = export_documents(
+            vec!["a.md".to_string()],
+            "out.pdf".to_string(),
+            ExportFormat::Pdf,
+            ImageHandling::Link,
+            true,
+            None,
+            &MarkdownOptions::default(),
+            &Progress::none(),
+        )
+        .await;
+        assert!(matches!(result, Err(MikuError::Unsupported(_))));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_export_escapes_titles() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("<img src=x onerror=alert(1)>.md");
+        std::fs::write(&note, "Body").unwrap();
+        let out = dir.path().join("<script>.html");
+
+        export_documents(
+            vec![note.to_string_lossy().to_string()],
+            out.to_string_lossy().to_string(),
+            ExportFormat::Html,
+            ImageHandling::Link,
+            true,
+            None,
+            &MarkdownOptions::default(),
+            &Progress::none(),
+        )
+        .await
+        .unwrap();
+
+        let html = std::fs::read_to_string(&out).unwrap();
+        assert!(html.contains("<title>&lt;script&gt;</title>"));
+        assert!(html.contains("<h1>&lt;img src=x onerror=alert(1)&gt;</h1>"));
+        assert!(!html.contains("<img"));
+    }
+
+    async fn render(content: &str, options: &MarkdownOptions) -> String {
+        let mut bundler = ImageBundler::new(ImageHandling::Link, Path::new("."), None);
+        render_html(content, options, Path::new("."), &mut bundler).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_render_cache_skips_unchanged_content() {
+        let cache = RenderCache::default();
+        let options = MarkdownOptions::default();
+        let renders = || cache.renders.load(Ordering::Relaxed);
+
+        let html = cache.render("note.md", Some("# One".to_string()), options).await.unwrap();
+        assert!(html.contains("<h1>One</h1>"));
+        assert_eq!(renders(), 1);
+
+        // Same content: served from the cache
+        assert_eq!(cache.render("note.md", Some("# One".to_string()), options).await.unwrap(), html);
+        assert_eq!(renders(), 1);
+
+        let html = cache.render("note.md", Some("# Two".to_string()), options).await.unwrap();
+        assert!(html.contains("<h1>Two</h1>"));
+        assert_eq!(renders(), 2);
+
+        // Other paths and changed options don't share a render
+        cache.render("other.md", Some("# Two".to_string()), options).await.unwrap();
+        let no_tables = MarkdownOptions { tables: false, ..options };
+        cache.render("note.md", Some("# Two".to_string()), no_tables).await.unwrap();
+        assert_eq!(renders(), 4);
+
+        // Without content the file on disk is hashed, so an edit there is
+        // rendered again
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("disk.md");
+        let note = note.to_str().unwrap();
+        std::fs::write(note, "# Disk").unwrap();
+        assert!(cache.render(note, None, options).await.unwrap().contains("<h1>Disk</h1>"));
+        assert!(cache.render(note, None, options).await.is_ok());
+        assert_eq!(renders(), 5);
+        std::fs::write(note, "# Edited").unwrap();
+        assert!(cache.render(note, None, options).await.unwrap().contains("<h1>Edited</h1>"));
+        assert_eq!(renders(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_render_cache_drops_least_recently_used() {
+        let cache = RenderCache::default();
+        let options = MarkdownOptions::default();
+        for i in 0..RENDER_CACHE_CAPACITY {
+            cache.render(&format!("{i}.md"), Some(format!("# {i}")), options).await.unwrap();
+        }
+        // Touch the first note so the second is the oldest
+        cache.render("0.md", Some("# 0".to_string()), options).await.unwrap();
+        cache.render("new.md", Some("# New".to_string()), options).await.unwrap();
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.renders.len(), RENDER_CACHE_CAPACITY);
+        assert!(entries.renders.contains_key("0.md"));
+        assert!(!entries.renders.contains_key("1.md"));
+        assert!(entries.renders.contains_key("new.md"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_extension_changes_render() {
+        let content = "| Step |\n| --- |\n| Ship |\n\n~~old~~";
+        let gfm = MarkdownOptions::default();
+        let no_tables = MarkdownOptions { tables: false, ..gfm };
+
+        assert!(render(content, &gfm).await.contains("<table>"));
+        let html = render(content, &no_tables).await;
+        assert!(!html.contains("<table>"));
+        assert!(html.contains("| Step |"));
+        assert!(html.contains("<del>old</del>"));
+
+        let no_html = MarkdownOptions { html: false, ..gfm };
+        assert!(render("<b>bold</b>", &gfm).await.contains("<b>bold</b>"));
+        assert!(render("<b>bold</b>", &no_html).await.contains("&lt;b&gt;bold&lt;/b&gt;"));
+    }
+
+    /// A note in `notes/` embedding a local and a remote image
+    fn image_fixture() -> (tempfile::TempDir, String, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes/assets")).unwrap();
+        std::fs::create_dir_all(dir.path().join("out")).unwrap();
+        std::fs::write(dir.path().join("notes/assets/dot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        let note = dir.path().join("notes/trip.md");
+        std::fs::write(&note, "![dot](assets/dot.png)\n\n![remote](https://example.com/a.png)").unwrap();
+        let out = dir.path().join("out/trip.html");
+        (dir, note.to_string_lossy().to_string(), out)
+    }
+
+    async fn export_with_images(note: &str, out: &Path, images: ImageHandling) -> String {
+        export_documents(
+            vec![note.to_string()],
+            out.to_string_lossy().to_string(),
+            ExportFormat::Html,
+            images,
+            true,
+            None,
+            &MarkdownOptions::default(),
+            &Progress::none(),
+        )
+        .await
+        .unwrap();
+        std::fs::read_to_string(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_export_inlines_local_images() {
+        let (_dir, note, out) = image_fixture();
+        let html = export_with_images(&note, &out, ImageHandling::Inline).await;
+
+        assert!(html.contains("src=\"data:image/png;base64,iVBORw==\""));
+        assert!(html.contains("src=\"https://example.com/a.png\""));
+    }
+
+    #[tokio::test]
+    async fn test_export_copies_local_images_beside_output() {
+        let (dir, note, out) = image_fixture();
+        let html = export_with_images(&note, &out, ImageHandling::Copy).await;
+
+        assert!(html.contains("src=\"dot.png\""));
+        assert_eq!(std::fs::read(dir.path().join("out/dot.png")).unwrap(), [0x89, b'P', b'N', b'G']);
+        assert!(html.contains("src=\"https://example.com/a.png\""));
+    }
+
+    #[tokio::test]
+    async fn test_reexport_reuses_copied_images() {
+        let (dir, note, out) = image_fixture();
+        // A different image already holds the name
+        std::fs::write(dir.path().join("out/dot.png"), "other").unwrap();
+
+        let first = export_with_images(&note, &out, ImageHandling::Copy).await;
+        let second = export_with_images(&note, &out, ImageHandling::Copy).await;
+        assert!(first.contains("src=\"dot%20(1).png\""));
+        assert_eq!(first, second);
+        assert!(!dir.path().join("out/dot (2).png").exists());
+    }
+
+    #[tokio::test]
+    async fn test_export_only_bundles_images_inside_the_workspace() {
+        let (dir, note, out) = image_fixture();
+        let secret = tempfile::tempdir().unwrap();
+        std::fs::write(secret.path().join("key.png"), "secret").unwrap();
+        std::fs::write(dir.path().join("notes/assets/my dot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        std::fs::write(
+            &note,
+            format!(
+                "![abs]({})\n\n![up](../escape.png)\n\n![spaced](assets/my%20d%6Ft.png)",
+                secret.path().join("key.png").display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("escape.png"), "outside").unwrap();
+
+        let html = export_with_images(&note, &out, ImageHandling::Inline).await;
+        assert_eq!(html.matches("src=\"data:").count(), 1);
+        assert!(html.contains("src=\"../escape.png\""));
+    }
+
+    /// a -> b -> sub/c -> (a, d), plus a remote link that must survive
+    fn link_graph() -> tempfile::TempDir {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir(vault.path().join("sub")).unwrap();
+        let a = "# A\nSee [[b|the B note]] and [site](https://example.com).\n";
+        std::fs::write(vault.path().join("a.md"), a).unwrap();
+        std::fs::write(vault.path().join("b.md"), "# B\nOn to [C](sub/c.md#details).\n").unwrap();
+        std::fs::write(vault.path().join("sub/c.md"), "# C\n## Details\nBack to [[a#A]], also [[d]].\n").unwrap();
+        std::fs::write(vault.path().join("d.md"), "# D\n").unwrap();
+        vault
+    }
+
+    fn names(included: &[String], vault: &Path) -> Vec<String> {
+        let vault = std::fs::canonicalize(vault).unwrap();
+        included
+            .iter()
+            .map(|path| Path::new(path).strip_prefix(&vault).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_linked_cluster_depth_one() {
+        let vault = link_graph();
+        let out = tempfile::tempdir().unwrap();
+        let options = MarkdownOptions::default();
+
+        let root = vault.path().join("a.md");
+        let included = export_linked_cluster_in(vault.path(), &root, out.path(), 1, true, &options, &Progress::none())
+            .await
+            .unwrap();
+        assert_eq!(names(&included, vault.path()), vec!["a.md", "b.md"]);
+
+        let a = std::fs::read_to_string(out.path().join("a.md")).unwrap();
+        assert_eq!(a, "# A\nSee [the B note](b.md) and [site](https://example.com).\n");
+        // C is two hops away, so the link to it is reduced to its text
+        let b = std::fs::read_to_string(out.path().join("b.md")).unwrap();
+        assert_eq!(b, "# B\nOn to C.\n");
+        assert!(!out.path().join("sub").exists());
+    }
+
+    #[tokio::test]
+    async fn test_export_linked_cluster_depth_two_stops_at_cycles() {
+        let vault = link_graph();
+        let out = tempfile::tempdir().unwrap();
+        let options = MarkdownOptions::default();
+
+        let root = vault.path().join("a.md");
+        let included = export_linked_cluster_in(vault.path(), &root, out.path(), 2, true, &options, &Progress::none())
+            .await
+            .unwrap();
+        assert_eq!(names(&included, vault.path()), vec!["a.md", "b.md", "sub/c.md"]);
+
+        let b = std::fs::read_to_string(out.path().join("b.md")).unwrap();
+        assert_eq!(b, "# B\nOn to [C](sub/c.md#details).\n");
+        let c = std::fs::read_to_string(out.path().join("sub/c.md")).unwrap();
+        assert_eq!(c, "# C\n## Details\nBack to [a#A](../a.md#a), also d.\n");
+        assert!(!out.path().join("d.md").exists());
+
+        let overwrite =
+            export_linked_cluster_in(vault.path(), &root, vault.path(), 2, true, &options, &Progress::none()).await;
+        assert!(matches!(overwrite, Err(MikuError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_export_linked_cluster_strips_frontmatter_when_asked() {
+        let vault = link_graph();
+        let root = vault.path().join("d.md");
+        std::fs::write(&root, "---\ntags: [draft]\n---\n# D\n").unwrap();
+        let options = MarkdownOptions::default();
+
+        let stripped = tempfile::tempdir().unwrap();
+        export_linked_cluster_in(vault.path(), &root, stripped.path(), 0, true, &options, &Progress::none())
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(stripped.path().join("d.md")).unwrap(), "# D\n");
+
+        let kept = tempfile::tempdir().unwrap();
+        export_linked_cluster_in(vault.path(), &root, kept.path(), 0, false, &options, &Progress::none())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(kept.path().join("d.md")).unwrap(),
+            "---\ntags: [draft]\n---\n# D\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+// Stable file ids for Miku
+// Gives files an id that survives renames, so data features keep about a
+// file (cursor position, goals, favorites, ...) isn't lost when it moves
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::commands::{app_data_dir, quarantine_config, read_config_file, write_atomic, ConfigLock, MikuError};
+use crate::file_ops::same_path;
+
+/// Name of the id store inside the app data directory
+pub(crate) const FILE_IDS_FILE: &str = "file_ids.json";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct FileIdStore {
+    /// Absolute path -> id
+    ids: BTreeMap<String, String>,
+    /// Id -> data stored about that file, keyed by feature
+    data: BTreeMap<String, Map<String, Value>>,
+}
+
+impl FileIdStore {
+    /// Id of the file at `path`, matching paths as the filesystem would
+    fn id_of(&self, path: &str) -> Option<&String> {
+        self.ids
+            .get(path)
+            .or_else(|| self.ids.iter().find(|(known, _)| same_path(known, path)).map(|(_, id)| id))
+    }
+
+    /// Point the ids of `old` and everything below it at `new`. Returns
+    /// whether any id moved.
+    fn rename(&mut self, old: &str, new: &str) -> bool {
+        let moved: Vec<(String, String)> = self
+            .ids
+            .keys()
+            .filter_map(|path| {
+                let rest = path_below(path, old)?;
+                let renamed = if rest.as_os_str().is_empty() {
+                    new.to_string()
+                } else {
+                    Path::new(new).join(rest).to_string_lossy().to_string()
+                };
+                Some((path.clone(), renamed))
+            })
+            .collect();
+
+        for (from, to) in &moved {
+            if let Some(id) = self.ids.remove(from) {
+                self.ids.insert(to.clone(), id);
+            }
+        }
+        !moved.is_empty()
+    }
+
+    /// Drop the ids of `path` and everything below it, with the data kept
+    /// under them. Returns whether anything was dropped.
+    fn forget(&mut self, path: &str) -> bool {
+        let gone: Vec<String> = self.ids.keys().filter(|known| path_below(known, path).is_some()).cloned().collect();
+        for known in &gone {
+            if let Some(id) = self.ids.remove(known) {
+                self.data.remove(&id);
+            }
+        }
+        !gone.is_empty()
+    }
+}
+
+/// The rest of `path` below `base`, empty for `base` itself, comparing
+/// components as the filesystem would
+fn path_below(path: &str, base: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let ancestor = path.ancestors().find(|ancestor| same_path(&ancestor.to_string_lossy(), base))?;
+    path.strip_prefix(ancestor).ok().map(Path::to_path_buf)
+}
+
+pub(crate) async fn load_file_ids_in(data_dir: &Path) -> Result<FileIdStore, MikuError> {
+    let store_path = data_dir.join(FILE_IDS_FILE);
+    let Some(content) = read_config_file(&store_path).await? else {
+        return Ok(FileIdStore::default());
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(store) => Ok(store),
+        Err(err) => {
+            quarantine_config(&store_path, &content, &err).await?;
+            Ok(FileIdStore::default())
+        }
+    }
+}
+
+async fn save_file_ids_in(data_dir: &Path, store: &FileIdStore) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    let content = serde_json::to_string_pretty(store)?;
+    write_atomic(&data_dir.join(FILE_IDS_FILE), content).await
+}
+
+pub(crate) async fn get_or_assign_file_id_in(data_dir: &Path, path: &str) -> Result<String, MikuError> {
+    if !Path::new(path).exists() {
+        return Err(MikuError::NotFound(path.to_string()));
+    }
+
+    let mut store = load_file_ids_in(data_dir).await?;
+    if let Some(id) = store.id_of(path) {
+        return Ok(id.clone());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    store.ids.insert(path.to_string(), id.clone());
+    save_file_ids_in(data_dir, &store).await?;
+    Ok(id)
+}
+
+/// Keep the id of `old_path`, and of everything inside it for a folder,
+/// attached after it was renamed or moved to `new_path`
+pub(crate) async fn move_file_ids_in(data_dir: &Path, old_path: &str, new_path: &str) -> Result<(), MikuError> {
+    let mut store = load_file_ids_in(data_dir).await?;
+    if store.rename(old_path, new_path) {
+        save_file_ids_in(data_dir, &store).await?;
+    }
+    Ok(())
+}
+
+/// Forget the id of `path`, and of everything inside it for a folder, along
+/// with the data stored under them, once it's been deleted
+pub(crate) async fn forget_file_ids_in(data_dir: &Path, path: &str) -> Result<(), MikuError> {
+    let mut store = load_file_ids_in(data_dir).await?;
+    if store.forget(path) {
+        save_file_ids_in(data_dir, &store).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn get_file_data_in(data_dir: &Path, path: &str) -> Result<Map<String, Value>, MikuError> {
+    let store = load_file_ids_in(data_dir).await?;
+    let data = store.id_of(path).and_then(|id| store.data.get(id)).cloned();
+    Ok(data.unwrap_or_default())
+}
+
+pub(crate) async fn set_file_data_in(data_dir: &Path, path: &str, key: &str, value: Value) -> Result<(), MikuError> {
+    let id = get_or_assign_file_id_in(data_dir, path).await?;
+    let mut store = load_file_ids_in(data_dir).await?;
+
+    let data = store.data.entry(id.clone()).or_default();
+    if value.is_null() {
+        data.remove(key);
+    } else {
+        data.insert(key.to_string(), value);
+    }
+    if data.is_empty() {
+        store.data.remove(&id);
+    }
+    save_file_ids_in(data_dir, &store).await
+}
+
+/// Stable id of the file at `path`, assigning one on first use. The id
+/// follows the file through `rename_file`.
+#[tauri::command]
+pub async fn get_or_assign_file_id(path: String, lock: State<'_, ConfigLock>) -> Result<String, MikuError> {
+    let _guard = lock.acquire().await;
+    get_or_assign_file_id_in(&app_data_dir()?, &path).await
+}
+
+/// Everything stored about the file at `path` with `set_file_data`
+#[tauri::command]
+pub async fn get_file_data(path: String) -> Result<Map<String, Value>, MikuError> {
+    get_file_data_in(&app_data_dir()?, &path).await
+}
+
+/// Store `value` under `key` for the file at `path`, keyed by its stable id
+/// so it survives renames. A `null` value removes the key.
+#[tauri::command]
+pub async fn set_file_data(
+    path: String,
+    key: String,
+    value: Value,
+    lock: State<'_, ConfigLock>,
+) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    set_file_data_in(&app_data_dir()?, &path, &key, value).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_file_id_is_stable_and_unique() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let a = vault.path().join("a.md");
+        let b = vault.path().join("b.md");
+        std::fs::write(&a, "").unwrap();
+        std::fs::write(&b, "").unwrap();
+        let (a, b) = (a.to_string_lossy().to_string(), b.to_string_lossy().to_string());
+
+        let id = get_or_assign_file_id_in(data.path(), &a).await.unwrap();
+        assert_eq!(get_or_assign_file_id_in(data.path(), &a).await.unwrap(), id);
+        assert_ne!(get_or_assign_file_id_in(data.path(), &b).await.unwrap(), id);
+
+        let missing = vault.path().join("missing.md").to_string_lossy().to_string();
+        assert!(matches!(
+            get_or_assign_file_id_in(data.path(), &missing).await,
+            Err(MikuError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_data_round_trip() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let note = vault.path().join("note.md");
+        std::fs::write(&note, "").unwrap();
+        let note = note.to_string_lossy().to_string();
+
+        assert!(get_file_data_in(data.path(), &note).await.unwrap().is_empty());
+        set_file_data_in(data.path(), &note, "cursor", json!({ "line": 4 })).await.unwrap();
+        set_file_data_in(data.path(), &note, "favorite", json!(true)).await.unwrap();
+        set_file_data_in(data.path(), &note, "favorite", Value::Null).await.unwrap();
+
+        let stored = get_file_data_in(data.path(), &note).await.unwrap();
+        assert_eq!(Value::Object(stored), json!({ "cursor": { "line": 4 } }));
+    }
+
+    #[tokio::test]
+    async fn test_ids_follow_folder_moves_and_go_with_deletes() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("Projects")).unwrap();
+        std::fs::write(vault.path().join("Projects/plan.md"), "").unwrap();
+        std::fs::write(vault.path().join("Projects-old.md"), "").unwrap();
+        let path = |name: &str| vault.path().join(name).to_string_lossy().to_string();
+
+        set_file_data_in(data.path(), &path("Projects/plan.md"), "cursor", json!(3)).await.unwrap();
+        set_file_data_in(data.path(), &path("Projects-old.md"), "cursor", json!(5)).await.unwrap();
+        let id = get_or_assign_file_id_in(data.path(), &path("Projects/plan.md")).await.unwrap();
+
+        // A sibling sharing the folder's name as a prefix stays put
+        std::fs::rename(path("Projects"), path("Archive")).unwrap();
+        move_file_ids_in(data.path(), &path("Projects"), &path("Archive")).await.unwrap();
+        assert_eq!(get_or_assign_file_id_in(data.path(), &path("Archive/plan.md")).await.unwrap(), id);
+        assert_eq!(get_file_data_in(data.path(), &path("Projects-old.md")).await.unwrap()["cursor"], json!(5));
+
+        forget_file_ids_in(data.path(), &path("Archive")).await.unwrap();
+        let store = load_file_ids_in(data.path()).await.unwrap();
+        assert!(store.id_of(&path("Archive/plan.md")).is_none());
+        assert!(!store.data.contains_key(&id));
+        assert!(store.id_of(&path("Projects-old.md")).is_some());
+    }
+}
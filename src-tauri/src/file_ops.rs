@@ -1,23 +1,62 @@
 // File operations module for Miku
 // This module contains additional file operation utilities
 
-#![allow(dead_code)]
-
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use unicode_normalization::UnicodeNormalization;
 
 /// Check if a file exists
 pub fn file_exists(path: &str) -> bool {
     Path::new(path).exists()
 }
 
+/// Extensions treated as markdown until the settings say otherwise
+pub const DEFAULT_MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown"];
+
+/// Extensions currently treated as markdown: lowercase, without the dot.
+/// Kept in sync with `EditorSettings::markdown_extensions`.
+fn markdown_extensions() -> &'static RwLock<Vec<String>> {
+    static EXTENSIONS: OnceLock<RwLock<Vec<String>>> = OnceLock::new();
+    EXTENSIONS.get_or_init(|| RwLock::new(DEFAULT_MARKDOWN_EXTENSIONS.iter().map(|e| e.to_string()).collect()))
+}
+
+/// Lowercase `extensions`, strip leading dots, and drop blanks and repeats.
+/// An empty result falls back to the defaults.
+pub fn normalize_extensions(extensions: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for extension in extensions {
+        let extension = extension.trim().trim_start_matches('.').to_lowercase();
+        if !extension.is_empty() && !normalized.contains(&extension) {
+            normalized.push(extension);
+        }
+    }
+    if normalized.is_empty() {
+        DEFAULT_MARKDOWN_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+    } else {
+        normalized
+    }
+}
+
+/// Change which extensions every markdown check in the app recognizes
+pub fn set_markdown_extensions(extensions: &[String]) {
+    *markdown_extensions().write().unwrap_or_else(|e| e.into_inner()) = normalize_extensions(extensions);
+}
+
+/// Whether `extension` (without the dot, any case) is a markdown extension
+pub fn is_markdown_extension(extension: &str) -> bool {
+    is_markdown_extension_in(&markdown_extensions().read().unwrap_or_else(|e| e.into_inner()), extension)
+}
+
+/// Whether `extension` (without the dot, any case) is one of `extensions`,
+/// a list from `normalize_extensions`
+pub fn is_markdown_extension_in(extensions: &[String], extension: &str) -> bool {
+    extensions.contains(&extension.to_lowercase())
+}
+
 /// Check if a path is a markdown file
 pub fn is_markdown_file(path: &str) -> bool {
-    let path = Path::new(path);
-    match path.extension() {
-        Some(ext) => {
-            let ext_lower = ext.to_string_lossy().to_lowercase();
-            ext_lower == "md" || ext_lower == "markdown" || ext_lower == "mdown"
-        }
+    match Path::new(path).extension() {
+        Some(ext) => is_markdown_extension(&ext.to_string_lossy()),
         None => false,
     }
 }
@@ -36,6 +75,53 @@ pub fn get_parent_dir(path: &str) -> Option<String> {
         .map(|p| p.to_string_lossy().to_string())
 }
 
+/// `path` relative to `base`, or `path` unchanged when it isn't inside `base`
+pub fn relativize_path(path: &str, base: &str) -> String {
+    match Path::new(path).strip_prefix(base) {
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Whether the platform's default filesystem ignores case in file names
+pub const CASE_INSENSITIVE_FS: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// Canonical (NFC) form of a file name. macOS hands out decomposed (NFD)
+/// names while typed or pasted text is usually composed.
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Compare two paths as the filesystem would, optionally ignoring case.
+/// Composed and decomposed spellings of the same characters are equal.
+pub fn paths_equal(a: &str, b: &str, case_insensitive: bool) -> bool {
+    let (a, b) = (normalize_name(a), normalize_name(b));
+    if case_insensitive {
+        a.to_lowercase() == b.to_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// Whether two paths name the same file on this platform
+pub fn same_path(a: &str, b: &str) -> bool {
+    paths_equal(a, b, CASE_INSENSITIVE_FS)
+}
+
+/// The entry of `dir` whose name is `name` up to Unicode normalization (and
+/// case, where the filesystem ignores it)
+pub fn equivalent_entry(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .find(|entry| same_path(&entry.file_name().to_string_lossy(), name))
+        .map(|entry| entry.path())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -66,4 +152,38 @@ mod tests {
         assert_eq!(get_parent_dir("/path/to/file.md"), Some("/path/to".to_string()));
         assert_eq!(get_parent_dir("file.md"), Some("".to_string()));
     }
+
+    #[test]
+    fn test_normalize_extensions() {
+        let extensions = vec![".MD".to_string(), "mkd".to_string(), " ".to_string(), "md".to_string()];
+        assert_eq!(normalize_extensions(&extensions), vec!["md", "mkd"]);
+        assert_eq!(normalize_extensions(&[]), DEFAULT_MARKDOWN_EXTENSIONS);
+    }
+
+    #[test]
+    fn test_paths_equal() {
+        assert!(paths_equal("/Notes/a.md", "/notes/A.md", true));
+        assert!(!paths_equal("/Notes/a.md", "/notes/A.md", false));
+        assert!(paths_equal("/notes/a.md", "/notes/a.md", false));
+        assert!(!paths_equal("/notes/a.md", "/notes/b.md", true));
+    }
+
+    #[test]
+    fn test_paths_equal_ignores_normalization_form() {
+        let composed = "/notes/caf\u{e9}.md";
+        let decomposed = "/notes/cafe\u{301}.md";
+        assert_ne!(composed, decomposed);
+        assert!(paths_equal(composed, decomposed, false));
+        assert!(paths_equal(composed, "/Notes/CAFE\u{301}.md", true));
+        assert_eq!(normalize_name(decomposed), composed);
+    }
+
+    #[test]
+    fn test_relativize_path() {
+        assert_eq!(relativize_path("/home/me/vault/notes/idea.md", "/home/me/vault"), "notes/idea.md");
+        assert_eq!(relativize_path("/home/me/vault/notes/idea.md", "/home/me/vault/"), "notes/idea.md");
+        assert_eq!(relativize_path("/home/me/other/idea.md", "/home/me/vault"), "/home/me/other/idea.md");
+        // Only whole components count as being inside the base
+        assert_eq!(relativize_path("/home/me/vault2/idea.md", "/home/me/vault"), "/home/me/vault2/idea.md");
+    }
 }
@@ -0,0 +1,281 @@
+// Git history for Miku
+// Reads the commit log and blame of notes that live in a git repository
+
+use git2::{BlameOptions, Commit, ErrorCode, Oid, Repository, Sort, Tree};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::commands::MikuError;
+
+/// A commit that touched a file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileCommit {
+    pub commit: String,
+    pub author: String,
+    /// Author date in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub summary: String,
+}
+
+/// The commit that last changed one line of a file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlameLine {
+    /// 1-based line number
+    pub line: usize,
+    pub content: String,
+    #[serde(flatten)]
+    pub commit: FileCommit,
+}
+
+impl From<&Commit<'_>> for FileCommit {
+    fn from(commit: &Commit<'_>) -> Self {
+        let author = commit.author();
+        FileCommit {
+            commit: commit.id().to_string(),
+            author: author.name().unwrap_or_default().to_string(),
+            timestamp: author.when().seconds().max(0) as u64 * 1000,
+            summary: commit.summary().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+fn git_error(err: git2::Error) -> MikuError {
+    MikuError::Git(err.message().to_string())
+}
+
+/// Open the repository containing `path` and return it along with `path`
+/// relative to its working directory
+fn open_repo(path: &Path) -> Result<(Repository, PathBuf), MikuError> {
+    let path = path.canonicalize().map_err(|e| MikuError::from_io(e, path))?;
+    let dir = if path.is_dir() {
+        path.as_path()
+    } else {
+        path.parent().unwrap_or(&path)
+    };
+    let not_a_repo = || MikuError::NotARepository(dir.to_string_lossy().to_string());
+
+    let repo = Repository::discover(dir).map_err(|e| match e.code() {
+        ErrorCode::NotFound => not_a_repo(),
+        _ => git_error(e),
+    })?;
+    let workdir = repo
+        .workdir()
+        .and_then(|w| w.canonicalize().ok())
+        .ok_or_else(not_a_repo)?;
+    let relative = path.strip_prefix(&workdir).map_err(|_| not_a_repo())?.to_path_buf();
+    Ok((repo, relative))
+}
+
+/// Id of the blob at `path` in `tree`, if there is one
+fn entry_id(tree: &Tree<'_>, path: &Path) -> Option<Oid> {
+    tree.get_path(path).ok().map(|entry| entry.id())
+}
+
+/// Whether `commit` changed the file at `path`, i.e. differs from every
+/// parent there, which skips merges that took one side unchanged
+fn touches(commit: &Commit<'_>, path: &Path) -> Result<bool, git2::Error> {
+    let blob = entry_id(&commit.tree()?, path);
+    if commit.parent_count() == 0 {
+        return Ok(blob.is_some());
+    }
+    for parent in commit.parents() {
+        if entry_id(&parent.tree()?, path) == blob {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn history(path: &Path, max_entries: usize) -> Result<Vec<FileCommit>, MikuError> {
+    let (repo, relative) = open_repo(path)?;
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    match walk.push_head() {
+        Ok(()) => {}
+        // Nothing has been committed yet
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(git_error(e)),
+    }
+    walk.set_sorting(Sort::TIME).map_err(git_error)?;
+
+    let mut commits = Vec::new();
+    for oid in walk {
+        if commits.len() >= max_entries {
+            break;
+        }
+        let commit = repo.find_commit(oid.map_err(git_error)?).map_err(git_error)?;
+        if touches(&commit, &relative).map_err(git_error)? {
+            commits.push(FileCommit::from(&commit));
+        }
+    }
+    Ok(commits)
+}
+
+pub(crate) async fn file_history_in(
+    workspace: &Path,
+    path: &Path,
+    max_entries: usize,
+) -> Result<Vec<FileCommit>, MikuError> {
+    let path = workspace.join(path);
+    if !path.exists() {
+        return Err(MikuError::NotFound(path.to_string_lossy().to_string()));
+    }
+
+    tokio::task::spawn_blocking(move || history(&path, max_entries))
+        .await
+        .map_err(|e| MikuError::Git(e.to_string()))?
+}
+
+/// Commits that changed the file at `path`, newest first. At most
+/// `max_entries` are returned (50 by default). Files outside a git
+/// repository fail with `NotARepository`.
+#[tauri::command]
+pub async fn file_history(
+    workspace_path: String,
+    path: String,
+    max_entries: Option<usize>,
+) -> Result<Vec<FileCommit>, MikuError> {
+    file_history_in(Path::new(&workspace_path), Path::new(&path), max_entries.unwrap_or(50)).await
+}
+
+fn blame(path: &Path, content: &str) -> Result<Vec<BlameLine>, MikuError> {
+    let (repo, relative) = open_repo(path)?;
+    let committed = repo
+        .blame_file(&relative, Some(&mut BlameOptions::new()))
+        .map_err(git_error)?;
+    // Blame the file as it is on disk so uncommitted edits show up
+    let blame = committed.blame_buffer(content.as_bytes()).map_err(git_error)?;
+
+    let mut commits: HashMap<Oid, FileCommit> = HashMap::new();
+    let mut lines = Vec::new();
+    for (index, text) in content.lines().enumerate() {
+        let line = index + 1;
+        let oid = blame
+            .get_line(line)
+            .map_or_else(Oid::zero, |hunk| hunk.final_commit_id());
+        let commit = match commits.get(&oid) {
+            Some(commit) => commit.clone(),
+            None => {
+                let commit = match repo.find_commit(oid) {
+                    Ok(commit) => FileCommit::from(&commit),
+                    Err(_) => FileCommit {
+                        commit: oid.to_string(),
+                        author: String::new(),
+                        timestamp: 0,
+                        summary: String::new(),
+                    },
+                };
+                commits.insert(oid, commit.clone());
+                commit
+            }
+        };
+        lines.push(BlameLine {
+            line,
+            content: text.to_string(),
+            commit,
+        });
+    }
+    Ok(lines)
+}
+
+pub(crate) async fn file_blame_in(path: &Path) -> Result<Vec<BlameLine>, MikuError> {
+    if !path.is_file() {
+        return Err(MikuError::NotFound(path.to_string_lossy().to_string()));
+    }
+
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| MikuError::from_io(e, path))?;
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || blame(&path, &content))
+        .await
+        .map_err(|e| MikuError::Git(e.to_string()))?
+}
+
+/// The commit that last changed each line of the file at `path`. Lines not
+/// committed yet carry an all-zero commit hash. Files outside a git
+/// repository fail with `NotARepository`.
+#[tauri::command]
+pub async fn file_blame(path: String) -> Result<Vec<BlameLine>, MikuError> {
+    file_blame_in(Path::new(&path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+
+    fn commit(repo: &Repository, file: &str, message: &str, seconds: i64) {
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new("Ada", "ada@example.com", &Time::new(seconds, 0)).unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_history_and_blame() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "first line\n").unwrap();
+        commit(&repo, "note.md", "Start the note", 1_700_000_000);
+        std::fs::write(dir.path().join("other.md"), "unrelated\n").unwrap();
+        commit(&repo, "other.md", "Add another note", 1_700_000_300);
+        std::fs::write(&note, "first line\nsecond line\n").unwrap();
+        commit(&repo, "note.md", "Add a second line", 1_700_000_600);
+
+        let history = file_history_in(dir.path(), Path::new("note.md"), 10).await.unwrap();
+        let summary: Vec<(&str, &str, u64)> = history
+            .iter()
+            .map(|c| (c.summary.as_str(), c.author.as_str(), c.timestamp))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Add a second line", "Ada", 1_700_000_600_000),
+                ("Start the note", "Ada", 1_700_000_000_000),
+            ]
+        );
+        assert_eq!(history[0].commit.len(), 40);
+        assert_eq!(file_history_in(dir.path(), &note, 1).await.unwrap().len(), 1);
+
+        std::fs::write(&note, "first line\nsecond line\nthird line\n").unwrap();
+        let blame = file_blame_in(&note).await.unwrap();
+        let lines: Vec<(usize, &str, &str)> = blame
+            .iter()
+            .map(|b| (b.line, b.content.as_str(), b.commit.summary.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (1, "first line", "Start the note"),
+                (2, "second line", "Add a second line"),
+                (3, "third line", ""),
+            ]
+        );
+        assert_eq!(blame[1].commit.commit, history[0].commit);
+        assert_eq!(blame[2].commit.commit, Oid::zero().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_history_outside_repo_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("loose.md");
+        std::fs::write(&note, "no repo here\n").unwrap();
+
+        assert!(matches!(
+            file_history_in(dir.path(), &note, 10).await,
+            Err(MikuError::NotARepository(_))
+        ));
+        assert!(matches!(file_blame_in(&note).await, Err(MikuError::NotARepository(_))));
+        assert!(matches!(
+            file_blame_in(&dir.path().join("missing.md")).await,
+            Err(MikuError::NotFound(_))
+        ));
+    }
+}
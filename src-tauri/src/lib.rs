@@ -1,6 +1,16 @@
 mod claude;
 mod commands;
+mod export;
+mod file_ids;
 mod file_ops;
+mod history;
+mod markdown;
+mod progress;
+mod search;
+mod stats;
+mod theme;
+mod trash_index;
+mod watcher;
 mod workspace;
 mod window_commands;
 
@@ -97,6 +107,19 @@ pub fn run() {
                 });
             }
 
+            // Create the data directory once, up front, rather than on each
+            // config load. Markdown checks and read timeouts use
+            // process-wide copies of their settings; apply the saved ones
+            // before setup returns so they're in place before the window can
+            // ask for a listing.
+            match commands::init_data_dir() {
+                Ok(data_dir) => {
+                    let settings = tauri::async_runtime::block_on(commands::settings_or_default(&data_dir));
+                    commands::apply_global_settings(&settings);
+                }
+                Err(err) => log::error!("app data directory unavailable: {err}"),
+            }
+
             // System tray setup (only on desktop with tray-icon feature)
             #[cfg(all(desktop, feature = "tray-icon"))]
             {
@@ -157,31 +180,161 @@ pub fn run() {
 
             Ok(())
         })
+        .on_window_event(theme::handle_window_event)
         .manage(std::sync::Arc::new(claude::ClaudeProcesses::new()))
+        .manage(commands::ConfigLock::default())
+        .manage(commands::AutosaveState::default())
+        .manage(commands::ReadonlyDocuments::default())
+        .manage(commands::DirtyDocuments::default())
+        .manage(workspace::FolderMtimeCache::default())
+        .manage(workspace::WorkspaceLocks::default())
+        .manage(workspace::ActiveWorkspace::default())
+        .manage(watcher::FileWatchers::default())
+        .manage(progress::Operations::default())
+        .manage(search::SearchIndex::default())
+        .manage(stats::CachedStats::default())
+        .manage(export::RenderCache::default())
         .invoke_handler(tauri::generate_handler![
             // Document commands
             commands::load_settings,
             commands::save_settings,
+            commands::set_markdown_extensions,
             commands::open_file,
+            commands::open_file_or_create,
+            commands::open_file_readonly,
+            commands::mark_dirty,
+            commands::close_file,
+            commands::dirty_summary,
+            commands::preview_file,
+            commands::preview_files,
+            commands::document_is_dirty,
             commands::save_file,
+            commands::save_file_as,
+            commands::detect_line_endings,
+            commands::normalize_content_line_endings,
+            commands::autosave,
+            commands::list_autosave_sidecars,
+            commands::apply_retention,
+            commands::prune_backups,
+            commands::append_to_file,
             commands::new_document,
             commands::get_recent_files,
+            commands::get_recent_files_for_workspace,
+            commands::get_recent_files_partitioned,
             commands::add_recent_file,
+            commands::remove_recent_file,
+            commands::relocate_recent_file,
+            commands::clear_recent_files,
             commands::get_app_version,
+            commands::diagnostics,
+            commands::verify_app_data,
+            commands::export_app_state,
+            commands::import_app_state,
+            commands::path_exists,
+            commands::is_markdown_file,
+            commands::file_name,
+            commands::parent_dir,
+            commands::relativize_path,
+            commands::open_external,
+            commands::get_app_data_dir,
+            commands::get_paths,
             commands::save_session,
             commands::load_session,
+            commands::save_layout,
+            commands::load_layout,
+            commands::get_preference,
+            commands::set_preference,
             commands::save_image_asset,
             // Workspace commands
             workspace::get_workspace_info,
             workspace::get_current_workspace,
             workspace::set_workspace,
+            workspace::list_workspace_templates,
+            workspace::create_workspace_from_template,
             workspace::get_recent_workspaces,
+            workspace::set_last_opened,
+            workspace::pin_workspace,
+            workspace::unpin_workspace,
+            workspace::set_workspace_name,
             workspace::list_workspace_files,
+            workspace::benchmark_listing,
+            workspace::list_workspace_files_flat,
+            workspace::open_most_recent,
+            workspace::clear_workspace_metadata,
+            workspace::find_case_conflicts,
+            workspace::stream_workspace_files,
             workspace::list_env_files,
+            workspace::generate_index_note,
+            workspace::workspace_disk_usage,
+            workspace::lint_workspace,
+            workspace::validate_frontmatter,
             workspace::create_file,
+            workspace::check_create_conflict,
+            workspace::create_files,
+            workspace::import_text_files,
+            workspace::create_and_open_file,
+            workspace::move_file_to_workspace,
+            workspace::add_tag_to_files,
+            workspace::apply_frontmatter_template,
+            workspace::remove_tag_from_files,
+            workspace::rename_tag,
+            workspace::workspace_tasks,
+            workspace::toggle_task,
+            workspace::resolve_link,
+            workspace::convert_links,
+            workspace::is_in_workspace,
+            history::file_history,
+            history::file_blame,
+            workspace::repair_workspace_links,
+            workspace::read_folder_as_document,
             workspace::create_folder,
+            workspace::create_folder_detailed,
+            workspace::quick_capture,
             workspace::delete_file,
+            workspace::delete_files,
+            trash_index::list_trashed,
+            trash_index::restore_trashed,
             workspace::rename_file,
+            file_ids::get_or_assign_file_id,
+            file_ids::get_file_data,
+            file_ids::set_file_data,
+            workspace::flatten_folder,
+            workspace::set_folder_order,
+            workspace::get_file_metadata,
+            workspace::set_file_readonly,
+            workspace::touch_file,
+            workspace::group_files_by_recency,
+            // Theme commands
+            theme::get_system_theme,
+            theme::list_themes,
+            theme::get_theme,
+            theme::resolve_theme,
+            // Markdown commands
+            markdown::document_outline,
+            markdown::extract_tasks,
+            markdown::heading_anchors,
+            markdown::lint_markdown,
+            markdown::strip_frontmatter,
+            markdown::slugify,
+            markdown::word_frequency,
+            markdown::word_count_delta,
+            markdown::shift_headings,
+            markdown::render_diagnostics,
+            markdown::source_map,
+            markdown::readability,
+            watcher::watch_file,
+            watcher::unwatch_file,
+            search::search_workspace,
+            search::find_in_content,
+            search::search_summary,
+            search::search_index,
+            search::rebuild_index,
+            stats::workspace_stats,
+            stats::get_cached_stats,
+            export::export_selection,
+            export::export_linked_cluster,
+            export::render_markdown_cached,
+            progress::cancel_operation,
             // Claude commands
             claude::claude_prompt,
             claude::claude_cancel,
@@ -197,6 +350,11 @@ pub fn run() {
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|_app_handle, _event| {
+            // Let other instances open the workspace without a warning
+            if let tauri::RunEvent::Exit = &_event {
+                _app_handle.state::<workspace::WorkspaceLocks>().release();
+            }
+
             // macOS / iOS deliver file-open requests (Finder double-click,
             // Dock drag, `open foo.md`) through `RunEvent::Opened` instead
             // of argv. The variant is cfg-gated by Tauri itself, so the
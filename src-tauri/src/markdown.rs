@@ -0,0 +1,1864 @@
+// Markdown analysis module for Miku
+// Pure helpers that work on document content rather than files on disk
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::commands::{markdown_options_or_saved, MikuError};
+
+/// Markdown syntax extensions recognized on top of CommonMark. Every
+/// command that parses markdown uses the same set so that, say, tables are
+/// treated alike in the outline and in exports.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct MarkdownOptions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub tasklists: bool,
+    pub strikethrough: bool,
+    /// `[[Page]]` style links
+    pub wikilinks: bool,
+    /// Raw HTML is passed through to the output; when off it's shown as text
+    pub html: bool,
+}
+
+impl Default for MarkdownOptions {
+    /// GitHub-flavored markdown
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            tasklists: true,
+            strikethrough: true,
+            wikilinks: false,
+            html: true,
+        }
+    }
+}
+
+impl MarkdownOptions {
+    pub(crate) fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_WIKILINKS, self.wikilinks);
+        options
+    }
+}
+
+/// A heading found in a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    /// 1-based line number
+    pub line: usize,
+}
+
+/// A heading in the nested document outline
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OutlineNode {
+    pub text: String,
+    pub slug: String,
+    pub level: u8,
+    pub line: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+/// Tracks whether we're inside a fenced code block while walking lines
+#[derive(Default)]
+pub(crate) struct FenceTracker {
+    open: Option<(char, usize)>,
+}
+
+impl FenceTracker {
+    /// Feed the next line. Returns true when the line is part of a fenced
+    /// code block, including the opening and closing fence lines.
+    pub(crate) fn is_code(&mut self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let fence = trimmed
+            .chars()
+            .next()
+            .filter(|c| indent <= 3 && (*c == '`' || *c == '~'))
+            .map(|c| (c, trimmed.chars().take_while(|x| *x == c).count()))
+            .filter(|(_, len)| *len >= 3);
+
+        match (self.open, fence) {
+            (None, Some(fence)) => {
+                self.open = Some(fence);
+                true
+            }
+            (Some((open_char, open_len)), Some((c, len)))
+                if c == open_char && len >= open_len && trimmed[len..].trim().is_empty() =>
+            {
+                self.open = None;
+                true
+            }
+            (Some(_), _) => true,
+            (None, None) => false,
+        }
+    }
+}
+
+/// Byte offsets of a leading `---`-delimited YAML block: the YAML itself
+/// (without delimiters) and where the body starts after the closing line
+fn frontmatter_bounds(content: &str) -> Option<(std::ops::Range<usize>, usize)> {
+    let first_line_end = content.find('\n')?;
+    if content[..first_line_end].trim_end() != "---" {
+        return None;
+    }
+
+    let yaml_start = first_line_end + 1;
+    let mut offset = yaml_start;
+    for line in content[yaml_start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some((yaml_start..offset, offset + line.len()));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Split a leading `---`-delimited YAML block from the document. Returns the
+/// YAML (without delimiters) and the remaining body, or `None` when the
+/// document has no frontmatter.
+pub(crate) fn split_frontmatter(content: &str) -> Option<(&str, &str)> {
+    frontmatter_bounds(content).map(|(yaml, body_start)| (&content[yaml], &content[body_start..]))
+}
+
+/// Top-level keys of the frontmatter block, in order, or `None` when the
+/// document has no frontmatter. Nested keys and list items are skipped.
+pub(crate) fn frontmatter_keys(content: &str) -> Option<Vec<String>> {
+    let (yaml, _) = split_frontmatter(content)?;
+    Some(
+        yaml.lines()
+            .filter(|line| !line.starts_with([' ', '\t', '-', '#']))
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, _)| key.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|key| !key.is_empty())
+            .collect(),
+    )
+}
+
+/// The top-level `title:` of the frontmatter, unquoted, when present and
+/// not empty
+pub(crate) fn frontmatter_title(content: &str) -> Option<String> {
+    let (yaml, _) = split_frontmatter(content)?;
+    let value = yaml.lines().find_map(|line| line.strip_prefix("title:"))?.trim();
+    let title = if value.starts_with('"') {
+        serde_json::from_str(value).unwrap_or_else(|_| value.trim_matches('"').to_string())
+    } else {
+        value.trim_matches('\'').to_string()
+    };
+    (!title.trim().is_empty()).then_some(title)
+}
+
+/// The `tags:` entry of a frontmatter block, by line index
+struct TagsEntry {
+    start: usize,
+    end: usize,
+    /// Written as a `- item` list rather than inline `[a, b]`
+    block: bool,
+    tags: Vec<String>,
+}
+
+fn parse_tag(raw: &str) -> Option<String> {
+    let tag = raw
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .trim_start_matches('#');
+    (!tag.is_empty()).then(|| tag.to_string())
+}
+
+fn find_tags_entry(lines: &[&str]) -> Option<TagsEntry> {
+    let start = lines.iter().position(|line| line.starts_with("tags:"))?;
+    let value = lines[start]["tags:".len()..].trim();
+
+    if !value.is_empty() {
+        let inline = value.trim_start_matches('[').trim_end_matches(']');
+        return Some(TagsEntry {
+            start,
+            end: start + 1,
+            block: false,
+            tags: inline.split(',').filter_map(parse_tag).collect(),
+        });
+    }
+
+    let items: Vec<&str> = lines[start + 1..]
+        .iter()
+        .take_while(|line| line.trim_start().starts_with('-'))
+        .copied()
+        .collect();
+    Some(TagsEntry {
+        start,
+        end: start + 1 + items.len(),
+        block: !items.is_empty(),
+        tags: items
+            .iter()
+            .filter_map(|item| parse_tag(&item.trim_start()[1..]))
+            .collect(),
+    })
+}
+
+/// Tags listed under the frontmatter `tags:` key, in either inline
+/// (`tags: [a, b]`) or block list form
+pub(crate) fn frontmatter_tags(content: &str) -> Vec<String> {
+    split_frontmatter(content)
+        .and_then(|(yaml, _)| find_tags_entry(&yaml.lines().collect::<Vec<_>>()))
+        .map(|entry| entry.tags)
+        .unwrap_or_default()
+}
+
+/// Replace the frontmatter `tags:` list, keeping the existing list style and
+/// every other key. A frontmatter block is created when needed; an empty
+/// list removes the key.
+pub(crate) fn set_frontmatter_tags(content: &str, tags: &[String]) -> String {
+    let Some((yaml_range, _)) = frontmatter_bounds(content) else {
+        if tags.is_empty() {
+            return content.to_string();
+        }
+        return format!("---\ntags: [{}]\n---\n{}", tags.join(", "), content);
+    };
+
+    let mut lines: Vec<String> = content[yaml_range.clone()]
+        .lines()
+        .map(str::to_string)
+        .collect();
+    let existing = find_tags_entry(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+    let block = existing.as_ref().is_some_and(|entry| entry.block);
+
+    let rendered: Vec<String> = match (tags.is_empty(), block) {
+        (true, _) => Vec::new(),
+        (false, true) => std::iter::once("tags:".to_string())
+            .chain(tags.iter().map(|tag| format!("  - {}", tag)))
+            .collect(),
+        (false, false) => vec![format!("tags: [{}]", tags.join(", "))],
+    };
+
+    match existing {
+        Some(entry) => {
+            lines.splice(entry.start..entry.end, rendered);
+        }
+        None => lines.extend(rendered),
+    }
+
+    let mut yaml = lines.join("\n");
+    if !yaml.is_empty() {
+        yaml.push('\n');
+    }
+
+    format!(
+        "{}{}{}",
+        &content[..yaml_range.start],
+        yaml,
+        &content[yaml_range.end..]
+    )
+}
+
+/// Add the keys of `template` that the frontmatter doesn't have yet, after
+/// the existing ones, creating the block when absent. Values are written in
+/// JSON form, which YAML reads the same. Returns the new content and the
+/// added keys, or `None` when every key was already there.
+pub(crate) fn merge_frontmatter(
+    content: &str,
+    template: &serde_json::Map<String, serde_json::Value>,
+) -> Option<(String, Vec<String>)> {
+    let existing = frontmatter_keys(content).unwrap_or_default();
+    let added: Vec<&String> = template.keys().filter(|key| !existing.contains(key)).collect();
+    if added.is_empty() {
+        return None;
+    }
+
+    let lines: String = added
+        .iter()
+        .map(|key| {
+            let plain = key.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | ' '))
+                && !key.starts_with([' ', '-']);
+            let key_text = if plain { key.to_string() } else { serde_json::Value::from(key.as_str()).to_string() };
+            format!("{}: {}\n", key_text, template[key.as_str()])
+        })
+        .collect();
+    let merged = match frontmatter_bounds(content) {
+        Some((yaml, _)) => {
+            let separator = if yaml.is_empty() || content[..yaml.end].ends_with('\n') { "" } else { "\n" };
+            format!("{}{}{}{}", &content[..yaml.end], separator, lines, &content[yaml.end..])
+        }
+        None => format!("---\n{}---\n{}", lines, content),
+    };
+    Some((merged, added.into_iter().cloned().collect()))
+}
+
+/// Remove a leading YAML frontmatter block. Documents without frontmatter
+/// are returned unchanged.
+#[tauri::command]
+pub fn strip_frontmatter(content: String) -> String {
+    match split_frontmatter(&content) {
+        Some((_, body)) => body.to_string(),
+        None => content,
+    }
+}
+
+/// Characters allowed inside an inline `#tag`
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '/')
+}
+
+/// Rename inline `#old` hashtags on one line. Only whole tags preceded by
+/// whitespace or opening punctuation count, which leaves URL fragments and
+/// `#older` alone; inline code spans, link destinations like `[x](#old)`
+/// and reference definitions like `[x]: #old` are skipped.
+fn rename_hashtags_in_line(line: &str, old: &str, new: &str) -> String {
+    if is_reference_definition(line) {
+        return line.to_string();
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut in_code = false;
+    let mut dest_depth = 0usize;
+    let mut prev: Option<char> = None;
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '`' && dest_depth == 0 {
+            in_code = !in_code;
+        } else if !in_code && c == '(' && (dest_depth > 0 || prev == Some(']')) {
+            dest_depth += 1;
+        } else if dest_depth > 0 && c == ')' {
+            dest_depth -= 1;
+        }
+
+        let starts_tag = c == '#'
+            && !in_code
+            && dest_depth == 0
+            && prev.map_or(true, |p| {
+                p.is_whitespace() || matches!(p, '(' | '[' | '{' | ',' | ';')
+            });
+        if starts_tag {
+            if let Some(after) = rest[1..].strip_prefix(old) {
+                if !after.chars().next().is_some_and(is_tag_char) {
+                    out.push('#');
+                    out.push_str(new);
+                    prev = new.chars().last();
+                    rest = after;
+                    continue;
+                }
+            }
+        }
+
+        out.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+/// Whether a line is a reference-style link definition (`[id]: target`)
+fn is_reference_definition(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with('[')
+        && !trimmed.starts_with("[^")
+        && trimmed
+            .find("]:")
+            .is_some_and(|end| !trimmed[1..end].contains(']'))
+}
+
+/// Rename a tag in a document, both as inline `#old` hashtags outside code
+/// and in the frontmatter `tags:` list
+pub(crate) fn rename_tag_in_content(content: &str, old: &str, new: &str) -> String {
+    let (prefix, body) = match frontmatter_bounds(content) {
+        Some((_, body_start)) => content.split_at(body_start),
+        None => ("", content),
+    };
+
+    let mut fences = FenceTracker::default();
+    let body: String = body
+        .split_inclusive('\n')
+        .map(|line| {
+            if fences.is_code(line) {
+                line.to_string()
+            } else {
+                rename_hashtags_in_line(line, old, new)
+            }
+        })
+        .collect();
+
+    let mut renamed = format!("{}{}", prefix, body);
+    let tags = frontmatter_tags(&renamed);
+    if tags.iter().any(|t| t == old) {
+        let mut updated: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = if tag == old { new.to_string() } else { tag };
+            if !updated.contains(&tag) {
+                updated.push(tag);
+            }
+        }
+        renamed = set_frontmatter_tags(&renamed, &updated);
+    }
+
+    renamed
+}
+
+/// Strip trailing spaces and tabs from every line outside fenced code
+/// blocks. With `preserve_hard_breaks`, lines ending in exactly two spaces
+/// (a markdown hard line break) are left alone.
+pub(crate) fn trim_trailing_whitespace(content: &str, preserve_hard_breaks: bool) -> String {
+    let mut fences = FenceTracker::default();
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            if fences.is_code(line) {
+                return line.to_string();
+            }
+            let text = line.trim_end_matches(['\n', '\r']);
+            let ending = &line[text.len()..];
+            let kept = text.trim_end_matches([' ', '\t']);
+            let trailing = &text[kept.len()..];
+            if preserve_hard_breaks && trailing == "  " && !kept.is_empty() {
+                line.to_string()
+            } else {
+                format!("{}{}", kept, ending)
+            }
+        })
+        .collect()
+}
+
+/// Collect every heading in the document, with inline formatting reduced
+/// to plain text
+pub fn extract_headings(content: &str, options: &MarkdownOptions) -> Vec<Heading> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut headings = Vec::new();
+    let mut current: Option<Heading> = None;
+
+    for (event, range) in Parser::new_ext(content, options.parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some(Heading {
+                    level: level as u8,
+                    text: String::new(),
+                    line: line_starts.partition_point(|start| *start <= range.start),
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    heading.text = heading.text.trim().to_string();
+                    headings.push(heading);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+/// GitHub-style slug: lowercase, punctuation stripped, whitespace runs
+/// collapsed into single hyphens
+pub fn slugify_text(text: &str) -> String {
+    let cleaned: String = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace() || *c == '-' || *c == '_')
+        .collect();
+
+    cleaned.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+/// Decode `%XX` escapes in a link destination. Malformed escapes are kept
+/// as written, and bytes that aren't valid UTF-8 become U+FFFD.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Slugify a single string for use as an anchor. Matches the slugs used in
+/// document outlines, minus the `-1`, `-2` suffixes for repeated headings.
+#[tauri::command]
+pub fn slugify(text: String) -> String {
+    slugify_text(&text)
+}
+
+/// Assigns slugs to headings, suffixing repeats with `-1`, `-2`, ...
+#[derive(Default)]
+pub(crate) struct SlugDeduper {
+    seen: HashMap<String, usize>,
+}
+
+impl SlugDeduper {
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let base = slugify_text(text);
+        let count = self.seen.entry(base.clone()).or_insert(0);
+        let slug = if *count == 0 {
+            base
+        } else {
+            format!("{}-{}", base, count)
+        };
+        *count += 1;
+        slug
+    }
+}
+
+fn nest_headings(headings: &[(Heading, String)], index: &mut usize, parent_level: u8) -> Vec<OutlineNode> {
+    let mut nodes = Vec::new();
+
+    while let Some((heading, slug)) = headings.get(*index) {
+        if heading.level <= parent_level {
+            break;
+        }
+        *index += 1;
+        let children = nest_headings(headings, index, heading.level);
+        nodes.push(OutlineNode {
+            text: heading.text.clone(),
+            slug: slug.clone(),
+            level: heading.level,
+            line: heading.line,
+            children,
+        });
+    }
+
+    nodes
+}
+
+/// Common English words left out of word frequency results on request
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "because",
+    "been", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have",
+    "he", "her", "his", "how", "i", "if", "in", "into", "is", "it", "its", "it's", "just", "me",
+    "my", "no", "not", "of", "on", "or", "our", "out", "she", "so", "some", "than", "that", "the",
+    "their", "them", "then", "there", "these", "they", "this", "to", "up", "was", "we", "were",
+    "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// A word and how often it appears
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WordCount {
+    pub word: String,
+    pub count: usize,
+}
+
+/// Lowercased words of a line with surrounding punctuation stripped.
+/// Apostrophes inside words are kept so contractions stay whole.
+fn words(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’')
+        .map(|word| word.trim_matches(|c| c == '\'' || c == '’'))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase().replace('’', "'"))
+}
+
+/// Number of words outside frontmatter and fenced code
+pub(crate) fn count_words(content: &str) -> usize {
+    let body = split_frontmatter(content).map_or(content, |(_, body)| body);
+    let mut fences = FenceTracker::default();
+    body.lines()
+        .filter(|line| !fences.is_code(line))
+        .map(|line| words(line).count())
+        .sum()
+}
+
+/// Words written and deleted between two versions of a document
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct WordCountDelta {
+    pub added: usize,
+    pub removed: usize,
+    /// `added - removed`
+    pub net: i64,
+}
+
+/// Words outside frontmatter and fenced code, in order
+fn body_words(content: &str) -> Vec<String> {
+    let body = split_frontmatter(content).map_or(content, |(_, body)| body);
+    let mut fences = FenceTracker::default();
+    body.lines()
+        .filter(|line| !fences.is_code(line))
+        .flat_map(words)
+        .collect()
+}
+
+/// Words added and removed going from `old_content` to `new_content`, from
+/// a word-level diff, so a writing session can add up what was written
+/// without recounting the document. Moving text around shows up as both
+/// added and removed words with no net change.
+#[tauri::command]
+pub fn word_count_delta(old_content: String, new_content: String) -> WordCountDelta {
+    let (old, new) = (body_words(&old_content), body_words(&new_content));
+    let (mut added, mut removed) = (0, 0);
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old, &new) {
+        match op {
+            similar::DiffOp::Equal { .. } => {}
+            similar::DiffOp::Delete { old_len, .. } => removed += old_len,
+            similar::DiffOp::Insert { new_len, .. } => added += new_len,
+            similar::DiffOp::Replace { old_len, new_len, .. } => {
+                removed += old_len;
+                added += new_len;
+            }
+        }
+    }
+    WordCountDelta {
+        added,
+        removed,
+        net: added as i64 - removed as i64,
+    }
+}
+
+/// Level of an ATX heading line (`## Title`) and the byte offset of its
+/// `#` run, or `None` for any other line
+fn atx_heading(line: &str) -> Option<(usize, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    let indent = line.len() - trimmed.len();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let rest = &trimmed[level..];
+    let valid = indent <= 3 && (1..=6).contains(&level) && (rest.trim().is_empty() || rest.starts_with([' ', '\t']));
+    valid.then_some((level, indent))
+}
+
+/// Move every ATX heading `offset` levels deeper (or shallower when
+/// negative), clamped to H1..H6. Frontmatter and fenced code are left as is.
+pub(crate) fn shift_heading_levels(content: &str, offset: i8) -> String {
+    let body_start = frontmatter_bounds(content).map_or(0, |(_, body_start)| body_start);
+    let mut shifted = content[..body_start].to_string();
+    let mut fences = FenceTracker::default();
+
+    for line in content[body_start..].split_inclusive('\n') {
+        let code = fences.is_code(line);
+        match atx_heading(line).filter(|_| !code) {
+            Some((level, indent)) => {
+                let new_level = (level as i64 + i64::from(offset)).clamp(1, 6) as usize;
+                shifted.push_str(&line[..indent]);
+                shifted.push_str(&"#".repeat(new_level));
+                shifted.push_str(&line[indent + level..]);
+            }
+            None => shifted.push_str(line),
+        }
+    }
+    shifted
+}
+
+/// Shift every heading by `offset` levels, e.g. 1 to turn a note's H1 into
+/// an H2 before merging it into a larger document. Levels are clamped to
+/// 1..=6 and code fences are skipped.
+#[tauri::command]
+pub fn shift_headings(content: String, offset: i8) -> String {
+    shift_heading_levels(&content, offset)
+}
+
+/// The `top_n` most used words outside of code fences, most frequent first.
+/// Ties are broken alphabetically.
+#[tauri::command]
+pub fn word_frequency(content: String, top_n: usize, stopwords: bool) -> Vec<WordCount> {
+    let mut fences = FenceTracker::default();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for line in content.lines().filter(|line| !fences.is_code(line)) {
+        for word in words(line) {
+            if stopwords && STOPWORDS.contains(&word.as_str()) {
+                continue;
+            }
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    let mut result: Vec<WordCount> = counts
+        .into_iter()
+        .map(|(word, count)| WordCount { word, count })
+        .collect();
+    result.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+    result.truncate(top_n);
+    result
+}
+
+/// Abbreviations whose trailing period doesn't end a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "inc", "ltd",
+    "co", "no", "fig", "approx", "cf", "a.m", "p.m",
+];
+
+/// Readability statistics for a document's prose
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Readability {
+    pub sentences: usize,
+    pub paragraphs: usize,
+    pub words: usize,
+    pub syllables: usize,
+    pub flesch_reading_ease: f64,
+    pub flesch_kincaid_grade: f64,
+    pub avg_sentence_length: f64,
+}
+
+/// Estimate syllables by counting vowel groups, ignoring a silent final `e`
+fn count_syllables(word: &str) -> usize {
+    let chars: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    if chars.is_empty() {
+        return 0;
+    }
+
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for &c in &chars {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+
+    // "make" has one syllable, but "table" keeps its "le"
+    let len = chars.len();
+    if count > 1
+        && chars[len - 1] == 'e'
+        && !(len > 2 && chars[len - 2] == 'l' && !is_vowel(chars[len - 3]))
+    {
+        count -= 1;
+    }
+
+    count.max(1)
+}
+
+/// Whether a whitespace-separated token ends a sentence
+fn ends_sentence(token: &str) -> bool {
+    let token = token.trim_end_matches(['"', '\'', ')', '*', '_', '”', '’']);
+    if !token.ends_with(['.', '!', '?']) {
+        return false;
+    }
+    if token.ends_with(['!', '?']) {
+        return true;
+    }
+
+    let stem = token
+        .trim_start_matches(|c: char| !c.is_alphanumeric())
+        .trim_end_matches('.')
+        .to_lowercase();
+    // Single-letter initials like "J. R. R."
+    let is_initial = stem.chars().count() == 1 && stem.chars().all(char::is_alphabetic);
+    !is_initial && !ABBREVIATIONS.contains(&stem.as_str())
+}
+
+/// Sentence, word and syllable counts plus Flesch scores for the document.
+/// Frontmatter and fenced code are ignored; a paragraph break also ends a
+/// sentence so headings and list items without punctuation still count.
+#[tauri::command]
+pub fn readability(content: String) -> Readability {
+    let body = split_frontmatter(&content).map_or(content.as_str(), |(_, body)| body);
+    let mut fences = FenceTracker::default();
+
+    let mut sentences = 0;
+    let mut paragraphs = 0;
+    let mut words_total = 0;
+    let mut syllables = 0;
+    let mut sentence_open = false;
+    let mut paragraph_open = false;
+
+    for line in body.lines() {
+        if fences.is_code(line) || line.trim().is_empty() {
+            if sentence_open {
+                sentences += 1;
+                sentence_open = false;
+            }
+            paragraph_open = false;
+            continue;
+        }
+
+        for token in line.split_whitespace() {
+            let token_words: Vec<String> = words(token).collect();
+            if !token_words.is_empty() {
+                words_total += token_words.len();
+                syllables += token_words
+                    .iter()
+                    .map(|w| count_syllables(w))
+                    .sum::<usize>();
+                sentence_open = true;
+                if !paragraph_open {
+                    paragraphs += 1;
+                    paragraph_open = true;
+                }
+            }
+            if sentence_open && ends_sentence(token) {
+                sentences += 1;
+                sentence_open = false;
+            }
+        }
+
+        // Headings and list items stand on their own
+        let trimmed = line.trim_start();
+        if sentence_open && (trimmed.starts_with('#') || trimmed.starts_with(['-', '*', '+'])) {
+            sentences += 1;
+            sentence_open = false;
+        }
+    }
+    if sentence_open {
+        sentences += 1;
+    }
+
+    let (avg_sentence_length, flesch_reading_ease, flesch_kincaid_grade) =
+        if sentences == 0 || words_total == 0 {
+            (0.0, 0.0, 0.0)
+        } else {
+            let words_per_sentence = words_total as f64 / sentences as f64;
+            let syllables_per_word = syllables as f64 / words_total as f64;
+            (
+                words_per_sentence,
+                206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word,
+                0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59,
+            )
+        };
+
+    Readability {
+        sentences,
+        paragraphs,
+        words: words_total,
+        syllables,
+        flesch_reading_ease,
+        flesch_kincaid_grade,
+        avg_sentence_length,
+    }
+}
+
+/// Build a nested outline of the document's headings. Headings that skip
+/// levels nest under the closest shallower heading.
+pub(crate) fn outline(content: &str, options: &MarkdownOptions) -> Vec<OutlineNode> {
+    let mut slugs = SlugDeduper::default();
+    let headings: Vec<(Heading, String)> = extract_headings(content, options)
+        .into_iter()
+        .map(|heading| {
+            let slug = slugs.slug(&heading.text);
+            (heading, slug)
+        })
+        .collect();
+
+    nest_headings(&headings, &mut 0, 0)
+}
+
+/// Nested outline of the document's headings. `options` defaults to the
+/// markdown extensions in the saved settings.
+#[tauri::command]
+pub async fn document_outline(
+    content: String,
+    options: Option<MarkdownOptions>,
+) -> Vec<OutlineNode> {
+    outline(&content, &markdown_options_or_saved(options).await)
+}
+
+/// Anchor slugs of every heading, with the same `-1`, `-2` suffixes for
+/// repeated headings as the outline
+pub(crate) fn heading_slugs(content: &str, options: &MarkdownOptions) -> BTreeSet<String> {
+    let mut slugs = SlugDeduper::default();
+    extract_headings(content, options)
+        .iter()
+        .map(|heading| slugs.slug(&heading.text))
+        .collect()
+}
+
+pub(crate) async fn heading_anchors_in(path: &str, options: &MarkdownOptions) -> Result<BTreeSet<String>, MikuError> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| MikuError::from_io(e, path))?;
+    Ok(heading_slugs(&content, options))
+}
+
+/// Anchors a `file.md#fragment` link into the file at `path` can target.
+/// `options` defaults to the markdown extensions in the saved settings.
+#[tauri::command]
+pub async fn heading_anchors(
+    path: String,
+    options: Option<MarkdownOptions>,
+) -> Result<BTreeSet<String>, MikuError> {
+    heading_anchors_in(&path, &markdown_options_or_saved(options).await).await
+}
+
+/// A link found in a document
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DocumentLink {
+    /// Byte range of the whole link in the source, brackets included
+    pub range: std::ops::Range<usize>,
+    /// Where the link points: the destination of a markdown link, or a
+    /// wikilink in its `[[target]]` form
+    pub target: String,
+    /// The link text as written, formatting included
+    pub text: String,
+}
+
+/// Every link in `content`, in document order. Wikilinks are recognized
+/// whether or not `options` enables them.
+pub(crate) fn document_links(content: &str, options: &MarkdownOptions) -> Vec<DocumentLink> {
+    let options = MarkdownOptions {
+        wikilinks: true,
+        ..*options
+    };
+    let mut links = Vec::new();
+    // Link being read, with the span of its text so far
+    let mut open: Option<(DocumentLink, Option<std::ops::Range<usize>>)> = None;
+
+    for (event, range) in Parser::new_ext(content, options.parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link { link_type, dest_url, .. }) => {
+                let target = match link_type {
+                    LinkType::WikiLink { .. } => format!("[[{}]]", dest_url),
+                    _ => dest_url.to_string(),
+                };
+                let link = DocumentLink {
+                    range,
+                    target,
+                    text: String::new(),
+                };
+                open = Some((link, None));
+            }
+            Event::End(TagEnd::Link) => {
+                if let Some((mut link, text)) = open.take() {
+                    link.text = text.map(|span| content[span].to_string()).unwrap_or_default();
+                    links.push(link);
+                }
+            }
+            _ => {
+                if let Some((_, text)) = open.as_mut() {
+                    *text = Some(match text.take() {
+                        Some(span) => span.start.min(range.start)..span.end.max(range.end),
+                        None => range,
+                    });
+                }
+            }
+        }
+    }
+    links
+}
+
+/// The `[label]: destination` reference definitions in `content`, in
+/// document order. `range` spans the whole definition and `text` holds the
+/// label.
+pub(crate) fn reference_definitions(content: &str, options: &MarkdownOptions) -> Vec<DocumentLink> {
+    let parser = Parser::new_ext(content, options.parser_options());
+    let mut definitions: Vec<DocumentLink> = parser
+        .reference_definitions()
+        .iter()
+        .map(|(label, definition)| DocumentLink {
+            range: definition.span.clone(),
+            target: definition.dest.to_string(),
+            text: label.to_string(),
+        })
+        .collect();
+    definitions.sort_by_key(|definition| definition.range.start);
+    definitions
+}
+
+/// A check `lint_markdown` can run
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LintRule {
+    /// Spaces or tabs ending a line outside fenced code, except two-space
+    /// hard line breaks
+    TrailingWhitespace,
+    /// A heading more than one level deeper than the heading before it
+    HeadingIncrement,
+    /// Headings with the same text, whose anchors then need suffixes
+    DuplicateHeading,
+    /// A fenced code block that runs to the end of the document
+    UnclosedFence,
+}
+
+impl LintRule {
+    pub const ALL: &'static [LintRule] = &[
+        LintRule::TrailingWhitespace,
+        LintRule::HeadingIncrement,
+        LintRule::DuplicateHeading,
+        LintRule::UnclosedFence,
+    ];
+}
+
+/// A problem `lint_markdown` found
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LintFinding {
+    pub rule: LintRule,
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+/// Run the enabled `rules` over a document, returning findings by line
+pub(crate) fn lint_content(content: &str, rules: &[LintRule], options: &MarkdownOptions) -> Vec<LintFinding> {
+    let enabled = |rule: LintRule| rules.contains(&rule);
+    let mut findings = Vec::new();
+    let mut push = |rule: LintRule, line: usize, message: String| {
+        if enabled(rule) {
+            findings.push(LintFinding { rule, line, message });
+        }
+    };
+
+    let mut fences = FenceTracker::default();
+    let mut open_fence_line = None;
+    for (index, line) in content.lines().enumerate() {
+        let was_open = fences.open.is_some();
+        if fences.is_code(line) {
+            if !was_open {
+                open_fence_line = Some(index + 1);
+            }
+            continue;
+        }
+        let kept = line.trim_end_matches([' ', '\t']);
+        let trailing = &line[kept.len()..];
+        if !trailing.is_empty() && (trailing != "  " || kept.is_empty()) {
+            push(LintRule::TrailingWhitespace, index + 1, "Trailing whitespace".to_string());
+        }
+    }
+    if let (Some(_), Some(line)) = (fences.open, open_fence_line) {
+        push(LintRule::UnclosedFence, line, "Code block is never closed".to_string());
+    }
+
+    let mut previous_level = None;
+    let mut first_use: HashMap<String, usize> = HashMap::new();
+    for heading in extract_headings(content, options) {
+        if let Some(previous) = previous_level.filter(|previous| heading.level > previous + 1) {
+            let message = format!("Heading level {} follows level {}", heading.level, previous);
+            push(LintRule::HeadingIncrement, heading.line, message);
+        }
+        previous_level = Some(heading.level);
+
+        match first_use.get(&slugify_text(&heading.text)) {
+            Some(first) => {
+                let message = format!("Duplicate heading \"{}\", first used on line {}", heading.text, first);
+                push(LintRule::DuplicateHeading, heading.line, message);
+            }
+            None => {
+                first_use.insert(slugify_text(&heading.text), heading.line);
+            }
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.line);
+    findings
+}
+
+/// Check a document for common markdown problems. `rules` defaults to all
+/// of them and `options` to the markdown extensions in the saved settings.
+#[tauri::command]
+pub async fn lint_markdown(
+    content: String,
+    rules: Option<Vec<LintRule>>,
+    options: Option<MarkdownOptions>,
+) -> Vec<LintFinding> {
+    let rules = rules.unwrap_or_else(|| LintRule::ALL.to_vec());
+    lint_content(&content, &rules, &markdown_options_or_saved(options).await)
+}
+
+/// Something that may render differently than it reads in the editor
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// HTML written into the document while HTML is disabled, so it shows
+    /// up as text
+    RawHtml,
+    /// `**` or `__` without a partner, shown literally
+    UnbalancedEmphasis,
+    /// A table that renders as plain text or drops cells
+    MalformedTable,
+    /// A fenced code block that swallows the rest of the document
+    UnclosedFence,
+}
+
+/// A rendering problem `render_diagnostics` found
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RenderDiagnostic {
+    /// 1-based line number
+    pub line: usize,
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+/// Cells of a table row, ignoring the optional outer pipes and escaped `\|`
+fn table_cells(line: &str) -> Vec<&str> {
+    let row = line.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = row.strip_suffix('|').filter(|_| !row.ends_with("\\|")).unwrap_or(row);
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in row.char_indices() {
+        match c {
+            '|' if !escaped => {
+                cells.push(&row[start..i]);
+                start = i + 1;
+            }
+            _ => escaped = c == '\\' && !escaped,
+        }
+    }
+    cells.push(&row[start..]);
+    cells
+}
+
+/// A table delimiter row like `| --- | :-: |`
+fn is_delimiter_row(line: &str) -> bool {
+    line.contains('|')
+        && table_cells(line).iter().all(|cell| {
+            let cell = cell.trim();
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn table_diagnostics(content: &str, diagnostics: &mut Vec<RenderDiagnostic>) {
+    let mut fences = FenceTracker::default();
+    let lines: Vec<(&str, bool)> = content.lines().map(|line| (line, fences.is_code(line))).collect();
+
+    let mut index = 1;
+    while index < lines.len() {
+        let ((header, header_code), (delimiter, delimiter_code)) = (lines[index - 1], lines[index]);
+        if header_code || delimiter_code || !header.contains('|') || !is_delimiter_row(delimiter) {
+            index += 1;
+            continue;
+        }
+
+        let columns = table_cells(header).len();
+        let delimiter_columns = table_cells(delimiter).len();
+        if columns != delimiter_columns {
+            diagnostics.push(RenderDiagnostic {
+                line: index + 1,
+                kind: DiagnosticKind::MalformedTable,
+                message: format!(
+                    "Table header has {} cells but its delimiter row has {}, so it renders as plain text",
+                    columns, delimiter_columns
+                ),
+            });
+            index += 1;
+            continue;
+        }
+
+        index += 1;
+        while let Some((row, false)) = lines.get(index).copied().filter(|(row, _)| !row.trim().is_empty()) {
+            let cells = table_cells(row).len();
+            if cells > columns {
+                diagnostics.push(RenderDiagnostic {
+                    line: index + 1,
+                    kind: DiagnosticKind::MalformedTable,
+                    message: format!(
+                        "Row has {} cells but the table has {}; the extra cells are dropped",
+                        cells, columns
+                    ),
+                });
+            }
+            index += 1;
+        }
+    }
+}
+
+/// Whether a run of text has an emphasis marker the parser left over.
+/// `__` between two word characters, as in `snake__case`, can't open or
+/// close emphasis and reads the same either way, so it isn't counted.
+fn has_stray_emphasis(text: &str) -> bool {
+    if text.contains("**") {
+        return true;
+    }
+    let word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    text.match_indices("__").any(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i..].trim_start_matches('_').chars().next();
+        !(word(before) && word(after))
+    })
+}
+
+/// Find markup that is likely to render oddly: raw HTML when HTML is
+/// disabled, unmatched `**` / `__`, malformed tables and unclosed code
+/// fences. Unlike the linter this is about the rendered output rather than
+/// style.
+pub(crate) fn render_diagnostics_in(content: &str, options: &MarkdownOptions) -> Vec<RenderDiagnostic> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |offset: usize| line_starts.partition_point(|start| *start <= offset);
+    let mut diagnostics = Vec::new();
+    let mut push = |line: usize, kind: DiagnosticKind, message: &str| {
+        if !diagnostics
+            .iter()
+            .any(|d: &RenderDiagnostic| d.line == line && d.kind == kind)
+        {
+            diagnostics.push(RenderDiagnostic {
+                line,
+                kind,
+                message: message.to_string(),
+            });
+        }
+    };
+
+    let mut in_code = false;
+    // The parser can split a leftover `**` across text events, so check
+    // each run of adjacent text as a whole
+    let mut text_run = (0..0, String::new());
+    for (event, range) in Parser::new_ext(content, options.parser_options()).into_offset_iter() {
+        if let Event::Text(text) = &event {
+            if !in_code {
+                if text_run.0.end != range.start {
+                    text_run = (range.clone(), String::new());
+                }
+                text_run.0.end = range.end;
+                text_run.1.push_str(text);
+                if has_stray_emphasis(&text_run.1) {
+                    push(
+                        line_of(text_run.0.start),
+                        DiagnosticKind::UnbalancedEmphasis,
+                        "Unmatched emphasis markers are shown literally",
+                    );
+                }
+            }
+        }
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Html(html) | Event::InlineHtml(html) if !options.html && !html.trim_start().starts_with("<!--") => {
+                push(
+                    line_of(range.start),
+                    DiagnosticKind::RawHtml,
+                    "HTML is disabled, so this markup is shown as text",
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if options.tables {
+        table_diagnostics(content, &mut diagnostics);
+    }
+
+    let mut fences = FenceTracker::default();
+    let mut open_fence_line = None;
+    for (index, line) in content.lines().enumerate() {
+        let was_open = fences.open.is_some();
+        if fences.is_code(line) && !was_open {
+            open_fence_line = Some(index + 1);
+        }
+    }
+    if let (Some(_), Some(line)) = (fences.open, open_fence_line) {
+        diagnostics.push(RenderDiagnostic {
+            line,
+            kind: DiagnosticKind::UnclosedFence,
+            message: "Code block is never closed, so everything after it renders as code".to_string(),
+        });
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    diagnostics
+}
+
+/// Warn about markup that may render differently than intended, e.g. before
+/// exporting. `options` defaults to the markdown extensions in the saved
+/// settings.
+#[tauri::command]
+pub async fn render_diagnostics(content: String, options: Option<MarkdownOptions>) -> Vec<RenderDiagnostic> {
+    render_diagnostics_in(&content, &markdown_options_or_saved(options).await)
+}
+
+/// Where a top-level rendered block starts in the source
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    /// 1-based line number
+    pub source_line: usize,
+    /// Position among the top-level blocks of the rendered HTML
+    pub block_index: usize,
+}
+
+/// The source line of every top-level block (heading, paragraph, list,
+/// table, rule, ...) in rendering order. Frontmatter isn't rendered, so it
+/// isn't a block.
+pub(crate) fn source_map_in(content: &str, options: &MarkdownOptions) -> Vec<SourceMapEntry> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let body_start = frontmatter_bounds(content).map_or(0, |(_, body_start)| body_start);
+    let mut entries: Vec<SourceMapEntry> = Vec::new();
+    let mut depth = 0usize;
+
+    for (event, range) in Parser::new_ext(&content[body_start..], options.parser_options()).into_offset_iter() {
+        let starts_block = match event {
+            Event::Start(_) => {
+                depth += 1;
+                depth == 1
+            }
+            Event::End(_) => {
+                depth -= 1;
+                false
+            }
+            _ => depth == 0,
+        };
+        if starts_block {
+            entries.push(SourceMapEntry {
+                source_line: line_starts.partition_point(|start| *start <= body_start + range.start),
+                block_index: entries.len(),
+            });
+        }
+    }
+    entries
+}
+
+/// Map each top-level block of the rendered preview to the editor line it
+/// starts on, for synchronized scrolling. `options` defaults to the markdown
+/// extensions in the saved settings.
+#[tauri::command]
+pub async fn source_map(content: String, options: Option<MarkdownOptions>) -> Vec<SourceMapEntry> {
+    source_map_in(&content, &markdown_options_or_saved(options).await)
+}
+
+/// A GFM task-list item (`- [ ] text` / `- [x] text`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Task {
+    /// 1-based line number
+    pub line: usize,
+    pub checked: bool,
+    pub text: String,
+    /// 0 for top-level tasks, 1 for tasks nested one list deeper, ...
+    pub indent_level: usize,
+}
+
+/// A task item found on a single line
+pub(crate) struct TaskLine<'a> {
+    /// Leading whitespace width, with tabs counted as four columns
+    pub(crate) indent: usize,
+    pub(crate) checked: bool,
+    /// Byte offset of the state character between the brackets
+    pub(crate) state_offset: usize,
+    pub(crate) text: &'a str,
+}
+
+/// Parse a list item line whose content starts with a `[ ]` / `[x]` box
+pub(crate) fn parse_task_line(line: &str) -> Option<TaskLine<'_>> {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let indent = line[..line.len() - rest.len()]
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+
+    let marker_len = match rest.chars().next()? {
+        '-' | '*' | '+' => 1,
+        c if c.is_ascii_digit() => {
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            match rest[digits..].chars().next() {
+                Some('.') | Some(')') if digits <= 9 => digits + 1,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let after_marker = &rest[marker_len..];
+    let content = after_marker.trim_start_matches([' ', '\t']);
+    if content.len() == after_marker.len() {
+        return None;
+    }
+
+    let bytes = content.as_bytes();
+    if bytes.len() < 3 || bytes[0] != b'[' || bytes[2] != b']' {
+        return None;
+    }
+    let checked = match bytes[1] {
+        b' ' => false,
+        b'x' | b'X' => true,
+        _ => return None,
+    };
+    let text = &content[3..];
+    if !text.is_empty() && !text.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    Some(TaskLine {
+        indent,
+        checked,
+        state_offset: line.len() - content.len() + 1,
+        text: text.trim(),
+    })
+}
+
+/// List every task item outside of fenced code blocks, in document order
+#[tauri::command]
+pub fn extract_tasks(content: String) -> Vec<Task> {
+    let mut fences = FenceTracker::default();
+    // Indents of the enclosing tasks, innermost last
+    let mut parents: Vec<usize> = Vec::new();
+    let mut tasks = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if fences.is_code(line) {
+            continue;
+        }
+        let Some(task) = parse_task_line(line) else {
+            continue;
+        };
+
+        while parents.last().is_some_and(|indent| *indent >= task.indent) {
+            parents.pop();
+        }
+        tasks.push(Task {
+            line: index + 1,
+            checked: task.checked,
+            text: task.text.to_string(),
+            indent_level: parents.len(),
+        });
+        parents.push(task.indent);
+    }
+
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_headings_skips_code_fences() {
+        let content = "# Title\n```\n# not a heading\n```\n## Section ##\n#hashtag";
+        let headings = extract_headings(content, &MarkdownOptions::default());
+        assert_eq!(headings.len(), 2);
+        assert_eq!(headings[0], Heading { level: 1, text: "Title".to_string(), line: 1 });
+        assert_eq!(headings[1], Heading { level: 2, text: "Section".to_string(), line: 5 });
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!".to_string()), "hello-world");
+        assert_eq!(slugify("  Many   spaces\there ".to_string()), "many-spaces-here");
+        assert_eq!(slugify("Ünïcode Café 日本語".to_string()), "ünïcode-café-日本語");
+        assert_eq!(slugify("keep-hyphens and_underscores".to_string()), "keep-hyphens-and_underscores");
+        assert_eq!(slugify("What's (new)?".to_string()), "whats-new");
+    }
+
+    #[test]
+    fn test_render_diagnostics() {
+        let content = "# Notes\n\n| A | B |\n| --- |\n| 1 | 2 |\n\n| A | B |\n|---|---|\n| 1 | 2 | 3 |\n\n<div>box</div>\n\nSome **bold text\n\n```rust\nfn main() {}\n";
+        let no_html = MarkdownOptions {
+            html: false,
+            ..MarkdownOptions::default()
+        };
+        let diagnostics = render_diagnostics_in(content, &no_html);
+        let found: Vec<(usize, DiagnosticKind)> = diagnostics.iter().map(|d| (d.line, d.kind)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (4, DiagnosticKind::MalformedTable),
+                (9, DiagnosticKind::MalformedTable),
+                (11, DiagnosticKind::RawHtml),
+                (13, DiagnosticKind::UnbalancedEmphasis),
+                (15, DiagnosticKind::UnclosedFence),
+            ]
+        );
+
+        let clean = "| A | B |\n|---|:-:|\n| a \\| b | c |\n\n**bold** and <!-- note -->\n\n```\n**not emphasis**\n```\n";
+        assert!(render_diagnostics_in(clean, &no_html).is_empty());
+
+        // HTML is only a problem when it's disabled; `__` inside a word
+        // never forms emphasis
+        let html = "<div>box</div>\n\nCall snake__case__name or __init__ and a__b.\n";
+        assert!(render_diagnostics_in(html, &MarkdownOptions::default()).is_empty());
+        let stray = render_diagnostics_in("A stray __ marker\n", &MarkdownOptions::default());
+        assert_eq!(stray[0].kind, DiagnosticKind::UnbalancedEmphasis);
+    }
+
+    #[test]
+    fn test_shift_headings() {
+        let content = "---\n# yaml comment\n---\n# Title\n\n## Part\n\n###### Deepest\n\n```\n# code\n```\n#hashtag\n";
+        let down = shift_headings(content.to_string(), 1);
+        assert_eq!(
+            down,
+            "---\n# yaml comment\n---\n## Title\n\n### Part\n\n###### Deepest\n\n```\n# code\n```\n#hashtag\n"
+        );
+
+        let up = shift_headings(down, -1);
+        assert_eq!(
+            up,
+            "---\n# yaml comment\n---\n# Title\n\n## Part\n\n##### Deepest\n\n```\n# code\n```\n#hashtag\n"
+        );
+        assert_eq!(shift_headings("# Top\r\n".to_string(), -3), "# Top\r\n");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let content = "\n# Title\n\nFirst paragraph\nstill first.\n\n- one\n- two\n\n---\n\n```\ncode\n```\n";
+        let map = source_map_in(content, &MarkdownOptions::default());
+        let lines: Vec<usize> = map.iter().map(|entry| entry.source_line).collect();
+        assert_eq!(lines, vec![2, 4, 7, 10, 12]);
+        assert!(map.iter().enumerate().all(|(index, entry)| entry.block_index == index));
+
+        // Frontmatter isn't a rule and a setext heading; the title is block 0
+        let with_frontmatter = "---\ntitle: x\n---\n# Title\n\nBody\n";
+        let map = source_map_in(with_frontmatter, &MarkdownOptions::default());
+        assert_eq!(
+            map,
+            vec![
+                SourceMapEntry { source_line: 4, block_index: 0 },
+                SourceMapEntry { source_line: 6, block_index: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_word_count_delta() {
+        let delta = |old: &str, new: &str| word_count_delta(old.to_string(), new.to_string());
+        let base = "The quick brown fox.\n";
+
+        let added = delta(base, "The quick brown fox jumps high.\n");
+        assert_eq!(added, WordCountDelta { added: 2, removed: 0, net: 2 });
+
+        let removed = delta(base, "The fox.\n");
+        assert_eq!(removed, WordCountDelta { added: 0, removed: 2, net: -2 });
+
+        // Moving a sentence rewrites words without changing the total
+        let moved = delta("One two three.\n\nFour five.\n", "Four five.\n\nOne two three.\n");
+        assert_eq!(moved.net, 0);
+        assert_eq!(moved.added, moved.removed);
+        assert!(moved.added > 0);
+
+        // Code and frontmatter don't count
+        assert_eq!(delta(base, "---\ntitle: x\n---\nThe quick brown fox.\n```\nlet y;\n```\n").net, 0);
+    }
+
+    #[test]
+    fn test_word_frequency_counts() {
+        let content = "Rust is fast. Rust, rust! Is it? Don't panic.";
+        let counts = word_frequency(content.to_string(), 3, false);
+        assert_eq!(
+            counts,
+            vec![
+                WordCount {
+                    word: "rust".to_string(),
+                    count: 3
+                },
+                WordCount {
+                    word: "is".to_string(),
+                    count: 2
+                },
+                WordCount {
+                    word: "don't".to_string(),
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_word_frequency_stopwords() {
+        let content = "The cat and the hat. The end.";
+        let counts = word_frequency(content.to_string(), 10, true);
+        let words: Vec<&str> = counts.iter().map(|c| c.word.as_str()).collect();
+        assert_eq!(words, vec!["cat", "end", "hat"]);
+    }
+
+    #[test]
+    fn test_word_frequency_skips_code_fences() {
+        let content = "prose here\n```rust\nlet code = code + code;\n```\nprose";
+        let counts = word_frequency(content.to_string(), 10, false);
+        assert_eq!(
+            counts[0],
+            WordCount {
+                word: "prose".to_string(),
+                count: 2
+            }
+        );
+        assert!(counts.iter().all(|c| c.word != "code" && c.word != "rust"));
+    }
+
+    #[test]
+    fn test_count_syllables() {
+        assert_eq!(count_syllables("cat"), 1);
+        assert_eq!(count_syllables("lazy"), 2);
+        assert_eq!(count_syllables("make"), 1);
+        assert_eq!(count_syllables("table"), 2);
+        assert_eq!(count_syllables("readability"), 5);
+    }
+
+    #[test]
+    fn test_readability_reference_sentences() {
+        // 9 words, 11 syllables: widely cited Flesch score of ~94.3
+        let stats = readability("The quick brown fox jumps over the lazy dog.".to_string());
+        assert_eq!(stats.sentences, 1);
+        assert_eq!(stats.words, 9);
+        assert_eq!(stats.syllables, 11);
+        assert!((stats.flesch_reading_ease - 94.3).abs() < 0.5);
+        assert!((stats.flesch_kincaid_grade - 2.3).abs() < 0.5);
+
+        // Monosyllabic six-word sentence scores ~116
+        let stats = readability("The cat sat on the mat.".to_string());
+        assert!((stats.flesch_reading_ease - 116.1).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_readability_sentences_and_paragraphs() {
+        let content = "# Title\n\nDr. Smith met Mr. Jones at 5 p.m. today. Was it late? Yes!\n\n```\nnot. counted.\n```\n\nLast line";
+        let stats = readability(content.to_string());
+        assert_eq!(stats.sentences, 5);
+        assert_eq!(stats.paragraphs, 3);
+        assert!((stats.avg_sentence_length - stats.words as f64 / 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_readability_empty() {
+        let stats = readability(String::new());
+        assert_eq!(stats.sentences, 0);
+        assert_eq!(stats.flesch_reading_ease, 0.0);
+    }
+
+    #[test]
+    fn test_strip_frontmatter() {
+        let content = "---\ntitle: Notes\ntags: [a, b]\n---\n# Body\n\nText\n";
+        let stripped = strip_frontmatter(content.to_string());
+        assert_eq!(stripped, "# Body\n\nText\n");
+        assert!(!stripped.contains("title:"));
+
+        let crlf = "---\r\ntitle: x\r\n---\r\nBody";
+        assert_eq!(strip_frontmatter(crlf.to_string()), "Body");
+    }
+
+    #[test]
+    fn test_frontmatter_tags_inline_and_block() {
+        let inline = "---\ntitle: A\ntags: [work, \"#urgent\"]\n---\nBody";
+        assert_eq!(frontmatter_tags(inline), vec!["work", "urgent"]);
+
+        let block = "---\ntags:\n  - work\n  - home\ntitle: A\n---\nBody";
+        assert_eq!(frontmatter_tags(block), vec!["work", "home"]);
+
+        assert!(frontmatter_tags("# No frontmatter").is_empty());
+    }
+
+    #[test]
+    fn test_set_frontmatter_tags_keeps_style_and_keys() {
+        let tags = vec!["work".to_string(), "new".to_string()];
+
+        let inline = "---\ntitle: A\ntags: [work]\n---\nBody";
+        assert_eq!(
+            set_frontmatter_tags(inline, &tags),
+            "---\ntitle: A\ntags: [work, new]\n---\nBody"
+        );
+
+        let block = "---\ntags:\n  - work\ntitle: A\n---\nBody";
+        assert_eq!(
+            set_frontmatter_tags(block, &tags),
+            "---\ntags:\n  - work\n  - new\ntitle: A\n---\nBody"
+        );
+
+        let untagged = "---\ntitle: A\n---\nBody";
+        assert_eq!(
+            set_frontmatter_tags(untagged, &tags),
+            "---\ntitle: A\ntags: [work, new]\n---\nBody"
+        );
+
+        assert_eq!(
+            set_frontmatter_tags(inline, &[]),
+            "---\ntitle: A\n---\nBody"
+        );
+        assert_eq!(
+            set_frontmatter_tags("Body", &tags),
+            "---\ntags: [work, new]\n---\nBody"
+        );
+    }
+
+    #[test]
+    fn test_rename_tag_in_content() {
+        let content = "---\ntags: [old, keep]\n---\nSee #old and (#old), not #older or page#old.\n\
+                       Link https://example.com/#old, [here](#old) and `#old` in code.\n\
+                       [ref]: #old\n\
+                       ```\n#old in a fence\n```\n#old/child stays\n";
+        let renamed = rename_tag_in_content(content, "old", "new");
+        assert_eq!(
+            renamed,
+            "---\ntags: [new, keep]\n---\nSee #new and (#new), not #older or page#old.\n\
+             Link https://example.com/#old, [here](#old) and `#old` in code.\n\
+             [ref]: #old\n\
+             ```\n#old in a fence\n```\n#old/child stays\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_tag_merges_existing_frontmatter_tag() {
+        let content = "---\ntags: [old, new]\n---\nBody";
+        assert_eq!(
+            rename_tag_in_content(content, "old", "new"),
+            "---\ntags: [new]\n---\nBody"
+        );
+    }
+
+    #[test]
+    fn test_strip_frontmatter_without_block_is_noop() {
+        for content in ["# Title\n---\nmore", "---\nnever closed\n", "", "---"] {
+            assert_eq!(strip_frontmatter(content.to_string()), content);
+        }
+    }
+
+    #[test]
+    fn test_document_outline_nesting() {
+        let content = "# One\n## One A\n### Deep\n## One B\n# Two\n## Two A";
+        let outline = outline(content, &MarkdownOptions::default());
+
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].text, "One");
+        assert_eq!(outline[0].children.len(), 2);
+        assert_eq!(outline[0].children[0].children[0].text, "Deep");
+        assert_eq!(outline[0].children[1].slug, "one-b");
+        assert_eq!(outline[1].children[0].line, 6);
+    }
+
+    #[test]
+    fn test_document_outline_skipped_levels() {
+        let content = "### Orphan\n# Top\n### Skipped\n## Back\n## Back";
+        let outline = outline(content, &MarkdownOptions::default());
+
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].text, "Orphan");
+        assert_eq!(outline[1].children.len(), 3);
+        assert_eq!(outline[1].children[0].level, 3);
+        assert_eq!(outline[1].children[1].slug, "back");
+        assert_eq!(outline[1].children[2].slug, "back-1");
+    }
+
+    #[test]
+    fn test_disabled_extension_changes_outline() {
+        let content = "# ~~Draft~~ Plan\n\n| Step |\n| --- |\n| Ship |";
+        let gfm = MarkdownOptions::default();
+        let plain = MarkdownOptions {
+            strikethrough: false,
+            ..gfm
+        };
+
+        assert_eq!(outline(content, &gfm)[0].text, "Draft Plan");
+        assert_eq!(outline(content, &plain)[0].text, "~~Draft~~ Plan");
+        assert_eq!(outline(content, &gfm)[0].slug, "draft-plan");
+    }
+
+    #[test]
+    fn test_extract_headings_setext() {
+        let headings = extract_headings(
+            "Title\n=====\n\nText\n\nSub\n---",
+            &MarkdownOptions::default(),
+        );
+        let found: Vec<(u8, &str, usize)> = headings
+            .iter()
+            .map(|h| (h.level, h.text.as_str(), h.line))
+            .collect();
+        assert_eq!(found, vec![(1, "Title", 1), (2, "Sub", 6)]);
+    }
+
+    #[test]
+    fn test_extract_tasks_nested() {
+        let content = "- [ ] Plan\n  - [x] Draft\n    * [ ] Review\n  - [X] Edit\n1. [ ] Ship it\n- [] not a task\n-[ ] nor this";
+        let tasks = extract_tasks(content.to_string());
+
+        let found: Vec<(usize, bool, &str, usize)> = tasks
+            .iter()
+            .map(|t| (t.line, t.checked, t.text.as_str(), t.indent_level))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, false, "Plan", 0),
+                (2, true, "Draft", 1),
+                (3, false, "Review", 2),
+                (4, true, "Edit", 1),
+                (5, false, "Ship it", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_tasks_ignores_code_fences() {
+        let content = "- [ ] Real\n```md\n- [ ] Example\n```\n- [x] Also real";
+        let tasks = extract_tasks(content.to_string());
+        let lines: Vec<usize> = tasks.iter().map(|t| t.line).collect();
+        assert_eq!(lines, vec![1, 5]);
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_skips_code_fences() {
+        let content = "Prose   \n```py\nx = 1    \n```\nMore\t\r\n";
+        assert_eq!(
+            trim_trailing_whitespace(content, true),
+            "Prose\n```py\nx = 1    \n```\nMore\r\n"
+        );
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_hard_breaks() {
+        let content = "Roses are red  \nViolets are blue   \n  \n";
+        assert_eq!(
+            trim_trailing_whitespace(content, true),
+            "Roses are red  \nViolets are blue\n\n"
+        );
+        assert_eq!(trim_trailing_whitespace(content, false), "Roses are red\nViolets are blue\n\n");
+    }
+
+    #[test]
+    fn test_lint_content_rules() {
+        let content = "# Intro\n\n### Deep  \ntext \n\n# Intro\n\n```\ncode   \n";
+        let options = MarkdownOptions::default();
+        let findings: Vec<(LintRule, usize)> = lint_content(content, LintRule::ALL, &options)
+            .into_iter()
+            .map(|f| (f.rule, f.line))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (LintRule::HeadingIncrement, 3),
+                (LintRule::TrailingWhitespace, 4),
+                (LintRule::DuplicateHeading, 6),
+                (LintRule::UnclosedFence, 8),
+            ]
+        );
+
+        let only_whitespace = lint_content(content, &[LintRule::TrailingWhitespace], &options);
+        assert_eq!(only_whitespace.len(), 1);
+        assert!(lint_content("# Title\n\nClean text.\n", LintRule::ALL, &options).is_empty());
+    }
+
+    #[test]
+    fn test_frontmatter_keys() {
+        let content = "---\ntitle: Plan\n\"date\": 2024-01-01\ntags:\n  - work\nauthor:\n  name: Me\n---\nBody: text\n";
+        assert_eq!(frontmatter_keys(content).unwrap(), vec!["title", "date", "tags", "author"]);
+        assert_eq!(frontmatter_keys("---\n---\nBody\n"), Some(Vec::new()));
+        assert_eq!(frontmatter_keys("title: not frontmatter\n"), None);
+    }
+
+    #[test]
+    fn test_heading_slugs_dedupe_repeats() {
+        let content = "# Setup\n## Setup\n## Usage\n### Setup";
+        let slugs: Vec<String> = heading_slugs(content, &MarkdownOptions::default()).into_iter().collect();
+        assert_eq!(slugs, vec!["setup", "setup-1", "setup-2", "usage"]);
+    }
+
+    #[test]
+    fn test_document_links_finds_markdown_and_wikilinks() {
+        let content = "See [the **guide**](docs/guide.md#setup) and [[Daily Log|today]].\n\n`[[not a link]]`\n";
+        let links = document_links(content, &MarkdownOptions::default());
+        let found: Vec<(&str, &str, &str)> = links
+            .iter()
+            .map(|link| (&content[link.range.clone()], link.target.as_str(), link.text.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("[the **guide**](docs/guide.md#setup)", "docs/guide.md#setup", "the **guide**"),
+                ("[[Daily Log|today]]", "[[Daily Log]]", "today"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+// Progress reporting for Miku
+// Lets long-running workspace operations report progress and be cancelled
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::MikuError;
+
+/// Payload of the `progress://<operation_id>` event
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ProgressEvent {
+    pub done: usize,
+    pub total: usize,
+    pub current_path: String,
+}
+
+type CancelFlags = Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>;
+
+/// Cancellation flags of the operations currently running, keyed by the
+/// `operation_id` the frontend passed in
+#[derive(Default)]
+pub struct Operations(CancelFlags);
+
+impl Operations {
+    /// Register `operation_id` and return its cancellation flag. Fails with
+    /// `Conflict` while another operation with that id is running, since
+    /// cancelling the id could then only reach one of them.
+    fn register(&self, operation_id: &str) -> Result<Arc<AtomicBool>, MikuError> {
+        let mut flags = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if flags.contains_key(operation_id) {
+            return Err(MikuError::Conflict(format!("Operation {} is already running", operation_id)));
+        }
+        let flag = Arc::new(AtomicBool::new(false));
+        flags.insert(operation_id.to_string(), flag.clone());
+        Ok(flag)
+    }
+
+    /// Ask a running operation to stop. Returns false if it isn't running.
+    fn cancel(&self, operation_id: &str) -> bool {
+        match self.0.lock().unwrap_or_else(|e| e.into_inner()).get(operation_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+type ProgressSink = Box<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Handle a long-running operation reports through. Without an operation
+/// id it does nothing and is never cancelled.
+pub(crate) struct Progress {
+    sink: Option<ProgressSink>,
+    cancelled: Arc<AtomicBool>,
+    /// Registry entry removed again when the operation finishes
+    registration: Option<(String, CancelFlags)>,
+}
+
+impl Progress {
+    /// A handle that reports nowhere
+    pub(crate) fn none() -> Self {
+        Progress {
+            sink: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            registration: None,
+        }
+    }
+
+    /// Report to `sink`, stopping once `cancelled` is set
+    pub(crate) fn with_sink(cancelled: Arc<AtomicBool>, sink: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        Progress {
+            sink: Some(Box::new(sink)),
+            cancelled,
+            registration: None,
+        }
+    }
+
+    /// Emit `progress://<operation_id>` events and register the operation
+    /// so `cancel_operation` can stop it
+    pub(crate) fn for_operation(
+        app: &AppHandle,
+        operations: &Operations,
+        operation_id: Option<String>,
+    ) -> Result<Self, MikuError> {
+        let Some(operation_id) = operation_id else {
+            return Ok(Self::none());
+        };
+
+        let cancelled = operations.register(&operation_id)?;
+        let app = app.clone();
+        let event = format!("progress://{}", operation_id);
+        let mut progress = Self::with_sink(cancelled, move |payload| {
+            let _ = app.emit(&event, payload);
+        });
+        progress.registration = Some((operation_id, operations.0.clone()));
+        Ok(progress)
+    }
+
+    /// Whether the operation has been asked to stop
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Cancelled` once the operation has been cancelled, for
+    /// operations that can't report a total
+    pub(crate) fn check(&self) -> Result<(), MikuError> {
+        if self.is_cancelled() {
+            let operation_id = self.registration.as_ref().map(|(id, _)| id.clone());
+            return Err(MikuError::Cancelled(operation_id.unwrap_or_default()));
+        }
+        Ok(())
+    }
+
+    /// Report that `done` of `total` items are finished, `current_path`
+    /// being the one just started. Fails with `Cancelled` once the
+    /// operation has been cancelled, so callers can bail out with `?`.
+    pub(crate) fn step(&self, done: usize, total: usize, current_path: &str) -> Result<(), MikuError> {
+        self.check()?;
+        if let Some(sink) = &self.sink {
+            sink(ProgressEvent {
+                done,
+                total,
+                current_path: current_path.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some((operation_id, registry)) = self.registration.take() {
+            registry.lock().unwrap_or_else(|e| e.into_inner()).remove(&operation_id);
+        }
+    }
+}
+
+/// Cooperatively cancel a running operation. The operation stops at its
+/// next progress step and fails with a `Cancelled` error. Returns false if
+/// no operation with that id is running.
+#[tauri::command]
+pub fn cancel_operation(operation_id: String, operations: State<'_, Operations>) -> bool {
+    operations.cancel(&operation_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for a long operation over `items`
+    fn process_all(items: &[&str], progress: &Progress) -> Result<usize, MikuError> {
+        for (index, item) in items.iter().enumerate() {
+            progress.step(index, items.len(), item)?;
+        }
+        Ok(items.len())
+    }
+
+    #[test]
+    fn test_progress_increases_and_honors_cancellation() {
+        let items = ["a.md", "b.md", "c.md", "d.md", "e.md"];
+        let operations = Arc::new(Operations::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let sink_events = events.clone();
+        let sink_operations = operations.clone();
+        let progress = Progress::with_sink(operations.register("export-1").unwrap(), move |event| {
+            // Simulate the user pressing cancel while the third item runs
+            if event.done == 2 {
+                sink_operations.cancel("export-1");
+            }
+            sink_events.lock().unwrap().push(event);
+        });
+
+        let result = process_all(&items, &progress);
+        assert!(matches!(result, Err(MikuError::Cancelled(_))));
+
+        let events = events.lock().unwrap();
+        let done: Vec<usize> = events.iter().map(|e| e.done).collect();
+        assert_eq!(done, vec![0, 1, 2]);
+        assert_eq!(events[2].current_path, "c.md");
+        assert!(events.iter().all(|e| e.total == items.len()));
+
+        assert!(!operations.cancel("unknown"));
+    }
+
+    #[test]
+    fn test_running_operation_id_cannot_be_reused() {
+        let operations = Arc::new(Operations::default());
+        let mut progress = Progress::with_sink(operations.register("rename-1").unwrap(), |_| {});
+        progress.registration = Some(("rename-1".to_string(), operations.0.clone()));
+
+        assert!(matches!(operations.register("rename-1"), Err(MikuError::Conflict(_))));
+        drop(progress);
+        assert!(operations.register("rename-1").is_ok());
+    }
+}
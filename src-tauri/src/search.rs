@@ -0,0 +1,975 @@
+// Workspace search for Miku
+// Plain-text search across the markdown files of a workspace
+
+use globset::{Glob, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::commands::{app_data_dir, max_tree_depth, MikuError};
+use crate::file_ops::is_markdown_file;
+use crate::watcher::{WatchedCache, WatchedValue};
+use crate::workspace::{get_current_workspace_in, is_ignored_name};
+
+/// A single matching line, with surrounding lines for context
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub path: String,
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+    /// Up to `context_lines` lines before the match, in file order
+    pub before: Vec<String>,
+    /// Up to `context_lines` lines after the match
+    pub after: Vec<String>,
+}
+
+/// Recursively collect the markdown files under `dir`, skipping the same
+/// hidden and tooling directories as the file tree and, like it, folders
+/// nested deeper than `max_tree_depth`
+pub(crate) async fn markdown_files(dir: &Path) -> Result<Vec<PathBuf>, MikuError> {
+    markdown_files_within(dir, max_tree_depth()).await
+}
+
+/// `markdown_files`, descending at most `levels` folders below `dir`
+pub(crate) fn markdown_files_within(
+    dir: &Path,
+    levels: usize,
+) -> Pin<Box<dyn Future<Output = Result<Vec<PathBuf>, MikuError>> + Send + '_>> {
+    Box::pin(async move {
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            if is_ignored_name(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                if levels > 0 {
+                    files.extend(markdown_files_within(&path, levels - 1).await?);
+                }
+            } else if is_markdown_file(&path.to_string_lossy()) {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    })
+}
+
+/// How a line is tested against the search query. Both forms are
+/// case-insensitive.
+pub(crate) enum LineMatcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl LineMatcher {
+    /// Build a matcher once per search. Regex compile errors are reported
+    /// as `InvalidPattern` with the parser's explanation.
+    pub(crate) fn new(query: &str, regex: bool) -> Result<Self, MikuError> {
+        if regex {
+            RegexBuilder::new(query)
+                .case_insensitive(true)
+                .build()
+                .map(LineMatcher::Regex)
+                .map_err(|e| MikuError::InvalidPattern(e.to_string()))
+        } else {
+            Ok(LineMatcher::Substring(query.to_lowercase()))
+        }
+    }
+
+    pub(crate) fn is_match(&self, line: &str) -> bool {
+        match self {
+            LineMatcher::Substring(query) => line.to_lowercase().contains(query),
+            LineMatcher::Regex(regex) => regex.is_match(line),
+        }
+    }
+}
+
+/// Compile a glob matched against workspace-relative paths
+fn compile_glob(pattern: &str) -> Result<GlobMatcher, MikuError> {
+    Glob::new(pattern)
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| MikuError::InvalidPattern(e.to_string()))
+}
+
+/// Find the lines of `content` accepted by `matcher`
+pub(crate) fn search_content(
+    path: &str,
+    content: &str,
+    matcher: &LineMatcher,
+    context_lines: usize,
+) -> Vec<SearchMatch> {
+    let lines: Vec<&str> = content.lines().collect();
+
+    (0..lines.len())
+        .filter(|index| matcher.is_match(lines[*index]))
+        .map(|index| match_at(path, &lines, index, context_lines))
+        .collect()
+}
+
+/// The match on `lines[index]` with up to `context_lines` lines around it
+fn match_at<S: AsRef<str>>(path: &str, lines: &[S], index: usize, context_lines: usize) -> SearchMatch {
+    let start = index.saturating_sub(context_lines);
+    let end = (index + 1 + context_lines).min(lines.len());
+    let owned = |range: &[S]| range.iter().map(|l| l.as_ref().to_string()).collect();
+    SearchMatch {
+        path: path.to_string(),
+        line: index + 1,
+        text: lines[index].as_ref().to_string(),
+        before: owned(&lines[start..index]),
+        after: owned(&lines[index + 1..end]),
+    }
+}
+
+/// How `find_in_content` matches its query
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    /// Only match where the query isn't part of a longer word
+    pub whole_word: bool,
+    /// Treat the query as a regular expression
+    pub regex: bool,
+}
+
+/// A match within one document. Offsets and columns count UTF-16 code
+/// units, like JavaScript string indices.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ContentMatch {
+    pub start: usize,
+    /// Exclusive
+    pub end: usize,
+    /// 1-based line of `start`
+    pub line: usize,
+    /// 1-based column of `start`
+    pub column: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte ranges of the matches of `query` in `content`, without overlaps
+fn find_ranges(content: &str, query: &str, options: FindOptions) -> Result<Vec<std::ops::Range<usize>>, MikuError> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = if options.regex { query.to_string() } else { regex::escape(query) };
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .multi_line(true)
+        .build()
+        .map_err(|e| MikuError::InvalidPattern(e.to_string()))?;
+
+    let mut ranges = Vec::new();
+    let mut from = 0;
+    while let Some(found) = regex.find_at(content, from) {
+        let whole_word = !options.whole_word
+            || (!content[..found.start()].chars().next_back().is_some_and(is_word_char)
+                && !content[found.end()..].chars().next().is_some_and(is_word_char));
+        if found.is_empty() || !whole_word {
+            // Retry from the next character, so a match inside a rejected
+            // one can still be found
+            match content[found.start()..].chars().next() {
+                Some(c) => from = found.start() + c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+        ranges.push(found.range());
+        from = found.end();
+    }
+    Ok(ranges)
+}
+
+/// Find every match of `query` in `content`, left to right and without
+/// overlaps. An empty query matches nothing.
+pub(crate) fn find_in_content_with(
+    content: &str,
+    query: &str,
+    options: FindOptions,
+) -> Result<Vec<ContentMatch>, MikuError> {
+    let ranges = find_ranges(content, query, options)?;
+
+    // Walk the content once, converting byte offsets as the matches go by.
+    // Returns the UTF-16 offset of `to` and the line it's on.
+    let (mut byte, mut unit, mut line, mut line_start) = (0, 0, 1, 0);
+    let mut advance_to = |to: usize| {
+        for c in content[byte..to].chars() {
+            unit += c.len_utf16();
+            if c == '\n' {
+                line += 1;
+                line_start = unit;
+            }
+        }
+        byte = to;
+        (unit, line, line_start)
+    };
+
+    let mut matches = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let (start, line, line_start) = advance_to(range.start);
+        let (end, _, _) = advance_to(range.end);
+        matches.push(ContentMatch {
+            start,
+            end,
+            line,
+            column: start - line_start + 1,
+        });
+    }
+    Ok(matches)
+}
+
+/// Find-in-file for an open document: every match of `query` in `content`,
+/// computed in memory so it can run on each keystroke. Invalid regular
+/// expressions fail with `InvalidPattern`.
+#[tauri::command]
+pub fn find_in_content(
+    content: String,
+    query: String,
+    options: Option<FindOptions>,
+) -> Result<Vec<ContentMatch>, MikuError> {
+    find_in_content_with(&content, &query, options.unwrap_or_default())
+}
+
+/// Filters shared by the search commands
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression
+    pub regex: bool,
+    /// Only search files whose workspace-relative path matches (e.g. `notes/**`)
+    pub path_glob: Option<String>,
+}
+
+/// Validate the workspace, compile the query and collect the files to
+/// search. Returns `None` for an empty query.
+async fn prepare_search(
+    workspace_path: &str,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Option<(LineMatcher, Vec<PathBuf>)>, MikuError> {
+    let root = Path::new(workspace_path);
+    if !root.is_dir() {
+        return Err(MikuError::NotFound(workspace_path.to_string()));
+    }
+    if query.is_empty() {
+        return Ok(None);
+    }
+
+    let matcher = LineMatcher::new(query, options.regex)?;
+    let glob = options.path_glob.as_deref().map(compile_glob).transpose()?;
+
+    let files = markdown_files(root)
+        .await?
+        .into_iter()
+        .filter(|file| {
+            let relative = file.strip_prefix(root).unwrap_or(file);
+            glob.as_ref().map_or(true, |glob| glob.is_match(relative))
+        })
+        .collect();
+
+    Ok(Some((matcher, files)))
+}
+
+/// One page of `search_workspace` results
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SearchPage {
+    pub matches: Vec<SearchMatch>,
+    /// Total number of matches. Exact when every file was read; when the
+    /// walk stopped early it's extrapolated from the files read so far.
+    pub total_estimated: usize,
+    /// Whether there are matches past this page
+    pub has_more: bool,
+}
+
+/// Search every markdown file in the workspace for `query`. Each match
+/// carries up to `context_lines` lines on either side, like `grep -C`.
+/// With `regex`, the query is a regular expression; `path_glob` limits the
+/// search to files whose workspace-relative path matches (e.g. `notes/**`).
+/// `offset` and `limit` select a page of matches; with a `limit`, files
+/// past the end of the page aren't read.
+#[tauri::command]
+pub async fn search_workspace(
+    workspace_path: String,
+    query: String,
+    context_lines: Option<usize>,
+    regex: Option<bool>,
+    path_glob: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<SearchPage, MikuError> {
+    let options = SearchOptions {
+        regex: regex.unwrap_or(false),
+        path_glob,
+    };
+    let Some((matcher, files)) = prepare_search(&workspace_path, &query, &options).await? else {
+        return Ok(SearchPage {
+            matches: Vec::new(),
+            total_estimated: 0,
+            has_more: false,
+        });
+    };
+
+    let offset = offset.unwrap_or(0);
+    // One match past the page tells whether there is a next page
+    let wanted = limit.map(|limit| offset.saturating_add(limit).saturating_add(1));
+    let total_files = files.len();
+    let mut files_read = 0;
+    let mut results = Vec::new();
+    for file in files {
+        if wanted.is_some_and(|wanted| results.len() >= wanted) {
+            break;
+        }
+        files_read += 1;
+        // Skip files that vanish or aren't valid UTF-8 mid-search
+        let Ok(content) = tokio::fs::read_to_string(&file).await else {
+            continue;
+        };
+        results.extend(search_content(
+            &file.to_string_lossy(),
+            &content,
+            &matcher,
+            context_lines.unwrap_or(0),
+        ));
+    }
+
+    let total_estimated = if files_read < total_files {
+        (results.len() * total_files / files_read).max(results.len())
+    } else {
+        results.len()
+    };
+    let has_more = limit.is_some_and(|limit| results.len() > offset.saturating_add(limit));
+    let matches = results
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    Ok(SearchPage {
+        matches,
+        total_estimated,
+        has_more,
+    })
+}
+
+/// Number of matching lines in one file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileMatchCount {
+    pub path: String,
+    pub match_count: usize,
+}
+
+/// Per-file match counts for `query`, most matches first, so the UI can
+/// render collapsed groups and fetch line details lazily
+#[tauri::command]
+pub async fn search_summary(
+    workspace_path: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<FileMatchCount>, MikuError> {
+    let options = options.unwrap_or_default();
+    let Some((matcher, files)) = prepare_search(&workspace_path, &query, &options).await? else {
+        return Ok(Vec::new());
+    };
+
+    let mut counts = Vec::new();
+    for file in files {
+        let Ok(content) = tokio::fs::read_to_string(&file).await else {
+            continue;
+        };
+        let match_count = content.lines().filter(|line| matcher.is_match(line)).count();
+        if match_count > 0 {
+            counts.push(FileMatchCount {
+                path: file.to_string_lossy().to_string(),
+                match_count,
+            });
+        }
+    }
+
+    counts.sort_by(|a, b| b.match_count.cmp(&a.match_count).then_with(|| a.path.cmp(&b.path)));
+    Ok(counts)
+}
+
+/// Lowercased alphanumeric runs of `text`, the unit the search index is keyed by
+fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+}
+
+/// Inverted index over the lines of every markdown file in a workspace
+#[derive(Default)]
+pub(crate) struct Index {
+    root: PathBuf,
+    /// Lines of every indexed file, in the same order as `markdown_files`
+    files: BTreeMap<Arc<Path>, Vec<String>>,
+    /// Token -> lines (file, 0-based line) containing it
+    postings: HashMap<String, BTreeSet<(Arc<Path>, usize)>>,
+}
+
+impl Index {
+    fn new(root: &Path) -> Self {
+        Index {
+            root: root.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// Index `content` as the current text of `path`
+    fn insert(&mut self, path: &Path, content: &str) {
+        self.remove(path);
+        let path: Arc<Path> = Arc::from(path);
+        let lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+        for (index, line) in lines.iter().enumerate() {
+            for token in tokens(line) {
+                self.postings.entry(token).or_default().insert((path.clone(), index));
+            }
+        }
+        self.files.insert(path, lines);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        let Some(lines) = self.files.remove(path) else {
+            return;
+        };
+        for (index, line) in lines.iter().enumerate() {
+            for token in tokens(line) {
+                if let Some(postings) = self.postings.get_mut(&token) {
+                    postings.retain(|(file, line)| !(*line == index && &**file == path));
+                    if postings.is_empty() {
+                        self.postings.remove(&token);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop `path` and, if it was a folder, everything that was inside it
+    fn remove_under(&mut self, path: &Path) {
+        let gone: Vec<Arc<Path>> = self.files.keys().filter(|file| file.starts_with(path)).cloned().collect();
+        for file in gone {
+            self.remove(&file);
+        }
+    }
+
+    /// Index every markdown file under `root`
+    async fn scan(root: &Path) -> Result<Index, MikuError> {
+        let mut index = Index::new(root);
+        for file in markdown_files(root).await? {
+            if let Ok(content) = tokio::fs::read_to_string(&file).await {
+                index.insert(&file, &content);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Same results as a linear `search_workspace` scan for `query`, using
+    /// the postings to pick candidate lines
+    fn search(&self, query: &str, context_lines: usize) -> Vec<SearchMatch> {
+        let matcher = LineMatcher::Substring(query.to_lowercase());
+        let query_tokens: Vec<String> = tokens(query).collect();
+        let mut results = Vec::new();
+
+        if query_tokens.is_empty() {
+            // Nothing to look up (e.g. only punctuation): check every line
+            for (path, lines) in &self.files {
+                let path = path.to_string_lossy();
+                for index in (0..lines.len()).filter(|i| matcher.is_match(&lines[*i])) {
+                    results.push(match_at(&path, lines, index, context_lines));
+                }
+            }
+            return results;
+        }
+
+        // A matching line contains each query token inside one of its own
+        // tokens, so candidates come from every indexed token containing it
+        let mut candidates: Option<BTreeSet<(Arc<Path>, usize)>> = None;
+        for query_token in &query_tokens {
+            let lines: BTreeSet<(Arc<Path>, usize)> = self
+                .postings
+                .iter()
+                .filter(|(token, _)| token.contains(query_token.as_str()))
+                .flat_map(|(_, postings)| postings.iter().cloned())
+                .collect();
+            candidates = Some(match candidates {
+                Some(previous) => previous.intersection(&lines).cloned().collect(),
+                None => lines,
+            });
+        }
+
+        for (path, index) in candidates.unwrap_or_default() {
+            let lines = &self.files[&path];
+            if matcher.is_match(&lines[index]) {
+                results.push(match_at(&path.to_string_lossy(), lines, index, context_lines));
+            }
+        }
+        results
+    }
+}
+
+/// Whether a watcher event for `path` should be ignored: it's outside
+/// `root` or inside a hidden or tooling directory
+pub(crate) fn is_ignored_path(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).map_or(true, |relative| {
+        relative.components().any(|c| is_ignored_name(&c.as_os_str().to_string_lossy()))
+    })
+}
+
+/// Blocking counterpart of `markdown_files` for the watcher thread
+pub(crate) fn markdown_files_blocking(dir: &Path) -> Vec<PathBuf> {
+    markdown_files_blocking_within(dir, max_tree_depth())
+}
+
+fn markdown_files_blocking_within(dir: &Path, levels: usize) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        if is_ignored_name(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if levels > 0 {
+                files.extend(markdown_files_blocking_within(&path, levels - 1));
+            }
+        } else if is_markdown_file(&path.to_string_lossy()) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+impl WatchedValue for Index {
+    fn apply_change(&mut self, path: &Path) {
+        if is_ignored_path(&self.root, path) {
+            return;
+        }
+
+        if path.is_dir() {
+            for file in markdown_files_blocking(path) {
+                if let Ok(content) = std::fs::read_to_string(&file) {
+                    self.insert(&file, &content);
+                }
+            }
+        } else if path.is_file() {
+            if is_markdown_file(&path.to_string_lossy()) {
+                if let Ok(content) = std::fs::read_to_string(path) {
+                    self.insert(path, &content);
+                }
+            }
+        } else {
+            self.remove_under(path);
+        }
+    }
+}
+
+/// Full-text index of the current workspace, kept up to date by a
+/// file-system watcher once built
+#[derive(Default, Clone)]
+pub struct SearchIndex(WatchedCache<Index>);
+
+impl SearchIndex {
+    /// Index every markdown file under `root`, replacing any previous index.
+    /// Returns the number of files indexed.
+    pub(crate) async fn rebuild(&self, root: &Path) -> Result<usize, MikuError> {
+        self.0.rebuild(root, Index::scan(root)).await?;
+        Ok(self.0.read(root, |index| index.files.len()).unwrap_or(0))
+    }
+
+    /// Index `root` unless it already is. Concurrent callers share one build.
+    pub(crate) async fn ensure(&self, root: &Path) -> Result<(), MikuError> {
+        self.0.ensure(root, Index::scan(root)).await
+    }
+
+    /// Forget the index, e.g. when another workspace is opened
+    pub(crate) fn clear(&self) {
+        self.0.clear();
+    }
+
+    /// Search the index of `root`, or `None` if it hasn't been built
+    pub(crate) fn search(&self, root: &Path, query: &str, context_lines: usize) -> Option<Vec<SearchMatch>> {
+        self.0.read(root, |index| {
+            if query.is_empty() {
+                Vec::new()
+            } else {
+                index.search(query, context_lines)
+            }
+        })
+    }
+}
+
+/// The `offset`/`limit` page of `results`, which holds every match
+fn page_of(results: Vec<SearchMatch>, offset: usize, limit: Option<usize>) -> SearchPage {
+    let total_estimated = results.len();
+    let has_more = limit.is_some_and(|limit| total_estimated > offset.saturating_add(limit));
+    let matches = results
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    SearchPage {
+        matches,
+        total_estimated,
+        has_more,
+    }
+}
+
+/// Search the current workspace through the full-text index, a page at a
+/// time like `search_workspace`. Until the index is built this falls back
+/// to a linear scan and builds it in the background for next time.
+#[tauri::command]
+pub async fn search_index(
+    query: String,
+    context_lines: Option<usize>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    index: State<'_, SearchIndex>,
+) -> Result<SearchPage, MikuError> {
+    let Some(workspace) = get_current_workspace_in(&app_data_dir()?).await? else {
+        return Ok(page_of(Vec::new(), 0, None));
+    };
+    if let Some(results) = index.search(Path::new(&workspace.path), &query, context_lines.unwrap_or(0)) {
+        return Ok(page_of(results, offset.unwrap_or(0), limit));
+    }
+    let results = search_workspace(workspace.path.clone(), query, context_lines, None, None, offset, limit).await?;
+
+    let index = index.inner().clone();
+    tokio::spawn(async move {
+        if let Err(err) = index.ensure(Path::new(&workspace.path)).await {
+            log::warn!("failed to build search index for {}: {}", workspace.path, err);
+        }
+    });
+    Ok(results)
+}
+
+/// Rebuild the full-text index for `workspace_path`. Returns the number of
+/// files indexed.
+#[tauri::command]
+pub async fn rebuild_index(workspace_path: String, index: State<'_, SearchIndex>) -> Result<usize, MikuError> {
+    index.rebuild(Path::new(&workspace_path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_is_clamped_at_file_boundaries() {
+        let content = "alpha\nbeta\ngamma\ndelta\nepsilon";
+        let matcher = |q: &str| LineMatcher::new(q, false).unwrap();
+
+        let first = search_content("a.md", content, &matcher("ALPHA"), 2);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].line, 1);
+        assert!(first[0].before.is_empty());
+        assert_eq!(first[0].after, vec!["beta", "gamma"]);
+
+        let last = search_content("a.md", content, &matcher("epsilon"), 3);
+        assert_eq!(last[0].before, vec!["beta", "gamma", "delta"]);
+        assert!(last[0].after.is_empty());
+
+        let middle = search_content("a.md", content, &matcher("gamma"), 1);
+        assert_eq!(middle[0].before, vec!["beta"]);
+        assert_eq!(middle[0].after, vec!["delta"]);
+    }
+
+    /// `(start, end, line, column)` of each match
+    fn find(content: &str, query: &str, options: FindOptions) -> Vec<(usize, usize, usize, usize)> {
+        find_in_content_with(content, query, options)
+            .unwrap()
+            .iter()
+            .map(|m| (m.start, m.end, m.line, m.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_in_content_skips_overlaps() {
+        let plain = FindOptions::default();
+        assert_eq!(find("aaaa", "aa", plain), vec![(0, 2, 1, 1), (2, 4, 1, 3)]);
+        assert_eq!(find("Note\nnote NOTE", "note", plain).len(), 3);
+
+        let case_sensitive = FindOptions {
+            case_sensitive: true,
+            ..plain
+        };
+        assert_eq!(find("Note\nnote NOTE", "note", case_sensitive), vec![(5, 9, 2, 1)]);
+        // Offsets count UTF-16 units, so an emoji takes two
+        assert_eq!(find("🎉 café\ncafé", "café", plain), vec![(3, 7, 1, 4), (8, 12, 2, 1)]);
+        assert!(find("anything", "", plain).is_empty());
+    }
+
+    #[test]
+    fn test_find_in_content_regex() {
+        let regex = FindOptions {
+            regex: true,
+            ..FindOptions::default()
+        };
+        assert_eq!(find("v1.2 and v10.0", r"v\d+\.\d", regex), vec![(0, 4, 1, 1), (9, 14, 1, 10)]);
+        assert_eq!(find("- [ ] one\n- [x] two", r"^- \[x\]", regex), vec![(10, 15, 2, 1)]);
+        // Empty matches are never reported
+        assert!(find("abc", "z*", regex).is_empty());
+        assert!(matches!(
+            find_in_content_with("abc", "(", regex),
+            Err(MikuError::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_find_in_content_whole_word() {
+        let whole_word = FindOptions {
+            whole_word: true,
+            ..FindOptions::default()
+        };
+        assert_eq!(find("cat concat cat_s cats cat.", "cat", whole_word), vec![(0, 3, 1, 1), (22, 25, 1, 23)]);
+        // A rejected match doesn't hide a word that starts inside it
+        assert_eq!(find("aaa aa", "aa", whole_word), vec![(4, 6, 1, 5)]);
+        assert_eq!(find("C++ and C", "C++", whole_word), vec![(0, 3, 1, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_search_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join("notes/plan.md"), "# Plan\nship the release\n").unwrap();
+        std::fs::write(dir.path().join("todo.md"), "release notes\n").unwrap();
+        std::fs::write(dir.path().join("data.txt"), "release").unwrap();
+        std::fs::write(dir.path().join(".git/HEAD.md"), "release").unwrap();
+
+        let workspace = dir.path().to_string_lossy().to_string();
+        let results = search_workspace(workspace, "Release".to_string(), Some(1), None, None, None, None)
+            .await
+            .unwrap()
+            .matches;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("plan.md"));
+        assert_eq!(results[0].line, 2);
+        assert_eq!(results[0].before, vec!["# Plan"]);
+        assert!(results[1].path.ends_with("todo.md"));
+    }
+
+    fn regex_fixture() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("journal/2024")).unwrap();
+        std::fs::write(dir.path().join("journal/2024/jan.md"), "met Alice\nlunch\nmet Bob\n").unwrap();
+        std::fs::write(dir.path().join("ideas.md"), "ask bob about it\n").unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        (dir, workspace)
+    }
+
+    #[tokio::test]
+    async fn test_search_workspace_regex_alternation() {
+        let (_dir, workspace) = regex_fixture();
+        let results = search_workspace(workspace, r"^met (alice|bob)$".to_string(), None, Some(true), None, None, None)
+            .await
+            .unwrap()
+            .matches;
+
+        let lines: Vec<usize> = results.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![1, 3]);
+        assert!(results.iter().all(|r| r.path.ends_with("jan.md")));
+    }
+
+    #[tokio::test]
+    async fn test_search_workspace_glob_limits_to_subfolder() {
+        let (_dir, workspace) = regex_fixture();
+        let results = search_workspace(
+            workspace,
+            "bob".to_string(),
+            None,
+            None,
+            Some("journal/**".to_string()),
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        .matches;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].text, "met Bob");
+    }
+
+    #[tokio::test]
+    async fn test_search_workspace_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            std::fs::write(dir.path().join(format!("{}.md", name)), "hit one\nmiss\nhit two\n").unwrap();
+        }
+        let workspace = dir.path().to_string_lossy().to_string();
+        let page = |offset, limit| {
+            search_workspace(workspace.clone(), "hit".to_string(), None, None, None, offset, limit)
+        };
+
+        let first = page(None, Some(3)).await.unwrap();
+        let found: Vec<(String, usize)> = first
+            .matches
+            .iter()
+            .map(|m| (Path::new(&m.path).file_name().unwrap().to_string_lossy().to_string(), m.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("a.md".to_string(), 1), ("a.md".to_string(), 3), ("b.md".to_string(), 1)]
+        );
+        assert!(first.has_more);
+        // Only a.md and b.md were read, extrapolated over four files
+        assert_eq!(first.total_estimated, 8);
+
+        let middle = page(Some(5), Some(2)).await.unwrap();
+        let lines: Vec<usize> = middle.matches.iter().map(|m| m.line).collect();
+        assert_eq!(lines, vec![3, 1]);
+        assert!(middle.has_more);
+
+        let last = page(Some(6), Some(5)).await.unwrap();
+        assert_eq!(last.matches.len(), 2);
+        assert!(!last.has_more);
+        assert_eq!(last.total_estimated, 8);
+
+        let everything = page(None, None).await.unwrap();
+        assert_eq!(everything.matches.len(), 8);
+        assert!(!everything.has_more);
+    }
+
+    #[tokio::test]
+    async fn test_search_summary_orders_by_match_count() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.md"), "todo\n").unwrap();
+        std::fs::write(dir.path().join("b.md"), "todo\ntodo\nTODO\n").unwrap();
+        std::fs::write(dir.path().join("c.md"), "todo\nskip\ntodo\n").unwrap();
+        std::fs::write(dir.path().join("d.md"), "nothing here\n").unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let summary = search_summary(workspace, "todo".to_string(), None).await.unwrap();
+        let counts: Vec<(String, usize)> = summary
+            .iter()
+            .map(|s| (Path::new(&s.path).file_name().unwrap().to_string_lossy().to_string(), s.match_count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![("b.md".to_string(), 3), ("c.md".to_string(), 2), ("a.md".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_workspace_invalid_regex() {
+        let (_dir, workspace) = regex_fixture();
+        let result = search_workspace(workspace, "(unclosed".to_string(), None, Some(true), None, None, None).await;
+        match result {
+            Err(MikuError::InvalidPattern(message)) => assert!(message.contains("unclosed")),
+            other => panic!("expected InvalidPattern, got {:?}", other.map(|r| r.matches.len())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_matches_linear_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join("notes/plan.md"), "# Plan\nship the release\nRelease notes -- v2\n").unwrap();
+        std::fs::write(dir.path().join("todo.md"), "release notes\nmisc\n").unwrap();
+        std::fs::write(dir.path().join(".git/HEAD.md"), "release").unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let index = SearchIndex::default();
+        assert!(index.search(dir.path(), "release", 0).is_none());
+        assert_eq!(index.rebuild(dir.path()).await.unwrap(), 2);
+
+        for query in ["release", "ease no", "NOTES", "--", "plan\nship", "absent"] {
+            let linear = search_workspace(workspace.clone(), query.to_string(), Some(1), None, None, None, None)
+                .await
+                .unwrap()
+                .matches;
+            assert_eq!(index.search(dir.path(), query, 1).unwrap(), linear, "query {:?}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_indexed_pages_match_linear_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines: String = (0..7).map(|i| format!("entry {}\n", i)).collect();
+        std::fs::write(dir.path().join("log.md"), lines).unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let index = SearchIndex::default();
+        index.rebuild(dir.path()).await.unwrap();
+        for (offset, limit) in [(0, Some(3)), (3, Some(3)), (6, Some(3)), (2, None)] {
+            let all = index.search(dir.path(), "entry", 0).unwrap();
+            let indexed = page_of(all, offset, limit);
+            let linear = search_workspace(workspace.clone(), "entry".to_string(), None, None, None, Some(offset), limit)
+                .await
+                .unwrap();
+            assert_eq!(indexed, linear, "offset {} limit {:?}", offset, limit);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_is_tied_to_its_workspace() {
+        let (old, new) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::write(old.path().join("a.md"), "old vault\n").unwrap();
+        std::fs::write(new.path().join("b.md"), "new vault\n").unwrap();
+
+        let index = SearchIndex::default();
+        index.ensure(old.path()).await.unwrap();
+        assert_eq!(index.search(old.path(), "vault", 0).unwrap().len(), 1);
+        // After a switch the old index isn't used for the new workspace
+        assert!(index.search(new.path(), "vault", 0).is_none());
+
+        let (first, second) = tokio::join!(index.ensure(new.path()), index.ensure(new.path()));
+        first.unwrap();
+        second.unwrap();
+        let hits = index.search(new.path(), "vault", 0).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("b.md"));
+
+        index.clear();
+        assert!(index.search(new.path(), "vault", 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_index_updates_after_file_change() {
+        let dir = tempfile::tempdir().unwrap();
+        // Watcher events carry the resolved path, e.g. /private/var on macOS
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+        let note = root.join("note.md");
+        std::fs::write(&note, "nothing yet\n").unwrap();
+
+        let index = SearchIndex::default();
+        index.rebuild(&root).await.unwrap();
+        assert!(index.search(&root, "milestone", 0).unwrap().is_empty());
+
+        std::fs::write(&note, "nothing yet\nhit the milestone\n").unwrap();
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/new.md"), "another milestone\n").unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let mut lines = Vec::new();
+        while std::time::Instant::now() < deadline {
+            lines = index
+                .search(&root, "milestone", 0)
+                .unwrap()
+                .into_iter()
+                .map(|m| (Path::new(&m.path).file_name().unwrap().to_string_lossy().to_string(), m.line))
+                .collect();
+            if lines.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(lines, vec![("note.md".to_string(), 2), ("new.md".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,231 @@
+// Workspace statistics for Miku
+// File and word totals for a workspace, kept current by a file-system
+// watcher so a live dashboard doesn't rescan the whole vault on every change
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+use crate::commands::MikuError;
+use crate::file_ops::is_markdown_file;
+use crate::markdown::count_words;
+use crate::progress::{Operations, Progress};
+use crate::search::{is_ignored_path, markdown_files, markdown_files_blocking};
+use crate::watcher::{WatchedCache, WatchedValue};
+
+/// Totals over the markdown files of a workspace
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WorkspaceStats {
+    pub file_count: usize,
+    /// Words outside frontmatter and fenced code
+    pub word_count: usize,
+}
+
+/// Compute the stats for `root` from scratch
+pub(crate) async fn workspace_stats_in(root: &Path, progress: &Progress) -> Result<WorkspaceStats, MikuError> {
+    if !root.is_dir() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let mut stats = WorkspaceStats::default();
+    let files = markdown_files(root).await?;
+    for (index, file) in files.iter().enumerate() {
+        progress.step(index, files.len(), &file.to_string_lossy())?;
+        if let Ok(content) = tokio::fs::read_to_string(&file).await {
+            stats.file_count += 1;
+            stats.word_count += count_words(&content);
+        }
+    }
+    Ok(stats)
+}
+
+/// Word count of every markdown file in a workspace, with running totals
+#[derive(Default)]
+pub(crate) struct StatsCache {
+    root: PathBuf,
+    words: BTreeMap<PathBuf, usize>,
+    total_words: usize,
+}
+
+impl StatsCache {
+    fn new(root: &Path) -> Self {
+        StatsCache {
+            root: root.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    fn totals(&self) -> WorkspaceStats {
+        WorkspaceStats {
+            file_count: self.words.len(),
+            word_count: self.total_words,
+        }
+    }
+
+    /// Record `words` as the count of `path`, replacing its previous one
+    fn insert(&mut self, path: &Path, words: usize) {
+        self.remove(path);
+        self.words.insert(path.to_path_buf(), words);
+        self.total_words += words;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(words) = self.words.remove(path) {
+            self.total_words -= words;
+        }
+    }
+
+    /// Count every markdown file under `root`
+    async fn scan(root: &Path, progress: &Progress) -> Result<StatsCache, MikuError> {
+        let mut cache = StatsCache::new(root);
+        let files = markdown_files(root).await?;
+        for (index, file) in files.iter().enumerate() {
+            progress.step(index, files.len(), &file.to_string_lossy())?;
+            if let Ok(content) = tokio::fs::read_to_string(file).await {
+                cache.insert(file, count_words(&content));
+            }
+        }
+        Ok(cache)
+    }
+}
+
+impl WatchedValue for StatsCache {
+    /// A new or modified file is re-read, a new folder adds its files, and
+    /// anything gone is subtracted
+    fn apply_change(&mut self, path: &Path) {
+        if is_ignored_path(&self.root, path) {
+            return;
+        }
+
+        if path.is_dir() {
+            for file in markdown_files_blocking(path) {
+                if let Ok(content) = std::fs::read_to_string(&file) {
+                    self.insert(&file, count_words(&content));
+                }
+            }
+        } else if path.is_file() {
+            if is_markdown_file(&path.to_string_lossy()) {
+                if let Ok(content) = std::fs::read_to_string(path) {
+                    self.insert(path, count_words(&content));
+                }
+            }
+        } else {
+            let gone: Vec<PathBuf> = self.words.keys().filter(|file| file.starts_with(path)).cloned().collect();
+            for file in gone {
+                self.remove(&file);
+            }
+        }
+    }
+}
+
+/// Stats of the workspace last asked about, updated incrementally by a
+/// file-system watcher once computed
+#[derive(Default, Clone)]
+pub struct CachedStats(WatchedCache<StatsCache>);
+
+impl CachedStats {
+    /// The totals for `root`, counting every markdown file and starting to
+    /// follow changes the first time. Replaces the cache of any previous
+    /// workspace.
+    pub(crate) async fn get_or_build(&self, root: &Path, progress: &Progress) -> Result<WorkspaceStats, MikuError> {
+        self.0.ensure(root, StatsCache::scan(root, progress)).await?;
+        Ok(self.get(root).unwrap_or_default())
+    }
+
+    /// The maintained totals for `root`, or `None` if it isn't cached
+    pub(crate) fn get(&self, root: &Path) -> Option<WorkspaceStats> {
+        self.0.read(root, StatsCache::totals)
+    }
+}
+
+/// File and word totals for `workspace_path`, counted from scratch. With an
+/// `operation_id`, reports progress and can be cancelled.
+#[tauri::command]
+pub async fn workspace_stats(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    workspace_path: String,
+    operation_id: Option<String>,
+) -> Result<WorkspaceStats, MikuError> {
+    let progress = Progress::for_operation(&app, &operations, operation_id)?;
+    workspace_stats_in(Path::new(&workspace_path), &progress).await
+}
+
+/// File and word totals for `workspace_path` as maintained by the watcher.
+/// The first call for a workspace counts everything and starts watching it;
+/// an `operation_id` reports progress of that count and lets it be cancelled.
+#[tauri::command]
+pub async fn get_cached_stats(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    workspace_path: String,
+    operation_id: Option<String>,
+    stats: State<'_, CachedStats>,
+) -> Result<WorkspaceStats, MikuError> {
+    let progress = Progress::for_operation(&app, &operations, operation_id)?;
+    stats.get_or_build(Path::new(&workspace_path), &progress).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_incremental_stats_match_full_recompute() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::write(root.join("a.md"), "one two three").unwrap();
+        std::fs::write(root.join("b.md"), "---\ntitle: B\n---\nfour five").unwrap();
+
+        let mut cache = StatsCache::new(root);
+        for file in markdown_files(root).await.unwrap() {
+            cache.apply_change(&file);
+        }
+        assert_eq!(cache.totals(), WorkspaceStats { file_count: 2, word_count: 5 });
+
+        // Create a file and a folder with a note in it
+        std::fs::write(root.join("c.md"), "six seven eight nine").unwrap();
+        cache.apply_change(&root.join("c.md"));
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/d.md"), "ten").unwrap();
+        cache.apply_change(&root.join("sub"));
+        assert_eq!(cache.totals(), workspace_stats_in(root, &Progress::none()).await.unwrap());
+
+        // Modify one, delete another, remove the folder and touch a non-note
+        std::fs::write(root.join("a.md"), "just one").unwrap();
+        cache.apply_change(&root.join("a.md"));
+        std::fs::remove_file(root.join("b.md")).unwrap();
+        cache.apply_change(&root.join("b.md"));
+        std::fs::remove_dir_all(root.join("sub")).unwrap();
+        cache.apply_change(&root.join("sub"));
+        std::fs::write(root.join("notes.txt"), "not counted").unwrap();
+        cache.apply_change(&root.join("notes.txt"));
+
+        let full = workspace_stats_in(root, &Progress::none()).await.unwrap();
+        assert_eq!(full, WorkspaceStats { file_count: 2, word_count: 6 });
+        assert_eq!(cache.totals(), full);
+    }
+
+    #[tokio::test]
+    async fn test_cached_stats_follow_the_watcher() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path().canonicalize().unwrap();
+        std::fs::write(root.join("a.md"), "one two").unwrap();
+
+        let stats = CachedStats::default();
+        assert!(stats.get(&root).is_none());
+        let first = stats.get_or_build(&root, &Progress::none()).await.unwrap();
+        assert_eq!(first, WorkspaceStats { file_count: 1, word_count: 2 });
+
+        std::fs::write(root.join("b.md"), "three four five").unwrap();
+        let expected = WorkspaceStats { file_count: 2, word_count: 5 };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while stats.get(&root) != Some(expected) && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(stats.get(&root), Some(expected));
+        // Already built: served from the cache without a rescan
+        assert_eq!(stats.get_or_build(&root, &Progress::none()).await.unwrap(), expected);
+    }
+}
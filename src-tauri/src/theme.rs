@@ -0,0 +1,375 @@
+// Theme module for Miku
+// Detects the OS appearance and loads built-in and custom themes
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, WebviewWindow, Window, WindowEvent};
+
+use crate::commands::{app_data_dir, MikuError, ThemePreference};
+
+/// Event fired whenever the OS switches between light and dark appearance.
+/// Payload is `"light"`, `"dark"`, or `"unknown"`.
+const SYSTEM_THEME_CHANGED_EVENT: &str = "theme://system-changed";
+
+/// Color tokens every theme must define, as `group.token`. Matches
+/// `REQUIRED_COLOR_TOKENS` in the frontend's theme validator.
+const REQUIRED_COLOR_KEYS: &[&str] = &[
+    "background.primary",
+    "background.secondary",
+    "background.tertiary",
+    "text.primary",
+    "text.secondary",
+    "text.tertiary",
+    "accent.primary",
+    "accent.subtle",
+    "border.default",
+    "border.subtle",
+    "border.focus",
+    "shadows.sm",
+    "shadows.md",
+    "shadows.lg",
+    "highlights.clarity",
+    "highlights.grammar",
+    "highlights.style",
+    "highlights.structure",
+    "highlights.economy",
+    "syntax.heading",
+    "syntax.emphasis",
+    "syntax.codeBackground",
+    "syntax.codeText",
+    "syntax.link",
+    "syntax.listMarker",
+];
+
+/// Manifest versions this build understands
+const SUPPORTED_THEME_VERSIONS: &[&str] = &["1.0"];
+
+/// Themes that ship with Miku: the built-in light and dark themes, then the
+/// presets. The frontend bundles the same files.
+const BUILTIN_THEME_FILES: &[&str] = &[
+    include_str!("../../src/themes/light.json"),
+    include_str!("../../src/themes/dark.json"),
+    include_str!("../../src/themes/gruvbox-dark.json"),
+    include_str!("../../src/themes/gruvbox-light.json"),
+    include_str!("../../src/themes/tokyo-night.json"),
+    include_str!("../../src/themes/tokyo-night-light.json"),
+    include_str!("../../src/themes/catppuccin-mocha.json"),
+    include_str!("../../src/themes/catppuccin-latte.json"),
+    include_str!("../../src/themes/nord.json"),
+    include_str!("../../src/themes/dracula.json"),
+    include_str!("../../src/themes/solarized-dark.json"),
+    include_str!("../../src/themes/solarized-light.json"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeVariant {
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ThemeAuthor {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// A theme manifest, in the same shape as the frontend's `ThemeManifest`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Theme {
+    pub version: String,
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub author: ThemeAuthor,
+    pub variant: ThemeVariant,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// Colors by group and token, e.g. `background` then `primary`
+    pub colors: HashMap<String, HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_css: Option<String>,
+}
+
+impl Theme {
+    /// Ensure the version is supported and every required color is present
+    fn validate(&self) -> Result<(), MikuError> {
+        if !SUPPORTED_THEME_VERSIONS.contains(&self.version.as_str()) {
+            return Err(MikuError::Path(format!(
+                "Theme '{}' has unsupported version {}",
+                self.id, self.version
+            )));
+        }
+
+        let missing: Vec<&str> = REQUIRED_COLOR_KEYS
+            .iter()
+            .copied()
+            .filter(|key| {
+                let (group, token) = key.split_once('.').unwrap_or_default();
+                !self.colors.get(group).is_some_and(|colors| colors.contains_key(token))
+            })
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MikuError::Path(format!(
+                "Theme '{}' is missing colors: {}",
+                self.id,
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+fn builtin_themes() -> Vec<Theme> {
+    BUILTIN_THEME_FILES
+        .iter()
+        .map(|content| serde_json::from_str(content).expect("built-in theme files are valid"))
+        .collect()
+}
+
+/// Custom themes live in `<app_data>/themes/<id>.json`
+fn themes_dir_in(data_dir: &Path) -> PathBuf {
+    data_dir.join("themes")
+}
+
+/// Load and validate a single custom theme file
+async fn load_theme_file(path: &Path) -> Result<Theme, MikuError> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let theme: Theme = serde_json::from_str(&content).map_err(|e| {
+        MikuError::Path(format!("Malformed theme file {}: {}", path.to_string_lossy(), e))
+    })?;
+    theme.validate()?;
+    Ok(theme)
+}
+
+pub(crate) async fn list_themes_in(data_dir: &Path) -> Result<Vec<Theme>, MikuError> {
+    let mut themes = builtin_themes();
+    let themes_dir = themes_dir_in(data_dir);
+
+    if !themes_dir.is_dir() {
+        return Ok(themes);
+    }
+
+    let mut custom = Vec::new();
+    let mut entries = tokio::fs::read_dir(&themes_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().map(|e| e != "json").unwrap_or(true) {
+            continue;
+        }
+        // A broken theme file shouldn't hide every other theme
+        match load_theme_file(&path).await {
+            Ok(theme) if !themes.iter().any(|t| t.id == theme.id) => custom.push(theme),
+            Ok(theme) => log::warn!("ignoring custom theme that shadows built-in '{}'", theme.id),
+            Err(err) => log::warn!("skipping theme {}: {}", path.to_string_lossy(), err),
+        }
+    }
+
+    custom.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    themes.extend(custom);
+
+    Ok(themes)
+}
+
+pub(crate) async fn get_theme_in(data_dir: &Path, id: &str) -> Result<Theme, MikuError> {
+    if let Some(theme) = builtin_themes().into_iter().find(|t| t.id == id) {
+        return Ok(theme);
+    }
+
+    if id.is_empty() || id.contains(['/', '\\']) || id.contains("..") {
+        return Err(MikuError::Path(format!("Invalid theme id '{}'", id)));
+    }
+
+    let path = themes_dir_in(data_dir).join(format!("{}.json", id));
+    if !path.exists() {
+        return Err(MikuError::NotFound(format!("Theme '{}'", id)));
+    }
+
+    load_theme_file(&path).await
+}
+
+/// List built-in themes plus any valid custom themes on disk
+#[tauri::command]
+pub async fn list_themes() -> Result<Vec<Theme>, MikuError> {
+    list_themes_in(&app_data_dir()?).await
+}
+
+/// Load a theme by ID, validating its colors
+#[tauri::command]
+pub async fn get_theme(id: String) -> Result<Theme, MikuError> {
+    get_theme_in(&app_data_dir()?, &id).await
+}
+
+pub(crate) async fn resolve_theme_in(
+    data_dir: &Path,
+    preference: &ThemePreference,
+    system: &str,
+) -> Result<String, MikuError> {
+    let is_dark = system == "dark";
+    let wanted = if preference.selected == "system" {
+        if is_dark {
+            &preference.dark_fallback
+        } else {
+            &preference.light_fallback
+        }
+    } else {
+        &preference.selected
+    };
+
+    let themes = list_themes_in(data_dir).await?;
+    if themes.iter().any(|t| &t.id == wanted) {
+        return Ok(wanted.clone());
+    }
+
+    // Fall back to the built-in matching the OS appearance
+    Ok(if is_dark { "dark" } else { "light" }.to_string())
+}
+
+/// Resolve the concrete theme ID to use from the user's preference and the
+/// current OS appearance (`"light"`, `"dark"`, or `"unknown"`)
+#[tauri::command]
+pub async fn resolve_theme(preference: ThemePreference, system: String) -> Result<String, MikuError> {
+    resolve_theme_in(&app_data_dir()?, &preference, &system).await
+}
+
+fn theme_name(theme: Option<tauri::Theme>) -> &'static str {
+    match theme {
+        Some(tauri::Theme::Light) => "light",
+        Some(tauri::Theme::Dark) => "dark",
+        _ => "unknown",
+    }
+}
+
+/// Get the current OS appearance: `"light"`, `"dark"`, or `"unknown"`
+#[tauri::command]
+pub fn get_system_theme(window: WebviewWindow) -> String {
+    theme_name(window.theme().ok()).to_string()
+}
+
+/// Forward OS appearance changes to the frontend so it can re-resolve the
+/// effective theme live.
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::ThemeChanged(theme) = event {
+        if let Err(err) = window.emit(SYSTEM_THEME_CHANGED_EVENT, theme_name(Some(*theme))) {
+            log::warn!("failed to emit {SYSTEM_THEME_CHANGED_EVENT}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_name_is_valid() {
+        let valid = ["light", "dark", "unknown"];
+        for theme in [Some(tauri::Theme::Light), Some(tauri::Theme::Dark), None] {
+            assert!(valid.contains(&theme_name(theme)));
+        }
+        assert_eq!(theme_name(Some(tauri::Theme::Dark)), "dark");
+        assert_eq!(theme_name(None), "unknown");
+    }
+
+    fn write_theme(data_dir: &Path, id: &str, colors: &[&str]) {
+        let themes_dir = themes_dir_in(data_dir);
+        std::fs::create_dir_all(&themes_dir).unwrap();
+        let mut groups: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+        for key in colors {
+            let (group, token) = key.split_once('.').unwrap();
+            groups.entry(group).or_default().insert(token, "#000000");
+        }
+        let theme = serde_json::json!({
+            "version": "1.0",
+            "id": id,
+            "name": "Midnight",
+            "description": "",
+            "author": { "name": "Someone" },
+            "variant": "dark",
+            "colors": groups,
+        });
+        std::fs::write(themes_dir.join(format!("{}.json", id)), theme.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_builtin_themes_are_valid() {
+        let themes = builtin_themes();
+        assert_eq!(themes.len(), BUILTIN_THEME_FILES.len());
+        for theme in &themes {
+            theme.validate().unwrap();
+        }
+        let ids: Vec<&str> = themes.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(&ids[..2], ["light", "dark"]);
+        assert!(ids.contains(&"nord") && ids.contains(&"solarized-light"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_theme_is_loaded() {
+        let data = tempfile::tempdir().unwrap();
+        write_theme(data.path(), "midnight", REQUIRED_COLOR_KEYS);
+
+        let themes = list_themes_in(data.path()).await.unwrap();
+        let ids: Vec<&str> = themes.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids.len(), BUILTIN_THEME_FILES.len() + 1);
+        assert_eq!(ids.last(), Some(&"midnight"));
+
+        let theme = get_theme_in(data.path(), "midnight").await.unwrap();
+        assert_eq!(theme.variant, ThemeVariant::Dark);
+        assert_eq!(theme.colors["syntax"].len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_theme_missing_required_keys_is_rejected() {
+        let data = tempfile::tempdir().unwrap();
+        write_theme(data.path(), "partial", &["background.primary", "text.primary"]);
+        std::fs::write(themes_dir_in(data.path()).join("broken.json"), "{ not json").unwrap();
+
+        let err = get_theme_in(data.path(), "partial").await.unwrap_err();
+        assert!(err.to_string().contains("accent.primary"));
+        let err = get_theme_in(data.path(), "broken").await.unwrap_err();
+        assert!(err.to_string().contains("Malformed theme file"));
+
+        // Invalid themes are left out of the list instead of failing it
+        let themes = list_themes_in(data.path()).await.unwrap();
+        assert_eq!(themes.len(), BUILTIN_THEME_FILES.len());
+    }
+
+    fn preference(selected: &str, light: &str, dark: &str) -> ThemePreference {
+        ThemePreference {
+            selected: selected.to_string(),
+            light_fallback: light.to_string(),
+            dark_fallback: dark.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_theme() {
+        let data = tempfile::tempdir().unwrap();
+        write_theme(data.path(), "midnight", REQUIRED_COLOR_KEYS);
+        let dir = data.path();
+
+        // Explicit selection ignores the system appearance
+        let explicit = preference("midnight", "light", "dark");
+        assert_eq!(resolve_theme_in(dir, &explicit, "light").await.unwrap(), "midnight");
+
+        let system = preference("system", "light", "midnight");
+        assert_eq!(resolve_theme_in(dir, &system, "light").await.unwrap(), "light");
+        assert_eq!(resolve_theme_in(dir, &system, "dark").await.unwrap(), "midnight");
+        assert_eq!(resolve_theme_in(dir, &system, "unknown").await.unwrap(), "light");
+
+        // Presets resolve like any other theme
+        let preset = preference("system", "solarized-light", "nord");
+        assert_eq!(resolve_theme_in(dir, &preset, "dark").await.unwrap(), "nord");
+        assert_eq!(resolve_theme_in(dir, &preset, "light").await.unwrap(), "solarized-light");
+
+        // Missing themes fall back to a built-in matching the system
+        let missing = preference("deleted-theme", "light", "dark");
+        assert_eq!(resolve_theme_in(dir, &missing, "dark").await.unwrap(), "dark");
+        let missing = preference("system", "gone", "gone");
+        assert_eq!(resolve_theme_in(dir, &missing, "light").await.unwrap(), "light");
+    }
+}
@@ -0,0 +1,323 @@
+// Recently deleted items for Miku
+// Remembers what was moved to the OS trash from within the app, so it can
+// be listed and put back without leaving Miku
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::commands::{app_data_dir, now_ms, quarantine_config, read_config_file, write_atomic, ConfigLock, MikuError};
+use crate::file_ids::forget_file_ids_in;
+use crate::file_ops::same_path;
+
+/// Name of the trash index inside the app data directory
+pub(crate) const TRASH_INDEX_FILE: &str = "trash_index.json";
+
+/// Something Miku moved to the trash
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TrashedItem {
+    pub original_path: String,
+    /// When it was trashed, in milliseconds since the Unix epoch
+    pub trashed_at: u64,
+    /// Where the item now lives inside the trash, when the platform tells us
+    /// (Linux and Windows). Items without one can't be restored from Miku.
+    pub trash_location: Option<String>,
+}
+
+pub(crate) async fn load_trash_index_in(data_dir: &Path) -> Result<Vec<TrashedItem>, MikuError> {
+    let index_path = data_dir.join(TRASH_INDEX_FILE);
+    let Some(content) = read_config_file(&index_path).await? else {
+        return Ok(Vec::new());
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(items) => Ok(items),
+        Err(err) => {
+            quarantine_config(&index_path, &content, &err).await?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+async fn save_trash_index_in(data_dir: &Path, items: &[TrashedItem]) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    write_atomic(&data_dir.join(TRASH_INDEX_FILE), serde_json::to_string_pretty(items)?).await
+}
+
+/// How long an item whose place in the trash is unknown (macOS) stays
+/// listed, since there's no way to tell when it leaves the trash
+const UNLOCATED_ITEM_TTL_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Where the trash put each of `originals`, which were just deleted. The
+/// paths must be canonical, as the trash reports them. Lists the trash once
+/// for the whole batch.
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+fn trash_locations(originals: &[PathBuf]) -> Vec<Option<PathBuf>> {
+    let listed = trash::os_limited::list().unwrap_or_default();
+    originals
+        .iter()
+        .map(|original| {
+            let item = listed
+                .iter()
+                .filter(|item| &item.original_path() == original)
+                .max_by_key(|item| item.time_deleted)?;
+            let id = PathBuf::from(&item.id);
+            // Freedesktop ids are the `.trashinfo` file; the item sits in `files`
+            match (id.extension(), id.parent().and_then(Path::parent), id.file_stem()) {
+                (Some(extension), Some(trash_dir), Some(name)) if extension == "trashinfo" => {
+                    Some(trash_dir.join("files").join(name))
+                }
+                _ => Some(id),
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn trash_locations(originals: &[PathBuf]) -> Vec<Option<PathBuf>> {
+    vec![None; originals.len()]
+}
+
+/// Remember that each `(original_path, trash_location)` was just trashed
+pub(crate) async fn record_trashed_in(
+    data_dir: &Path,
+    trashed: &[(String, Option<PathBuf>)],
+) -> Result<(), MikuError> {
+    let mut items = load_trash_index_in(data_dir).await?;
+    let trashed_at = now_ms();
+    items.splice(
+        0..0,
+        trashed.iter().rev().map(|(original_path, location)| TrashedItem {
+            original_path: original_path.clone(),
+            trashed_at,
+            trash_location: location.as_ref().map(|location| location.to_string_lossy().to_string()),
+        }),
+    );
+    save_trash_index_in(data_dir, &items).await
+}
+
+/// Move each of `paths` to the OS trash and add them to the trash index,
+/// returning the outcome per path in order
+pub(crate) async fn trash_paths_in(data_dir: &Path, paths: &[String]) -> Vec<Result<(), MikuError>> {
+    let targets = paths.to_vec();
+    let deleted = tokio::task::spawn_blocking(move || {
+        let mut results = Vec::with_capacity(targets.len());
+        let mut trashed = Vec::new();
+        for path in targets {
+            let target = Path::new(&path);
+            // Resolved before the item is gone, to match what the trash records
+            let Ok(canonical) = std::fs::canonicalize(target) else {
+                results.push(Err(MikuError::NotFound(path)));
+                continue;
+            };
+            match trash::delete(target) {
+                Ok(()) => {
+                    results.push(Ok(()));
+                    trashed.push((path, canonical));
+                }
+                Err(e) => results.push(Err(MikuError::Path(format!("Could not move to trash: {}", e)))),
+            }
+        }
+        let canonical: Vec<PathBuf> = trashed.iter().map(|(_, canonical)| canonical.clone()).collect();
+        let trashed: Vec<(String, Option<PathBuf>)> = trashed
+            .into_iter()
+            .map(|(path, _)| path)
+            .zip(trash_locations(&canonical))
+            .collect();
+        (results, trashed)
+    })
+    .await;
+
+    let (results, trashed) = match deleted {
+        Ok(deleted) => deleted,
+        Err(e) => return paths.iter().map(|_| Err(MikuError::Path(e.to_string()))).collect(),
+    };
+    // The items are in the trash either way; only the in-app list misses them
+    if let Err(err) = record_trashed_in(data_dir, &trashed).await {
+        log::warn!("failed to record {} trashed items: {}", trashed.len(), err);
+    }
+    results
+}
+
+/// Move `path` to the OS trash and add it to the trash index
+pub(crate) async fn trash_path_in(data_dir: &Path, path: &str) -> Result<(), MikuError> {
+    trash_paths_in(data_dir, &[path.to_string()])
+        .await
+        .pop()
+        .unwrap_or(Ok(()))
+}
+
+/// Whether the trashed item can still be found as of `now`. Items without a
+/// known location can't be checked, so they're kept for a while and then
+/// dropped.
+fn still_trashed(item: &TrashedItem, now: u64) -> bool {
+    match &item.trash_location {
+        Some(location) => Path::new(location).exists(),
+        None => now.saturating_sub(item.trashed_at) < UNLOCATED_ITEM_TTL_MS,
+    }
+}
+
+/// Items trashed from Miku, newest first. Entries whose item has since
+/// left the trash (emptied or restored elsewhere) are dropped, and so is
+/// the data kept by file id for items that are gone for good.
+pub(crate) async fn list_trashed_in(data_dir: &Path) -> Result<Vec<TrashedItem>, MikuError> {
+    let now = now_ms();
+    let (items, gone): (Vec<TrashedItem>, Vec<TrashedItem>) = load_trash_index_in(data_dir)
+        .await?
+        .into_iter()
+        .partition(|item| still_trashed(item, now));
+    if gone.is_empty() {
+        return Ok(items);
+    }
+
+    save_trash_index_in(data_dir, &items).await?;
+    for item in gone.iter().filter(|item| !Path::new(&item.original_path).exists()) {
+        forget_file_ids_in(data_dir, &item.original_path).await?;
+    }
+    Ok(items)
+}
+
+/// Remove the trash's own bookkeeping for an item moved out of it: the
+/// freedesktop `.trashinfo` file, or the Windows `$I` file
+fn forget_trash_metadata(location: &Path) {
+    let (Some(dir), Some(name)) = (location.parent(), location.file_name()) else {
+        return;
+    };
+    let name = name.to_string_lossy();
+    let metadata = if dir.file_name().is_some_and(|files| files == "files") {
+        dir.with_file_name("info").join(format!("{}.trashinfo", name))
+    } else if let Some(rest) = name.strip_prefix("$R") {
+        dir.join(format!("$I{}", rest))
+    } else {
+        return;
+    };
+    let _ = std::fs::remove_file(metadata);
+}
+
+/// Move the most recently trashed item from `original_path` back there.
+/// Fails with `AlreadyExists` when something new took its place.
+pub(crate) async fn restore_trashed_in(data_dir: &Path, original_path: &str) -> Result<(), MikuError> {
+    let mut items = list_trashed_in(data_dir).await?;
+    let index = items
+        .iter()
+        .position(|item| same_path(&item.original_path, original_path))
+        .ok_or_else(|| MikuError::NotFound(original_path.to_string()))?;
+    let Some(location) = items[index].trash_location.clone().map(PathBuf::from) else {
+        return Err(MikuError::Path(format!(
+            "{} can only be restored from the system trash",
+            original_path
+        )));
+    };
+
+    let original = Path::new(original_path);
+    if original.exists() {
+        return Err(MikuError::AlreadyExists(original_path.to_string()));
+    }
+    if let Some(parent) = original.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| MikuError::from_io(e, parent))?;
+    }
+    tokio::fs::rename(&location, original)
+        .await
+        .map_err(|e| MikuError::from_io(e, &location))?;
+    forget_trash_metadata(&location);
+
+    items.remove(index);
+    save_trash_index_in(data_dir, &items).await
+}
+
+/// Items moved to the trash from Miku that are still there, newest first
+#[tauri::command]
+pub async fn list_trashed(lock: State<'_, ConfigLock>) -> Result<Vec<TrashedItem>, MikuError> {
+    let _guard = lock.acquire().await;
+    list_trashed_in(&app_data_dir()?).await
+}
+
+/// Put the item last trashed from `original_path` back where it was,
+/// unless something else exists there now
+#[tauri::command]
+pub async fn restore_trashed(original_path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    restore_trashed_in(&app_data_dir()?, &original_path).await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Move `path` into a freedesktop-style trash under `trash_dir`, the
+    /// way the OS trash would, and record it
+    pub(crate) async fn fake_trash(data_dir: &Path, trash_dir: &Path, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        std::fs::create_dir_all(trash_dir.join("files")).unwrap();
+        std::fs::create_dir_all(trash_dir.join("info")).unwrap();
+        let location = trash_dir.join("files").join(&name);
+        std::fs::rename(path, &location).unwrap();
+        std::fs::write(trash_dir.join("info").join(format!("{}.trashinfo", name)), "[Trash Info]").unwrap();
+        record_trashed_in(data_dir, &[(path.to_string_lossy().to_string(), Some(location.clone()))])
+            .await
+            .unwrap();
+        location
+    }
+
+    #[tokio::test]
+    async fn test_trash_then_restore() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let trash_dir = tempfile::tempdir().unwrap();
+        let note = vault.path().join("note.md");
+        std::fs::write(&note, "# Keep me").unwrap();
+        let note_path = note.to_string_lossy().to_string();
+
+        let location = fake_trash(data.path(), trash_dir.path(), &note).await;
+        let trashed = list_trashed_in(data.path()).await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].original_path, note_path);
+
+        // Something new in its place blocks the restore
+        std::fs::write(&note, "new").unwrap();
+        assert!(matches!(
+            restore_trashed_in(data.path(), &note_path).await,
+            Err(MikuError::AlreadyExists(_))
+        ));
+        std::fs::remove_file(&note).unwrap();
+
+        restore_trashed_in(data.path(), &note_path).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "# Keep me");
+        assert!(!location.exists());
+        assert!(!trash_dir.path().join("info/note.md.trashinfo").exists());
+        assert!(list_trashed_in(data.path()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_emptied_items_are_pruned() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let trash_dir = tempfile::tempdir().unwrap();
+        for name in ["kept.md", "emptied.md"] {
+            std::fs::write(vault.path().join(name), "").unwrap();
+        }
+        fake_trash(data.path(), trash_dir.path(), &vault.path().join("kept.md")).await;
+        let emptied = fake_trash(data.path(), trash_dir.path(), &vault.path().join("emptied.md")).await;
+        std::fs::remove_file(emptied).unwrap();
+
+        let trashed = list_trashed_in(data.path()).await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert!(trashed[0].original_path.ends_with("kept.md"));
+        assert_eq!(load_trash_index_in(data.path()).await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unlocated_items_age_out() {
+        let item = TrashedItem { original_path: "/notes/a.md".to_string(), trashed_at: 1_000, trash_location: None };
+        assert!(still_trashed(&item, 1_000 + UNLOCATED_ITEM_TTL_MS - 1));
+        assert!(!still_trashed(&item, 1_000 + UNLOCATED_ITEM_TTL_MS));
+    }
+}
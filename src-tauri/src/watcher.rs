@@ -0,0 +1,412 @@
+// File watching for Miku
+// Notifies the frontend when the open document is changed outside the app,
+// and keeps workspace-wide caches current as files change
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::{content_hash, MikuError};
+
+/// Quiet period after the last change before the file is re-read
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Last content hash known to a single-file watcher, whether we wrote it or
+/// already reported it. Changes matching it are not reported.
+type KnownHash = Arc<Mutex<Option<u64>>>;
+
+/// The known hash of every live single-file watcher, by watched path.
+/// Entries are removed when their watcher stops, so only files being
+/// watched take up room.
+fn watched_files() -> &'static Mutex<HashMap<PathBuf, Vec<KnownHash>>> {
+    static WATCHED: OnceLock<Mutex<HashMap<PathBuf, Vec<KnownHash>>>> = OnceLock::new();
+    WATCHED.get_or_init(Default::default)
+}
+
+/// Normalized key for `path`. The parent is canonicalized rather than the
+/// file itself so the key is stable for files that don't exist yet.
+fn watch_key(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => std::fs::canonicalize(parent)
+            .unwrap_or_else(|_| parent.to_path_buf())
+            .join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Record content we've just written ourselves, so the resulting file
+/// system event isn't reported as an external change. Call it only once
+/// the write has succeeded; a no-op for files nobody is watching.
+pub(crate) async fn record_write(path: &Path, hash: u64) {
+    let path = path.to_path_buf();
+    let Ok(key) = tokio::task::spawn_blocking(move || watch_key(&path)).await else {
+        return;
+    };
+    let watched = watched_files().lock().unwrap_or_else(|e| e.into_inner());
+    for known in watched.get(&key).into_iter().flatten() {
+        *known.lock().unwrap_or_else(|e| e.into_inner()) = Some(hash);
+    }
+}
+
+/// Re-read `path` and return its hash if it differs from the last known one
+fn detect_change(path: &Path, known: &Mutex<Option<u64>>) -> Option<u64> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let hash = content_hash(&content);
+
+    let mut known = known.lock().unwrap_or_else(|e| e.into_inner());
+    if *known == Some(hash) {
+        return None;
+    }
+    *known = Some(hash);
+    Some(hash)
+}
+
+/// Watch a single file, calling `on_change` with the new content hash when
+/// it's modified by someone else. Watching stops when the returned watcher
+/// is dropped.
+pub(crate) fn watch_single_file(
+    path: &Path,
+    on_change: impl Fn(u64) + Send + 'static,
+) -> Result<RecommendedWatcher, MikuError> {
+    let target = watch_key(path);
+    let parent = target
+        .parent()
+        .ok_or_else(|| MikuError::Path(format!("Cannot watch {}", path.display())))?
+        .to_path_buf();
+
+    let baseline = std::fs::read_to_string(&target).ok().map(|content| content_hash(&content));
+    let known: KnownHash = Arc::new(Mutex::new(baseline));
+
+    let (tx, rx) = mpsc::channel::<()>();
+    let event_target = target.clone();
+    // Watch the parent directory so editors that save by renaming a temp
+    // file over the original are still picked up
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+            if relevant && event.paths.iter().any(|p| p == &event_target) {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .map_err(std::io::Error::other)?;
+    watcher
+        .watch(&parent, RecursiveMode::NonRecursive)
+        .map_err(std::io::Error::other)?;
+
+    watched_files()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(target.clone())
+        .or_default()
+        .push(known.clone());
+
+    // Exits once the watcher (and with it the sender) is dropped
+    std::thread::spawn(move || {
+        while rx.recv().is_ok() {
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+            if let Some(hash) = detect_change(&target, &known) {
+                on_change(hash);
+            }
+        }
+
+        let mut watched = watched_files().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entries) = watched.get_mut(&target) {
+            entries.retain(|entry| !Arc::ptr_eq(entry, &known));
+            if entries.is_empty() {
+                watched.remove(&target);
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// Something computed from the files under a workspace root (an index,
+/// totals, ...) that can be updated one changed path at a time
+pub(crate) trait WatchedValue: Send + 'static {
+    /// Bring the value up to date after a change on disk at `path`
+    fn apply_change(&mut self, path: &Path);
+}
+
+enum Slot<T> {
+    Empty,
+    /// The initial scan is running. Paths changed meanwhile are applied
+    /// once it's done.
+    Scanning(Vec<PathBuf>),
+    Ready { root: PathBuf, value: T },
+}
+
+struct WatchedState<T> {
+    slot: Mutex<Slot<T>>,
+    /// Keeps the value current while alive
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Held for the whole of a build, so concurrent builds run one at a time
+    building: tokio::sync::Mutex<()>,
+}
+
+/// A `WatchedValue` for one workspace root at a time: built by a full scan,
+/// then kept current by a file-system watcher
+pub(crate) struct WatchedCache<T>(Arc<WatchedState<T>>);
+
+impl<T> Default for WatchedCache<T> {
+    fn default() -> Self {
+        WatchedCache(Arc::new(WatchedState {
+            slot: Mutex::new(Slot::Empty),
+            watcher: Mutex::new(None),
+            building: tokio::sync::Mutex::new(()),
+        }))
+    }
+}
+
+impl<T> Clone for WatchedCache<T> {
+    fn clone(&self) -> Self {
+        WatchedCache(self.0.clone())
+    }
+}
+
+impl<T: WatchedValue> WatchedCache<T> {
+    /// Build the value for `root` with `scan`, replacing any previous one
+    pub(crate) async fn rebuild(
+        &self,
+        root: &Path,
+        scan: impl Future<Output = Result<T, MikuError>>,
+    ) -> Result<(), MikuError> {
+        let _building = self.0.building.lock().await;
+        self.build(root, scan).await
+    }
+
+    /// Build the value for `root` with `scan` unless it's already built.
+    /// Callers arriving during a build wait for it rather than scanning again.
+    pub(crate) async fn ensure(
+        &self,
+        root: &Path,
+        scan: impl Future<Output = Result<T, MikuError>>,
+    ) -> Result<(), MikuError> {
+        let _building = self.0.building.lock().await;
+        if self.read(root, |_| ()).is_some() {
+            return Ok(());
+        }
+        self.build(root, scan).await
+    }
+
+    async fn build(&self, root: &Path, scan: impl Future<Output = Result<T, MikuError>>) -> Result<(), MikuError> {
+        if !root.is_dir() {
+            return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+        }
+        self.clear();
+
+        let state = Arc::downgrade(&self.0);
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let (Ok(event), Some(state)) = (res, state.upgrade()) else {
+                return;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            let mut slot = state.slot.lock().unwrap_or_else(|e| e.into_inner());
+            match &mut *slot {
+                Slot::Scanning(pending) => pending.extend(event.paths),
+                Slot::Ready { value, .. } => {
+                    for path in &event.paths {
+                        value.apply_change(path);
+                    }
+                }
+                Slot::Empty => {}
+            }
+        })
+        .map_err(std::io::Error::other)?;
+
+        // Watch before scanning and hold on to what changes meanwhile, so
+        // nothing written during the scan is lost
+        *self.0.slot.lock().unwrap_or_else(|e| e.into_inner()) = Slot::Scanning(Vec::new());
+        let scanned = match watcher.watch(root, RecursiveMode::Recursive) {
+            Ok(()) => scan.await,
+            Err(err) => Err(std::io::Error::other(err).into()),
+        };
+
+        let mut slot = self.0.slot.lock().unwrap_or_else(|e| e.into_inner());
+        let pending = match std::mem::replace(&mut *slot, Slot::Empty) {
+            Slot::Scanning(pending) => pending,
+            _ => Vec::new(),
+        };
+        let mut value = scanned?;
+        for path in &pending {
+            value.apply_change(path);
+        }
+        *slot = Slot::Ready {
+            root: root.to_path_buf(),
+            value,
+        };
+        drop(slot);
+        *self.0.watcher.lock().unwrap_or_else(|e| e.into_inner()) = Some(watcher);
+        Ok(())
+    }
+
+    /// Run `f` on the value if it's built for `root`
+    pub(crate) fn read<R>(&self, root: &Path, f: impl FnOnce(&T) -> R) -> Option<R> {
+        match &*self.0.slot.lock().unwrap_or_else(|e| e.into_inner()) {
+            Slot::Ready { root: built, value } if built == root => Some(f(value)),
+            _ => None,
+        }
+    }
+
+    /// Drop the value and stop watching
+    pub(crate) fn clear(&self) {
+        *self.0.watcher.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.0.slot.lock().unwrap_or_else(|e| e.into_inner()) = Slot::Empty;
+    }
+}
+
+/// Active single-file watchers, keyed by the path passed to `watch_file`
+#[derive(Default)]
+pub struct FileWatchers(Mutex<HashMap<String, RecommendedWatcher>>);
+
+/// Payload of the `file://external-change` event
+#[derive(Debug, Serialize, Clone)]
+pub struct ExternalChangeEvent {
+    pub path: String,
+    /// Hex-encoded hash of the new content
+    pub hash: String,
+}
+
+/// Start emitting `file://external-change` when `path` is modified outside
+/// the app. Watching an already watched path is a no-op.
+#[tauri::command]
+pub fn watch_file(app: AppHandle, path: String, watchers: State<'_, FileWatchers>) -> Result<(), MikuError> {
+    let mut watchers = watchers.0.lock().unwrap_or_else(|e| e.into_inner());
+    if watchers.contains_key(&path) {
+        return Ok(());
+    }
+
+    let event_path = path.clone();
+    let watcher = watch_single_file(Path::new(&path), move |hash| {
+        let _ = app.emit(
+            "file://external-change",
+            ExternalChangeEvent {
+                path: event_path.clone(),
+                hash: format!("{:016x}", hash),
+            },
+        );
+    })?;
+    watchers.insert(path, watcher);
+    Ok(())
+}
+
+/// Stop watching `path`
+#[tauri::command]
+pub fn unwatch_file(path: String, watchers: State<'_, FileWatchers>) {
+    watchers
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::write_document;
+
+    /// Every path the watcher reported
+    #[derive(Default)]
+    struct Changes(Vec<PathBuf>);
+
+    impl WatchedValue for Changes {
+        fn apply_change(&mut self, path: &Path) {
+            self.0.push(path.to_path_buf());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_changes_during_scan_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let cache = WatchedCache::<Changes>::default();
+
+        let state = cache.0.clone();
+        let written = root.join("during.md");
+        let scan = async {
+            std::fs::write(&written, "written mid-scan").unwrap();
+            // Finish only once the watcher has buffered the write
+            let deadline = std::time::Instant::now() + Duration::from_secs(5);
+            while std::time::Instant::now() < deadline {
+                let buffered = matches!(&*state.slot.lock().unwrap(), Slot::Scanning(pending) if !pending.is_empty());
+                if buffered {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            Ok(Changes::default())
+        };
+        cache.rebuild(&root, scan).await.unwrap();
+
+        assert_eq!(cache.read(&root, |changes| changes.0.contains(&written)), Some(true));
+
+        // Concurrent builds of the same root scan once
+        cache.clear();
+        let scans = std::sync::atomic::AtomicUsize::new(0);
+        let scan = || async {
+            scans.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(Changes::default())
+        };
+        let (first, second) = tokio::join!(cache.ensure(&root, scan()), cache.ensure(&root, scan()));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(scans.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        assert!(cache.read(&root.join("elsewhere"), |_| ()).is_none());
+        cache.clear();
+        assert!(cache.read(&root, |_| ()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_external_write_is_reported_but_own_save_is_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.md");
+        std::fs::write(&path, "original").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = watch_single_file(&path, move |hash| {
+            let _ = tx.send(hash);
+        })
+        .unwrap();
+
+        write_document(path.to_string_lossy().to_string(), "ours".to_string())
+            .await
+            .unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(600)).is_err());
+
+        std::fs::write(&path, "theirs").unwrap();
+        let hash = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(hash, content_hash("theirs"));
+    }
+
+    #[tokio::test]
+    async fn test_stopped_watchers_are_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.md");
+        std::fs::write(&path, "original").unwrap();
+        let key = watch_key(&path);
+        let is_registered = || watched_files().lock().unwrap().contains_key(&key);
+
+        let watcher = watch_single_file(&path, |_| {}).unwrap();
+        assert!(is_registered());
+
+        drop(watcher);
+        for _ in 0..50 {
+            if !is_registered() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("watcher entry was not removed");
+    }
+}
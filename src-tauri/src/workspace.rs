@@ -1,13 +1,43 @@
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::future::Future;
-use crate::commands::MikuError;
+use std::time::{Duration, Instant, SystemTime};
+use crate::commands::{
+    add_recent_file_in, app_data_dir, append_to_file, content_hash, content_hash_hex, emit_recents_changed,
+    emit_recovered_configs, io_concurrency_or_saved, markdown_options_or_saved, max_tree_depth, move_recent_file_in,
+    now_ms, quarantine_config, read_document, settings_or_default, with_read_timeout, write_atomic, ConfigLock,
+    DirtyDocuments, Document, MikuError, ReadonlyDocuments, DEFAULT_MAX_TREE_DEPTH,
+};
+use crate::export::{merge_documents_with_offsets, section_title};
+use crate::file_ids::{forget_file_ids_in, move_file_ids_in};
+use crate::file_ops::{equivalent_entry, is_markdown_extension, is_markdown_file, normalize_name, same_path};
+use crate::markdown::{
+    count_words, document_links, extract_tasks, frontmatter_keys, frontmatter_tags, frontmatter_title,
+    heading_anchors_in, lint_content, merge_frontmatter, parse_task_line, reference_definitions, rename_tag_in_content,
+    set_frontmatter_tags, slugify_text, LintFinding, LintRule, MarkdownOptions,
+};
+use crate::progress::{Operations, Progress};
+use crate::search::{markdown_files, SearchIndex};
+use crate::trash_index::{trash_path_in, trash_paths_in};
+use crate::watcher::record_write;
+use tauri::{AppHandle, Emitter, State};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Workspace {
     pub path: String,
     pub name: String,
+    /// File that was last opened in this workspace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_opened_file: Option<String>,
+    /// Pinned workspaces sort to the top and are never evicted from recents
+    #[serde(default)]
+    pub pinned: bool,
+    /// Custom name shown instead of the folder name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,6 +48,25 @@ pub struct WorkspaceFile {
     pub is_directory: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<WorkspaceFile>>,
+    /// Path relative to the workspace root, for display
+    #[serde(rename = "relativePath", default, skip_serializing_if = "Option::is_none")]
+    pub relative_path: Option<String>,
+    /// Words in a markdown file, or in all markdown files below a folder.
+    /// Only filled in when the listing was asked for word counts.
+    #[serde(rename = "wordCount", default, skip_serializing_if = "Option::is_none")]
+    pub word_count: Option<usize>,
+    /// Entries directly inside a folder that the tree leaves out: files it
+    /// doesn't show and pruned empty folders. Hidden dotfiles and tooling
+    /// folders aren't counted.
+    #[serde(rename = "hiddenCount", default, skip_serializing_if = "Option::is_none")]
+    pub hidden_count: Option<usize>,
+    /// A folder nested too deeply to be listed; its children are left out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// The frontmatter `title` of a file, or its name without the
+    /// extension. Only filled in when the listing was asked for titles.
+    #[serde(rename = "displayTitle", default, skip_serializing_if = "Option::is_none")]
+    pub display_title: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -26,61 +75,126 @@ pub struct WorkspaceConfig {
     pub recent_workspaces: Vec<Workspace>,
 }
 
-/// Get the workspace config file path
-fn get_workspace_config_path() -> Result<PathBuf, MikuError> {
-    dirs::data_dir()
-        .map(|p| p.join("miku").join("workspace_config.json"))
-        .ok_or_else(|| MikuError::Path("Could not determine app data directory".to_string()))
+/// Get the workspace config file path inside `data_dir`
+pub(crate) fn workspace_config_path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join("workspace_config.json")
 }
 
-/// Load workspace configuration
-async fn load_workspace_config() -> Result<WorkspaceConfig, MikuError> {
-    let config_path = get_workspace_config_path()?;
+/// Load workspace configuration from `data_dir`
+pub(crate) async fn load_workspace_config_in(data_dir: &Path) -> Result<WorkspaceConfig, MikuError> {
+    let config_path = workspace_config_path_in(data_dir);
 
     if config_path.exists() {
         let content = tokio::fs::read_to_string(&config_path).await?;
-        let config: WorkspaceConfig = serde_json::from_str(&content)?;
-        Ok(config)
+        match serde_json::from_str(&content) {
+            Ok(config) => Ok(config),
+            Err(err) => {
+                quarantine_config(&config_path, &content, &err).await?;
+                Ok(WorkspaceConfig::default())
+            }
+        }
     } else {
         Ok(WorkspaceConfig::default())
     }
 }
 
-/// Save workspace configuration
-async fn save_workspace_config(config: &WorkspaceConfig) -> Result<(), MikuError> {
-    let config_path = get_workspace_config_path()?;
-
-    if let Some(parent) = config_path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
-    }
+/// Save workspace configuration into `data_dir`
+pub(crate) async fn save_workspace_config_in(data_dir: &Path, config: &WorkspaceConfig) -> Result<(), MikuError> {
+    tokio::fs::create_dir_all(data_dir).await?;
 
     let content = serde_json::to_string_pretty(config)?;
-    tokio::fs::write(&config_path, content).await?;
-
-    Ok(())
+    write_atomic(&workspace_config_path_in(data_dir), content).await
 }
 
-/// Get workspace info from a path
-#[tauri::command]
-pub async fn get_workspace_info(path: String) -> Result<Workspace, MikuError> {
+/// Build workspace info from a path, naming it after its folder
+fn workspace_from_path(path: String) -> Workspace {
     let path_obj = Path::new(&path);
     let name = path_obj
         .file_name()
         .map(|n| n.to_string_lossy().to_string())
         .unwrap_or_else(|| "Workspace".to_string());
 
-    Ok(Workspace { path, name })
+    Workspace {
+        path,
+        name,
+        last_opened_file: None,
+        pinned: false,
+        display_name: None,
+    }
 }
 
-/// Get current workspace
+/// `path` made absolute against the current directory, with `.` and `..`
+/// resolved, so a stored workspace doesn't depend on where the app was
+/// started. Symlinks are kept as written.
+fn absolute_workspace_path(path: &str) -> Result<String, MikuError> {
+    use std::path::Component;
+
+    let path = Path::new(path);
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let mut absolute = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            other => absolute.push(other),
+        }
+    }
+    Ok(absolute.to_string_lossy().to_string())
+}
+
+/// Prefer the custom display name over the folder name
+fn apply_display_name(mut workspace: Workspace) -> Workspace {
+    if let Some(display_name) = &workspace.display_name {
+        workspace.name = display_name.clone();
+    }
+    workspace
+}
+
+pub(crate) async fn get_workspace_info_in(data_dir: &Path, path: String) -> Result<Workspace, MikuError> {
+    let path = absolute_workspace_path(&path)?;
+    let config = load_workspace_config_in(data_dir).await?;
+
+    let workspace = match config.recent_workspaces.into_iter().find(|w| same_path(&w.path, &path)) {
+        Some(workspace) => workspace,
+        None => workspace_from_path(path),
+    };
+
+    Ok(apply_display_name(workspace))
+}
+
+/// Get workspace info from a path. Relative paths are resolved against the
+/// current directory.
 #[tauri::command]
-pub async fn get_current_workspace() -> Result<Option<Workspace>, MikuError> {
-    let config = load_workspace_config().await?;
+pub async fn get_workspace_info(path: String) -> Result<Workspace, MikuError> {
+    get_workspace_info_in(&app_data_dir()?, path).await
+}
+
+/// Forget the last opened file if it has since been deleted or moved
+fn drop_missing_last_opened(mut workspace: Workspace) -> Workspace {
+    if let Some(file) = &workspace.last_opened_file {
+        if !Path::new(file).exists() {
+            workspace.last_opened_file = None;
+        }
+    }
+    workspace
+}
+
+pub(crate) async fn get_current_workspace_in(data_dir: &Path) -> Result<Option<Workspace>, MikuError> {
+    let config = load_workspace_config_in(data_dir).await?;
 
     if let Some(path) = config.current_workspace {
         // Verify the path still exists
         if Path::new(&path).exists() {
-            let workspace = get_workspace_info(path).await?;
+            let workspace = match config.recent_workspaces.into_iter().find(|w| same_path(&w.path, &path)) {
+                Some(workspace) => apply_display_name(drop_missing_last_opened(workspace)),
+                None => workspace_from_path(path),
+            };
             return Ok(Some(workspace));
         }
     }
@@ -88,264 +202,5739 @@ pub async fn get_current_workspace() -> Result<Option<Workspace>, MikuError> {
     Ok(None)
 }
 
-/// Set current workspace
-#[tauri::command]
-pub async fn set_workspace(path: String) -> Result<(), MikuError> {
-    let mut config = load_workspace_config().await?;
+pub(crate) async fn set_workspace_in(data_dir: &Path, path: String) -> Result<(), MikuError> {
+    let path = absolute_workspace_path(&path)?;
+    let mut config = load_workspace_config_in(data_dir).await?;
 
     // Update current workspace
     config.current_workspace = Some(path.clone());
 
-    // Add to recent workspaces if not already there
-    let workspace = get_workspace_info(path).await?;
-    config.recent_workspaces.retain(|w| w.path != workspace.path);
-    config.recent_workspaces.insert(0, workspace);
-    config.recent_workspaces.truncate(10);
+    // Move to the front of recent workspaces, keeping anything remembered
+    // about it. Pinned workspaces keep their slot so pin order is stable.
+    match config.recent_workspaces.iter().position(|w| same_path(&w.path, &path)) {
+        Some(index) if config.recent_workspaces[index].pinned => {}
+        Some(index) => {
+            let workspace = config.recent_workspaces.remove(index);
+            config.recent_workspaces.insert(0, workspace);
+        }
+        None => config.recent_workspaces.insert(0, workspace_from_path(path)),
+    }
+    let limit = settings_or_default(data_dir).await.recent_workspaces_limit();
+    truncate_recent_workspaces(&mut config.recent_workspaces, limit);
 
-    save_workspace_config(&config).await?;
+    save_workspace_config_in(data_dir, &config).await
+}
 
-    Ok(())
+/// Keep at most `max` unpinned workspaces; pinned ones are always kept
+fn truncate_recent_workspaces(workspaces: &mut Vec<Workspace>, max: usize) {
+    let mut kept = 0;
+    workspaces.retain(|w| {
+        if w.pinned {
+            return true;
+        }
+        kept += 1;
+        kept <= max
+    });
 }
 
-/// Get recent workspaces
-#[tauri::command]
-pub async fn get_recent_workspaces() -> Result<Vec<Workspace>, MikuError> {
-    let config = load_workspace_config().await?;
+/// Drop recent workspaces whose folder is gone and merge entries naming the
+/// same folder, e.g. with a trailing slash or, where the filesystem ignores
+/// case, in another case. Returns whether anything changed.
+fn prune_recent_workspaces(workspaces: &mut Vec<Workspace>) -> bool {
+    let mut changed = false;
+    let mut kept: Vec<Workspace> = Vec::with_capacity(workspaces.len());
+
+    for mut workspace in workspaces.drain(..) {
+        if !Path::new(&workspace.path).exists() {
+            changed = true;
+            continue;
+        }
+        if let Ok(path) = absolute_workspace_path(&workspace.path) {
+            changed |= path != workspace.path;
+            workspace.path = path;
+        }
+
+        // The earlier entry is the more recent one; it keeps its slot and
+        // picks up anything only the duplicate remembered
+        match kept.iter_mut().find(|w| same_path(&w.path, &workspace.path)) {
+            Some(existing) => {
+                changed = true;
+                existing.pinned |= workspace.pinned;
+                existing.display_name = existing.display_name.take().or(workspace.display_name);
+                existing.last_opened_file = existing.last_opened_file.take().or(workspace.last_opened_file);
+            }
+            None => kept.push(workspace),
+        }
+    }
+
+    *workspaces = kept;
+    changed
+}
+
+/// Recent workspaces, pinned first. Missing folders and duplicates are
+/// pruned from the stored list as well, so they aren't read again.
+pub(crate) async fn get_recent_workspaces_in(data_dir: &Path) -> Result<Vec<Workspace>, MikuError> {
+    let mut config = load_workspace_config_in(data_dir).await?;
+    if prune_recent_workspaces(&mut config.recent_workspaces) {
+        save_workspace_config_in(data_dir, &config).await?;
+    }
 
-    // Filter to only existing workspaces
     let valid_workspaces: Vec<Workspace> = config
         .recent_workspaces
         .into_iter()
-        .filter(|w| Path::new(&w.path).exists())
+        .map(drop_missing_last_opened)
+        .map(apply_display_name)
         .collect();
 
-    Ok(valid_workspaces)
+    // Pinned workspaces first (in pin order), then the rest by recency
+    let (mut workspaces, unpinned): (Vec<Workspace>, Vec<Workspace>) =
+        valid_workspaces.into_iter().partition(|w| w.pinned);
+    workspaces.extend(unpinned);
+
+    Ok(workspaces)
 }
 
-/// List files in a workspace
-#[tauri::command]
-pub async fn list_workspace_files(workspace_path: String) -> Result<Vec<WorkspaceFile>, MikuError> {
-    let path = Path::new(&workspace_path);
+/// Order of `get_recent_workspaces`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceSort {
+    /// Most recently selected first
+    #[default]
+    SelectionOrder,
+    /// Most recently modified workspace folder first
+    FolderModified,
+}
 
-    if !path.exists() {
-        return Err(MikuError::Path("Workspace path does not exist".to_string()));
+/// How long a workspace folder's mtime is reused before it's read again
+const FOLDER_MTIME_TTL: Duration = Duration::from_secs(10);
+
+/// Recently read workspace folder mtimes, so re-rendering the recent list
+/// doesn't stat every folder each time
+#[derive(Default)]
+pub struct FolderMtimeCache(std::sync::Mutex<HashMap<String, (Option<SystemTime>, Instant)>>);
+
+impl FolderMtimeCache {
+    fn mtime(&self, path: &str) -> Option<SystemTime> {
+        let mut cache = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((mtime, read_at)) = cache.get(path) {
+            if read_at.elapsed() < FOLDER_MTIME_TTL {
+                return *mtime;
+            }
+        }
+        let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        cache.insert(path.to_string(), (mtime, Instant::now()));
+        mtime
     }
+}
 
-    list_directory(path, true).await
+/// Sort `workspaces` by `sort`. Pinned workspaces stay on top in pin order;
+/// only the rest are reordered.
+fn sort_recent_workspaces(workspaces: &mut [Workspace], sort: WorkspaceSort, mtimes: &FolderMtimeCache) {
+    if sort == WorkspaceSort::SelectionOrder {
+        return;
+    }
+    let pinned = workspaces.iter().take_while(|w| w.pinned).count();
+    // Stable, so folders with equal mtimes keep their selection order
+    workspaces[pinned..].sort_by_key(|w| std::cmp::Reverse(mtimes.mtime(&w.path)));
 }
 
-/// Recursively list directory contents
-/// Uses Box::pin to handle async recursion
-fn list_directory(path: &Path, is_root: bool) -> Pin<Box<dyn Future<Output = Result<Vec<WorkspaceFile>, MikuError>> + Send + '_>> {
-    Box::pin(async move {
-        let mut files = Vec::new();
-        let mut entries = tokio::fs::read_dir(path).await?;
+pub(crate) async fn pin_workspace_in(data_dir: &Path, path: String) -> Result<(), MikuError> {
+    let path = absolute_workspace_path(&path)?;
+    let mut config = load_workspace_config_in(data_dir).await?;
 
-        while let Some(entry) = entries.next_entry().await? {
-            let entry_path = entry.path();
-            let file_name = entry.file_name().to_string_lossy().to_string();
+    let mut workspace = match config.recent_workspaces.iter().position(|w| same_path(&w.path, &path)) {
+        Some(index) if config.recent_workspaces[index].pinned => return Ok(()),
+        Some(index) => config.recent_workspaces.remove(index),
+        None => workspace_from_path(path),
+    };
+    workspace.pinned = true;
 
-            // Skip hidden files and common non-content directories
-            if file_name.starts_with('.') || file_name == "node_modules" || file_name == "target" {
-                continue;
-            }
+    // Place it after the last pinned workspace so it sorts last in pin order
+    let insert_at = config
+        .recent_workspaces
+        .iter()
+        .rposition(|w| w.pinned)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    config.recent_workspaces.insert(insert_at, workspace);
 
-            let metadata = entry.metadata().await?;
-            let is_directory = metadata.is_dir();
+    save_workspace_config_in(data_dir, &config).await
+}
 
-            if is_directory {
-                // Recursively list subdirectories
-                let children = list_directory(&entry_path, false).await.ok();
+pub(crate) async fn unpin_workspace_in(data_dir: &Path, path: &str) -> Result<(), MikuError> {
+    let path = absolute_workspace_path(path)?;
+    let mut config = load_workspace_config_in(data_dir).await?;
 
-                // Only include directories that have markdown files or subdirectories
-                let has_content = children.as_ref().map(|c| !c.is_empty()).unwrap_or(false);
+    if let Some(workspace) = config.recent_workspaces.iter_mut().find(|w| same_path(&w.path, &path)) {
+        workspace.pinned = false;
+    }
+    let limit = settings_or_default(data_dir).await.recent_workspaces_limit();
+    truncate_recent_workspaces(&mut config.recent_workspaces, limit);
 
-                if has_content || is_root {
-                    files.push(WorkspaceFile {
-                        name: file_name,
-                        path: entry_path.to_string_lossy().to_string(),
-                        is_directory: true,
-                        children,
-                    });
-                }
-            } else {
-                // Include markdown files, kanban boards, and docs collections
-                if let Some(ext) = entry_path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    if ext_str == "md" || ext_str == "markdown" || ext_str == "mdown"
-                        || ext_str == "kanban" || ext_str == "miku-kanban"
-                        || ext_str == "docs" || ext_str == "miku-docs"
-                        || ext_str == "miku-chat"
-                    {
-                        files.push(WorkspaceFile {
-                            name: file_name,
-                            path: entry_path.to_string_lossy().to_string(),
-                            is_directory: false,
-                            children: None,
-                        });
-                    }
-                }
-            }
-        }
+    save_workspace_config_in(data_dir, &config).await
+}
 
-        // Sort: directories first, then alphabetically
-        files.sort_by(|a, b| {
-            match (a.is_directory, b.is_directory) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-            }
-        });
+/// Set a custom display name for a recent workspace. An empty name clears it.
+pub(crate) async fn set_workspace_name_in(data_dir: &Path, path: &str, name: &str) -> Result<(), MikuError> {
+    let path = absolute_workspace_path(path)?;
+    let mut config = load_workspace_config_in(data_dir).await?;
 
-        Ok(files)
-    })
+    let workspace = config
+        .recent_workspaces
+        .iter_mut()
+        .find(|w| same_path(&w.path, &path))
+        .ok_or_else(|| MikuError::Path("Workspace is not in the recent list".to_string()))?;
+
+    let name = name.trim();
+    workspace.display_name = if name.is_empty() { None } else { Some(name.to_string()) };
+
+    save_workspace_config_in(data_dir, &config).await
 }
 
-/// Create a new file
+/// Remember `file` as the last opened file of a recent workspace.
+/// Workspaces that aren't in the recent list are ignored.
+pub(crate) async fn set_last_opened_in(data_dir: &Path, workspace_path: &str, file: &str) -> Result<(), MikuError> {
+    let workspace_path = absolute_workspace_path(workspace_path)?;
+    let mut config = load_workspace_config_in(data_dir).await?;
+
+    match config.recent_workspaces.iter_mut().find(|w| same_path(&w.path, &workspace_path)) {
+        Some(workspace) => workspace.last_opened_file = Some(file.to_string()),
+        None => return Ok(()),
+    }
+
+    save_workspace_config_in(data_dir, &config).await
+}
+
+/// Get current workspace
 #[tauri::command]
-pub async fn create_file(base_path: String, name: String) -> Result<String, MikuError> {
-    let file_path = Path::new(&base_path).join(&name);
+pub async fn get_current_workspace(
+    app: AppHandle,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<Option<Workspace>, MikuError> {
+    let workspace = get_current_workspace_in(&app_data_dir()?).await;
+    emit_recovered_configs(&app);
+    if let Ok(Some(workspace)) = &workspace {
+        active.set(Path::new(&workspace.path));
+    }
+    workspace
+}
 
-    if file_path.exists() {
-        return Err(MikuError::Path("File already exists".to_string()));
+/// Contents of `<workspace>/.miku/lock`, written by the instance that has
+/// the workspace open
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkspaceLock {
+    pub pid: u32,
+    pub timestamp_ms: u64,
+}
+
+fn workspace_lock_path(workspace: &Path) -> PathBuf {
+    workspace.join(".miku").join("lock")
+}
+
+/// Whether a process with this id is still running
+fn process_alive(pid: u32) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        Path::new("/proc").join(pid.to_string()).exists()
     }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+    #[cfg(windows)]
+    {
+        std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+            .unwrap_or(false)
+    }
+}
 
-    tokio::fs::write(&file_path, "").await?;
+/// The lock another running instance holds on `workspace`. Locks of this
+/// process and of processes that have exited are ignored.
+fn live_workspace_lock(workspace: &Path) -> Option<WorkspaceLock> {
+    let content = std::fs::read_to_string(workspace_lock_path(workspace)).ok()?;
+    let lock: WorkspaceLock = serde_json::from_str(&content).ok()?;
+    (lock.pid != std::process::id() && process_alive(lock.pid)).then_some(lock)
+}
 
-    Ok(file_path.to_string_lossy().to_string())
+/// Whether `workspace` is locked by a running instance, this one included
+fn lock_is_held(workspace: &Path) -> bool {
+    std::fs::read_to_string(workspace_lock_path(workspace))
+        .ok()
+        .and_then(|content| serde_json::from_str::<WorkspaceLock>(&content).ok())
+        .is_some_and(|lock| lock.pid == std::process::id() || process_alive(lock.pid))
 }
 
-/// Create a new folder
-#[tauri::command]
-pub async fn create_folder(base_path: String, name: String) -> Result<String, MikuError> {
-    let folder_path = Path::new(&base_path).join(&name);
+/// The workspace whose advisory lock this instance holds
+#[derive(Default)]
+pub struct WorkspaceLocks(std::sync::Mutex<Option<PathBuf>>);
 
-    if folder_path.exists() {
-        return Err(MikuError::Path("Folder already exists".to_string()));
+impl WorkspaceLocks {
+    /// Release the lock on the previous workspace and take one on `workspace`.
+    /// The lock is only a warning for other instances, so failing to write
+    /// it (e.g. a read-only folder) doesn't stop the switch.
+    pub(crate) fn hold(&self, workspace: &Path) {
+        self.release();
+        let lock = WorkspaceLock {
+            pid: std::process::id(),
+            timestamp_ms: now_ms(),
+        };
+        let path = workspace_lock_path(workspace);
+        let written = std::fs::create_dir_all(path.parent().unwrap_or(workspace))
+            .and_then(|_| std::fs::write(&path, serde_json::to_string(&lock).unwrap_or_default()));
+        match written {
+            Ok(()) => *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(workspace.to_path_buf()),
+            Err(err) => log::warn!("failed to write workspace lock {}: {}", path.to_string_lossy(), err),
+        }
+    }
+
+    /// Remove the lock this instance holds, if it still owns it
+    pub fn release(&self) {
+        let Some(workspace) = self.0.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return;
+        };
+        let path = workspace_lock_path(&workspace);
+        let owned = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<WorkspaceLock>(&content).ok())
+            .is_some_and(|lock| lock.pid == std::process::id());
+        if owned {
+            let _ = std::fs::remove_file(&path);
+        }
     }
+}
 
-    tokio::fs::create_dir(&folder_path).await?;
+/// Root of the open workspace, kept current by `set_workspace`, so commands
+/// that guard paths or resolve links don't need it passed in every time
+#[derive(Default)]
+pub struct ActiveWorkspace(std::sync::RwLock<Option<PathBuf>>);
 
-    Ok(folder_path.to_string_lossy().to_string())
+impl ActiveWorkspace {
+    pub(crate) fn set(&self, root: &Path) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Some(root.to_path_buf());
+    }
+
+    pub(crate) fn get(&self) -> Option<PathBuf> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// `explicit` when the caller passed a root, otherwise the open workspace
+    pub(crate) fn root_or(&self, explicit: Option<String>) -> Result<PathBuf, MikuError> {
+        explicit
+            .map(PathBuf::from)
+            .or_else(|| self.get())
+            .ok_or_else(|| MikuError::Path("No workspace is open".to_string()))
+    }
 }
 
-/// List environment files (.miku-env) in a workspace
-/// These are stored at the workspace root and are not included in the regular file listing
-#[tauri::command]
-pub async fn list_env_files(workspace_path: String) -> Result<Vec<WorkspaceFile>, MikuError> {
-    let path = Path::new(&workspace_path);
+/// Returned by `set_workspace` instead of switching while documents have
+/// unsaved changes or another instance has the workspace open
+#[derive(Debug, Serialize, PartialEq)]
+pub struct WorkspaceSwitchWarning {
+    pub dirty_documents: Vec<String>,
+    pub already_locked_by: Option<WorkspaceLock>,
+}
 
-    if !path.exists() {
-        return Err(MikuError::Path("Workspace path does not exist".to_string()));
+/// Switch workspaces unless documents are dirty or the workspace is locked
+/// by another instance and `force` is false. A forced switch forgets the
+/// dirty documents, as the frontend discards them, and takes over the lock.
+pub(crate) async fn switch_workspace_in(
+    data_dir: &Path,
+    dirty_docs: &DirtyDocuments,
+    workspace_locks: &WorkspaceLocks,
+    active: &ActiveWorkspace,
+    path: String,
+    force: bool,
+) -> Result<Option<WorkspaceSwitchWarning>, MikuError> {
+    let path = absolute_workspace_path(&path)?;
+    let dirty_documents = dirty_docs.paths();
+    let already_locked_by = live_workspace_lock(Path::new(&path));
+    if (!dirty_documents.is_empty() || already_locked_by.is_some()) && !force {
+        return Ok(Some(WorkspaceSwitchWarning {
+            dirty_documents,
+            already_locked_by,
+        }));
     }
 
-    let mut env_files = Vec::new();
-    let mut entries = tokio::fs::read_dir(path).await?;
+    set_workspace_in(data_dir, path.clone()).await?;
+    dirty_docs.clear();
+    workspace_locks.hold(Path::new(&path));
+    active.set(Path::new(&path));
+    Ok(None)
+}
+
+/// Set current workspace. Returns a warning instead of switching when
+/// documents have unsaved changes or another running instance has the
+/// workspace open, unless `force` is set.
+#[tauri::command]
+pub async fn set_workspace(
+    path: String,
+    force: Option<bool>,
+    lock: State<'_, ConfigLock>,
+    dirty_docs: State<'_, DirtyDocuments>,
+    workspace_locks: State<'_, WorkspaceLocks>,
+    active: State<'_, ActiveWorkspace>,
+    index: State<'_, SearchIndex>,
+) -> Result<Option<WorkspaceSwitchWarning>, MikuError> {
+    let _guard = lock.acquire().await;
+    let force = force.unwrap_or(false);
+    let warning = switch_workspace_in(&app_data_dir()?, &dirty_docs, &workspace_locks, &active, path, force).await?;
+    if warning.is_none() {
+        // The index belongs to the previous vault; the next search rebuilds it
+        index.clear();
+    }
+    Ok(warning)
+}
+
+/// Get recent workspaces, pinned first. `sort_by` orders the rest by when
+/// they were last selected (the default) or by folder modification time.
+#[tauri::command]
+pub async fn get_recent_workspaces(
+    sort_by: Option<WorkspaceSort>,
+    mtimes: State<'_, FolderMtimeCache>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Vec<Workspace>, MikuError> {
+    let _guard = lock.acquire().await;
+    let mut workspaces = get_recent_workspaces_in(&app_data_dir()?).await?;
+    sort_recent_workspaces(&mut workspaces, sort_by.unwrap_or_default(), &mtimes);
+    Ok(workspaces)
+}
+
+/// Pin a workspace so it sorts to the top of the recent list
+#[tauri::command]
+pub async fn pin_workspace(path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    pin_workspace_in(&app_data_dir()?, path).await
+}
+
+/// Unpin a previously pinned workspace
+#[tauri::command]
+pub async fn unpin_workspace(path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    unpin_workspace_in(&app_data_dir()?, &path).await
+}
+
+/// Set a custom display name for a workspace
+#[tauri::command]
+pub async fn set_workspace_name(path: String, name: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    set_workspace_name_in(&app_data_dir()?, &path, &name).await
+}
+
+/// Remember the last opened file for a workspace
+#[tauri::command]
+pub async fn set_last_opened(workspace_path: String, file: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    set_last_opened_in(&app_data_dir()?, &workspace_path, &file).await
+}
 
+/// Template that ships with Miku, always offered first
+const BUILTIN_WORKSPACE_TEMPLATE: &str = "basic";
+
+/// Custom workspace templates are folders in
+/// `<app_data>/workspace_templates/<name>/`, copied as-is into new workspaces
+fn workspace_templates_dir_in(data_dir: &Path) -> PathBuf {
+    data_dir.join("workspace_templates")
+}
+
+pub(crate) async fn list_workspace_templates_in(data_dir: &Path) -> Result<Vec<String>, MikuError> {
+    let mut templates = vec![BUILTIN_WORKSPACE_TEMPLATE.to_string()];
+    let templates_dir = workspace_templates_dir_in(data_dir);
+    if !templates_dir.is_dir() {
+        return Ok(templates);
+    }
+
+    let mut custom = Vec::new();
+    let mut entries = tokio::fs::read_dir(&templates_dir).await?;
     while let Some(entry) = entries.next_entry().await? {
-        let entry_path = entry.path();
-        let file_name = entry.file_name().to_string_lossy().to_string();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().await?.is_dir() && !is_ignored_name(&name) && name != BUILTIN_WORKSPACE_TEMPLATE {
+            custom.push(name);
+        }
+    }
+    custom.sort_by_key(|name| name.to_lowercase());
+    templates.extend(custom);
 
-        // Only include .miku-env files
-        if file_name.ends_with(".miku-env") {
-            let metadata = entry.metadata().await?;
-            if metadata.is_file() {
-                env_files.push(WorkspaceFile {
-                    name: file_name,
-                    path: entry_path.to_string_lossy().to_string(),
-                    is_directory: false,
-                    children: None,
-                });
+    Ok(templates)
+}
+
+/// Names of the templates `create_workspace_from_template` accepts
+#[tauri::command]
+pub async fn list_workspace_templates() -> Result<Vec<String>, MikuError> {
+    list_workspace_templates_in(&app_data_dir()?).await
+}
+
+/// Copy the contents of `src` into `dest`, creating folders as needed
+fn copy_dir_contents<'a>(
+    src: &'a Path,
+    dest: &'a Path,
+) -> Pin<Box<dyn Future<Output = Result<(), MikuError>> + Send + 'a>> {
+    Box::pin(async move {
+        tokio::fs::create_dir_all(dest).await?;
+        let mut entries = tokio::fs::read_dir(src).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = dest.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                copy_dir_contents(&entry.path(), &target).await?;
+            } else {
+                tokio::fs::copy(entry.path(), &target).await?;
             }
         }
-    }
+        Ok(())
+    })
+}
 
-    // Sort alphabetically
-    env_files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+/// Folders and a README for a fresh vault
+async fn write_builtin_template(dest: &Path) -> Result<(), MikuError> {
+    for folder in ["inbox", "projects", "archive"] {
+        tokio::fs::create_dir_all(dest.join(folder)).await?;
+    }
+    let title = dest.file_name().unwrap_or_default().to_string_lossy();
+    let readme = format!(
+        "# {}\n\n- `inbox/` for quick captures\n- `projects/` for active work\n- `archive/` for finished notes\n",
+        title
+    );
+    tokio::fs::write(dest.join("README.md"), readme).await?;
+    Ok(())
+}
 
-    Ok(env_files)
+/// A workspace scaffolded from a template
+#[derive(Debug, Serialize)]
+pub struct TemplateWorkspace {
+    pub workspace: Workspace,
+    /// Why the new workspace wasn't switched to; it stays scaffolded either way
+    pub switch_warning: Option<WorkspaceSwitchWarning>,
 }
 
-/// Delete a file or folder
-#[tauri::command]
-pub async fn delete_file(path: String) -> Result<(), MikuError> {
-    let path_obj = Path::new(&path);
+pub(crate) async fn create_workspace_from_template_in(
+    data_dir: &Path,
+    dirty_docs: &DirtyDocuments,
+    workspace_locks: &WorkspaceLocks,
+    active: &ActiveWorkspace,
+    dest_dir: &str,
+    template_name: &str,
+    force: bool,
+) -> Result<TemplateWorkspace, MikuError> {
+    let template_name = sanitize_name(template_name)?;
+    let custom = workspace_templates_dir_in(data_dir).join(&template_name);
+    if !custom.is_dir() && template_name != BUILTIN_WORKSPACE_TEMPLATE {
+        return Err(MikuError::NotFound(template_name));
+    }
 
-    if !path_obj.exists() {
-        return Err(MikuError::Path("Path does not exist".to_string()));
+    // Never scaffold over existing notes
+    let dest = Path::new(dest_dir);
+    if dest.exists() && std::fs::read_dir(dest)?.next().is_some() {
+        return Err(MikuError::AlreadyExists(dest_dir.to_string()));
     }
 
-    if path_obj.is_dir() {
-        tokio::fs::remove_dir_all(&path).await?;
+    if custom.is_dir() {
+        copy_dir_contents(&custom, dest).await?;
     } else {
-        tokio::fs::remove_file(&path).await?;
+        write_builtin_template(dest).await?;
     }
 
-    Ok(())
+    let switch_warning =
+        switch_workspace_in(data_dir, dirty_docs, workspace_locks, active, dest_dir.to_string(), force).await?;
+    let workspace = get_workspace_info_in(data_dir, dest_dir.to_string()).await?;
+    Ok(TemplateWorkspace {
+        workspace,
+        switch_warning,
+    })
 }
 
-/// Rename a file or folder
+/// Scaffold a new workspace in `dest_dir` (missing or empty) from a
+/// template and make it the current workspace. Like `set_workspace`, the
+/// switch is held back with a warning while documents have unsaved
+/// changes, unless `force` is set.
 #[tauri::command]
-pub async fn rename_file(old_path: String, new_name: String) -> Result<String, MikuError> {
-    let old_path_obj = Path::new(&old_path);
-
-    if !old_path_obj.exists() {
-        return Err(MikuError::Path("Path does not exist".to_string()));
+#[allow(clippy::too_many_arguments)]
+pub async fn create_workspace_from_template(
+    dest_dir: String,
+    template_name: String,
+    force: Option<bool>,
+    lock: State<'_, ConfigLock>,
+    dirty_docs: State<'_, DirtyDocuments>,
+    workspace_locks: State<'_, WorkspaceLocks>,
+    active: State<'_, ActiveWorkspace>,
+    index: State<'_, SearchIndex>,
+) -> Result<TemplateWorkspace, MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    let force = force.unwrap_or(false);
+    let created = create_workspace_from_template_in(
+        &data_dir,
+        &dirty_docs,
+        &workspace_locks,
+        &active,
+        &dest_dir,
+        &template_name,
+        force,
+    )
+    .await?;
+    if created.switch_warning.is_none() {
+        index.clear();
     }
+    Ok(created)
+}
 
-    let parent = old_path_obj.parent()
-        .ok_or_else(|| MikuError::Path("Cannot determine parent directory".to_string()))?;
+/// Hidden files and common non-content directories are left out of listings
+pub(crate) fn is_ignored_name(file_name: &str) -> bool {
+    file_name.starts_with('.') || file_name == "node_modules" || file_name == "target"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    pub file_count: u64,
+}
+
+/// Sum the size of every file in a workspace, not just markdown. With
+/// `respect_ignore`, hidden files and non-content directories are skipped.
+#[tauri::command]
+pub async fn workspace_disk_usage(workspace_path: String, respect_ignore: bool) -> Result<DiskUsage, MikuError> {
+    let concurrency = io_concurrency_or_saved().await;
+    disk_usage_in(Path::new(&workspace_path), respect_ignore, concurrency, |path| async move {
+        tokio::fs::metadata(path).await
+    })
+    .await
+}
 
-    let new_path = parent.join(&new_name);
+/// Size the files under `root`, calling `stat` on at most `concurrency`
+/// files at once
+pub(crate) async fn disk_usage_in<F, Fut>(
+    root: &Path,
+    respect_ignore: bool,
+    concurrency: usize,
+    stat: F,
+) -> Result<DiskUsage, MikuError>
+where
+    F: Fn(PathBuf) -> Fut,
+    Fut: Future<Output = std::io::Result<std::fs::Metadata>>,
+{
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
 
-    if new_path.exists() {
-        return Err(MikuError::Path("A file with that name already exists".to_string()));
+    // Collect file paths first, then read their metadata with bounded concurrency
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if respect_ignore && is_ignored_name(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
     }
 
-    tokio::fs::rename(&old_path, &new_path).await?;
+    let usage = stream::iter(files)
+        .map(stat)
+        .buffer_unordered(concurrency.max(1))
+        .fold(DiskUsage::default(), |mut usage, metadata| async move {
+            // Files that vanish or can't be read mid-walk are skipped
+            if let Ok(metadata) = metadata {
+                usage.total_bytes += metadata.len();
+                usage.file_count += 1;
+            }
+            usage
+        })
+        .await;
 
-    Ok(new_path.to_string_lossy().to_string())
+    Ok(usage)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Lint findings for one file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileLint {
+    pub path: String,
+    pub findings: Vec<LintFinding>,
+}
 
-    #[test]
-    fn test_workspace_serialization() {
-        let workspace = Workspace {
-            path: "/path/to/workspace".to_string(),
-            name: "My Workspace".to_string(),
-        };
+/// Lint every markdown file under `root`, reading at most `concurrency`
+/// files at once. Files without findings are left out.
+pub(crate) async fn lint_workspace_in(
+    root: &Path,
+    rules: &[LintRule],
+    options: MarkdownOptions,
+    concurrency: usize,
+) -> Result<Vec<FileLint>, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
 
-        let json = serde_json::to_string(&workspace).unwrap();
-        let deserialized: Workspace = serde_json::from_str(&json).unwrap();
+    let mut results: Vec<FileLint> = stream::iter(markdown_files(root).await?)
+        .map(|path| async move {
+            // Files that vanish or can't be read mid-walk are skipped
+            let content = tokio::fs::read_to_string(&path).await.ok()?;
+            let findings = lint_content(&content, rules, &options);
+            (!findings.is_empty()).then(|| FileLint {
+                path: path.to_string_lossy().to_string(),
+                findings,
+            })
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
 
-        assert_eq!(workspace.path, deserialized.path);
-        assert_eq!(workspace.name, deserialized.name);
-    }
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(results)
+}
 
-    #[test]
-    fn test_workspace_file_serialization() {
-        let file = WorkspaceFile {
-            name: "test.md".to_string(),
-            path: "/path/to/test.md".to_string(),
-            is_directory: false,
-            children: None,
-        };
+/// Lint every markdown file in a workspace. `rules` defaults to all of them.
+#[tauri::command]
+pub async fn lint_workspace(workspace_path: String, rules: Option<Vec<LintRule>>) -> Result<Vec<FileLint>, MikuError> {
+    let rules = rules.unwrap_or_else(|| LintRule::ALL.to_vec());
+    let options = markdown_options_or_saved(None).await;
+    lint_workspace_in(Path::new(&workspace_path), &rules, options, io_concurrency_or_saved().await).await
+}
 
-        let json = serde_json::to_string(&file).unwrap();
-        assert!(json.contains("\"isDirectory\":false"));
+/// A note whose frontmatter is missing required keys
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FrontmatterIssue {
+    pub path: String,
+    pub missing_keys: Vec<String>,
+    /// False when the note has no frontmatter block at all
+    pub has_frontmatter: bool,
+}
 
-        let deserialized: WorkspaceFile = serde_json::from_str(&json).unwrap();
-        assert_eq!(file.name, deserialized.name);
-        assert!(!deserialized.is_directory);
+/// Check the frontmatter of every markdown file under `root` for
+/// `required_keys`, reading at most `concurrency` files at once. Notes
+/// without frontmatter are always reported.
+pub(crate) async fn validate_frontmatter_in(
+    root: &Path,
+    required_keys: &[String],
+    concurrency: usize,
+) -> Result<Vec<FrontmatterIssue>, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
     }
 
-    #[test]
-    fn test_workspace_config_default() {
-        let config = WorkspaceConfig::default();
-        assert!(config.current_workspace.is_none());
-        assert!(config.recent_workspaces.is_empty());
+    let mut issues: Vec<FrontmatterIssue> = stream::iter(markdown_files(root).await?)
+        .map(|path| async move {
+            // Files that vanish or can't be read mid-walk are skipped
+            let content = tokio::fs::read_to_string(&path).await.ok()?;
+            let keys = frontmatter_keys(&content);
+            let missing_keys: Vec<String> = required_keys
+                .iter()
+                .filter(|required| !keys.as_ref().is_some_and(|keys| keys.contains(required)))
+                .cloned()
+                .collect();
+            (keys.is_none() || !missing_keys.is_empty()).then(|| FrontmatterIssue {
+                path: path.to_string_lossy().to_string(),
+                missing_keys,
+                has_frontmatter: keys.is_some(),
+            })
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|issue| async move { issue })
+        .collect()
+        .await;
+
+    issues.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(issues)
+}
+
+/// Notes whose frontmatter lacks any of `required_keys` or is missing
+/// entirely, for a "fix metadata" pass
+#[tauri::command]
+pub async fn validate_frontmatter(
+    workspace_path: String,
+    required_keys: Vec<String>,
+) -> Result<Vec<FrontmatterIssue>, MikuError> {
+    validate_frontmatter_in(Path::new(&workspace_path), &required_keys, io_concurrency_or_saved().await).await
+}
+
+/// List files in a workspace. Folders without any listed files are pruned
+/// below the top level unless `show_empty_dirs` is set. `word_counts` and
+/// `display_titles` read every markdown file to fill in those fields.
+#[tauri::command]
+pub async fn list_workspace_files(
+    workspace_path: String,
+    show_empty_dirs: Option<bool>,
+    word_counts: Option<bool>,
+    grouping: Option<TreeGrouping>,
+    display_titles: Option<bool>,
+) -> Result<Vec<WorkspaceFile>, MikuError> {
+    let path = Path::new(&workspace_path);
+
+    if !path.exists() {
+        return Err(MikuError::NotFound(workspace_path));
+    }
+
+    let options = ListingOptions {
+        show_empty_dirs: show_empty_dirs.unwrap_or(false),
+        word_counts: word_counts.unwrap_or(false),
+        display_titles: display_titles.unwrap_or(false),
+        grouping: grouping.unwrap_or_default(),
+        max_depth: max_tree_depth(),
+        ..Default::default()
+    };
+    let mut errors = Vec::new();
+    let (files, _) = with_read_timeout(path, list_directory(path, path, options, &mut errors)).await?;
+    for error in errors {
+        log::warn!("skipping unreadable entry {}: {}", error.path, error.message);
+    }
+    Ok(files)
+}
+
+/// How long listing a workspace took and how big the listing was
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ListingBenchmark {
+    pub duration_ms: f64,
+    pub file_count: usize,
+    pub folder_count: usize,
+    /// Content read from files during the listing, i.e. the markdown files
+    /// when word counts were asked for
+    pub bytes_read: u64,
+}
+
+fn count_tree(files: &[WorkspaceFile], benchmark: &mut ListingBenchmark) {
+    for file in files {
+        match &file.children {
+            _ if !file.is_directory => {
+                benchmark.file_count += 1;
+                if file.word_count.is_some() {
+                    benchmark.bytes_read += std::fs::metadata(&file.path).map_or(0, |m| m.len());
+                }
+            }
+            Some(children) => {
+                benchmark.folder_count += 1;
+                count_tree(children, benchmark);
+            }
+            None => benchmark.folder_count += 1,
+        }
+    }
+}
+
+/// Time one `list_directory` run over `root` with `options`
+async fn benchmark_listing_in(root: &Path, options: ListingOptions) -> Result<ListingBenchmark, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let started = Instant::now();
+    let (files, _) = list_directory(root, root, options, &mut Vec::new()).await?;
+    let mut benchmark = ListingBenchmark {
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        file_count: 0,
+        folder_count: 0,
+        bytes_read: 0,
+    };
+    count_tree(&files, &mut benchmark);
+    Ok(benchmark)
+}
+
+/// List a workspace the way the file tree does and report how long it took
+/// and how much it covered, for diagnosing slow vaults. Not used by the
+/// tree itself.
+#[tauri::command]
+pub async fn benchmark_listing(
+    workspace_path: String,
+    word_counts: Option<bool>,
+) -> Result<ListingBenchmark, MikuError> {
+    let options = ListingOptions {
+        word_counts: word_counts.unwrap_or(false),
+        max_depth: max_tree_depth(),
+        ..Default::default()
+    };
+    benchmark_listing_in(Path::new(&workspace_path), options).await
+}
+
+/// What `list_directory` includes beyond the plain tree
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ListingOptions {
+    /// Keep folders without any listed files below the top level
+    show_empty_dirs: bool,
+    /// Read every markdown file to fill in `word_count`
+    word_counts: bool,
+    /// Read every markdown file to fill in `display_title`
+    display_titles: bool,
+    grouping: TreeGrouping,
+    /// Folders nested deeper than this are marked `truncated` instead of
+    /// being listed
+    max_depth: usize,
+    /// Entries with a longer path, in bytes, are reported as unreadable
+    max_path_len: usize,
+}
+
+/// Longest path the OS can open, in bytes
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 32_767;
+#[cfg(target_os = "macos")]
+const MAX_PATH_LEN: usize = 1024;
+#[cfg(not(any(windows, target_os = "macos")))]
+const MAX_PATH_LEN: usize = 4096;
+
+impl Default for ListingOptions {
+    fn default() -> Self {
+        ListingOptions {
+            show_empty_dirs: false,
+            word_counts: false,
+            display_titles: false,
+            grouping: TreeGrouping::default(),
+            max_depth: DEFAULT_MAX_TREE_DEPTH,
+            max_path_len: MAX_PATH_LEN,
+        }
+    }
+}
+
+/// Where folders go relative to files within a directory listing
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeGrouping {
+    #[default]
+    DirsFirst,
+    FilesFirst,
+    /// Folders and files mixed, purely alphabetical
+    Interleaved,
+}
+
+/// Name of the per-directory file holding a custom sort order
+const FOLDER_ORDER_FILE: &str = ".miku-order";
+
+/// Read the custom entry order for a directory. Missing or malformed order
+/// files are treated as no custom order.
+async fn read_folder_order(dir: &Path) -> Vec<String> {
+    match tokio::fs::read_to_string(dir.join(FOLDER_ORDER_FILE)).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Persist a custom order for the entries of a directory. Names that don't
+/// match an entry are ignored when listing.
+#[tauri::command]
+pub async fn set_folder_order(dir: String, ordered_names: Vec<String>) -> Result<(), MikuError> {
+    let dir_path = Path::new(&dir);
+
+    if !dir_path.is_dir() {
+        return Err(MikuError::NotFound(dir));
+    }
+
+    let content = serde_json::to_string_pretty(&ordered_names)?;
+    write_atomic(&dir_path.join(FOLDER_ORDER_FILE), content).await
+}
+
+/// An entry skipped while listing because it couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ListingError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ListingError {
+    fn new(path: &Path, err: std::io::Error) -> Self {
+        ListingError {
+            path: path.to_string_lossy().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Files shown in the workspace tree: markdown files, kanban boards, docs
+/// collections and chats
+fn is_tree_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        is_markdown_extension(&ext)
+            || matches!(ext.as_str(), "kanban" | "miku-kanban" | "docs" | "miku-docs" | "miku-chat")
+    })
+}
+
+/// Whether the folder at `path` is nested too deep below `root` to be
+/// looked into
+fn past_depth_limit(root: &Path, path: &Path, max_depth: usize) -> bool {
+    let depth = path.strip_prefix(root).map_or(0, |rest| rest.components().count());
+    depth > max_depth
+}
+
+/// A folder or tree file found in a directory, before it's listed
+struct TreeEntry {
+    path: PathBuf,
+    name: String,
+    is_directory: bool,
+}
+
+/// The folders and tree files directly in `path`, along with how many other
+/// entries were left out. Entries that can't be read, including those whose
+/// path is longer than `max_path_len`, are skipped and recorded in `errors`.
+async fn read_tree_entries(
+    path: &Path,
+    max_path_len: usize,
+    errors: &mut Vec<ListingError>,
+) -> Result<(Vec<TreeEntry>, usize), std::io::Error> {
+    let mut hidden_count = 0;
+    let mut listed = Vec::new();
+    let mut entries = tokio::fs::read_dir(path).await?;
+
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(err) => {
+                errors.push(ListingError::new(path, err));
+                break;
+            }
+        };
+        let entry_path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Skip hidden files and common non-content directories
+        if is_ignored_name(&name) {
+            continue;
+        }
+
+        if entry_path.as_os_str().len() > max_path_len {
+            let err = std::io::Error::other("path is too long to open");
+            errors.push(ListingError::new(&entry_path, err));
+            continue;
+        }
+        let metadata = match entry.metadata().await {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                errors.push(ListingError::new(&entry_path, err));
+                continue;
+            }
+        };
+        if metadata.is_dir() || is_tree_file(&entry_path) {
+            listed.push(TreeEntry {
+                path: entry_path,
+                name,
+                is_directory: metadata.is_dir(),
+            });
+        } else {
+            hidden_count += 1;
+        }
+    }
+
+    Ok((listed, hidden_count))
+}
+
+fn relative_to(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string()
+}
+
+/// The listing of a tree file, reading it if `options` asks for its word
+/// count or display title
+async fn tree_file(
+    root: &Path,
+    entry: TreeEntry,
+    options: ListingOptions,
+    errors: &mut Vec<ListingError>,
+) -> WorkspaceFile {
+    let content = if (options.word_counts || options.display_titles) && is_markdown_file(&entry.name) {
+        match tokio::fs::read_to_string(&entry.path).await {
+            Ok(content) => Some(content),
+            Err(err) => {
+                errors.push(ListingError::new(&entry.path, err));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let word_count = content.as_deref().filter(|_| options.word_counts).map(count_words);
+    let display_title = options.display_titles.then(|| {
+        content
+            .as_deref()
+            .and_then(frontmatter_title)
+            .unwrap_or_else(|| section_title(&entry.name))
+    });
+    WorkspaceFile {
+        relative_path: Some(relative_to(root, &entry.path)),
+        name: entry.name,
+        path: entry.path.to_string_lossy().to_string(),
+        is_directory: false,
+        children: None,
+        word_count,
+        hidden_count: None,
+        truncated: false,
+        display_title,
+    }
+}
+
+/// Entries of one folder and how many of its entries were left out
+type DirectoryListing = (Vec<WorkspaceFile>, usize);
+
+/// Recursively list directory contents, along with how many entries of
+/// `path` itself were left out (see `WorkspaceFile::hidden_count`).
+/// Uses Box::pin to handle async recursion. Entries below the top level
+/// that can't be read are skipped and recorded in `errors` so one bad file
+/// doesn't hide the rest of the tree.
+fn list_directory<'a>(
+    root: &'a Path,
+    path: &'a Path,
+    options: ListingOptions,
+    errors: &'a mut Vec<ListingError>,
+) -> Pin<Box<dyn Future<Output = Result<DirectoryListing, MikuError>> + Send + 'a>> {
+    Box::pin(async move {
+        let is_root = path == root;
+        let (entries, mut hidden_count) = read_tree_entries(path, options.max_path_len, errors).await?;
+        let mut files = Vec::new();
+
+        for entry in entries {
+            if !entry.is_directory {
+                files.push(tree_file(root, entry, options, errors).await);
+                continue;
+            }
+
+            // Stop descending past the depth limit rather than recursing
+            // without bound through a pathological tree
+            let truncated = past_depth_limit(root, &entry.path, options.max_depth);
+            let listing = if truncated {
+                Ok((Vec::new(), 0))
+            } else {
+                // Recursively list subdirectories
+                list_directory(root, &entry.path, options, errors).await
+            };
+            let (children, children_hidden) = match listing {
+                Ok((children, hidden)) => (Some(children), Some(hidden)),
+                Err(err) => {
+                    errors.push(ListingError {
+                        path: entry.path.to_string_lossy().to_string(),
+                        message: err.to_string(),
+                    });
+                    (None, None)
+                }
+            };
+
+            // Only include directories that have markdown files or
+            // subdirectories, or that weren't looked into
+            let has_content = truncated || children.as_ref().map(|c| !c.is_empty()).unwrap_or(false);
+
+            if has_content || is_root || options.show_empty_dirs {
+                let word_count = match &children {
+                    Some(children) if options.word_counts => {
+                        Some(children.iter().filter_map(|child| child.word_count).sum())
+                    }
+                    _ => None,
+                };
+                files.push(WorkspaceFile {
+                    relative_path: Some(relative_to(root, &entry.path)),
+                    name: entry.name,
+                    path: entry.path.to_string_lossy().to_string(),
+                    is_directory: true,
+                    children,
+                    word_count,
+                    hidden_count: children_hidden,
+                    truncated,
+                    display_title: None,
+                });
+            } else {
+                hidden_count += 1;
+            }
+        }
+
+        sort_tree_entries(&mut files, read_folder_order(path).await, options.grouping);
+
+        Ok((files, hidden_count))
+    })
+}
+
+/// Event sent for each entry `stream_workspace_files` finds
+pub const WORKSPACE_ENTRY_EVENT: &str = "workspace://entry";
+/// Event sent once `stream_workspace_files` has walked the whole tree
+pub const WORKSPACE_COMPLETE_EVENT: &str = "workspace://complete";
+
+/// A file or folder found by a streaming workspace walk
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceEntry {
+    pub name: String,
+    pub path: String,
+    /// Folder the entry belongs in; the workspace root for top-level entries
+    pub parent_path: String,
+    pub is_directory: bool,
+    pub relative_path: String,
+    /// Same as `WorkspaceFile::display_title`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_title: Option<String>,
+    /// Same as `WorkspaceFile::truncated`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceEntryEvent {
+    operation_id: String,
+    #[serde(flatten)]
+    entry: WorkspaceEntry,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceCompleteEvent {
+    operation_id: String,
+    entry_count: usize,
+    /// Why the walk stopped early, including when it was cancelled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Depth-first walk handing entries to `emit` in file tree order, listing
+/// each folder the same way as `list_directory`
+struct TreeWalk<'a> {
+    root: &'a Path,
+    options: ListingOptions,
+    progress: &'a Progress,
+    emit: &'a mut (dyn FnMut(WorkspaceEntry) + Send),
+    /// Folders below the top level that were entered but not emitted yet,
+    /// outermost first. They are only sent once they turn out to contain a
+    /// listed file, so empty folders stay pruned.
+    pending: Vec<WorkspaceEntry>,
+    errors: Vec<ListingError>,
+    count: usize,
+}
+
+impl TreeWalk<'_> {
+    fn send(&mut self, entry: WorkspaceEntry) -> Result<(), MikuError> {
+        self.progress.check()?;
+        for folder in self.pending.drain(..).chain(std::iter::once(entry)) {
+            (self.emit)(folder);
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    fn walk<'b>(&'b mut self, dir: &'b Path) -> Pin<Box<dyn Future<Output = Result<(), MikuError>> + Send + 'b>> {
+        Box::pin(async move {
+            let entries = match read_tree_entries(dir, self.options.max_path_len, &mut self.errors).await {
+                Ok((entries, _)) => entries,
+                Err(err) if dir == self.root => return Err(MikuError::from_io(err, dir)),
+                Err(err) => {
+                    self.errors.push(ListingError::new(dir, err));
+                    return Ok(());
+                }
+            };
+
+            let mut listed = Vec::new();
+            for entry in entries {
+                if entry.is_directory {
+                    listed.push(WorkspaceFile {
+                        truncated: past_depth_limit(self.root, &entry.path, self.options.max_depth),
+                        relative_path: Some(relative_to(self.root, &entry.path)),
+                        name: entry.name,
+                        path: entry.path.to_string_lossy().to_string(),
+                        is_directory: true,
+                        children: None,
+                        word_count: None,
+                        hidden_count: None,
+                        display_title: None,
+                    });
+                } else {
+                    listed.push(tree_file(self.root, entry, self.options, &mut self.errors).await);
+                }
+            }
+            sort_tree_entries(&mut listed, read_folder_order(dir).await, self.options.grouping);
+
+            for file in listed {
+                let path = PathBuf::from(&file.path);
+                let entry = WorkspaceEntry {
+                    name: file.name,
+                    path: file.path,
+                    parent_path: dir.to_string_lossy().to_string(),
+                    is_directory: file.is_directory,
+                    relative_path: file.relative_path.unwrap_or_default(),
+                    display_title: file.display_title,
+                    truncated: file.truncated,
+                };
+
+                // Like the tree, a folder past the depth limit is listed but
+                // not looked into
+                if !entry.is_directory || entry.truncated {
+                    self.send(entry)?;
+                    continue;
+                }
+                let pending_before = self.pending.len();
+                if dir == self.root || self.options.show_empty_dirs {
+                    self.send(entry)?;
+                } else {
+                    self.pending.push(entry);
+                }
+                self.walk(&path).await?;
+                // Drop the folder again if nothing inside it was listed
+                self.pending.truncate(pending_before);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Walk `root`, handing every listed entry to `emit` as soon as it's found.
+/// Returns the number of entries emitted. Word counts aren't streamed, so
+/// `options.word_counts` is ignored.
+pub(crate) async fn stream_workspace_files_in(
+    root: &Path,
+    options: ListingOptions,
+    progress: &Progress,
+    emit: &mut (dyn FnMut(WorkspaceEntry) + Send),
+) -> Result<usize, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let mut walk = TreeWalk {
+        root,
+        options: ListingOptions {
+            word_counts: false,
+            ..options
+        },
+        progress,
+        emit,
+        pending: Vec::new(),
+        errors: Vec::new(),
+        count: 0,
+    };
+    let result = walk.walk(root).await;
+    for error in walk.errors {
+        log::warn!("skipping unreadable entry {}: {}", error.path, error.message);
+    }
+    result.map(|()| walk.count)
+}
+
+/// List a workspace incrementally for very large vaults: each file and
+/// folder is sent as a `workspace://entry` event as soon as it's found,
+/// followed by `workspace://complete` once the walk ends, also when it
+/// fails or is cancelled. Both carry `operation_id`, which
+/// `cancel_operation` also accepts. The other options are the same as for
+/// `list_workspace_files`. Returns the number of entries sent.
+#[tauri::command]
+pub async fn stream_workspace_files(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    workspace_path: String,
+    operation_id: String,
+    show_empty_dirs: Option<bool>,
+    grouping: Option<TreeGrouping>,
+    display_titles: Option<bool>,
+) -> Result<usize, MikuError> {
+    let progress = Progress::for_operation(&app, &operations, Some(operation_id.clone()))?;
+    let options = ListingOptions {
+        show_empty_dirs: show_empty_dirs.unwrap_or(false),
+        display_titles: display_titles.unwrap_or(false),
+        grouping: grouping.unwrap_or_default(),
+        max_depth: max_tree_depth(),
+        ..Default::default()
+    };
+    let mut entry_count = 0;
+    let mut emit = |entry| {
+        entry_count += 1;
+        let operation_id = operation_id.clone();
+        let _ = app.emit(WORKSPACE_ENTRY_EVENT, WorkspaceEntryEvent { operation_id, entry });
+    };
+    let result = stream_workspace_files_in(Path::new(&workspace_path), options, &progress, &mut emit).await;
+
+    let error = result.as_ref().err().map(ToString::to_string);
+    let _ = app.emit(
+        WORKSPACE_COMPLETE_EVENT,
+        WorkspaceCompleteEvent {
+            operation_id,
+            entry_count,
+            error,
+        },
+    );
+    result
+}
+
+/// A markdown file in a flat workspace listing
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FlatFile {
+    pub name: String,
+    pub path: String,
+    pub relative_path: String,
+    /// Last modification time in milliseconds since the Unix epoch
+    pub modified_ms: u64,
+}
+
+/// Every markdown file below `root` in one walk, skipping the same entries
+/// as the file tree, folders deeper than `max_depth` included. Sorted by
+/// relative path.
+pub(crate) async fn list_workspace_files_flat_in(root: &Path, max_depth: usize) -> Result<Vec<FlatFile>, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if dir == root => return Err(MikuError::from_io(err, &dir)),
+            Err(err) => {
+                log::warn!("skipping unreadable entry {}: {}", dir.to_string_lossy(), err);
+                continue;
+            }
+        };
+
+        while let Some(entry) = entries.next_entry().await.ok().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_ignored_name(&name) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            let entry_path = entry.path();
+            if metadata.is_dir() {
+                if depth < max_depth {
+                    dirs.push((entry_path, depth + 1));
+                }
+            } else if is_markdown_file(&name) {
+                let modified_ms = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|age| age.as_millis() as u64)
+                    .unwrap_or(0);
+                files.push(FlatFile {
+                    name,
+                    relative_path: entry_path.strip_prefix(root).unwrap_or(&entry_path).to_string_lossy().to_string(),
+                    path: entry_path.to_string_lossy().to_string(),
+                    modified_ms,
+                });
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    Ok(files)
+}
+
+/// Every markdown file in a workspace as a flat list, for views like tables
+/// and pickers that don't want the nested tree
+#[tauri::command]
+pub async fn list_workspace_files_flat(workspace_path: String) -> Result<Vec<FlatFile>, MikuError> {
+    let path = Path::new(&workspace_path);
+    with_read_timeout(path, list_workspace_files_flat_in(path, max_tree_depth())).await
+}
+
+/// Open the most recently modified markdown file in `workspace` and add it
+/// to the recent files. Ties go to the first by relative path.
+pub(crate) async fn open_most_recent_in(data_dir: &Path, workspace: &Path) -> Result<Document, MikuError> {
+    let newest = list_workspace_files_flat_in(workspace, max_tree_depth())
+        .await?
+        .into_iter()
+        .reduce(|newest, file| if file.modified_ms > newest.modified_ms { file } else { newest })
+        .ok_or_else(|| MikuError::NotFound(workspace.to_string_lossy().to_string()))?;
+
+    let document = read_document(newest.path.clone(), false).await?;
+    add_recent_file_in(data_dir, newest.path).await?;
+    Ok(document)
+}
+
+/// Open the note modified last in a workspace, to continue where the vault
+/// left off. Fails with `NotFound` when it has no markdown files.
+#[tauri::command]
+pub async fn open_most_recent(
+    app: AppHandle,
+    workspace_path: String,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Document, MikuError> {
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    let workspace = Path::new(&workspace_path);
+    let document = with_read_timeout(workspace, open_most_recent_in(&data_dir, workspace)).await?;
+    readonly_docs.set(&document.path.clone().unwrap_or_default(), false);
+    emit_recents_changed(&app, &data_dir).await?;
+    Ok(document)
+}
+
+/// Remove what Miku keeps inside `workspace`: the `.miku` folder (lock and
+/// other metadata) and every `.miku-order` file. Only the open workspace
+/// or one in the recent list is accepted. Returns the removed paths.
+pub(crate) async fn clear_workspace_metadata_in(
+    data_dir: &Path,
+    active: &ActiveWorkspace,
+    workspace: &Path,
+) -> Result<Vec<String>, MikuError> {
+    let workspace_str = workspace.to_string_lossy();
+    let known = active.get().is_some_and(|root| same_path(&root.to_string_lossy(), &workspace_str))
+        || is_known_workspace_in(data_dir, &workspace_str).await?;
+    if !known {
+        return Err(MikuError::Path(format!("{} is not a known workspace", workspace_str)));
+    }
+    if !workspace.is_dir() {
+        return Err(MikuError::NotFound(workspace_str.to_string()));
+    }
+
+    let mut removed = Vec::new();
+    let miku_dir = workspace.join(".miku");
+    let lock_path = workspace_lock_path(workspace);
+    if lock_is_held(workspace) {
+        // Keep the lock of a running instance, this one included, so no
+        // other window can open the workspace meanwhile
+        let mut entries = tokio::fs::read_dir(&miku_dir)
+            .await
+            .map_err(|e| MikuError::from_io(e, &miku_dir))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path == lock_path {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                tokio::fs::remove_dir_all(&path).await
+            } else {
+                tokio::fs::remove_file(&path).await
+            }
+            .map_err(|e| MikuError::from_io(e, &path))?;
+            removed.push(path.to_string_lossy().to_string());
+        }
+    } else if miku_dir.is_dir() {
+        tokio::fs::remove_dir_all(&miku_dir)
+            .await
+            .map_err(|e| MikuError::from_io(e, &miku_dir))?;
+        removed.push(miku_dir.to_string_lossy().to_string());
+    }
+
+    let mut dirs = vec![workspace.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let order_file = dir.join(FOLDER_ORDER_FILE);
+        if order_file.is_file() {
+            tokio::fs::remove_file(&order_file)
+                .await
+                .map_err(|e| MikuError::from_io(e, &order_file))?;
+            removed.push(order_file.to_string_lossy().to_string());
+        }
+
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Some(entry) = entries.next_entry().await.ok().flatten() {
+            let is_dir = entry.file_type().await.is_ok_and(|t| t.is_dir());
+            if is_dir && !is_ignored_name(&entry.file_name().to_string_lossy()) {
+                dirs.push(entry.path());
+            }
+        }
+    }
+
+    removed.sort();
+    Ok(removed)
+}
+
+/// Forget Miku's per-workspace metadata (folder orders, ...) for the open
+/// or a recent workspace. Notes are never touched, and neither is the lock
+/// of a window that has the workspace open. Returns the removed paths.
+#[tauri::command]
+pub async fn clear_workspace_metadata(
+    workspace_path: String,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<Vec<String>, MikuError> {
+    clear_workspace_metadata_in(&app_data_dir()?, &active, Path::new(&workspace_path)).await
+}
+
+/// Groups of entries in the same folder whose names differ only by case
+/// (or Unicode normalization), which would merge on a case-insensitive
+/// filesystem. Walks everything the file tree doesn't ignore, folders
+/// included, down to `max_depth` like the tree.
+pub(crate) async fn find_case_conflicts_in(root: &Path, max_depth: usize) -> Result<Vec<Vec<String>>, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let mut conflicts = Vec::new();
+    let mut dirs = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if dir == root => return Err(MikuError::from_io(err, &dir)),
+            Err(err) => {
+                log::warn!("skipping unreadable entry {}: {}", dir.to_string_lossy(), err);
+                continue;
+            }
+        };
+
+        let mut by_folded_name: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        while let Some(entry) = entries.next_entry().await.ok().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_ignored_name(&name) {
+                continue;
+            }
+            let entry_path = entry.path();
+            if depth < max_depth && entry.file_type().await.is_ok_and(|kind| kind.is_dir()) {
+                dirs.push((entry_path.clone(), depth + 1));
+            }
+            by_folded_name
+                .entry(normalize_name(&name).to_lowercase())
+                .or_default()
+                .push(entry_path.to_string_lossy().to_string());
+        }
+
+        conflicts.extend(by_folded_name.into_values().filter(|group| group.len() > 1).map(|mut group| {
+            group.sort();
+            group
+        }));
+    }
+
+    conflicts.sort();
+    Ok(conflicts)
+}
+
+/// Find files and folders whose names differ only by case within the same
+/// folder (`Readme.md` vs `README.md`), so they can be resolved before the
+/// vault is synced to a filesystem that treats them as one
+#[tauri::command]
+pub async fn find_case_conflicts(workspace_path: String) -> Result<Vec<Vec<String>>, MikuError> {
+    let path = Path::new(&workspace_path);
+    with_read_timeout(path, find_case_conflicts_in(path, max_tree_depth())).await
+}
+
+/// Sort directory entries like the file tree: entries from `.miku-order`
+/// first in that order, then folders and files as `grouping` says, then
+/// alphabetically
+fn sort_tree_entries(files: &mut [WorkspaceFile], custom_order: Vec<String>, grouping: TreeGrouping) {
+    let order: HashMap<String, usize> = custom_order
+        .into_iter()
+        .enumerate()
+        .map(|(index, name)| (name, index))
+        .collect();
+    files.sort_by(|a, b| {
+        match (order.get(&a.name), order.get(&b.name)) {
+            (Some(x), Some(y)) => return x.cmp(y),
+            (Some(_), None) => return std::cmp::Ordering::Less,
+            (None, Some(_)) => return std::cmp::Ordering::Greater,
+            (None, None) => {}
+        }
+        let by_kind = match grouping {
+            TreeGrouping::DirsFirst => b.is_directory.cmp(&a.is_directory),
+            TreeGrouping::FilesFirst => a.is_directory.cmp(&b.is_directory),
+            TreeGrouping::Interleaved => std::cmp::Ordering::Equal,
+        };
+        by_kind.then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+/// Where a source file starts within a folder read as one document
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DocumentSegment {
+    pub path: String,
+    /// 1-based line of the file's heading in the combined document
+    pub start_line: usize,
+}
+
+/// A folder's markdown files combined into one read-only document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FolderDocument {
+    pub document: Document,
+    pub segments: Vec<DocumentSegment>,
+}
+
+/// Read every markdown file directly inside `folder_path`, in file tree
+/// order, as a single "book" document with a heading per file
+#[tauri::command]
+pub async fn read_folder_as_document(folder_path: String) -> Result<FolderDocument, MikuError> {
+    let folder = Path::new(&folder_path);
+    if !folder.is_dir() {
+        return Err(MikuError::NotFound(folder_path));
+    }
+
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(folder).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path().to_string_lossy().to_string();
+        if !is_ignored_name(&name) && entry.file_type().await?.is_file() && is_markdown_file(&path) {
+            files.push(WorkspaceFile {
+                name,
+                path,
+                is_directory: false,
+                children: None,
+                relative_path: None,
+                word_count: None,
+                hidden_count: None,
+                truncated: false,
+                display_title: None,
+            });
+        }
+    }
+    sort_tree_entries(&mut files, read_folder_order(folder).await, TreeGrouping::default());
+
+    let mut documents = Vec::with_capacity(files.len());
+    for file in &files {
+        let content = tokio::fs::read_to_string(&file.path)
+            .await
+            .map_err(|e| MikuError::from_io(e, &file.path))?;
+        documents.push((section_title(&file.path), content));
+    }
+
+    let (content, offsets) = merge_documents_with_offsets(&documents);
+    let segments = files
+        .into_iter()
+        .zip(offsets)
+        .map(|(file, start_line)| DocumentSegment {
+            path: file.path,
+            start_line,
+        })
+        .collect();
+
+    Ok(FolderDocument {
+        document: Document {
+            path: None,
+            content_hash: content_hash_hex(&content),
+            content,
+            is_modified: false,
+            readonly: true,
+            ..Default::default()
+        },
+        segments,
+    })
+}
+
+/// Device names Windows reserves regardless of extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Validate a user-supplied file or folder name. Trailing spaces and dots
+/// are trimmed; separators and control characters are rejected since they
+/// fail or create nested paths on some platforms, and so are reserved
+/// device names on Windows, the only platform where they can't be created.
+pub(crate) fn sanitize_name(name: &str) -> Result<String, MikuError> {
+    let trimmed = name.trim_start().trim_end_matches([' ', '.']);
+
+    if trimmed.is_empty() {
+        return Err(MikuError::Path(format!("\"{}\" is not a valid name", name)));
+    }
+    if let Some(c) = trimmed.chars().find(|c| matches!(c, '/' | '\\') || c.is_control()) {
+        let what = if c.is_control() { "control characters".to_string() } else { format!("'{}'", c) };
+        return Err(MikuError::Path(format!("Names cannot contain {}: \"{}\"", what, name)));
+    }
+
+    let stem = trimmed.split('.').next().unwrap_or(trimmed);
+    if cfg!(windows) && RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return Err(MikuError::Path(format!(
+            "\"{}\" is a reserved device name on Windows; choose another name",
+            trimmed
+        )));
+    }
+
+    Ok(normalize_name(trimmed))
+}
+
+/// Whether `name` ends in a file extension: a markdown one, or something
+/// shaped like one such as `csv`. Dots inside a title (`v1.2 notes`,
+/// `v1.2`) don't count.
+fn has_file_extension(name: &str) -> bool {
+    if is_markdown_file(name) {
+        return true;
+    }
+    Path::new(name).extension().map(|ext| ext.to_string_lossy()).is_some_and(|ext| {
+        ext.len() <= 10
+            && ext.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && ext.chars().any(|c| c.is_ascii_alphabetic())
+    })
+}
+
+/// Append `.md` to names typed without an extension, so new notes don't
+/// end up as extensionless files the tree hides. Dotfiles are left alone.
+fn with_markdown_extension(name: String) -> String {
+    if name.starts_with('.') || has_file_extension(&name) {
+        name
+    } else {
+        format!("{}.md", name)
+    }
+}
+
+/// Create `name` in `base_path` with the given content, refusing to
+/// overwrite an existing file
+async fn create_file_with_content(base_path: &str, name: &str, content: &str) -> Result<String, MikuError> {
+    let name = with_markdown_extension(sanitize_name(name)?);
+    let file_path = Path::new(base_path).join(&name);
+
+    if let Some(existing) = equivalent_entry(Path::new(base_path), &name) {
+        return Err(MikuError::AlreadyExists(existing.to_string_lossy().to_string()));
+    }
+
+    tokio::fs::write(&file_path, content).await?;
+
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+/// What creating a file named `name` would run into
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CreateConflict {
+    /// An entry with that name, or another spelling of it, already exists
+    pub exists: bool,
+    /// The existing entry is a folder, so it can't be overwritten with a file
+    pub is_directory: bool,
+    /// Free name to create instead: `name` itself when there's no conflict,
+    /// otherwise with a ` (1)`, ` (2)`, ... suffix
+    pub suggested_name: String,
+}
+
+/// Check whether `create_file` would collide with an existing entry, so the
+/// UI can offer to overwrite, rename or cancel up front. `name` is completed
+/// and validated the same way `create_file` does.
+#[tauri::command]
+pub async fn check_create_conflict(base_path: String, name: String) -> Result<CreateConflict, MikuError> {
+    let name = with_markdown_extension(sanitize_name(&name)?);
+    let dir = Path::new(&base_path);
+    let existing = equivalent_entry(dir, &name);
+    let suggested = unique_path(dir, &name);
+
+    Ok(CreateConflict {
+        exists: existing.is_some(),
+        is_directory: existing.is_some_and(|path| path.is_dir()),
+        suggested_name: suggested.file_name().unwrap_or_default().to_string_lossy().to_string(),
+    })
+}
+
+/// Create a new file
+#[tauri::command]
+pub async fn create_file(base_path: String, name: String) -> Result<String, MikuError> {
+    create_file_with_content(&base_path, &name, "").await
+}
+
+pub(crate) async fn create_and_open_file_in(
+    data_dir: &Path,
+    base_path: &str,
+    name: &str,
+    content: String,
+) -> Result<Document, MikuError> {
+    let path = create_file_with_content(base_path, name, &content).await?;
+    add_recent_file_in(data_dir, path.clone()).await?;
+
+    Ok(Document::saved(path, content, false))
+}
+
+/// Create a new file and return it as a document ready to edit, adding it to
+/// recent files. Saves the frontend a follow-up `open_file` call.
+#[tauri::command]
+pub async fn create_and_open_file(
+    base_path: String,
+    name: String,
+    content: String,
+    lock: State<'_, ConfigLock>,
+) -> Result<Document, MikuError> {
+    let _guard = lock.acquire().await;
+    create_and_open_file_in(&app_data_dir()?, &base_path, &name, content).await
+}
+
+/// Create folder `name` in `base_path`. With `recursive`, `name` may be a
+/// nested path like `projects/2024` and missing parents are created too.
+async fn create_folder_in(base_path: &str, name: &str, recursive: bool) -> Result<PathBuf, MikuError> {
+    let relative: PathBuf = if recursive {
+        name.split(['/', '\\'])
+            .filter(|part| !part.is_empty())
+            .map(sanitize_name)
+            .collect::<Result<_, _>>()?
+    } else {
+        PathBuf::from(sanitize_name(name)?)
+    };
+    if relative.as_os_str().is_empty() {
+        return Err(MikuError::Path(format!("\"{}\" is not a valid name", name)));
+    }
+    let folder_path = Path::new(base_path).join(relative);
+
+    if folder_path.exists() {
+        return Err(MikuError::AlreadyExists(folder_path.to_string_lossy().to_string()));
+    }
+
+    if recursive {
+        tokio::fs::create_dir_all(&folder_path).await?;
+    } else {
+        tokio::fs::create_dir(&folder_path).await?;
+    }
+
+    Ok(folder_path)
+}
+
+/// Create a new folder
+#[tauri::command]
+pub async fn create_folder(base_path: String, name: String) -> Result<String, MikuError> {
+    let folder_path = create_folder_in(&base_path, &name, false).await?;
+    Ok(folder_path.to_string_lossy().to_string())
+}
+
+/// Create a new folder and return it as a tree node, so the UI can splice
+/// it in without listing the workspace again
+#[tauri::command]
+pub async fn create_folder_detailed(
+    base_path: String,
+    name: String,
+    recursive: Option<bool>,
+) -> Result<WorkspaceFile, MikuError> {
+    let folder_path = create_folder_in(&base_path, &name, recursive.unwrap_or(false)).await?;
+    Ok(WorkspaceFile {
+        name: folder_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        path: folder_path.to_string_lossy().to_string(),
+        is_directory: true,
+        children: Some(Vec::new()),
+        relative_path: None,
+        word_count: None,
+        hidden_count: None,
+        truncated: false,
+        display_title: None,
+    })
+}
+
+/// Append a timestamped entry to the workspace's `inbox.md`, creating it if
+/// needed. The file is opened in append mode so concurrent captures never
+/// overwrite each other.
+#[tauri::command]
+pub async fn quick_capture(workspace_path: String, text: String) -> Result<String, MikuError> {
+    let workspace = Path::new(&workspace_path);
+
+    if !workspace.is_dir() {
+        return Err(MikuError::NotFound(workspace_path));
+    }
+
+    let inbox_path = workspace.join("inbox.md");
+    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M");
+    let entry = format!("## {}\n\n{}\n\n", timestamp, text.trim_end());
+
+    let inbox_path = inbox_path.to_string_lossy().to_string();
+    append_to_file(inbox_path.clone(), entry, false).await?;
+
+    Ok(inbox_path)
+}
+
+/// List environment files (.miku-env) in a workspace
+/// These are stored at the workspace root and are not included in the regular file listing
+#[tauri::command]
+pub async fn list_env_files(workspace_path: String) -> Result<Vec<WorkspaceFile>, MikuError> {
+    let path = Path::new(&workspace_path);
+
+    if !path.exists() {
+        return Err(MikuError::NotFound(workspace_path));
+    }
+
+    let mut env_files = with_read_timeout(path, env_files_in(path)).await?;
+
+    // Sort alphabetically
+    env_files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+
+    Ok(env_files)
+}
+
+async fn env_files_in(path: &Path) -> Result<Vec<WorkspaceFile>, MikuError> {
+    let mut env_files = Vec::new();
+    let mut entries = tokio::fs::read_dir(path).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let entry_path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        // Only include .miku-env files
+        if file_name.ends_with(".miku-env") {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                env_files.push(WorkspaceFile {
+                    name: file_name,
+                    path: entry_path.to_string_lossy().to_string(),
+                    is_directory: false,
+                    children: None,
+                    relative_path: None,
+                    word_count: None,
+                    hidden_count: None,
+                    truncated: false,
+                    display_title: None,
+                });
+            }
+        }
+    }
+
+    Ok(env_files)
+}
+
+/// Link from a note in `from_dir` to `target`, with forward slashes and
+/// spaces encoded so it works as a markdown link target
+pub(crate) fn relative_link(from_dir: &Path, target: &Path) -> String {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    std::iter::repeat("..".to_string())
+        .take(from.len() - common)
+        .chain(to[common..].iter().map(|part| part.as_os_str().to_string_lossy().to_string()))
+        .collect::<Vec<_>>()
+        .join("/")
+        .replace(' ', "%20")
+}
+
+/// Nested bullets linking every note in `files` from the note at
+/// `out_path`. Folders without notes are left out.
+fn index_bullets(files: &[WorkspaceFile], out_path: &Path, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let out_dir = out_path.parent().unwrap_or(Path::new(""));
+
+    for file in files {
+        if file.is_directory {
+            let mut children = Vec::new();
+            index_bullets(file.children.as_deref().unwrap_or_default(), out_path, depth + 1, &mut children);
+            if !children.is_empty() {
+                lines.push(format!("{}- {}", indent, file.name));
+                lines.extend(children);
+            }
+        } else if is_markdown_file(&file.path) && !same_path(&file.path, &out_path.to_string_lossy()) {
+            let title = Path::new(&file.name).file_stem().unwrap_or_default().to_string_lossy();
+            let link = relative_link(out_dir, Path::new(&file.path));
+            lines.push(format!("{}- [{}]({})", indent, title, link));
+        }
+    }
+}
+
+/// Markdown "map of contents" for the workspace tree `files`, to be saved at
+/// `out_path`. With `group_by_folder`, each top-level folder gets its own
+/// heading after the notes at the root.
+fn index_note_content(files: &[WorkspaceFile], out_path: &Path, group_by_folder: bool) -> String {
+    let title = out_path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut sections = vec![format!("# {}", title)];
+
+    if group_by_folder {
+        let (folders, notes): (Vec<_>, Vec<_>) = files.iter().cloned().partition(|file| file.is_directory);
+        let mut lines = Vec::new();
+        index_bullets(&notes, out_path, 0, &mut lines);
+        if !lines.is_empty() {
+            sections.push(lines.join("\n"));
+        }
+        for folder in folders {
+            let mut lines = Vec::new();
+            index_bullets(folder.children.as_deref().unwrap_or_default(), out_path, 0, &mut lines);
+            if !lines.is_empty() {
+                sections.push(format!("## {}\n\n{}", folder.name, lines.join("\n")));
+            }
+        }
+    } else {
+        let mut lines = Vec::new();
+        index_bullets(files, out_path, 0, &mut lines);
+        sections.push(lines.join("\n"));
+    }
+
+    sections.join("\n\n") + "\n"
+}
+
+pub(crate) async fn generate_index_note_in(
+    workspace: &Path,
+    out_path: &Path,
+    group_by_folder: bool,
+    overwrite: bool,
+) -> Result<PathBuf, MikuError> {
+    if !workspace.exists() {
+        return Err(MikuError::NotFound(workspace.to_string_lossy().to_string()));
+    }
+
+    let relative = out_path.strip_prefix(workspace).unwrap_or(out_path);
+    let out_path = resolve_in_workspace(workspace, &relative.to_string_lossy())?;
+    if out_path.exists() && !overwrite {
+        return Err(MikuError::AlreadyExists(out_path.to_string_lossy().to_string()));
+    }
+    let mut errors = Vec::new();
+    let (files, _) = list_directory(workspace, workspace, ListingOptions::default(), &mut errors).await?;
+    let content = index_note_content(&files, &out_path, group_by_folder);
+    crate::commands::write_document(out_path.to_string_lossy().to_string(), content).await?;
+    Ok(out_path)
+}
+
+/// Write a note linking every note in the workspace as nested bullets that
+/// mirror the folder tree. `out_path` may be relative to the workspace and
+/// must lie inside it; an existing file there is only replaced with
+/// `overwrite`, otherwise this fails with `AlreadyExists`. Returns the path
+/// of the generated note.
+#[tauri::command]
+pub async fn generate_index_note(
+    workspace_path: String,
+    out_path: String,
+    group_by_folder: Option<bool>,
+    overwrite: Option<bool>,
+) -> Result<String, MikuError> {
+    let path = generate_index_note_in(
+        Path::new(&workspace_path),
+        Path::new(&out_path),
+        group_by_folder.unwrap_or(false),
+        overwrite.unwrap_or(false),
+    )
+    .await?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Outcome of one path in a batch operation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchItemResult {
+    pub path: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchItemResult {
+    fn from_result<T>(path: String, result: Result<T, MikuError>) -> Self {
+        match result {
+            Ok(_) => Self { path, ok: true, error: None },
+            Err(e) => Self { path, ok: false, error: Some(e.to_string()) },
+        }
+    }
+}
+
+/// Delete a path permanently. Trashing goes through `trash_path_in`, which
+/// also remembers the item for `list_trashed`.
+async fn remove_path(path: &str) -> Result<(), MikuError> {
+    let path_obj = Path::new(path);
+
+    if !path_obj.exists() {
+        return Err(MikuError::NotFound(path.to_string()));
+    }
+
+    if path_obj.is_dir() {
+        tokio::fs::remove_dir_all(path)
+            .await
+            .map_err(|e| MikuError::from_io(e, path))?;
+    } else {
+        tokio::fs::remove_file(path)
+            .await
+            .map_err(|e| MikuError::from_io(e, path))?;
+    }
+
+    Ok(())
+}
+
+/// Delete a file or folder, with the data kept about it by file id
+#[tauri::command]
+pub async fn delete_file(path: String, lock: State<'_, ConfigLock>) -> Result<(), MikuError> {
+    let _guard = lock.acquire().await;
+    remove_path(&path).await?;
+    forget_file_ids_in(&app_data_dir()?, &path).await
+}
+
+/// Kind of step a destructive command takes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Delete,
+    Trash,
+    Move,
+    RemoveFolder,
+}
+
+/// One step of a destructive command, as planned by a dry run or as
+/// carried out
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlannedChange {
+    pub action: ChangeAction,
+    pub from: String,
+    /// Destination of a move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Why the step failed, or would fail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PlannedChange {
+    fn new(action: ChangeAction, from: String, to: Option<String>) -> Self {
+        PlannedChange { action, from, to, error: None }
+    }
+}
+
+pub(crate) async fn delete_files_in(
+    data_dir: &Path,
+    paths: Vec<String>,
+    to_trash: bool,
+    dry_run: bool,
+) -> Vec<PlannedChange> {
+    let action = if to_trash { ChangeAction::Trash } else { ChangeAction::Delete };
+    let results = if dry_run {
+        paths
+            .iter()
+            .map(|path| {
+                if Path::new(path).exists() {
+                    Ok(())
+                } else {
+                    Err(MikuError::NotFound(path.clone()))
+                }
+            })
+            .collect()
+    } else if to_trash {
+        // One pass over the trash for the whole batch
+        trash_paths_in(data_dir, &paths).await
+    } else {
+        let mut results = Vec::with_capacity(paths.len());
+        for path in &paths {
+            let result = remove_path(path).await;
+            if result.is_ok() {
+                if let Err(err) = forget_file_ids_in(data_dir, path).await {
+                    log::warn!("failed to forget file ids of {}: {}", path, err);
+                }
+            }
+            results.push(result);
+        }
+        results
+    };
+
+    paths
+        .into_iter()
+        .zip(results)
+        .map(|(path, result)| {
+            let mut change = PlannedChange::new(action, path, None);
+            change.error = result.err().map(|e| e.to_string());
+            change
+        })
+        .collect()
+}
+
+/// Delete or trash several paths, reporting the outcome of each one.
+/// A failure on one path doesn't stop the rest of the batch. With
+/// `dry_run`, nothing is deleted and the plan is returned instead, paths
+/// that would fail included.
+#[tauri::command]
+pub async fn delete_files(
+    paths: Vec<String>,
+    to_trash: bool,
+    dry_run: Option<bool>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Vec<PlannedChange>, MikuError> {
+    let _guard = lock.acquire().await;
+    Ok(delete_files_in(&app_data_dir()?, paths, to_trash, dry_run.unwrap_or(false)).await)
+}
+
+/// Fill in a new-file template: `{{title}}` becomes the file name without
+/// its extension and `{{date}}` today's date as `YYYY-MM-DD`
+fn expand_file_template(template: &str, path: &Path) -> String {
+    let title = path.file_stem().unwrap_or_default().to_string_lossy();
+    template
+        .replace("{{title}}", &title)
+        .replace("{{date}}", &chrono::Local::now().format("%Y-%m-%d").to_string())
+}
+
+/// Create several files in `base_path`, each named like `create_file` would
+/// name it and filled from `template`. Existing files and invalid names are
+/// reported per file rather than aborting the batch.
+#[tauri::command]
+pub async fn create_files(
+    base_path: String,
+    names: Vec<String>,
+    template: Option<String>,
+) -> Result<Vec<BatchItemResult>, MikuError> {
+    let mut results = Vec::with_capacity(names.len());
+
+    for name in names {
+        let target = sanitize_name(&name)
+            .ok()
+            .map(|sanitized| Path::new(&base_path).join(with_markdown_extension(sanitized)));
+        let content = match (&template, &target) {
+            (Some(template), Some(target)) => expand_file_template(template, target),
+            _ => String::new(),
+        };
+        let path = target.map_or(name.clone(), |target| target.to_string_lossy().to_string());
+        let result = create_file_with_content(&base_path, &name, &content).await;
+        results.push(BatchItemResult::from_result(path, result));
+    }
+
+    Ok(results)
+}
+
+/// Markdown for an imported plain text file. With `add_frontmatter` the
+/// first non-blank line becomes the `title` and an H1, and `created` is
+/// recorded; otherwise the text is kept as is.
+fn text_to_markdown(text: &str, add_frontmatter: bool, created: Option<&str>) -> String {
+    if !add_frontmatter {
+        return text.to_string();
+    }
+
+    let body = text.trim_start_matches(['\r', '\n']);
+    let (title, rest) = match body.split_once('\n') {
+        Some((first, rest)) => (first.trim(), rest),
+        None => (body.trim(), ""),
+    };
+
+    let mut out = String::from("---\n");
+    if !title.is_empty() {
+        // A JSON string is also a valid double-quoted YAML scalar
+        out.push_str(&format!("title: {}\n", serde_json::Value::from(title)));
+    }
+    if let Some(created) = created {
+        out.push_str(&format!("created: {}\n", created));
+    }
+    out.push_str("---\n");
+    if !title.is_empty() {
+        out.push_str(&format!("# {}\n", title));
+        let rest = rest.trim_start_matches(['\r', '\n']);
+        if !rest.is_empty() {
+            out.push('\n');
+            out.push_str(rest);
+        }
+    }
+    out
+}
+
+pub(crate) async fn import_text_files_in(
+    src_dir: &Path,
+    dest_dir: &Path,
+    add_frontmatter: bool,
+) -> Result<Vec<BatchItemResult>, MikuError> {
+    let mut entries = tokio::fs::read_dir(src_dir)
+        .await
+        .map_err(|e| MikuError::from_io(e, src_dir))?;
+    let mut sources = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_text = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("txt"));
+        if is_text && entry.file_type().await?.is_file() {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+
+    tokio::fs::create_dir_all(dest_dir)
+        .await
+        .map_err(|e| MikuError::from_io(e, dest_dir))?;
+    let dest = dest_dir.to_string_lossy().to_string();
+
+    let mut results = Vec::with_capacity(sources.len());
+    for source in sources {
+        let name = format!("{}.md", source.file_stem().unwrap_or_default().to_string_lossy());
+        let target = dest_dir.join(&name).to_string_lossy().to_string();
+        let result = async {
+            let text = tokio::fs::read_to_string(&source)
+                .await
+                .map_err(|e| MikuError::from_io(e, &source))?;
+            let created = tokio::fs::metadata(&source)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(|time| chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d").to_string());
+            let markdown = text_to_markdown(&text, add_frontmatter, created.as_deref());
+            create_file_with_content(&dest, &name, &markdown).await
+        }
+        .await;
+        results.push(BatchItemResult::from_result(target, result));
+    }
+    Ok(results)
+}
+
+/// Copy every `.txt` file directly inside `src_dir` into `dest_dir` as
+/// markdown, optionally adding frontmatter and turning the first line into
+/// a title. Files whose name is already taken in `dest_dir` are reported
+/// rather than overwritten. Results are per file, by destination path.
+#[tauri::command]
+pub async fn import_text_files(
+    src_dir: String,
+    dest_dir: String,
+    add_frontmatter: bool,
+) -> Result<Vec<BatchItemResult>, MikuError> {
+    import_text_files_in(Path::new(&src_dir), Path::new(&dest_dir), add_frontmatter).await
+}
+
+/// Rewrite the frontmatter tags of `path` with `edit`, which returns whether
+/// it changed anything. Unchanged files aren't rewritten.
+async fn edit_file_tags(path: &str, edit: impl Fn(&mut Vec<String>) -> bool) -> Result<(), MikuError> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| MikuError::from_io(e, path))?;
+
+    let mut tags = frontmatter_tags(&content);
+    if edit(&mut tags) {
+        write_atomic(Path::new(path), set_frontmatter_tags(&content, &tags)).await?;
+    }
+    Ok(())
+}
+
+fn normalize_tag(tag: &str) -> Result<String, MikuError> {
+    let tag = tag.trim().trim_start_matches('#');
+    if tag.is_empty() || tag.contains([',', '[', ']']) {
+        return Err(MikuError::Path(format!("\"{}\" is not a valid tag", tag)));
+    }
+    Ok(tag.to_string())
+}
+
+/// Add `tag` to the frontmatter of each file, creating the frontmatter when
+/// absent. Files that already have the tag are left alone.
+#[tauri::command]
+pub async fn add_tag_to_files(paths: Vec<String>, tag: String) -> Result<Vec<BatchItemResult>, MikuError> {
+    let tag = normalize_tag(&tag)?;
+    let mut results = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let result = edit_file_tags(&path, |tags| {
+            if tags.contains(&tag) {
+                return false;
+            }
+            tags.push(tag.clone());
+            true
+        })
+        .await;
+        results.push(BatchItemResult::from_result(path, result));
+    }
+
+    Ok(results)
+}
+
+/// Outcome of applying a frontmatter template to one file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FrontmatterChange {
+    pub path: String,
+    /// Template keys that were missing and got added
+    pub added: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub(crate) async fn apply_frontmatter_template_in(
+    paths: Vec<String>,
+    template: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<FrontmatterChange> {
+    let mut changes = Vec::with_capacity(paths.len());
+    for path in paths {
+        let result = async {
+            let content = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| MikuError::from_io(e, &path))?;
+            let Some((merged, added)) = merge_frontmatter(&content, template) else {
+                return Ok(Vec::new());
+            };
+            write_atomic(Path::new(&path), merged).await?;
+            Ok::<_, MikuError>(added)
+        }
+        .await;
+        changes.push(match result {
+            Ok(added) => FrontmatterChange { path, added, error: None },
+            Err(e) => FrontmatterChange { path, added: Vec::new(), error: Some(e.to_string()) },
+        });
+    }
+    changes
+}
+
+/// Backfill frontmatter keys: each key of `template` a file doesn't have
+/// yet is added with the template's value, creating the frontmatter when
+/// absent. Keys already present keep their value. Reports what was added to
+/// each file; a failure on one file doesn't stop the rest.
+#[tauri::command]
+pub async fn apply_frontmatter_template(
+    paths: Vec<String>,
+    template: serde_json::Map<String, serde_json::Value>,
+) -> Result<Vec<FrontmatterChange>, MikuError> {
+    Ok(apply_frontmatter_template_in(paths, &template).await)
+}
+
+/// Open and completed task counts for one file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileTaskCount {
+    pub path: String,
+    pub open: usize,
+    pub done: usize,
+}
+
+/// Count task items in every markdown file of the workspace. Files without
+/// tasks are left out.
+#[tauri::command]
+pub async fn workspace_tasks(workspace_path: String) -> Result<Vec<FileTaskCount>, MikuError> {
+    let root = Path::new(&workspace_path);
+    if !root.is_dir() {
+        return Err(MikuError::NotFound(workspace_path));
+    }
+
+    let mut counts = Vec::new();
+    for file in markdown_files(root).await? {
+        let Ok(content) = tokio::fs::read_to_string(&file).await else {
+            continue;
+        };
+        let tasks = extract_tasks(content);
+        if tasks.is_empty() {
+            continue;
+        }
+        let done = tasks.iter().filter(|task| task.checked).count();
+        counts.push(FileTaskCount {
+            path: file.to_string_lossy().to_string(),
+            open: tasks.len() - done,
+            done,
+        });
+    }
+
+    Ok(counts)
+}
+
+/// Flip the checkbox of the task item on `line` (1-based) and save the file.
+/// Returns the new checked state.
+#[tauri::command]
+pub async fn toggle_task(path: String, line: usize) -> Result<bool, MikuError> {
+    let mut content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    let not_a_task = || MikuError::Path(format!("Line {} of {} is not a task item", line, path));
+
+    // Same rules as extract_tasks, so examples inside code fences are refused
+    if !extract_tasks(content.clone()).iter().any(|task| task.line == line) {
+        return Err(not_a_task());
+    }
+    let line_start: usize = content.split_inclusive('\n').take(line - 1).map(str::len).sum();
+    let line_text = content[line_start..].lines().next().unwrap_or_default();
+    let task = parse_task_line(line_text).ok_or_else(not_a_task)?;
+
+    let checked = !task.checked;
+    let state = line_start + task.state_offset;
+    content.replace_range(state..state + 1, if checked { "x" } else { " " });
+
+    if tokio::fs::metadata(&path).await?.permissions().readonly() {
+        return Err(MikuError::ReadOnly(path));
+    }
+    let hash = content_hash(&content);
+    write_atomic(Path::new(&path), content).await?;
+    record_write(Path::new(&path), hash).await;
+    Ok(checked)
+}
+
+/// Remove `tag` from the frontmatter of each file
+#[tauri::command]
+pub async fn remove_tag_from_files(paths: Vec<String>, tag: String) -> Result<Vec<BatchItemResult>, MikuError> {
+    let tag = normalize_tag(&tag)?;
+    let mut results = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let result = edit_file_tags(&path, |tags| {
+            let before = tags.len();
+            tags.retain(|t| t != &tag);
+            tags.len() != before
+        })
+        .await;
+        results.push(BatchItemResult::from_result(path, result));
+    }
+
+    Ok(results)
+}
+
+/// Outcome of renaming a tag across a workspace
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TagRenameResult {
+    /// Files rewritten, including those done before a cancel
+    pub changed_files: usize,
+    /// Whether the rename was cancelled before reaching every file. Files
+    /// already rewritten keep the new tag.
+    pub cancelled: bool,
+}
+
+/// Rename a tag across every markdown file in the workspace, covering inline
+/// hashtags and frontmatter lists. Returns how many files changed. With an
+/// `operation_id`, reports progress and can be cancelled like an export; a
+/// cancelled rename still reports the files it already changed.
+#[tauri::command]
+pub async fn rename_tag(
+    app: AppHandle,
+    operations: State<'_, Operations>,
+    workspace_path: String,
+    old_tag: String,
+    new_tag: String,
+    operation_id: Option<String>,
+) -> Result<TagRenameResult, MikuError> {
+    let progress = Progress::for_operation(&app, &operations, operation_id)?;
+    rename_tag_in(&workspace_path, &old_tag, &new_tag, &progress).await
+}
+
+pub(crate) async fn rename_tag_in(
+    workspace_path: &str,
+    old_tag: &str,
+    new_tag: &str,
+    progress: &Progress,
+) -> Result<TagRenameResult, MikuError> {
+    let root = Path::new(workspace_path);
+    if !root.is_dir() {
+        return Err(MikuError::NotFound(workspace_path.to_string()));
+    }
+    let old_tag = normalize_tag(old_tag)?;
+    let new_tag = normalize_tag(new_tag)?;
+    let mut result = TagRenameResult { changed_files: 0, cancelled: false };
+    if old_tag == new_tag {
+        return Ok(result);
+    }
+
+    let files = markdown_files(root).await?;
+    for (index, file) in files.iter().enumerate() {
+        if progress.is_cancelled() {
+            result.cancelled = true;
+            break;
+        }
+        progress.step(index, files.len(), &file.to_string_lossy())?;
+        let Ok(content) = tokio::fs::read_to_string(file).await else {
+            continue;
+        };
+        let renamed = rename_tag_in_content(&content, &old_tag, &new_tag);
+        if renamed != content {
+            let hash = content_hash(&renamed);
+            write_atomic(file, renamed).await?;
+            record_write(file, hash).await;
+            result.changed_files += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileMetadata {
+    pub path: String,
+    pub size: u64,
+    /// Last modification time in milliseconds since the Unix epoch
+    pub modified_ms: Option<u64>,
+    pub is_directory: bool,
+    pub is_readonly: bool,
+}
+
+/// Get size, modification time, and permission info for a path
+#[tauri::command]
+pub async fn get_file_metadata(path: String) -> Result<FileMetadata, MikuError> {
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    let modified_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+
+    Ok(FileMetadata {
+        path,
+        size: metadata.len(),
+        modified_ms,
+        is_directory: metadata.is_dir(),
+        is_readonly: metadata.permissions().readonly(),
+    })
+}
+
+/// Bump a file's modification time to now without touching its content.
+/// Returns the new modification time in milliseconds since the Unix epoch.
+#[tauri::command]
+pub async fn touch_file(path: String) -> Result<u64, MikuError> {
+    if !Path::new(&path).exists() {
+        return Err(MikuError::NotFound(path));
+    }
+
+    let now = filetime::FileTime::now();
+    filetime::set_file_mtime(&path, now).map_err(|e| MikuError::from_io(e, &path))?;
+    Ok(now.unix_seconds() as u64 * 1000 + u64::from(now.nanoseconds()) / 1_000_000)
+}
+
+/// How long ago a file was last modified, in local calendar days
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RecencyBucket {
+    Today,
+    Yesterday,
+    ThisWeek,
+    ThisMonth,
+    Older,
+}
+
+impl RecencyBucket {
+    /// Bucket for a file modified at `modified`, counting calendar days in
+    /// the local timezone. Times in the future count as today.
+    pub(crate) fn for_time(modified: chrono::DateTime<chrono::Local>, now: chrono::DateTime<chrono::Local>) -> Self {
+        match (now.date_naive() - modified.date_naive()).num_days() {
+            ..=0 => RecencyBucket::Today,
+            1 => RecencyBucket::Yesterday,
+            2..=6 => RecencyBucket::ThisWeek,
+            7..=29 => RecencyBucket::ThisMonth,
+            _ => RecencyBucket::Older,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModifiedFile {
+    pub path: String,
+    pub name: String,
+    /// Last modification time in milliseconds since the Unix epoch
+    pub modified_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecencyGroup {
+    pub bucket: RecencyBucket,
+    /// Newest first
+    pub files: Vec<ModifiedFile>,
+}
+
+/// Paths of every file in a workspace tree
+fn tree_file_paths(files: &[WorkspaceFile], paths: &mut Vec<String>) {
+    for file in files {
+        if file.is_directory {
+            tree_file_paths(file.children.as_deref().unwrap_or_default(), paths);
+        } else {
+            paths.push(file.path.clone());
+        }
+    }
+}
+
+pub(crate) async fn group_files_by_recency_in(
+    root: &Path,
+    now: chrono::DateTime<chrono::Local>,
+    concurrency: usize,
+) -> Result<Vec<RecencyGroup>, MikuError> {
+    if !root.exists() {
+        return Err(MikuError::NotFound(root.to_string_lossy().to_string()));
+    }
+
+    let (files, _) = list_directory(root, root, ListingOptions::default(), &mut Vec::new()).await?;
+    let mut paths = Vec::new();
+    tree_file_paths(&files, &mut paths);
+
+    let mut modified: Vec<(RecencyBucket, ModifiedFile)> = stream::iter(paths)
+        .map(|path| async move {
+            // Files that vanish mid-walk or report no mtime are skipped
+            let time = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+            let local = chrono::DateTime::<chrono::Local>::from(time);
+            let name = Path::new(&path).file_name()?.to_string_lossy().to_string();
+            let modified_ms = local.timestamp_millis().max(0) as u64;
+            Some((RecencyBucket::for_time(local, now), ModifiedFile { path, name, modified_ms }))
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+
+    modified.sort_by(|(a_bucket, a), (b_bucket, b)| {
+        a_bucket
+            .cmp(b_bucket)
+            .then(b.modified_ms.cmp(&a.modified_ms))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let mut groups: Vec<RecencyGroup> = Vec::new();
+    for (bucket, file) in modified {
+        match groups.last_mut() {
+            Some(group) if group.bucket == bucket => group.files.push(file),
+            _ => groups.push(RecencyGroup {
+                bucket,
+                files: vec![file],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Group the files of a workspace into Today, Yesterday, This week, This
+/// month and Older by their local modification date. Empty groups are left
+/// out.
+#[tauri::command]
+pub async fn group_files_by_recency(workspace_path: String) -> Result<Vec<RecencyGroup>, MikuError> {
+    group_files_by_recency_in(Path::new(&workspace_path), chrono::Local::now(), io_concurrency_or_saved().await).await
+}
+
+/// Mark a file read-only (or writable again) at the OS permission level
+#[tauri::command]
+pub async fn set_file_readonly(path: String, readonly: bool) -> Result<(), MikuError> {
+    let mut permissions = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?
+        .permissions();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Only restore the owner's write bit rather than making it world-writable
+        let mode = permissions.mode();
+        permissions.set_mode(if readonly { mode & !0o222 } else { mode | 0o200 });
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(readonly);
+
+    tokio::fs::set_permissions(&path, permissions)
+        .await
+        .map_err(|e| MikuError::from_io(e, &path))?;
+    Ok(())
+}
+
+/// Pick a free path in `dir` for `name`, appending ` (1)`, ` (2)`, ... to the
+/// file stem when the name, or another spelling of it, is already taken
+pub(crate) fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    unique_path_besides(dir, name, &[])
+}
+
+/// `unique_path`, also treating the paths in `reserved` as taken
+fn unique_path_besides(dir: &Path, name: &str, reserved: &[PathBuf]) -> PathBuf {
+    let taken = |candidate: &str| {
+        equivalent_entry(dir, candidate).is_some()
+            || reserved
+                .iter()
+                .any(|path| same_path(&path.to_string_lossy(), &dir.join(candidate).to_string_lossy()))
+    };
+    if !taken(name) {
+        return dir.join(name);
+    }
+
+    let name_path = Path::new(name);
+    let stem = name_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| name.to_string());
+    let extension = name_path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut n = 1;
+    loop {
+        let candidate = format!("{} ({}){}", stem, n, extension);
+        if !taken(&candidate) {
+            return dir.join(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Moves that flatten `folder` into its parent, then the removal of the
+/// folder itself
+async fn plan_flatten(data_dir: &Path, folder_path: &str) -> Result<Vec<PlannedChange>, MikuError> {
+    let folder = Path::new(folder_path);
+
+    if !folder.is_dir() {
+        return Err(MikuError::NotFound(folder_path.to_string()));
+    }
+
+    let config = load_workspace_config_in(data_dir).await?;
+    let absolute = absolute_workspace_path(folder_path)?;
+    if config.current_workspace.as_deref().is_some_and(|current| same_path(current, &absolute)) {
+        return Err(MikuError::Path("Cannot flatten the workspace root".to_string()));
+    }
+
+    let parent = folder
+        .parent()
+        .ok_or_else(|| MikuError::Path("Cannot determine parent directory".to_string()))?;
+
+    let mut names = Vec::new();
+    let mut entries = tokio::fs::read_dir(folder).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        names.push(entry.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+
+    // Earlier moves claim their targets, so two entries never land on the
+    // same free name
+    let mut targets: Vec<PathBuf> = Vec::with_capacity(names.len());
+    for name in &names {
+        targets.push(unique_path_besides(parent, name, &targets));
+    }
+
+    let mut plan: Vec<PlannedChange> = names
+        .iter()
+        .zip(&targets)
+        .map(|(name, target)| {
+            let from = folder.join(name).to_string_lossy().to_string();
+            PlannedChange::new(ChangeAction::Move, from, Some(target.to_string_lossy().to_string()))
+        })
+        .collect();
+    plan.push(PlannedChange::new(ChangeAction::RemoveFolder, folder_path.to_string(), None));
+    Ok(plan)
+}
+
+pub(crate) async fn flatten_folder_in(
+    data_dir: &Path,
+    folder_path: String,
+    dry_run: bool,
+) -> Result<Vec<PlannedChange>, MikuError> {
+    let plan = plan_flatten(data_dir, &folder_path).await?;
+    if dry_run {
+        return Ok(plan);
+    }
+
+    for change in &plan {
+        match (change.action, &change.to) {
+            (ChangeAction::Move, Some(to)) => {
+                tokio::fs::rename(&change.from, to).await?;
+                move_file_ids_in(data_dir, &change.from, to).await?;
+            }
+            (ChangeAction::RemoveFolder, _) => tokio::fs::remove_dir(&change.from).await?,
+            _ => {}
+        }
+    }
+    Ok(plan)
+}
+
+/// Move everything in a folder up into its parent, then remove the folder.
+/// Name collisions in the parent are resolved by suffixing ` (1)`, ` (2)`, ...
+/// Returns the steps taken; with `dry_run`, only plans them.
+#[tauri::command]
+pub async fn flatten_folder(
+    folder_path: String,
+    dry_run: Option<bool>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Vec<PlannedChange>, MikuError> {
+    let _guard = lock.acquire().await;
+    flatten_folder_in(&app_data_dir()?, folder_path, dry_run.unwrap_or(false)).await
+}
+
+/// What to do when a rename target already exists
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Fail with `AlreadyExists`
+    #[default]
+    Error,
+    /// Move the existing target to the trash, then rename over it
+    Overwrite,
+    /// Pick a free name by appending ` (1)`, ` (2)`, ... to the stem
+    Rename,
+}
+
+/// Rename a file or folder on disk. Returns the new path. An overwritten
+/// target is trashed and recorded in the trash index in `data_dir`.
+async fn rename_entry(
+    data_dir: &Path,
+    old_path: String,
+    new_name: String,
+    on_conflict: Option<ConflictPolicy>,
+) -> Result<String, MikuError> {
+    let trash = |existing: PathBuf| async move { trash_path_in(data_dir, &existing.to_string_lossy()).await };
+    rename_entry_with(old_path, new_name, on_conflict, trash).await
+}
+
+/// `rename_entry`, moving an overwritten target away with `trash`
+async fn rename_entry_with<F, Fut>(
+    old_path: String,
+    new_name: String,
+    on_conflict: Option<ConflictPolicy>,
+    trash: F,
+) -> Result<String, MikuError>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<(), MikuError>>,
+{
+    let old_path_obj = Path::new(&old_path);
+
+    if !old_path_obj.exists() {
+        return Err(MikuError::NotFound(old_path));
+    }
+
+    let parent = old_path_obj.parent()
+        .ok_or_else(|| MikuError::Path("Cannot determine parent directory".to_string()))?;
+
+    let new_name = sanitize_name(&new_name)?;
+    let mut new_path = parent.join(&new_name);
+
+    // Only the case or Unicode form changes: the filesystem may see the new
+    // name as the existing entry, so this can't go through the conflict check
+    let old_name = old_path_obj.file_name().unwrap_or_default().to_string_lossy();
+    if old_name != new_name && same_path(&old_name, &new_name) {
+        rename_respelled(old_path_obj, &new_path).await?;
+        return Ok(new_path.to_string_lossy().to_string());
+    }
+
+    // Another spelling of the old name (case, composed vs decomposed) isn't a conflict
+    if let Some(existing) = equivalent_entry(parent, &new_name).filter(|existing| existing != old_path_obj) {
+        match on_conflict.unwrap_or_default() {
+            ConflictPolicy::Error => {
+                return Err(MikuError::AlreadyExists(existing.to_string_lossy().to_string()));
+            }
+            ConflictPolicy::Overwrite => trash(existing).await?,
+            ConflictPolicy::Rename => new_path = unique_path(parent, &new_name),
+        }
+    }
+
+    tokio::fs::rename(&old_path, &new_path)
+        .await
+        .map_err(|e| MikuError::from_io(e, &old_path))?;
+
+    Ok(new_path.to_string_lossy().to_string())
+}
+
+pub(crate) async fn rename_file_in(
+    data_dir: &Path,
+    old_path: String,
+    new_name: String,
+    on_conflict: Option<ConflictPolicy>,
+) -> Result<String, MikuError> {
+    let new_path = rename_entry(data_dir, old_path.clone(), new_name, on_conflict).await?;
+    move_file_ids_in(data_dir, &old_path, &new_path).await?;
+    Ok(new_path)
+}
+
+/// Rename a file or folder. Stable file ids, and the data kept under them,
+/// follow it to the new name.
+#[tauri::command]
+pub async fn rename_file(
+    old_path: String,
+    new_name: String,
+    on_conflict: Option<ConflictPolicy>,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    lock: State<'_, ConfigLock>,
+) -> Result<String, MikuError> {
+    let _guard = lock.acquire().await;
+    let old_key = ReadonlyDocuments::key(&old_path);
+    let new_path = rename_file_in(&app_data_dir()?, old_path, new_name, on_conflict).await?;
+    readonly_docs.moved(&old_key, &new_path);
+    Ok(new_path)
+}
+
+/// Rename to a name that differs only in case or Unicode form. Goes through
+/// a temporary name, since some case-insensitive filesystems treat a direct
+/// rename as a no-op.
+async fn rename_respelled(old_path: &Path, new_path: &Path) -> Result<(), MikuError> {
+    let temp = new_path.with_file_name(format!(".miku-rename-{}-{}", std::process::id(), now_ms()));
+    tokio::fs::rename(old_path, &temp)
+        .await
+        .map_err(|e| MikuError::from_io(e, old_path))?;
+
+    if let Err(e) = tokio::fs::rename(&temp, new_path).await {
+        // Put the original name back rather than leave a hidden temp file
+        let _ = tokio::fs::rename(&temp, old_path).await;
+        return Err(MikuError::from_io(e, new_path));
+    }
+    Ok(())
+}
+
+/// Resolve `relative` inside `workspace`, rejecting absolute paths and any
+/// `..` or symlink that would escape it
+pub(crate) fn resolve_in_workspace(workspace: &Path, relative: &str) -> Result<PathBuf, MikuError> {
+    use std::path::Component;
+
+    let outside = || MikuError::OutsideWorkspace(relative.to_string());
+    let mut resolved = workspace.to_path_buf();
+    let mut depth = 0usize;
+
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => {
+                resolved.pop();
+                depth -= 1;
+            }
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(outside()),
+        }
+    }
+
+    // Catch symlinks pointing out of the workspace. Paths that don't exist
+    // yet are checked through their deepest existing ancestor.
+    let existing = resolved.ancestors().find(|path| path.symlink_metadata().is_ok());
+    if let (Some(existing), Ok(root)) = (existing, std::fs::canonicalize(workspace)) {
+        match std::fs::canonicalize(existing) {
+            Ok(real) if real.starts_with(&root) => {}
+            _ => return Err(outside()),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Whether `path` lies inside `workspace`, by the same rules as
+/// `resolve_in_workspace`. A relative `path` is taken from the workspace
+/// root.
+pub(crate) fn is_in_workspace_in(workspace: &Path, path: &str) -> bool {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return resolve_in_workspace(workspace, &path.to_string_lossy()).is_ok();
+    }
+
+    let relative = match path.strip_prefix(workspace) {
+        Ok(relative) => relative.to_path_buf(),
+        // The workspace may have been opened through a symlink
+        Err(_) => match (std::fs::canonicalize(workspace), std::fs::canonicalize(path)) {
+            (Ok(root), Ok(real)) => match real.strip_prefix(root) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => return false,
+            },
+            _ => return false,
+        },
+    };
+    resolve_in_workspace(workspace, &relative.to_string_lossy()).is_ok()
+}
+
+/// Whether `path` belongs to the open workspace, or to `workspace_path`
+/// when given, e.g. to decide between copying and linking a dropped file
+#[tauri::command]
+pub async fn is_in_workspace(
+    path: String,
+    workspace_path: Option<String>,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<bool, MikuError> {
+    Ok(is_in_workspace_in(&active.root_or(workspace_path)?, &path))
+}
+
+/// Move a file, falling back to copy-and-delete when a plain rename fails
+/// (e.g. across devices)
+async fn move_file(from: &Path, to: &Path) -> Result<(), MikuError> {
+    if let Err(rename_err) = tokio::fs::rename(from, to).await {
+        if tokio::fs::copy(from, to).await.is_err() {
+            return Err(MikuError::from_io(rename_err, from));
+        }
+        tokio::fs::remove_file(from)
+            .await
+            .map_err(|e| MikuError::from_io(e, from))?;
+    }
+    Ok(())
+}
+
+/// Whether `workspace` is the current workspace or one of the recent ones
+pub(crate) async fn is_known_workspace_in(data_dir: &Path, workspace: &str) -> Result<bool, MikuError> {
+    let config = load_workspace_config_in(data_dir).await?;
+    Ok(config.current_workspace.as_deref().is_some_and(|current| same_path(current, workspace))
+        || config.recent_workspaces.iter().any(|w| same_path(&w.path, workspace)))
+}
+
+pub(crate) async fn move_file_to_workspace_in(
+    data_dir: &Path,
+    source_path: &str,
+    dest_workspace: &str,
+    relative_dest_dir: &str,
+) -> Result<String, MikuError> {
+    let source = Path::new(source_path);
+    if !source.is_file() {
+        return Err(MikuError::NotFound(source_path.to_string()));
+    }
+    let workspace = Path::new(dest_workspace);
+    if !workspace.is_dir() {
+        return Err(MikuError::NotFound(dest_workspace.to_string()));
+    }
+    if !is_known_workspace_in(data_dir, dest_workspace).await? {
+        return Err(MikuError::Path(format!("{} is not a known workspace", dest_workspace)));
+    }
+
+    let dest_dir = resolve_in_workspace(workspace, relative_dest_dir)?;
+    let file_name = source
+        .file_name()
+        .ok_or_else(|| MikuError::Path("Cannot determine file name".to_string()))?;
+    let dest = dest_dir.join(file_name);
+    if dest.exists() {
+        return Err(MikuError::AlreadyExists(dest.to_string_lossy().to_string()));
+    }
+
+    tokio::fs::create_dir_all(&dest_dir).await?;
+    move_file(source, &dest).await?;
+
+    let dest_path = dest.to_string_lossy().to_string();
+    move_recent_file_in(data_dir, source_path, &dest_path, Some(dest_workspace)).await?;
+    move_file_ids_in(data_dir, source_path, &dest_path).await?;
+
+    Ok(dest_path)
+}
+
+/// Move a file into `relative_dest_dir` of another workspace, keeping recent
+/// files pointed at its new location. Returns the new path.
+#[tauri::command]
+pub async fn move_file_to_workspace(
+    source_path: String,
+    dest_workspace: String,
+    relative_dest_dir: String,
+    readonly_docs: State<'_, ReadonlyDocuments>,
+    lock: State<'_, ConfigLock>,
+) -> Result<String, MikuError> {
+    let _guard = lock.acquire().await;
+    let old_key = ReadonlyDocuments::key(&source_path);
+    let dest_path =
+        move_file_to_workspace_in(&app_data_dir()?, &source_path, &dest_workspace, &relative_dest_dir).await?;
+    readonly_docs.moved(&old_key, &dest_path);
+    Ok(dest_path)
+}
+
+/// Where a link in a document points
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResolvedLink {
+    pub path: String,
+    pub exists: bool,
+    /// Whether the `#fragment` names a heading in the target. `None` when
+    /// the link has no fragment or the target isn't an existing markdown file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor_exists: Option<bool>,
+}
+
+/// The note among `files` that the wikilink `name` refers to. When several
+/// notes share a name, the first path in sort order wins.
+fn find_wikilink_target<'a>(workspace: &Path, files: &'a [PathBuf], name: &str) -> Option<&'a PathBuf> {
+    let wanted = name.trim().trim_end_matches(".md").to_lowercase();
+    files
+        .iter()
+        .filter(|file| {
+            let relative = file.strip_prefix(workspace).unwrap_or(file).with_extension("");
+            let candidate = if wanted.contains('/') {
+                relative.to_string_lossy().replace('\\', "/")
+            } else {
+                relative.file_name().unwrap_or_default().to_string_lossy().to_string()
+            };
+            candidate.to_lowercase() == wanted
+        })
+        .min()
+}
+
+/// Find the markdown file a `[[wikilink]]` refers to: by file stem anywhere
+/// in the vault, or by workspace-relative path when it contains a `/`
+async fn resolve_wikilink(workspace: &Path, name: &str) -> Result<ResolvedLink, MikuError> {
+    let name = name.trim();
+    if let Some(file) = find_wikilink_target(workspace, &markdown_files(workspace).await?, name) {
+        return Ok(ResolvedLink {
+            path: file.to_string_lossy().to_string(),
+            exists: true,
+            anchor_exists: None,
+        });
+    }
+
+    // Not created yet: point where a new note would go
+    let path = resolve_in_workspace(workspace, &with_markdown_extension(name.to_string()))?;
+    Ok(ResolvedLink {
+        path: path.to_string_lossy().to_string(),
+        exists: false,
+        anchor_exists: None,
+    })
+}
+
+/// Check `fragment` against the headings of the link's target
+async fn check_anchor(
+    mut link: ResolvedLink,
+    fragment: Option<&str>,
+    options: &MarkdownOptions,
+) -> Result<ResolvedLink, MikuError> {
+    let Some(fragment) = fragment.filter(|f| !f.is_empty()) else {
+        return Ok(link);
+    };
+    if link.exists && is_markdown_file(&link.path) {
+        let anchors = heading_anchors_in(&link.path, options).await?;
+        link.anchor_exists = Some(anchors.contains(&slugify_text(&fragment.replace("%20", " "))));
+    }
+    Ok(link)
+}
+
+pub(crate) async fn resolve_link_within(
+    workspace: &Path,
+    source_path: &str,
+    link_target: &str,
+    options: &MarkdownOptions,
+) -> Result<ResolvedLink, MikuError> {
+    let source_dir = Path::new(source_path)
+        .parent()
+        .and_then(|dir| dir.strip_prefix(workspace).ok())
+        .ok_or_else(|| MikuError::OutsideWorkspace(source_path.to_string()))?;
+
+    if let Some(inner) = link_target.strip_prefix("[[").and_then(|t| t.strip_suffix("]]")) {
+        // Drop the `|alias` part; `#heading` is checked against the target
+        let inner = inner.split('|').next().unwrap_or_default();
+        let (name, heading) = match inner.split_once('#') {
+            Some((name, heading)) => (name, Some(heading)),
+            None => (inner, None),
+        };
+        let link = resolve_wikilink(workspace, name).await?;
+        return check_anchor(link, heading, options).await;
+    }
+
+    let without_query = link_target.split('?').next().unwrap_or_default();
+    let (target, fragment) = match without_query.split_once('#') {
+        Some((target, fragment)) => (target, Some(fragment)),
+        None => (without_query, None),
+    };
+    let target = target.replace("%20", " ");
+
+    // A bare `#heading` points into the source document itself
+    let path = if target.is_empty() {
+        PathBuf::from(source_path)
+    } else {
+        // A leading slash is relative to the workspace root rather than the disk
+        let relative = match target.strip_prefix('/') {
+            Some(from_root) => PathBuf::from(from_root),
+            None => source_dir.join(&target),
+        };
+        resolve_in_workspace(workspace, &relative.to_string_lossy())
+            .map_err(|_| MikuError::OutsideWorkspace(link_target.to_string()))?
+    };
+
+    let link = ResolvedLink {
+        exists: path.exists(),
+        path: path.to_string_lossy().to_string(),
+        anchor_exists: None,
+    };
+    check_anchor(link, fragment, options).await
+}
+
+pub(crate) async fn resolve_link_in(
+    data_dir: &Path,
+    active: &ActiveWorkspace,
+    workspace_path: Option<String>,
+    source_path: &str,
+    link_target: &str,
+) -> Result<ResolvedLink, MikuError> {
+    let workspace = active.root_or(workspace_path)?;
+    let options = settings_or_default(data_dir).await.markdown_options;
+    resolve_link_within(&workspace, source_path, link_target, &options).await
+}
+
+/// Turn a link clicked in `source_path` into an absolute path inside the
+/// open workspace, or inside `workspace_path` when given. Relative links
+/// resolve against the source file's folder; `[[wikilinks]]` are looked up
+/// by file name across the vault. A `#fragment` is validated against the
+/// target's heading anchors.
+#[tauri::command]
+pub async fn resolve_link(
+    source_path: String,
+    link_target: String,
+    workspace_path: Option<String>,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<ResolvedLink, MikuError> {
+    resolve_link_in(&app_data_dir()?, &active, workspace_path, &source_path, &link_target).await
+}
+
+/// A file or folder that moved from `old` to `new`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PathMove {
+    pub old: String,
+    pub new: String,
+}
+
+/// How many links `repair_workspace_links` rewrote in one file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkRepair {
+    pub path: String,
+    pub edits: usize,
+}
+
+/// Where `path` is after `moves`, or before them when `forward` is false.
+/// `None` when no move touches it. A moved folder carries everything in it.
+fn moved_path(path: &Path, moves: &[(PathBuf, PathBuf)], forward: bool) -> Option<PathBuf> {
+    moves.iter().find_map(|(old, new)| {
+        let (from, to) = if forward { (old, new) } else { (new, old) };
+        let rest = path.strip_prefix(from).ok()?;
+        Some(if rest.as_os_str().is_empty() { to.clone() } else { to.join(rest) })
+    })
+}
+
+/// Replace the first `old` in `link` after `start` with `new`
+fn replace_in_link(link: &str, start: &str, old: &str, new: &str) -> Option<String> {
+    let after = link.find(start)? + start.len();
+    let at = after + link[after..].find(old)?;
+    Some(format!("{}{}{}", &link[..at], new, &link[at + old.len()..]))
+}
+
+/// The workspace's notes after a set of moves, paired with where each was
+/// before them
+struct MovedNotes {
+    now: Vec<PathBuf>,
+    before: Vec<PathBuf>,
+}
+
+impl MovedNotes {
+    fn new(notes: Vec<PathBuf>, moves: &[(PathBuf, PathBuf)]) -> Self {
+        let before = notes
+            .iter()
+            .map(|note| moved_path(note, moves, false).unwrap_or_else(|| note.clone()))
+            .collect();
+        MovedNotes { now: notes, before }
+    }
+}
+
+/// New name for a `[[wikilink]]` in a note whose target moved, or `None`
+/// when the link still resolves
+fn repaired_wikilink(
+    workspace: &Path,
+    name: &str,
+    moves: &[(PathBuf, PathBuf)],
+    notes: &MovedNotes,
+) -> Option<String> {
+    let name = name.trim();
+    if name.contains('/') {
+        let has_extension = has_file_extension(name);
+        let old = resolve_in_workspace(workspace, &with_markdown_extension(name.to_string())).ok()?;
+        let new = moved_path(&old, moves, true)?;
+        let relative = new.strip_prefix(workspace).ok()?;
+        let relative = if has_extension { relative.to_path_buf() } else { relative.with_extension("") };
+        return Some(relative.to_string_lossy().replace('\\', "/"));
+    }
+
+    // A bare name only breaks when the note it found before the moves
+    // moved and the name no longer finds it
+    let old = find_wikilink_target(workspace, &notes.before, name)?;
+    let new = moved_path(old, moves, true)?;
+    if find_wikilink_target(workspace, &notes.now, name) == Some(&new) {
+        return None;
+    }
+    let stem = new.file_stem()?.to_string_lossy().to_string();
+    if find_wikilink_target(workspace, &notes.now, &stem) == Some(&new) {
+        return Some(stem);
+    }
+    // Another note took the name, so spell out the path
+    let relative = new.strip_prefix(workspace).ok()?.with_extension("");
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// New destination for a markdown link written as `target` in a note now
+/// at `source` and formerly at `old_source`, or `None` when it still works
+fn repaired_link_target(
+    workspace: &Path,
+    source: &Path,
+    old_source: &Path,
+    target: &str,
+    moves: &[(PathBuf, PathBuf)],
+) -> Option<String> {
+    let split_at = target.find(['#', '?']).unwrap_or(target.len());
+    let (path, suffix) = target.split_at(split_at);
+    if path.is_empty() || path.contains("://") || path.starts_with("mailto:") {
+        return None;
+    }
+    let path = path.replace("%20", " ");
+
+    let old_target = match path.strip_prefix('/') {
+        Some(from_root) => resolve_in_workspace(workspace, from_root).ok()?,
+        None => {
+            let old_dir = old_source.parent()?.strip_prefix(workspace).ok()?;
+            resolve_in_workspace(workspace, &old_dir.join(&path).to_string_lossy()).ok()?
+        }
+    };
+    let new_target = moved_path(&old_target, moves, true);
+    if new_target.is_none() && source == old_source {
+        return None;
+    }
+    let new_target = new_target.unwrap_or(old_target);
+
+    let link = if path.starts_with('/') {
+        let relative = new_target.strip_prefix(workspace).ok()?;
+        format!("/{}", relative.to_string_lossy().replace('\\', "/").replace(' ', "%20"))
+    } else {
+        relative_link(source.parent()?, &new_target)
+    };
+    Some(format!("{}{}", link, suffix))
+}
+
+/// Rewrite the links in `content`, a note now at `source`, that `moves`
+/// broke. Returns the new content and how many links changed.
+fn repair_links_in_content(
+    workspace: &Path,
+    source: &Path,
+    content: &str,
+    moves: &[(PathBuf, PathBuf)],
+    notes: &MovedNotes,
+    options: &MarkdownOptions,
+) -> (String, usize) {
+    let old_source = moved_path(source, moves, false).unwrap_or_else(|| source.to_path_buf());
+    let mut repaired = content.to_string();
+    let mut edits = 0;
+
+    // Reference-style links are fixed at their `[label]: destination`
+    // definition, which `start` finds
+    let mut links: Vec<_> = document_links(content, options)
+        .into_iter()
+        .map(|link| (link, "]("))
+        .chain(reference_definitions(content, options).into_iter().map(|link| (link, "]:")))
+        .collect();
+    links.sort_by_key(|(link, _)| link.range.start);
+
+    // Back to front so earlier ranges stay valid
+    for (link, start) in links.into_iter().rev() {
+        let written = &content[link.range.clone()];
+        let replacement = match link.target.strip_prefix("[[").and_then(|t| t.strip_suffix("]]")) {
+            Some(inner) => {
+                let name = inner.split('#').next().unwrap_or_default();
+                repaired_wikilink(workspace, name, moves, notes)
+                    .and_then(|new_name| replace_in_link(written, "[[", name.trim(), &new_name))
+            }
+            None => repaired_link_target(workspace, source, &old_source, &link.target, moves)
+                .filter(|new_target| *new_target != link.target)
+                .and_then(|new_target| replace_in_link(written, start, &link.target, &new_target)),
+        };
+        if let Some(replacement) = replacement {
+            repaired.replace_range(link.range, &replacement);
+            edits += 1;
+        }
+    }
+
+    (repaired, edits)
+}
+
+pub(crate) async fn repair_workspace_links_in(
+    workspace: &Path,
+    moves: &[PathMove],
+    options: &MarkdownOptions,
+) -> Result<Vec<LinkRepair>, MikuError> {
+    if !workspace.is_dir() {
+        return Err(MikuError::NotFound(workspace.to_string_lossy().to_string()));
+    }
+    let moves: Vec<(PathBuf, PathBuf)> = moves
+        .iter()
+        .map(|m| (PathBuf::from(&m.old), PathBuf::from(&m.new)))
+        .collect();
+
+    let notes = MovedNotes::new(markdown_files(workspace).await?, &moves);
+    let mut repairs = Vec::new();
+    for file in &notes.now {
+        // Files that vanish or can't be read mid-walk are skipped
+        let Ok(content) = tokio::fs::read_to_string(file).await else {
+            continue;
+        };
+        let (repaired, edits) = repair_links_in_content(workspace, file, &content, &moves, &notes, options);
+        if edits > 0 {
+            let hash = content_hash(&repaired);
+            write_atomic(file, repaired).await?;
+            record_write(file, hash).await;
+            repairs.push(LinkRepair {
+                path: file.to_string_lossy().to_string(),
+                edits,
+            });
+        }
+    }
+
+    repairs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(repairs)
+}
+
+/// After files or folders were moved from `old` to `new`, rewrite every
+/// link across the workspace that pointed at their old location, including
+/// relative links inside the moved notes themselves. Stable file ids follow
+/// the moves too. Returns how many links changed in each file; untouched
+/// files are left out.
+#[tauri::command]
+pub async fn repair_workspace_links(
+    workspace_path: String,
+    moves: Vec<PathMove>,
+    lock: State<'_, ConfigLock>,
+) -> Result<Vec<LinkRepair>, MikuError> {
+    let options = markdown_options_or_saved(None).await;
+    let repairs = repair_workspace_links_in(Path::new(&workspace_path), &moves, &options).await?;
+
+    let _guard = lock.acquire().await;
+    let data_dir = app_data_dir()?;
+    for PathMove { old, new } in &moves {
+        move_file_ids_in(&data_dir, old, new).await?;
+    }
+    Ok(repairs)
+}
+
+/// Link syntax for `convert_links` to write
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStyle {
+    /// `[[note]]`
+    Wikilink,
+    /// `[note](note.md)`
+    MarkdownLink,
+}
+
+/// A document with its internal links rewritten by `convert_links`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkConversion {
+    pub content: String,
+    /// Links left as written because their target isn't a note in the
+    /// workspace, in document order
+    pub unresolved: Vec<String>,
+}
+
+/// `[text](path)` for the wikilink `inner` (its `[[...]]` contents), or
+/// `None` if no note has that name
+fn wikilink_as_markdown(
+    workspace: &Path,
+    files: &[PathBuf],
+    source_dir: Option<&Path>,
+    inner: &str,
+    text: &str,
+) -> Option<String> {
+    let (name, heading) = match inner.split_once('#') {
+        Some((name, heading)) => (name, Some(heading)),
+        None => (inner, None),
+    };
+    let path = if name.trim().is_empty() {
+        String::new()
+    } else {
+        let target = find_wikilink_target(workspace, files, name)?;
+        let relative = target.strip_prefix(workspace).ok()?;
+        match source_dir {
+            Some(dir) => relative_link(dir, relative),
+            None => format!("/{}", relative.to_string_lossy().replace('\\', "/").replace(' ', "%20")),
+        }
+    };
+    let fragment = heading.map(|h| format!("#{}", slugify_text(h))).unwrap_or_default();
+    let text = if text.is_empty() { inner } else { text };
+    Some(format!("[{}]({}{})", text, path, fragment))
+}
+
+/// `[[name]]` for the markdown link target `path`, or `None` if it doesn't
+/// point at an existing note. The name is the file stem unless another
+/// note shares it, in which case it's the workspace-relative path.
+fn markdown_link_as_wikilink(
+    workspace: &Path,
+    files: &[PathBuf],
+    source_dir: Option<&Path>,
+    path: &str,
+    fragment: &str,
+    text: &str,
+) -> Option<String> {
+    let path = path.replace("%20", " ");
+    let relative = match path.strip_prefix('/') {
+        Some(from_root) => PathBuf::from(from_root),
+        None => source_dir.unwrap_or(Path::new("")).join(&path),
+    };
+    let target = resolve_in_workspace(workspace, &relative.to_string_lossy()).ok()?;
+    if !target.is_file() {
+        return None;
+    }
+
+    let stem = target.file_stem()?.to_string_lossy().to_string();
+    let name = if find_wikilink_target(workspace, files, &stem) == Some(&target) {
+        stem
+    } else {
+        let relative = target.strip_prefix(workspace).ok()?.with_extension("");
+        relative.to_string_lossy().replace('\\', "/")
+    };
+    let fragment = fragment.strip_prefix('#').map(|f| format!("#{}", f.replace("%20", " "))).unwrap_or_default();
+    let link = format!("{}{}", name, fragment);
+    Some(if text.is_empty() || text == link {
+        format!("[[{}]]", link)
+    } else {
+        format!("[[{}|{}]]", link, text)
+    })
+}
+
+/// Rewrite the links between notes in `content` to the `to` style.
+/// Wikilinks are looked up by name and become paths relative to the folder
+/// of `source_path`, or from the workspace root (`/...`) without one.
+/// Markdown links to notes lose their path. External links, links within
+/// the document and links to other files are left alone.
+pub(crate) async fn convert_links_in(
+    workspace: &Path,
+    source_path: Option<&Path>,
+    content: &str,
+    to: LinkStyle,
+    options: &MarkdownOptions,
+) -> Result<LinkConversion, MikuError> {
+    if !workspace.is_dir() {
+        return Err(MikuError::NotFound(workspace.to_string_lossy().to_string()));
+    }
+    let files = markdown_files(workspace).await?;
+    let source_dir = match source_path.and_then(Path::parent) {
+        Some(dir) => Some(
+            dir.strip_prefix(workspace)
+                .map_err(|_| MikuError::OutsideWorkspace(dir.to_string_lossy().to_string()))?,
+        ),
+        None => None,
+    };
+
+    let mut converted = content.to_string();
+    let mut unresolved = Vec::new();
+    // Back to front so earlier ranges stay valid
+    for link in document_links(content, options).into_iter().rev() {
+        let wikilink = link.target.strip_prefix("[[").and_then(|t| t.strip_suffix("]]"));
+        let replacement = match (to, wikilink) {
+            (LinkStyle::MarkdownLink, Some(inner)) => {
+                wikilink_as_markdown(workspace, &files, source_dir, inner, &link.text)
+            }
+            (LinkStyle::Wikilink, None) => {
+                let split_at = link.target.find(['#', '?']).unwrap_or(link.target.len());
+                let (path, fragment) = link.target.split_at(split_at);
+                let external = path.contains("://") || path.starts_with("mailto:");
+                if path.is_empty() || external || !is_markdown_file(path) {
+                    continue;
+                }
+                markdown_link_as_wikilink(workspace, &files, source_dir, path, fragment, &link.text)
+            }
+            _ => continue,
+        };
+        match replacement {
+            Some(replacement) => converted.replace_range(link.range, &replacement),
+            None => unresolved.push(content[link.range].to_string()),
+        }
+    }
+    unresolved.reverse();
+
+    Ok(LinkConversion {
+        content: converted,
+        unresolved,
+    })
+}
+
+/// Rewrite every link between notes in `content` as a wikilink or as a
+/// markdown link, resolving against the open workspace or `workspace_path`.
+/// Markdown links are written relative to `source_path` when it's given.
+#[tauri::command]
+pub async fn convert_links(
+    content: String,
+    to: LinkStyle,
+    workspace_path: Option<String>,
+    source_path: Option<String>,
+    active: State<'_, ActiveWorkspace>,
+) -> Result<LinkConversion, MikuError> {
+    let workspace = active.root_or(workspace_path)?;
+    let options = markdown_options_or_saved(None).await;
+    convert_links_in(&workspace, source_path.as_deref().map(Path::new), &content, to, &options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_serialization() {
+        let workspace = Workspace {
+            path: "/path/to/workspace".to_string(),
+            name: "My Workspace".to_string(),
+            last_opened_file: None,
+            pinned: false,
+            display_name: None,
+        };
+
+        let json = serde_json::to_string(&workspace).unwrap();
+        let deserialized: Workspace = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(workspace.path, deserialized.path);
+        assert_eq!(workspace.name, deserialized.name);
+    }
+
+    #[test]
+    fn test_workspace_file_serialization() {
+        let file = WorkspaceFile {
+            name: "test.md".to_string(),
+            path: "/path/to/test.md".to_string(),
+            is_directory: false,
+            children: None,
+            relative_path: None,
+            word_count: None,
+            hidden_count: None,
+            truncated: false,
+            display_title: None,
+        };
+
+        let json = serde_json::to_string(&file).unwrap();
+        assert!(json.contains("\"isDirectory\":false"));
+
+        let deserialized: WorkspaceFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.name, deserialized.name);
+        assert!(!deserialized.is_directory);
+    }
+
+    #[test]
+    fn test_workspace_config_default() {
+        let config = WorkspaceConfig::default();
+        assert!(config.current_workspace.is_none());
+        assert!(config.recent_workspaces.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_workspace_config_round_trip() {
+        let data = tempfile::tempdir().unwrap();
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert!(config.current_workspace.is_none());
+
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+        set_workspace_in(data.path(), vault_path.clone()).await.unwrap();
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.current_workspace, Some(vault_path.clone()));
+        assert_eq!(config.recent_workspaces.len(), 1);
+        assert_eq!(config.recent_workspaces[0].path, vault_path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_workspace_stores_absolute_path() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        // The same folder, relative to the current directory and with a
+        // stray `.` in it
+        let cwd = std::env::current_dir().unwrap();
+        let mut relative = PathBuf::new();
+        for _ in cwd.components().skip(1) {
+            relative.push("..");
+        }
+        let relative = relative
+            .join(vault.path().strip_prefix("/").unwrap())
+            .join(".")
+            .to_string_lossy()
+            .to_string();
+        assert!(Path::new(&relative).is_relative());
+
+        set_workspace_in(data.path(), relative.clone()).await.unwrap();
+        set_workspace_in(data.path(), vault_path.clone()).await.unwrap();
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.current_workspace, Some(vault_path.clone()));
+        assert_eq!(config.recent_workspaces.len(), 1);
+        assert_eq!(config.recent_workspaces[0].path, vault_path);
+
+        let info = get_workspace_info_in(data.path(), relative.clone()).await.unwrap();
+        assert_eq!(info.path, vault_path);
+        assert_eq!(info.name, vault.path().file_name().unwrap().to_string_lossy());
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert_eq!(current.path, vault_path);
+
+        // Later updates find the entry through the relative spelling too
+        pin_workspace_in(data.path(), relative.clone()).await.unwrap();
+        set_workspace_name_in(data.path(), &relative, "Vault").await.unwrap();
+        set_last_opened_in(data.path(), &relative, "a.md").await.unwrap();
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.recent_workspaces.len(), 1);
+        assert!(config.recent_workspaces[0].pinned);
+        assert_eq!(config.recent_workspaces[0].display_name.as_deref(), Some("Vault"));
+        assert_eq!(config.recent_workspaces[0].last_opened_file.as_deref(), Some("a.md"));
+        unpin_workspace_in(data.path(), &relative).await.unwrap();
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert!(!config.recent_workspaces[0].pinned);
+
+        assert!(flatten_folder_in(data.path(), relative, true).await.is_err());
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[tokio::test]
+    async fn test_set_workspace_dedupe_ignores_case() {
+        let data = tempfile::tempdir().unwrap();
+        set_workspace_in(data.path(), "/Users/me/Notes".to_string()).await.unwrap();
+        set_workspace_in(data.path(), "/users/me/notes".to_string()).await.unwrap();
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.recent_workspaces.len(), 1);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[tokio::test]
+    async fn test_recent_workspace_updates_ignore_case() {
+        let data = tempfile::tempdir().unwrap();
+        set_workspace_in(data.path(), "/Users/me/Notes".to_string()).await.unwrap();
+        pin_workspace_in(data.path(), "/users/me/notes".to_string()).await.unwrap();
+        set_workspace_name_in(data.path(), "/USERS/me/notes", "Notes").await.unwrap();
+        set_last_opened_in(data.path(), "/users/ME/notes", "/Users/me/Notes/a.md").await.unwrap();
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.recent_workspaces.len(), 1);
+        let workspace = &config.recent_workspaces[0];
+        assert!(workspace.pinned);
+        assert_eq!(workspace.display_name.as_deref(), Some("Notes"));
+        assert_eq!(workspace.last_opened_file.as_deref(), Some("/Users/me/Notes/a.md"));
+
+        unpin_workspace_in(data.path(), "/users/me/notes").await.unwrap();
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert!(!config.recent_workspaces[0].pinned);
+    }
+
+    #[tokio::test]
+    async fn test_switch_workspace_warns_about_dirty_documents() {
+        let data = tempfile::tempdir().unwrap();
+        let dirty_docs = DirtyDocuments::default();
+        let locks = WorkspaceLocks::default();
+        let active = ActiveWorkspace::default();
+        set_workspace_in(data.path(), "/notes/a".to_string()).await.unwrap();
+        dirty_docs.set("/notes/a/draft.md", true);
+        dirty_docs.set("/notes/a/clean.md", true);
+        dirty_docs.set("/notes/a/clean.md", false);
+
+        let warning = switch_workspace_in(data.path(), &dirty_docs, &locks, &active, "/notes/b".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            warning,
+            Some(WorkspaceSwitchWarning {
+                dirty_documents: vec!["/notes/a/draft.md".to_string()],
+                already_locked_by: None,
+            })
+        );
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some("/notes/a"));
+
+        let forced = switch_workspace_in(data.path(), &dirty_docs, &locks, &active, "/notes/b".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(forced, None);
+        assert!(dirty_docs.paths().is_empty());
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some("/notes/b"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_workspace_lock_acquire_detect_and_override() {
+        let data = tempfile::tempdir().unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let first_path = first.path().to_string_lossy().to_string();
+        let second_path = second.path().to_string_lossy().to_string();
+        let dirty_docs = DirtyDocuments::default();
+        let locks = WorkspaceLocks::default();
+        let active = ActiveWorkspace::default();
+        let read_lock = |workspace: &Path| -> Option<WorkspaceLock> {
+            serde_json::from_str(&std::fs::read_to_string(workspace_lock_path(workspace)).ok()?).ok()
+        };
+
+        // Acquire
+        switch_workspace_in(data.path(), &dirty_docs, &locks, &active, first_path.clone(), false).await.unwrap();
+        assert_eq!(read_lock(first.path()).unwrap().pid, std::process::id());
+
+        // A lock left by an exited process is stale
+        let mut exited = std::process::Command::new("true").spawn().unwrap();
+        let exited_pid = exited.id();
+        exited.wait().unwrap();
+        let stale = WorkspaceLock { pid: exited_pid, timestamp_ms: 1 };
+        std::fs::create_dir_all(second.path().join(".miku")).unwrap();
+        std::fs::write(workspace_lock_path(second.path()), serde_json::to_string(&stale).unwrap()).unwrap();
+        assert!(live_workspace_lock(second.path()).is_none());
+
+        // A live one is reported and blocks the switch
+        let mut other = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let held = WorkspaceLock { pid: other.id(), timestamp_ms: 1 };
+        std::fs::write(workspace_lock_path(second.path()), serde_json::to_string(&held).unwrap()).unwrap();
+        let warning = switch_workspace_in(data.path(), &dirty_docs, &locks, &active, second_path.clone(), false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(warning.already_locked_by, Some(held));
+        assert!(warning.dirty_documents.is_empty());
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.current_workspace.as_deref(), Some(first_path.as_str()));
+
+        // Forcing takes the lock over and releases the previous workspace
+        let forced = switch_workspace_in(data.path(), &dirty_docs, &locks, &active, second_path, true).await.unwrap();
+        assert!(forced.is_none());
+        assert_eq!(read_lock(second.path()).unwrap().pid, std::process::id());
+        assert!(!workspace_lock_path(first.path()).exists());
+
+        locks.release();
+        assert!(!workspace_lock_path(second.path()).exists());
+        other.kill().unwrap();
+        other.wait().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recent_workspaces_custom_limit() {
+        let data = tempfile::tempdir().unwrap();
+        let settings = crate::commands::EditorSettings {
+            max_recent_workspaces: 3,
+            ..Default::default()
+        };
+        crate::commands::save_settings_in(data.path(), &settings).await.unwrap();
+
+        for name in ["a", "b", "c", "d"] {
+            set_workspace_in(data.path(), format!("/vaults/{}", name)).await.unwrap();
+        }
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        let paths: Vec<&str> = config.recent_workspaces.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(paths, vec!["/vaults/d", "/vaults/c", "/vaults/b"]);
+    }
+
+    #[tokio::test]
+    async fn test_last_opened_file_is_remembered() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+        let note = vault.path().join("note.md");
+        std::fs::write(&note, "# Note").unwrap();
+        let note_path = note.to_string_lossy().to_string();
+
+        set_workspace_in(data.path(), vault_path.clone()).await.unwrap();
+        set_last_opened_in(data.path(), &vault_path, &note_path).await.unwrap();
+
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert_eq!(current.last_opened_file, Some(note_path.clone()));
+
+        // Re-selecting the workspace keeps the remembered file
+        set_workspace_in(data.path(), vault_path).await.unwrap();
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert_eq!(current.last_opened_file, Some(note_path));
+    }
+
+    #[tokio::test]
+    async fn test_missing_last_opened_file_is_dropped() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+        let note = vault.path().join("gone.md");
+        std::fs::write(&note, "").unwrap();
+
+        set_workspace_in(data.path(), vault_path.clone()).await.unwrap();
+        set_last_opened_in(data.path(), &vault_path, &note.to_string_lossy()).await.unwrap();
+        std::fs::remove_file(&note).unwrap();
+
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert!(current.last_opened_file.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_workspaces_sort_first() {
+        let data = tempfile::tempdir().unwrap();
+        let vaults: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<String> = vaults.iter().map(|v| v.path().to_string_lossy().to_string()).collect();
+
+        for path in &paths {
+            set_workspace_in(data.path(), path.clone()).await.unwrap();
+        }
+        pin_workspace_in(data.path(), paths[1].clone()).await.unwrap();
+        pin_workspace_in(data.path(), paths[0].clone()).await.unwrap();
+
+        let recent: Vec<String> = get_recent_workspaces_in(data.path())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|w| w.path)
+            .collect();
+        assert_eq!(recent, vec![paths[1].clone(), paths[0].clone(), paths[2].clone()]);
+
+        unpin_workspace_in(data.path(), &paths[1]).await.unwrap();
+        let recent = get_recent_workspaces_in(data.path()).await.unwrap();
+        assert_eq!(recent[0].path, paths[0]);
+        assert!(!recent.iter().any(|w| w.path == paths[1] && w.pinned));
+    }
+
+    #[tokio::test]
+    async fn test_recent_workspaces_are_pruned_and_deduped_on_disk() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+        let other_path = other.path().to_string_lossy().to_string();
+        let workspace = |path: String, pinned: bool| Workspace {
+            pinned,
+            ..workspace_from_path(path)
+        };
+
+        let config = WorkspaceConfig {
+            current_workspace: Some(vault_path.clone()),
+            recent_workspaces: vec![
+                workspace(vault_path.clone(), false),
+                workspace("/definitely/missing/vault".to_string(), false),
+                workspace(format!("{}/", vault_path), true),
+                workspace(other_path.clone(), false),
+            ],
+        };
+        save_workspace_config_in(data.path(), &config).await.unwrap();
+
+        let recent = get_recent_workspaces_in(data.path()).await.unwrap();
+        let listed: Vec<(&str, bool)> = recent.iter().map(|w| (w.path.as_str(), w.pinned)).collect();
+        assert_eq!(listed, vec![(vault_path.as_str(), true), (other_path.as_str(), false)]);
+
+        let stored = load_workspace_config_in(data.path()).await.unwrap();
+        let stored: Vec<&str> = stored.recent_workspaces.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(stored, vec![vault_path.as_str(), other_path.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_create_workspace_from_template() {
+        let data = tempfile::tempdir().unwrap();
+        let dirty_docs = DirtyDocuments::default();
+        let locks = WorkspaceLocks::default();
+        let active = ActiveWorkspace::default();
+        let template = workspace_templates_dir_in(data.path()).join("Research");
+        std::fs::create_dir_all(template.join("sources")).unwrap();
+        std::fs::write(template.join("sources/reading-list.md"), "# Reading").unwrap();
+        std::fs::write(template.join("README.md"), "# Research").unwrap();
+        assert_eq!(
+            list_workspace_templates_in(data.path()).await.unwrap(),
+            vec!["basic".to_string(), "Research".to_string()]
+        );
+
+        let parent = tempfile::tempdir().unwrap();
+        let dest = parent.path().join("thesis");
+        let dest_path = dest.to_string_lossy().to_string();
+        let created =
+            create_workspace_from_template_in(data.path(), &dirty_docs, &locks, &active, &dest_path, "Research", false)
+                .await
+                .unwrap();
+        assert_eq!(created.switch_warning, None);
+        assert_eq!(created.workspace.path, dest_path);
+        assert_eq!(std::fs::read_to_string(dest.join("sources/reading-list.md")).unwrap(), "# Reading");
+        assert!(dest.join("README.md").is_file());
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert_eq!(current.path, dest_path);
+        assert_eq!(active.get().as_deref(), Some(dest.as_path()));
+
+        // The destination now has notes in it
+        let again =
+            create_workspace_from_template_in(data.path(), &dirty_docs, &locks, &active, &dest_path, "basic", false)
+                .await;
+        assert!(matches!(again, Err(MikuError::AlreadyExists(_))));
+
+        // Unsaved changes hold back the switch, but the folder is scaffolded
+        dirty_docs.set(&dest.join("README.md").to_string_lossy(), true);
+        let fresh = parent.path().join("journal");
+        let fresh_path = fresh.to_string_lossy().to_string();
+        let created =
+            create_workspace_from_template_in(data.path(), &dirty_docs, &locks, &active, &fresh_path, "basic", false)
+                .await
+                .unwrap();
+        assert_eq!(created.switch_warning.unwrap().dirty_documents.len(), 1);
+        assert!(fresh.join("inbox").is_dir());
+        assert!(fresh.join("projects").is_dir());
+        assert!(std::fs::read_to_string(fresh.join("README.md")).unwrap().starts_with("# journal"));
+        let current = get_current_workspace_in(data.path()).await.unwrap().unwrap();
+        assert_eq!(current.path, dest_path);
+
+        let missing =
+            create_workspace_from_template_in(data.path(), &dirty_docs, &locks, &active, &dest_path, "nope", true)
+                .await;
+        assert!(matches!(missing, Err(MikuError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_recent_workspaces_by_folder_modified() {
+        let data = tempfile::tempdir().unwrap();
+        let vaults: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<String> = vaults.iter().map(|v| v.path().to_string_lossy().to_string()).collect();
+        for (path, mtime) in paths.iter().zip([3_000_000, 1_000_000, 2_000_000]) {
+            filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(mtime, 0)).unwrap();
+            set_workspace_in(data.path(), path.clone()).await.unwrap();
+        }
+        let ordered = |workspaces: &[Workspace]| -> Vec<String> { workspaces.iter().map(|w| w.path.clone()).collect() };
+        let mtimes = FolderMtimeCache::default();
+
+        let mut recent = get_recent_workspaces_in(data.path()).await.unwrap();
+        sort_recent_workspaces(&mut recent, WorkspaceSort::SelectionOrder, &mtimes);
+        assert_eq!(ordered(&recent), vec![paths[2].clone(), paths[1].clone(), paths[0].clone()]);
+
+        sort_recent_workspaces(&mut recent, WorkspaceSort::FolderModified, &mtimes);
+        assert_eq!(ordered(&recent), vec![paths[0].clone(), paths[2].clone(), paths[1].clone()]);
+
+        // Within the cache window the earlier mtimes are reused
+        filetime::set_file_mtime(&paths[1], filetime::FileTime::from_unix_time(4_000_000, 0)).unwrap();
+        sort_recent_workspaces(&mut recent, WorkspaceSort::FolderModified, &mtimes);
+        assert_eq!(recent[0].path, paths[0]);
+        sort_recent_workspaces(&mut recent, WorkspaceSort::FolderModified, &FolderMtimeCache::default());
+        assert_eq!(recent[0].path, paths[1]);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_workspace_survives_recent_cap() {
+        let data = tempfile::tempdir().unwrap();
+        let pinned = tempfile::tempdir().unwrap();
+        let pinned_path = pinned.path().to_string_lossy().to_string();
+
+        set_workspace_in(data.path(), pinned_path.clone()).await.unwrap();
+        pin_workspace_in(data.path(), pinned_path.clone()).await.unwrap();
+
+        let others: Vec<_> = (0..12).map(|_| tempfile::tempdir().unwrap()).collect();
+        for other in &others {
+            set_workspace_in(data.path(), other.path().to_string_lossy().to_string())
+                .await
+                .unwrap();
+        }
+
+        let config = load_workspace_config_in(data.path()).await.unwrap();
+        assert_eq!(config.recent_workspaces.len(), 11);
+        assert!(config.recent_workspaces.iter().any(|w| w.path == pinned_path && w.pinned));
+    }
+
+    #[tokio::test]
+    async fn test_custom_workspace_name() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let notes = vault.path().join("notes");
+        std::fs::create_dir(&notes).unwrap();
+        let notes_path = notes.to_string_lossy().to_string();
+
+        set_workspace_in(data.path(), notes_path.clone()).await.unwrap();
+        let info = get_workspace_info_in(data.path(), notes_path.clone()).await.unwrap();
+        assert_eq!(info.name, "notes");
+
+        set_workspace_name_in(data.path(), &notes_path, "Work notes").await.unwrap();
+        let info = get_workspace_info_in(data.path(), notes_path.clone()).await.unwrap();
+        assert_eq!(info.name, "Work notes");
+        let recent = get_recent_workspaces_in(data.path()).await.unwrap();
+        assert_eq!(recent[0].name, "Work notes");
+
+        // Clearing the custom name falls back to the folder name
+        set_workspace_name_in(data.path(), &notes_path, "  ").await.unwrap();
+        let info = get_workspace_info_in(data.path(), notes_path).await.unwrap();
+        assert_eq!(info.name, "notes");
+    }
+
+    #[tokio::test]
+    async fn test_create_and_open_file() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let base = vault.path().to_string_lossy().to_string();
+
+        let doc = create_and_open_file_in(data.path(), &base, "new.md", "# New".to_string())
+            .await
+            .unwrap();
+        let expected = vault.path().join("new.md").to_string_lossy().to_string();
+        assert_eq!(doc.path.as_deref(), Some(expected.as_str()));
+        assert_eq!(doc.content, "# New");
+        assert!(!doc.is_modified);
+        assert_eq!(std::fs::read_to_string(vault.path().join("new.md")).unwrap(), "# New");
+
+        let recents = crate::commands::load_recent_files_in(data.path()).await.unwrap();
+        assert_eq!(recents[0].path, expected);
+
+        let err = create_and_open_file_in(data.path(), &base, "new.md", String::new()).await;
+        assert!(matches!(err, Err(MikuError::AlreadyExists(_))));
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("notes.md").unwrap(), "notes.md");
+        assert_eq!(sanitize_name("draft.  ").unwrap(), "draft");
+        assert_eq!(sanitize_name("v1.2...").unwrap(), "v1.2");
+        assert!(matches!(sanitize_name("a/b.md"), Err(MikuError::Path(_))));
+        assert!(matches!(sanitize_name("a\\b.md"), Err(MikuError::Path(_))));
+        assert!(matches!(sanitize_name("bell\u{7}"), Err(MikuError::Path(_))));
+        assert!(matches!(sanitize_name(".."), Err(MikuError::Path(_))));
+    }
+
+    #[test]
+    fn test_sanitize_name_reserved() {
+        for name in ["CON", "nul.md", "Com1.txt"] {
+            if cfg!(windows) {
+                let err = sanitize_name(name).unwrap_err();
+                assert!(err.to_string().contains("reserved"), "{}", err);
+            } else {
+                assert_eq!(sanitize_name(name).unwrap(), name);
+            }
+        }
+        assert!(sanitize_name("console.md").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_and_rename_use_sanitized_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+
+        let created = create_file(base.clone(), "plan.md. ".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("plan.md").to_string_lossy());
+
+        assert!(create_folder(base.clone(), "nested/dir".to_string()).await.is_err());
+        assert!(rename_entry(dir.path(), created, "a/b.md".to_string(), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_file_appends_markdown_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+
+        let created = create_file(base.clone(), "meeting notes".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("meeting notes.md").to_string_lossy());
+
+        let created = create_file(base.clone(), "todo.markdown".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("todo.markdown").to_string_lossy());
+
+        let created = create_file(base.clone(), "data.csv".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("data.csv").to_string_lossy());
+
+        // Dots in the title aren't an extension
+        let created = create_file(base.clone(), "v1.2 notes".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("v1.2 notes.md").to_string_lossy());
+        let created = create_file(base.clone(), "v1.2".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("v1.2.md").to_string_lossy());
+        let created = create_file(base, "staging.miku-env".to_string()).await.unwrap();
+        assert_eq!(created, dir.path().join("staging.miku-env").to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn test_create_folder_detailed() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+
+        let folder = create_folder_detailed(base.clone(), "ideas".to_string(), None).await.unwrap();
+        assert!(folder.is_directory);
+        assert_eq!(folder.name, "ideas");
+        assert_eq!(folder.path, dir.path().join("ideas").to_string_lossy());
+        assert!(folder.children.as_ref().is_some_and(|children| children.is_empty()));
+        assert!(dir.path().join("ideas").is_dir());
+
+        let nested = create_folder_detailed(base.clone(), "projects/2024".to_string(), Some(true)).await.unwrap();
+        assert_eq!(nested.name, "2024");
+        assert_eq!(nested.path, dir.path().join("projects").join("2024").to_string_lossy());
+        assert!(dir.path().join("projects/2024").is_dir());
+
+        let flat = create_folder_detailed(base, "a/b".to_string(), None).await;
+        assert!(matches!(flat, Err(MikuError::Path(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_files_reports_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Goals.md"), "keep me").unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+        let names = vec!["Overview".to_string(), "Goals".to_string(), "Risks.md".to_string()];
+
+        let results = create_files(base, names, Some("# {{title}}\n".to_string())).await.unwrap();
+        let outcomes: Vec<(String, bool)> = results
+            .iter()
+            .map(|r| (Path::new(&r.path).file_name().unwrap().to_string_lossy().to_string(), r.ok))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("Overview.md".to_string(), true),
+                ("Goals.md".to_string(), false),
+                ("Risks.md".to_string(), true),
+            ]
+        );
+        assert!(results[1].error.as_deref().unwrap().starts_with("Already exists"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("Overview.md")).unwrap(), "# Overview\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("Risks.md")).unwrap(), "# Risks\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("Goals.md")).unwrap(), "keep me");
+    }
+
+    #[tokio::test]
+    async fn test_create_and_rename_treat_normalization_forms_as_equal() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+        // Decomposed, as macOS stores it
+        std::fs::write(dir.path().join("cafe\u{301}.md"), "existing").unwrap();
+
+        let created = create_file(base.clone(), "caf\u{e9}".to_string()).await;
+        assert!(matches!(created, Err(MikuError::AlreadyExists(_))));
+
+        let other = create_file(base, "menu".to_string()).await.unwrap();
+        let renamed = rename_entry(dir.path(), other, "caf\u{e9}.md".to_string(), None).await;
+        assert!(matches!(renamed, Err(MikuError::AlreadyExists(_))));
+        assert_eq!(std::fs::read_to_string(dir.path().join("cafe\u{301}.md")).unwrap(), "existing");
+    }
+
+    /// Names currently in `dir`, as the filesystem spells them
+    fn entry_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[tokio::test]
+    async fn test_rename_case_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("a.md");
+        std::fs::write(&old, "note").unwrap();
+
+        let renamed = rename_entry(dir.path(), old.to_string_lossy().to_string(), "A.md".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(renamed, dir.path().join("A.md").to_string_lossy());
+        assert_eq!(entry_names(dir.path()), vec!["A.md"]);
+        assert_eq!(std::fs::read_to_string(renamed).unwrap(), "note");
+    }
+
+    #[tokio::test]
+    async fn test_rename_respelled_goes_through_temp_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("notes.md");
+        std::fs::write(&old, "note").unwrap();
+
+        rename_respelled(&old, &dir.path().join("Notes.md")).await.unwrap();
+        assert_eq!(entry_names(dir.path()), vec!["Notes.md"]);
+        assert_eq!(std::fs::read_to_string(dir.path().join("Notes.md")).unwrap(), "note");
+    }
+
+    #[tokio::test]
+    async fn test_check_create_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().to_string();
+        std::fs::write(dir.path().join("plan.md"), "").unwrap();
+        std::fs::write(dir.path().join("plan (1).md"), "").unwrap();
+        std::fs::create_dir(dir.path().join("archive.md")).unwrap();
+
+        let conflict = check_create_conflict(base.clone(), "plan".to_string()).await.unwrap();
+        assert_eq!(
+            conflict,
+            CreateConflict {
+                exists: true,
+                is_directory: false,
+                suggested_name: "plan (2).md".to_string(),
+            }
+        );
+
+        let conflict = check_create_conflict(base.clone(), "archive.md".to_string()).await.unwrap();
+        assert!(conflict.exists && conflict.is_directory);
+        assert_eq!(conflict.suggested_name, "archive (1).md");
+
+        let conflict = check_create_conflict(base.clone(), "ideas".to_string()).await.unwrap();
+        assert_eq!(
+            conflict,
+            CreateConflict {
+                exists: false,
+                is_directory: false,
+                suggested_name: "ideas.md".to_string(),
+            }
+        );
+
+        assert!(check_create_conflict(base, "a/b".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unique_path_skips_other_spellings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cafe\u{301}.md"), "").unwrap();
+        assert_eq!(unique_path(dir.path(), "caf\u{e9}.md"), dir.path().join("caf\u{e9} (1).md"));
+    }
+
+    fn rename_fixture() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("draft.md"), "draft").unwrap();
+        std::fs::write(dir.path().join("final.md"), "final").unwrap();
+        let old = dir.path().join("draft.md").to_string_lossy().to_string();
+        (dir, old)
+    }
+
+    #[tokio::test]
+    async fn test_rename_conflict_error() {
+        let (dir, old) = rename_fixture();
+        let err = rename_entry(dir.path(), old.clone(), "final.md".to_string(), None).await;
+        assert!(matches!(err, Err(MikuError::AlreadyExists(_))));
+        assert!(Path::new(&old).exists());
+        assert_eq!(std::fs::read_to_string(dir.path().join("final.md")).unwrap(), "final");
+    }
+
+    #[tokio::test]
+    async fn test_rename_conflict_rename() {
+        let (dir, old) = rename_fixture();
+        std::fs::write(dir.path().join("final (1).md"), "taken").unwrap();
+
+        let renamed = rename_entry(dir.path(), old, "final.md".to_string(), Some(ConflictPolicy::Rename))
+            .await
+            .unwrap();
+        assert_eq!(renamed, dir.path().join("final (2).md").to_string_lossy());
+        assert_eq!(std::fs::read_to_string(dir.path().join("final.md")).unwrap(), "final");
+        assert_eq!(std::fs::read_to_string(&renamed).unwrap(), "draft");
+    }
+
+    #[tokio::test]
+    async fn test_rename_conflict_overwrite() {
+        let (dir, old) = rename_fixture();
+        let data = tempfile::tempdir().unwrap();
+        let trash_dir = tempfile::tempdir().unwrap();
+        // CI machines may have no OS trash, so stand in for it
+        let (data_dir, trash_path) = (data.path(), trash_dir.path());
+        let trash = |existing: PathBuf| async move {
+            crate::trash_index::tests::fake_trash(data_dir, trash_path, &existing).await;
+            Ok(())
+        };
+        let renamed = rename_entry_with(old.clone(), "final.md".to_string(), Some(ConflictPolicy::Overwrite), trash)
+            .await
+            .unwrap();
+        assert_eq!(renamed, dir.path().join("final.md").to_string_lossy());
+        assert_eq!(std::fs::read_to_string(renamed).unwrap(), "draft");
+        assert!(!Path::new(&old).exists());
+        // The replaced file can be found again under recently deleted
+        let trashed = crate::trash_index::load_trash_index_in(data.path()).await.unwrap();
+        assert_eq!(trashed[0].original_path, dir.path().join("final.md").to_string_lossy());
+        assert_eq!(std::fs::read_to_string(trash_dir.path().join("files/final.md")).unwrap(), "final");
+    }
+
+    #[tokio::test]
+    async fn test_move_file_to_workspace() {
+        let data = tempfile::tempdir().unwrap();
+        let inbox = tempfile::tempdir().unwrap();
+        let main = tempfile::tempdir().unwrap();
+        let source = inbox.path().join("idea.md");
+        std::fs::write(&source, "# Idea").unwrap();
+        let source_path = source.to_string_lossy().to_string();
+        add_recent_file_in(data.path(), source_path.clone()).await.unwrap();
+
+        let main_path = main.path().to_string_lossy().to_string();
+        // Only workspaces Miku knows about can receive files
+        let unknown = move_file_to_workspace_in(data.path(), &source_path, &main_path, "projects").await;
+        assert!(matches!(unknown, Err(MikuError::Path(_))));
+        set_workspace_in(data.path(), main_path.clone()).await.unwrap();
+
+        let moved = move_file_to_workspace_in(data.path(), &source_path, &main_path, "projects/new")
+            .await
+            .unwrap();
+
+        let expected = main.path().join("projects/new/idea.md");
+        assert_eq!(moved, expected.to_string_lossy());
+        assert!(!source.exists());
+        assert_eq!(std::fs::read_to_string(&expected).unwrap(), "# Idea");
+
+        let recents = crate::commands::load_recent_files_in(data.path()).await.unwrap();
+        assert_eq!(recents[0].path, moved);
+        assert_eq!(recents[0].workspace.as_deref(), Some(main_path.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_move_file_to_workspace_rejects_traversal() {
+        let data = tempfile::tempdir().unwrap();
+        let inbox = tempfile::tempdir().unwrap();
+        let main = tempfile::tempdir().unwrap();
+        let source = inbox.path().join("idea.md");
+        std::fs::write(&source, "# Idea").unwrap();
+        let main_path = main.path().to_string_lossy().to_string();
+        set_workspace_in(data.path(), main_path.clone()).await.unwrap();
+
+        for relative in ["../escape", "/etc", "a/../../b"] {
+            let result =
+                move_file_to_workspace_in(data.path(), &source.to_string_lossy(), &main_path, relative).await;
+            assert!(matches!(result, Err(MikuError::OutsideWorkspace(_))), "{}", relative);
+        }
+        assert!(source.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_in_workspace_follows_symlinked_ancestors() {
+        let vault = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::create_dir(vault.path().join("notes")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), vault.path().join("link_outside")).unwrap();
+        std::os::unix::fs::symlink(vault.path().join("notes"), vault.path().join("link_inside")).unwrap();
+
+        for relative in ["link_outside", "link_outside/newdir", "link_outside/newdir/deeper/note.md"] {
+            let result = resolve_in_workspace(vault.path(), relative);
+            assert!(matches!(result, Err(MikuError::OutsideWorkspace(_))), "{}", relative);
+        }
+        assert!(resolve_in_workspace(vault.path(), "link_inside/new/note.md").is_ok());
+        assert!(resolve_in_workspace(vault.path(), "notes/not/yet/there.md").is_ok());
+    }
+
+    #[test]
+    fn test_is_in_workspace() {
+        let vault = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        std::fs::create_dir(vault.path().join("notes")).unwrap();
+        let inside = |path: &Path| is_in_workspace_in(vault.path(), &path.to_string_lossy());
+
+        assert!(inside(&vault.path().join("notes/idea.md")));
+        assert!(inside(&vault.path().join("notes/../idea.md")));
+        assert!(is_in_workspace_in(vault.path(), "notes/idea.md"));
+
+        assert!(!inside(&other.path().join("idea.md")));
+        // Only reachable by climbing out of the workspace first
+        let name = other.path().file_name().unwrap();
+        assert!(!inside(&vault.path().join("..").join(name).join("idea.md")));
+        assert!(!inside(&vault.path().join("notes/../../escape.md")));
+        assert!(!is_in_workspace_in(vault.path(), "../escape.md"));
+    }
+
+    #[tokio::test]
+    async fn test_add_tag_creates_frontmatter() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain.md");
+        let tagged = dir.path().join("tagged.md");
+        std::fs::write(&plain, "# Plain\n").unwrap();
+        std::fs::write(&tagged, "---\ntags: [review]\n---\n# Tagged\n").unwrap();
+        let paths = vec![
+            plain.to_string_lossy().to_string(),
+            tagged.to_string_lossy().to_string(),
+            dir.path().join("missing.md").to_string_lossy().to_string(),
+        ];
+
+        let results = add_tag_to_files(paths, "#review".to_string()).await.unwrap();
+        assert!(results[0].ok && results[1].ok);
+        assert!(!results[2].ok);
+
+        assert_eq!(std::fs::read_to_string(&plain).unwrap(), "---\ntags: [review]\n---\n# Plain\n");
+        // Already tagged: untouched
+        assert_eq!(
+            std::fs::read_to_string(&tagged).unwrap(),
+            "---\ntags: [review]\n---\n# Tagged\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_frontmatter_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let partial = dir.path().join("partial.md");
+        let bare = dir.path().join("bare.md");
+        std::fs::write(&partial, "---\ntitle: Partial\nstatus: done\n---\nBody\n").unwrap();
+        std::fs::write(&bare, "# Bare\n").unwrap();
+        let template = serde_json::json!({ "status": "draft", "tags": ["inbox"], "reviewed": false });
+        let paths = vec![
+            partial.to_string_lossy().to_string(),
+            bare.to_string_lossy().to_string(),
+            dir.path().join("missing.md").to_string_lossy().to_string(),
+        ];
+
+        let changes = apply_frontmatter_template_in(paths, template.as_object().unwrap()).await;
+        assert_eq!(changes[0].added, vec!["reviewed", "tags"]);
+        assert_eq!(changes[1].added, vec!["reviewed", "status", "tags"]);
+        assert!(changes[2].error.is_some());
+        // Existing keys keep their value
+        assert_eq!(
+            std::fs::read_to_string(&partial).unwrap(),
+            "---\ntitle: Partial\nstatus: done\nreviewed: false\ntags: [\"inbox\"]\n---\nBody\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&bare).unwrap(),
+            "---\nreviewed: false\nstatus: \"draft\"\ntags: [\"inbox\"]\n---\n# Bare\n"
+        );
+        assert_eq!(crate::markdown::frontmatter_tags(&std::fs::read_to_string(&bare).unwrap()), vec!["inbox"]);
+
+        // Applying again changes nothing
+        let bare_path = vec![bare.to_string_lossy().to_string()];
+        let again = apply_frontmatter_template_in(bare_path, template.as_object().unwrap()).await;
+        assert!(again[0].added.is_empty() && again[0].error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_remove_tag_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "---\ntitle: Note\ntags:\n  - draft\n  - work\n---\nBody\n").unwrap();
+
+        let results = remove_tag_from_files(vec![note.to_string_lossy().to_string()], "draft".to_string())
+            .await
+            .unwrap();
+        assert!(results[0].ok);
+        assert_eq!(
+            std::fs::read_to_string(&note).unwrap(),
+            "---\ntitle: Note\ntags:\n  - work\n---\nBody\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_tag_across_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("inline.md"), "Working on #draft today\n").unwrap();
+        std::fs::write(dir.path().join("sub/front.md"), "---\ntags:\n  - draft\n---\nBody\n").unwrap();
+        std::fs::write(dir.path().join("code.md"), "```\n#draft\n```\n").unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let result = rename_tag_in(&workspace, "draft", "wip", &Progress::none()).await.unwrap();
+        assert_eq!(result, TagRenameResult { changed_files: 2, cancelled: false });
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("inline.md")).unwrap(),
+            "Working on #wip today\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("sub/front.md")).unwrap(),
+            "---\ntags:\n  - wip\n---\nBody\n"
+        );
+        assert_eq!(std::fs::read_to_string(dir.path().join("code.md")).unwrap(), "```\n#draft\n```\n");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_tag_rename_reports_files_changed_so_far() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.md", "b.md", "c.md"] {
+            std::fs::write(dir.path().join(name), "#draft\n").unwrap();
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        // Cancel while the first file is being renamed
+        let progress = Progress::with_sink(cancelled, move |_| flag.store(true, Ordering::SeqCst));
+
+        let workspace = dir.path().to_string_lossy().to_string();
+        let result = rename_tag_in(&workspace, "draft", "wip", &progress).await.unwrap();
+        assert_eq!(result, TagRenameResult { changed_files: 1, cancelled: true });
+    }
+
+    #[tokio::test]
+    async fn test_workspace_tasks_counts_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("projects")).unwrap();
+        std::fs::write(dir.path().join("projects/launch.md"), "- [x] a\n- [ ] b\n  - [ ] c").unwrap();
+        std::fs::write(dir.path().join("inbox.md"), "- [X] done").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "- plain item").unwrap();
+
+        let counts = workspace_tasks(dir.path().to_string_lossy().to_string()).await.unwrap();
+        let found: Vec<(&str, usize, usize)> = counts
+            .iter()
+            .map(|c| (Path::new(&c.path).file_name().unwrap().to_str().unwrap(), c.open, c.done))
+            .collect();
+        assert_eq!(found, vec![("inbox.md", 0, 1), ("launch.md", 2, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_task() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todo.md");
+        std::fs::write(&path, "# Todo\r\n- [ ] write\r\n  - [X] review\r\nnotes\r\n").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        assert!(toggle_task(path_str.clone(), 2).await.unwrap());
+        assert!(!toggle_task(path_str.clone(), 3).await.unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# Todo\r\n- [x] write\r\n  - [ ] review\r\nnotes\r\n"
+        );
+
+        for line in [1, 4, 99] {
+            assert!(matches!(toggle_task(path_str.clone(), line).await, Err(MikuError::Path(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_link() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("notes/deep")).unwrap();
+        std::fs::create_dir_all(vault.path().join("journal")).unwrap();
+        std::fs::write(vault.path().join("notes/idea.md"), "").unwrap();
+        std::fs::write(vault.path().join("journal/Daily Log.md"), "").unwrap();
+        let source = vault.path().join("notes/deep/draft.md");
+        let source = source.to_string_lossy().to_string();
+        let active = ActiveWorkspace::default();
+        assert!(matches!(
+            resolve_link_in(data.path(), &active, None, &source, "../idea.md").await,
+            Err(MikuError::Path(_))
+        ));
+
+        // Switching workspaces updates the root resolve_link reads by default
+        let vault_path = vault.path().to_string_lossy().to_string();
+        let (dirty_docs, locks) = (DirtyDocuments::default(), WorkspaceLocks::default());
+        switch_workspace_in(data.path(), &dirty_docs, &locks, &active, vault_path, false).await.unwrap();
+        locks.release();
+        assert_eq!(active.get().as_deref(), Some(vault.path()));
+        let resolve = |target: &'static str| resolve_link_in(data.path(), &active, None, &source, target);
+
+        let relative = resolve("../idea.md#intro").await.unwrap();
+        assert_eq!(Path::new(&relative.path), vault.path().join("notes/idea.md"));
+        assert!(relative.exists);
+
+        let wiki = resolve("[[daily log|today]]").await.unwrap();
+        assert_eq!(Path::new(&wiki.path), vault.path().join("journal/Daily Log.md"));
+        assert!(wiki.exists);
+
+        let missing = resolve("[[New Note]]").await.unwrap();
+        assert_eq!(Path::new(&missing.path), vault.path().join("New Note.md"));
+        assert!(!missing.exists);
+
+        let escape = resolve("../../../secrets.md").await;
+        assert!(matches!(escape, Err(MikuError::OutsideWorkspace(_))));
+
+        // An explicit root overrides the open workspace
+        let notes = Some(vault.path().join("notes").to_string_lossy().to_string());
+        let scoped = resolve_link_in(data.path(), &active, notes, &source, "../idea.md").await.unwrap();
+        assert_eq!(Path::new(&scoped.path), vault.path().join("notes/idea.md"));
+        let deep = Some(vault.path().join("notes/deep").to_string_lossy().to_string());
+        let escape = resolve_link_in(data.path(), &active, deep, &source, "../idea.md").await;
+        assert!(matches!(escape, Err(MikuError::OutsideWorkspace(_))));
+    }
+
+    #[tokio::test]
+    async fn test_convert_links() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join("projects")).unwrap();
+        std::fs::create_dir_all(root.join("journal")).unwrap();
+        std::fs::write(root.join("projects/Road Map.md"), "# Goals\n").unwrap();
+        std::fs::write(root.join("journal/today.md"), "").unwrap();
+        let source = root.join("journal/today.md");
+        let options = MarkdownOptions::default();
+
+        let wiki = "See [[road map#Goals|the plan]] and [[Nowhere]], or [site](https://example.com).\n";
+        let converted = convert_links_in(root, Some(&source), wiki, LinkStyle::MarkdownLink, &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            converted.content,
+            "See [the plan](../projects/Road%20Map.md#goals) and [[Nowhere]], or [site](https://example.com).\n"
+        );
+        assert_eq!(converted.unresolved, vec!["[[Nowhere]]".to_string()]);
+
+        // Without a source, paths start at the workspace root
+        let rooted = convert_links_in(root, None, "[[Road Map]]", LinkStyle::MarkdownLink, &options)
+            .await
+            .unwrap();
+        assert_eq!(rooted.content, "[Road Map](/projects/Road%20Map.md)");
+
+        let markdown = "[Road Map](../projects/Road%20Map.md#goals) [plan](/projects/Road%20Map.md) [gone](gone.md)";
+        let converted = convert_links_in(root, Some(&source), markdown, LinkStyle::Wikilink, &options)
+            .await
+            .unwrap();
+        assert_eq!(converted.content, "[[Road Map#goals|Road Map]] [[Road Map|plan]] [gone](gone.md)");
+        assert_eq!(converted.unresolved, vec!["[gone](gone.md)".to_string()]);
+
+        // With a stem shared by two notes, each wikilink still finds its own
+        std::fs::write(root.join("Road Map.md"), "").unwrap();
+        let shared = "[a](/projects/Road%20Map.md) [b](/Road%20Map.md)";
+        let converted = convert_links_in(root, None, shared, LinkStyle::Wikilink, &options).await.unwrap();
+        let links = document_links(&converted.content, &options);
+        assert_eq!(links.len(), 2);
+        for (link, expected) in links.iter().zip(["projects/Road Map.md", "Road Map.md"]) {
+            let name = link.target.trim_start_matches("[[").trim_end_matches("]]");
+            let resolved = resolve_wikilink(root, name).await.unwrap();
+            assert_eq!(Path::new(&resolved.path), root.join(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_link_checks_heading_anchors() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("guide.md"), "# Guide\n## Setup\ntext\n## Setup\n").unwrap();
+        let source = vault.path().join("index.md");
+        std::fs::write(&source, "# Index\n").unwrap();
+        let source = &source.to_string_lossy().to_string();
+        let options = &MarkdownOptions::default();
+        let workspace = vault.path();
+        let anchor_exists = |target: &'static str| async move {
+            resolve_link_within(workspace, source, target, options).await.unwrap().anchor_exists
+        };
+
+        assert_eq!(anchor_exists("guide.md#setup-1").await, Some(true));
+        assert_eq!(anchor_exists("guide.md#setup-2").await, Some(false));
+        assert_eq!(anchor_exists("[[guide#Setup]]").await, Some(true));
+        assert_eq!(anchor_exists("#index").await, Some(true));
+        assert_eq!(anchor_exists("guide.md").await, None);
+        assert_eq!(anchor_exists("missing.md#setup").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_folder_as_document() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.md"), "Second\nmore").unwrap();
+        std::fs::write(dir.path().join("a.md"), "First").unwrap();
+        std::fs::write(dir.path().join("c.md"), "Third").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "skip").unwrap();
+        std::fs::write(dir.path().join(FOLDER_ORDER_FILE), r#"["c.md"]"#).unwrap();
+
+        let book = read_folder_as_document(dir.path().to_string_lossy().to_string())
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = book
+            .segments
+            .iter()
+            .map(|s| Path::new(&s.path).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["c.md", "a.md", "b.md"]);
+
+        let starts: Vec<usize> = book.segments.iter().map(|s| s.start_line).collect();
+        assert_eq!(starts, vec![1, 7, 13]);
+
+        let lines: Vec<&str> = book.document.content.lines().collect();
+        for (segment, title) in book.segments.iter().zip(["# c", "# a", "# b"]) {
+            assert_eq!(lines[segment.start_line - 1], title);
+        }
+        assert!(book.document.readonly);
+    }
+
+    #[tokio::test]
+    async fn test_quick_capture_appends_in_order() {
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let inbox = quick_capture(vault_path.clone(), "first thought".to_string()).await.unwrap();
+        quick_capture(vault_path, "second thought\n".to_string()).await.unwrap();
+
+        assert!(inbox.ends_with("inbox.md"));
+        let content = std::fs::read_to_string(&inbox).unwrap();
+        let first = content.find("first thought").unwrap();
+        let second = content.find("second thought").unwrap();
+        assert!(first < second);
+        assert_eq!(content.matches("## ").count(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_quick_capture_concurrent_appends() {
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| tokio::spawn(quick_capture(vault_path.clone(), format!("note {}", i))))
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+
+        let content = std::fs::read_to_string(vault.path().join("inbox.md")).unwrap();
+        assert_eq!(content.matches("## ").count(), 8);
+        for i in 0..8 {
+            assert!(content.contains(&format!("note {}\n", i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_workspace_disk_usage() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("a.md"), "hello").unwrap();
+        std::fs::create_dir(vault.path().join("nested")).unwrap();
+        std::fs::write(vault.path().join("nested").join("image.png"), [0u8; 100]).unwrap();
+        std::fs::create_dir(vault.path().join(".git")).unwrap();
+        std::fs::write(vault.path().join(".git").join("HEAD"), "ref").unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let usage = workspace_disk_usage(vault_path.clone(), true).await.unwrap();
+        assert_eq!(usage, DiskUsage { total_bytes: 105, file_count: 2 });
+
+        let usage = workspace_disk_usage(vault_path, false).await.unwrap();
+        assert_eq!(usage, DiskUsage { total_bytes: 108, file_count: 3 });
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage_respects_io_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let vault = tempfile::tempdir().unwrap();
+        for i in 0..8 {
+            std::fs::write(vault.path().join(format!("{}.md", i)), "note").unwrap();
+        }
+
+        let (open, peak) = (&AtomicUsize::new(0), &AtomicUsize::new(0));
+        let usage = disk_usage_in(vault.path(), true, 3, move |path| async move {
+            peak.fetch_max(open.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            let metadata = tokio::fs::metadata(path).await;
+            open.fetch_sub(1, Ordering::SeqCst);
+            metadata
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(usage, DiskUsage { total_bytes: 32, file_count: 8 });
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        let settings = crate::commands::EditorSettings {
+            io_concurrency: 0,
+            ..Default::default()
+        };
+        assert_eq!(settings.io_concurrency_limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lint_workspace_reports_only_problem_files() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("clean.md"), "# Clean\n\nNothing to see.\n").unwrap();
+        std::fs::create_dir(vault.path().join("drafts")).unwrap();
+        let messy = vault.path().join("drafts").join("messy.md");
+        std::fs::write(&messy, "# Messy\n\n### Skipped a level\n").unwrap();
+
+        let results = lint_workspace_in(vault.path(), LintRule::ALL, MarkdownOptions::default(), 2)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(Path::new(&results[0].path), messy);
+        assert_eq!(results[0].findings.len(), 1);
+        assert_eq!(results[0].findings[0].rule, LintRule::HeadingIncrement);
+        assert_eq!(results[0].findings[0].line, 3);
+    }
+
+    fn index_vault() -> tempfile::TempDir {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("a.md"), "").unwrap();
+        std::fs::write(vault.path().join("board.kanban"), "").unwrap();
+        std::fs::create_dir_all(vault.path().join("projects").join("deep")).unwrap();
+        std::fs::write(vault.path().join("projects").join("b.md"), "").unwrap();
+        std::fs::write(vault.path().join("projects").join("deep").join("c d.md"), "").unwrap();
+        std::fs::create_dir(vault.path().join("boards")).unwrap();
+        std::fs::write(vault.path().join("boards").join("todo.kanban"), "").unwrap();
+        std::fs::create_dir(vault.path().join("maps")).unwrap();
+        vault
+    }
+
+    #[tokio::test]
+    async fn test_generate_index_note_nests_bullets() {
+        let vault = index_vault();
+
+        let out = generate_index_note_in(vault.path(), Path::new("Index.md"), false, false).await.unwrap();
+        assert_eq!(out, vault.path().join("Index.md"));
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            concat!(
+                "# Index\n\n",
+                "- projects\n  - deep\n    - [c d](projects/deep/c%20d.md)\n  - [b](projects/b.md)\n",
+                "- [a](a.md)\n",
+            )
+        );
+
+        // Regenerating doesn't list the index itself, and links climb out of
+        // the folder the index lives in
+        let out = generate_index_note_in(vault.path(), Path::new("maps/Contents.md"), false, false).await.unwrap();
+        let content = std::fs::read_to_string(&out).unwrap();
+        assert!(content.contains("    - [c d](../projects/deep/c%20d.md)\n"));
+        assert!(content.contains("- [a](../a.md)\n"));
+        assert!(content.contains("- [Index](../Index.md)\n"));
+        assert!(!content.contains("Contents.md"));
+
+        // An existing note is only replaced when asked to, and the index
+        // can't be written outside the workspace
+        let again = generate_index_note_in(vault.path(), &out, false, false).await;
+        assert!(matches!(again, Err(MikuError::AlreadyExists(_))));
+        assert_eq!(generate_index_note_in(vault.path(), &out, false, true).await.unwrap(), out);
+        let outside = generate_index_note_in(vault.path(), Path::new("../Index.md"), false, false).await;
+        assert!(matches!(outside, Err(MikuError::OutsideWorkspace(_))));
+        let elsewhere = tempfile::tempdir().unwrap();
+        let outside = generate_index_note_in(vault.path(), &elsewhere.path().join("Index.md"), false, false).await;
+        assert!(matches!(outside, Err(MikuError::OutsideWorkspace(_))));
+    }
+
+    #[tokio::test]
+    async fn test_generate_index_note_groups_by_folder() {
+        let vault = index_vault();
+
+        let out = generate_index_note_in(vault.path(), Path::new("Index.md"), true, false).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out).unwrap(),
+            concat!(
+                "# Index\n\n- [a](a.md)\n\n",
+                "## projects\n\n- deep\n  - [c d](projects/deep/c%20d.md)\n- [b](projects/b.md)\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_frontmatter_reports_missing_keys() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("good.md"), "---\ntitle: Good\ndate: 2024-05-01\n---\nBody\n").unwrap();
+        let partial = vault.path().join("partial.md");
+        std::fs::write(&partial, "---\ntitle: Partial\ntags: [a]\n---\nBody\n").unwrap();
+        let bare = vault.path().join("bare.md");
+        std::fs::write(&bare, "# No metadata\n").unwrap();
+
+        let required = vec!["title".to_string(), "date".to_string()];
+        let issues = validate_frontmatter_in(vault.path(), &required, 4).await.unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                FrontmatterIssue {
+                    path: bare.to_string_lossy().to_string(),
+                    missing_keys: required.clone(),
+                    has_frontmatter: false,
+                },
+                FrontmatterIssue {
+                    path: partial.to_string_lossy().to_string(),
+                    missing_keys: vec!["date".to_string()],
+                    has_frontmatter: true,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_workspace_files_grouping() {
+        let vault = tempfile::tempdir().unwrap();
+        for dir in ["beta", "delta"] {
+            std::fs::create_dir(vault.path().join(dir)).unwrap();
+            std::fs::write(vault.path().join(dir).join("note.md"), "").unwrap();
+        }
+        for file in ["alpha.md", "charlie.md", "echo.md"] {
+            std::fs::write(vault.path().join(file), "").unwrap();
+        }
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let names = |files: Vec<WorkspaceFile>| -> Vec<String> { files.into_iter().map(|f| f.name).collect() };
+        let listing = |grouping| list_workspace_files(vault_path.clone(), None, None, grouping, None);
+
+        let expected = vec!["beta", "delta", "alpha.md", "charlie.md", "echo.md"];
+        assert_eq!(names(listing(None).await.unwrap()), expected);
+        assert_eq!(names(listing(Some(TreeGrouping::DirsFirst)).await.unwrap()), expected);
+        assert_eq!(
+            names(listing(Some(TreeGrouping::FilesFirst)).await.unwrap()),
+            vec!["alpha.md", "charlie.md", "echo.md", "beta", "delta"]
+        );
+        assert_eq!(
+            names(listing(Some(TreeGrouping::Interleaved)).await.unwrap()),
+            vec!["alpha.md", "beta", "charlie.md", "delta", "echo.md"]
+        );
+    }
+
+    fn count_tree(files: &[WorkspaceFile]) -> usize {
+        files.iter().map(|file| 1 + count_tree(file.children.as_deref().unwrap_or_default())).sum()
+    }
+
+    #[tokio::test]
+    async fn test_stream_workspace_files_matches_tree() {
+        let vault = index_vault();
+        std::fs::create_dir_all(vault.path().join("projects").join("empty").join("deeper")).unwrap();
+        std::fs::write(vault.path().join("projects").join("notes.txt"), "").unwrap();
+        std::fs::create_dir(vault.path().join(".git")).unwrap();
+        std::fs::write(vault.path().join(".git").join("x.md"), "").unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let mut entries = Vec::new();
+        let mut emit = |entry| entries.push(entry);
+        let count = stream_workspace_files_in(vault.path(), ListingOptions::default(), &Progress::none(), &mut emit)
+            .await
+            .unwrap();
+
+        let tree = list_workspace_files(vault_path.clone(), None, None, None, None).await.unwrap();
+        assert_eq!(count, count_tree(&tree));
+        assert_eq!(entries.len(), count);
+
+        // Parents always arrive before their children
+        let deep = entries.iter().position(|e| e.relative_path == Path::new("projects/deep/c d.md").to_string_lossy());
+        let deep = &entries[deep.unwrap()];
+        assert_eq!(Path::new(&deep.parent_path), vault.path().join("projects").join("deep"));
+        let parent = entries.iter().position(|e| e.path == deep.parent_path).unwrap();
+        assert!(parent < entries.iter().position(|e| e.path == deep.path).unwrap());
+        assert!(!entries.iter().any(|e| e.name == "empty" || e.name == "notes.txt" || e.name == "x.md"));
+        assert!(entries.iter().any(|e| e.name == "maps" && e.parent_path == vault_path));
+
+        // Other listing options apply the same way as to the tree
+        let options = ListingOptions {
+            display_titles: true,
+            grouping: TreeGrouping::FilesFirst,
+            ..Default::default()
+        };
+        let mut streamed = Vec::new();
+        let mut emit = |entry: WorkspaceEntry| streamed.push((entry.path, entry.display_title));
+        stream_workspace_files_in(vault.path(), options, &Progress::none(), &mut emit).await.unwrap();
+        let (tree, _) = list_directory(vault.path(), vault.path(), options, &mut Vec::new()).await.unwrap();
+        assert_eq!(streamed, flatten_tree(&tree));
+    }
+
+    fn flatten_tree(files: &[WorkspaceFile]) -> Vec<(String, Option<String>)> {
+        files
+            .iter()
+            .flat_map(|file| {
+                let children = flatten_tree(file.children.as_deref().unwrap_or_default());
+                std::iter::once((file.path.clone(), file.display_title.clone())).chain(children)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_delete_files_reports_per_path_results() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let existing = vault.path().join("a.md");
+        std::fs::write(&existing, "").unwrap();
+        let missing = vault.path().join("missing.md");
+
+        let paths = vec![
+            missing.to_string_lossy().to_string(),
+            existing.to_string_lossy().to_string(),
+        ];
+
+        // A dry run predicts the same outcome without deleting anything
+        let plan = delete_files_in(data.path(), paths.clone(), false, true).await;
+        assert!(existing.exists());
+
+        let results = delete_files_in(data.path(), paths, false, false).await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].error.is_some());
+        assert!(results[1].error.is_none());
+        assert_eq!(results[1].action, ChangeAction::Delete);
+        assert!(!existing.exists());
+        assert_eq!(plan, results);
+    }
+
+    #[tokio::test]
+    async fn test_touch_file_bumps_mtime_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.md");
+        std::fs::write(&path, "unchanged").unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_000_000, 0)).unwrap();
+        let path_str = path.to_string_lossy().to_string();
+        let before = get_file_metadata(path_str.clone()).await.unwrap().modified_ms.unwrap();
+
+        let modified_ms = touch_file(path_str.clone()).await.unwrap();
+
+        assert!(modified_ms > before);
+        let after = get_file_metadata(path_str.clone()).await.unwrap().modified_ms.unwrap();
+        assert_eq!(after, modified_ms);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "unchanged");
+
+        let missing = dir.path().join("missing.md").to_string_lossy().to_string();
+        assert!(matches!(touch_file(missing).await, Err(MikuError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_group_files_by_recency() {
+        use chrono::TimeZone;
+
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir(vault.path().join("notes")).unwrap();
+        let now = chrono::Local.with_ymd_and_hms(2024, 3, 20, 15, 0, 0).single().unwrap();
+        let stamps = [
+            ("fresh.md", now - chrono::Duration::hours(1)),
+            ("notes/late.md", chrono::Local.with_ymd_and_hms(2024, 3, 19, 23, 30, 0).single().unwrap()),
+            ("notes/week.md", now - chrono::Duration::days(4)),
+            ("month.md", now - chrono::Duration::days(15)),
+            ("ancient.md", now - chrono::Duration::days(90)),
+            ("future.md", now + chrono::Duration::hours(2)),
+        ];
+        for (name, time) in &stamps {
+            let path = vault.path().join(name);
+            std::fs::write(&path, "x").unwrap();
+            filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(time.timestamp(), 0)).unwrap();
+        }
+
+        let groups = group_files_by_recency_in(vault.path(), now, 4).await.unwrap();
+        let summary: Vec<(RecencyBucket, Vec<&str>)> = groups
+            .iter()
+            .map(|group| (group.bucket, group.files.iter().map(|f| f.name.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (RecencyBucket::Today, vec!["future.md", "fresh.md"]),
+                // Under 24 hours ago, but on the previous calendar day
+                (RecencyBucket::Yesterday, vec!["late.md"]),
+                (RecencyBucket::ThisWeek, vec!["week.md"]),
+                (RecencyBucket::ThisMonth, vec!["month.md"]),
+                (RecencyBucket::Older, vec!["ancient.md"]),
+            ]
+        );
+        assert_eq!(groups[4].files[0].modified_ms, (stamps[4].1.timestamp() * 1000) as u64);
+
+        let missing = vault.path().join("missing");
+        assert!(matches!(
+            group_files_by_recency_in(&missing, now, 4).await,
+            Err(MikuError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_file_readonly_blocks_save() {
+        let vault = tempfile::tempdir().unwrap();
+        let path = vault.path().join("reference.md");
+        std::fs::write(&path, "original").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        set_file_readonly(path_str.clone(), true).await.unwrap();
+        assert!(get_file_metadata(path_str.clone()).await.unwrap().is_readonly);
+
+        let result = crate::commands::write_document(path_str.clone(), "changed".to_string()).await;
+        assert!(matches!(result, Err(MikuError::ReadOnly(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+
+        set_file_readonly(path_str.clone(), false).await.unwrap();
+        assert!(!get_file_metadata(path_str.clone()).await.unwrap().is_readonly);
+        crate::commands::write_document(path_str, "changed".to_string()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "changed");
+    }
+
+    #[tokio::test]
+    async fn test_flatten_folder_renames_collisions() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let folder = vault.path().join("drafts");
+        std::fs::create_dir(&folder).unwrap();
+        std::fs::write(folder.join("idea.md"), "draft idea").unwrap();
+        std::fs::write(folder.join("todo.md"), "todo").unwrap();
+        std::fs::write(vault.path().join("idea.md"), "existing idea").unwrap();
+
+        let folder_path = folder.to_string_lossy().to_string();
+        let plan = flatten_folder_in(data.path(), folder_path.clone(), true).await.unwrap();
+        assert!(folder.join("idea.md").exists());
+        assert!(!vault.path().join("idea (1).md").exists());
+
+        let moved = flatten_folder_in(data.path(), folder_path, false).await.unwrap();
+        assert_eq!(moved, plan);
+        let path = |name: &str| vault.path().join(name).to_string_lossy().to_string();
+        let targets: Vec<_> = moved.iter().map(|change| (change.action, change.to.clone())).collect();
+        assert_eq!(
+            targets,
+            vec![
+                (ChangeAction::Move, Some(path("idea (1).md"))),
+                (ChangeAction::Move, Some(path("todo.md"))),
+                (ChangeAction::RemoveFolder, None),
+            ]
+        );
+        assert!(!folder.exists());
+        assert_eq!(std::fs::read_to_string(vault.path().join("idea.md")).unwrap(), "existing idea");
+        assert_eq!(std::fs::read_to_string(vault.path().join("idea (1).md")).unwrap(), "draft idea");
+        assert!(vault.path().join("todo.md").exists());
+    }
+
+    #[tokio::test]
+    async fn test_flatten_dry_run_plans_distinct_targets() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let folder = vault.path().join("inbox");
+        std::fs::create_dir(&folder).unwrap();
+        std::fs::write(folder.join("a.md"), "").unwrap();
+        std::fs::write(folder.join("a (1).md"), "").unwrap();
+        std::fs::write(vault.path().join("a.md"), "").unwrap();
+        let before = entry_names(vault.path());
+
+        let plan = flatten_folder_in(data.path(), folder.to_string_lossy().to_string(), true)
+            .await
+            .unwrap();
+
+        let targets: Vec<_> = plan.iter().filter_map(|change| change.to.clone()).collect();
+        let path = |name: &str| vault.path().join(name).to_string_lossy().to_string();
+        assert_eq!(targets, vec![path("a (1).md"), path("a (2).md")]);
+        assert_eq!(entry_names(vault.path()), before);
+        assert_eq!(entry_names(&folder).len(), 2);
+    }
+    #[tokio::test]
+    async fn test_flatten_folder_refuses_workspace_root() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+        set_workspace_in(data.path(), vault_path.clone()).await.unwrap();
+
+        assert!(flatten_folder_in(data.path(), vault_path, true).await.is_err());
+        assert!(vault.path().exists());
+    }
+
+    /// A path longer than the limit stands in for an entry that fails to
+    /// read, which permissions can't produce when running as root
+    #[tokio::test]
+    async fn test_unreadable_entry_does_not_hide_tree() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("notes")).unwrap();
+        std::fs::write(vault.path().join("notes/ok.md"), "").unwrap();
+        let leaf = "x".repeat(40);
+        std::fs::create_dir_all(vault.path().join("deep").join(&leaf)).unwrap();
+        std::fs::write(vault.path().join("deep").join(&leaf).join("a.md"), "").unwrap();
+
+        let options = ListingOptions {
+            max_path_len: vault.path().join("deep").join(&leaf).as_os_str().len() - 1,
+            ..ListingOptions::default()
+        };
+        let mut errors = Vec::new();
+        let (files, _) = list_directory(vault.path(), vault.path(), options, &mut errors).await.unwrap();
+
+        let notes = files.iter().find(|f| f.name == "notes").unwrap();
+        assert_eq!(notes.children.as_ref().unwrap()[0].name, "ok.md");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].path.ends_with(&leaf));
+    }
+
+    #[tokio::test]
+    async fn test_listing_counts_hidden_entries() {
+        let vault = tempfile::tempdir().unwrap();
+        let notes = vault.path().join("notes");
+        std::fs::create_dir_all(notes.join("empty")).unwrap();
+        std::fs::create_dir_all(notes.join("images").join("raw")).unwrap();
+        std::fs::write(notes.join("a.md"), "").unwrap();
+        std::fs::write(notes.join("photo.png"), "").unwrap();
+        std::fs::write(notes.join("data.csv"), "").unwrap();
+        std::fs::write(notes.join(".miku-order"), "[]").unwrap();
+        std::fs::write(notes.join("images").join("cover.jpg"), "").unwrap();
+        std::fs::create_dir(vault.path().join("kept")).unwrap();
+        std::fs::write(vault.path().join("kept").join("board.kanban"), "").unwrap();
+
+        let options = ListingOptions::default();
+        let (files, root_hidden) = list_directory(vault.path(), vault.path(), options, &mut Vec::new()).await.unwrap();
+        assert_eq!(root_hidden, 0);
+        let notes = files.iter().find(|f| f.name == "notes").unwrap();
+        // photo.png, data.csv and the two empty-looking folders; not .miku-order
+        assert_eq!(notes.hidden_count, Some(4));
+        assert_eq!(notes.children.as_ref().unwrap().len(), 1);
+        let kept = files.iter().find(|f| f.name == "kept").unwrap();
+        assert_eq!(kept.hidden_count, Some(0));
+
+        // Shown empty folders aren't hidden, but their own contents can be
+        let options = ListingOptions {
+            show_empty_dirs: true,
+            ..Default::default()
+        };
+        let (files, _) = list_directory(vault.path(), vault.path(), options, &mut Vec::new()).await.unwrap();
+        let notes = files.iter().find(|f| f.name == "notes").unwrap();
+        assert_eq!(notes.hidden_count, Some(2));
+        let images = notes.children.as_ref().unwrap().iter().find(|f| f.name == "images").unwrap();
+        assert_eq!(images.hidden_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_show_empty_dirs() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("projects/new")).unwrap();
+        std::fs::write(vault.path().join("projects/plan.md"), "").unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let child_names = |files: Vec<WorkspaceFile>| -> Vec<String> {
+            files[0].children.clone().unwrap().into_iter().map(|f| f.name).collect()
+        };
+
+        let pruned = list_workspace_files(vault_path.clone(), None, None, None, None).await.unwrap();
+        assert_eq!(child_names(pruned), vec!["plan.md"]);
+
+        let shown = list_workspace_files(vault_path, Some(true), None, None, None).await.unwrap();
+        assert_eq!(child_names(shown), vec!["new", "plan.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_workspace_files_word_counts() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("projects/drafts")).unwrap();
+        std::fs::write(vault.path().join("projects/plan.md"), "---\ntags: [x]\n---\nShip the beta soon").unwrap();
+        std::fs::write(vault.path().join("projects/drafts/intro.md"), "Hello there\n```\nnot counted\n```\n").unwrap();
+        std::fs::write(vault.path().join("readme.md"), "one two three").unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let files = list_workspace_files(vault_path.clone(), None, Some(true), None, None).await.unwrap();
+        let projects = &files[0];
+        assert!(projects.is_directory);
+        let children = projects.children.as_ref().unwrap();
+        // Directories still sort first
+        assert_eq!(children[0].name, "drafts");
+        assert_eq!(children[0].word_count, Some(2));
+        assert_eq!(children[1].word_count, Some(4));
+        assert_eq!(projects.word_count, Some(6));
+        assert_eq!(files[1].word_count, Some(3));
+
+        let plain = list_workspace_files(vault_path, None, None, None, None).await.unwrap();
+        assert_eq!(plain[0].word_count, None);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_listing_counts_tree() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("projects/drafts")).unwrap();
+        std::fs::write(vault.path().join("projects/plan.md"), "Ship it").unwrap();
+        std::fs::write(vault.path().join("projects/drafts/intro.md"), "Hello").unwrap();
+        std::fs::write(vault.path().join("readme.md"), "one two").unwrap();
+
+        let plain = benchmark_listing_in(vault.path(), ListingOptions::default()).await.unwrap();
+        assert_eq!((plain.file_count, plain.folder_count, plain.bytes_read), (3, 2, 0));
+        assert!(plain.duration_ms > 0.0);
+
+        let options = ListingOptions {
+            word_counts: true,
+            ..Default::default()
+        };
+        let counted = benchmark_listing_in(vault.path(), options).await.unwrap();
+        assert_eq!(counted.file_count, 3);
+        assert_eq!(counted.bytes_read, 19);
+    }
+
+    #[tokio::test]
+    async fn test_list_workspace_files_display_titles() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("2024-01-05.md"), "---\ntitle: \"Kickoff: day one\"\n---\nNotes").unwrap();
+        std::fs::write(vault.path().join("plain.md"), "# Heading only").unwrap();
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        let files = list_workspace_files(vault_path.clone(), None, None, None, Some(true)).await.unwrap();
+        let titles: Vec<Option<&str>> = files.iter().map(|f| f.display_title.as_deref()).collect();
+        assert_eq!(titles, vec![Some("Kickoff: day one"), Some("plain")]);
+
+        let plain = list_workspace_files(vault_path, None, None, None, None).await.unwrap();
+        assert!(plain.iter().all(|f| f.display_title.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_custom_markdown_extensions_are_saved() {
+        use crate::file_ops::is_markdown_extension_in;
+
+        let data = tempfile::tempdir().unwrap();
+        let extensions = vec!["md".to_string(), "markdown".to_string(), "mdown".to_string(), ".mkd".to_string()];
+        let saved = crate::commands::set_markdown_extensions_in(data.path(), &extensions).await.unwrap();
+        assert_eq!(saved.last().map(String::as_str), Some("mkd"));
+        assert!(is_markdown_extension_in(&saved, "MKD"));
+        let settings = crate::commands::settings_or_default(data.path()).await;
+        assert_eq!(settings.markdown_extensions, saved);
+        // Saving doesn't touch the process-wide list other tests read
+        assert!(!is_markdown_file("/notes/legacy.mkd"));
+
+        // Back to the defaults, the extension is unknown again
+        let saved = crate::commands::set_markdown_extensions_in(data.path(), &[]).await.unwrap();
+        assert!(!is_markdown_extension_in(&saved, "mkd"));
+    }
+
+    #[tokio::test]
+    async fn test_flat_listing_matches_tree() {
+        let vault = index_vault();
+        std::fs::write(vault.path().join("projects").join("notes.txt"), "").unwrap();
+        std::fs::create_dir(vault.path().join(".git")).unwrap();
+        std::fs::write(vault.path().join(".git").join("x.md"), "").unwrap();
+
+        let tree = list_workspace_files(vault.path().to_string_lossy().to_string(), None, None, None, None)
+            .await
+            .unwrap();
+        let mut paths = Vec::new();
+        tree_file_paths(&tree, &mut paths);
+        paths.retain(|path| is_markdown_file(path));
+
+        let flat = list_workspace_files_flat_in(vault.path(), DEFAULT_MAX_TREE_DEPTH).await.unwrap();
+        assert_eq!(flat.len(), paths.len());
+        assert!(flat.iter().all(|file| paths.contains(&file.path) && file.modified_ms > 0));
+        let deep = flat.iter().find(|file| file.name == "c d.md").unwrap();
+        assert_eq!(deep.relative_path, Path::new("projects/deep/c d.md").to_string_lossy());
+    }
+
+    // Case-only collisions can only be created on a case-sensitive filesystem
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_find_case_conflicts() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("Readme.md"), "").unwrap();
+        std::fs::write(vault.path().join("README.md"), "").unwrap();
+        std::fs::write(vault.path().join("notes.md"), "").unwrap();
+        std::fs::create_dir(vault.path().join("Projects")).unwrap();
+        std::fs::create_dir(vault.path().join("projects")).unwrap();
+        std::fs::write(vault.path().join("Projects").join("plan.md"), "").unwrap();
+        // Same name in different folders isn't a conflict
+        std::fs::write(vault.path().join("projects").join("Plan.md"), "").unwrap();
+
+        let path = |name: &str| vault.path().join(name).to_string_lossy().to_string();
+        let conflicts = find_case_conflicts_in(vault.path(), DEFAULT_MAX_TREE_DEPTH).await.unwrap();
+        assert_eq!(
+            conflicts,
+            vec![
+                vec![path("Projects"), path("projects")],
+                vec![path("README.md"), path("Readme.md")],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deep_tree_listing_stops_at_depth_limit() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut deepest = vault.path().to_path_buf();
+        for _ in 0..200 {
+            deepest.push("d");
+        }
+        std::fs::create_dir_all(&deepest).unwrap();
+        std::fs::write(deepest.join("bottom.md"), "").unwrap();
+
+        let (files, _) = list_directory(vault.path(), vault.path(), ListingOptions::default(), &mut Vec::new())
+            .await
+            .unwrap();
+        let mut depth = 0;
+        let mut node = &files[0];
+        while let Some(child) = node.children.as_deref().and_then(|c| c.first()) {
+            assert!(!node.truncated);
+            node = child;
+            depth += 1;
+        }
+        // `node` is the first folder past the limit, nested one level deeper
+        assert_eq!(depth, DEFAULT_MAX_TREE_DEPTH);
+        assert!(node.truncated);
+        assert_eq!(node.children.as_deref().map(<[_]>::len), Some(0));
+
+        let options = ListingOptions { max_depth: 2, ..ListingOptions::default() };
+        let (files, _) = list_directory(vault.path(), vault.path(), options, &mut Vec::new()).await.unwrap();
+        let second = &files[0].children.as_ref().unwrap()[0];
+        assert!(!files[0].truncated);
+        assert!(second.children.as_ref().unwrap()[0].truncated);
+    }
+
+    #[tokio::test]
+    async fn test_walkers_stop_at_the_depth_limit() {
+        let vault = tempfile::tempdir().unwrap();
+        let deep = vault.path().join("a").join("b").join("c");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::write(vault.path().join("a").join("b").join("shallow.md"), "").unwrap();
+        std::fs::write(deep.join("deep.md"), "").unwrap();
+        std::fs::write(deep.join("Deep.md"), "").unwrap();
+
+        let flat = list_workspace_files_flat_in(vault.path(), 2).await.unwrap();
+        let names: Vec<_> = flat.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["shallow.md"]);
+
+        let mut entries = Vec::new();
+        let mut emit = |entry: WorkspaceEntry| entries.push(entry.name);
+        let options = ListingOptions { max_depth: 2, ..ListingOptions::default() };
+        stream_workspace_files_in(vault.path(), options, &Progress::none(), &mut emit).await.unwrap();
+        assert_eq!(entries, ["a", "b", "c", "shallow.md"]);
+
+        assert!(find_case_conflicts_in(vault.path(), 2).await.unwrap().is_empty());
+        let files = crate::search::markdown_files_within(vault.path(), 2).await.unwrap();
+        assert_eq!(files, [vault.path().join("a").join("b").join("shallow.md")]);
+    }
+
+    #[tokio::test]
+    async fn test_import_text_files() {
+        let src = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(src.path().join("monday.txt"), "A \"good\" day\n\nWent for a walk.\n").unwrap();
+        std::fs::write(src.path().join("tuesday.TXT"), "Rain\n").unwrap();
+        std::fs::write(src.path().join("photo.jpg"), "").unwrap();
+        std::fs::write(vault.path().join("tuesday.md"), "already here").unwrap();
+
+        let results = import_text_files_in(src.path(), vault.path(), true).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].ok);
+        assert_eq!(Path::new(&results[0].path), vault.path().join("monday.md"));
+        let monday = std::fs::read_to_string(vault.path().join("monday.md")).unwrap();
+        assert!(monday.starts_with("---\ntitle: \"A \\\"good\\\" day\"\ncreated: "));
+        assert!(monday.ends_with("---\n# A \"good\" day\n\nWent for a walk.\n"));
+
+        // A name collision is reported and the existing file kept
+        assert!(!results[1].ok);
+        assert!(results[1].error.as_deref().unwrap().contains("Already exists"));
+        assert_eq!(std::fs::read_to_string(vault.path().join("tuesday.md")).unwrap(), "already here");
+
+        let plain = tempfile::tempdir().unwrap();
+        let results = import_text_files_in(src.path(), plain.path(), false).await.unwrap();
+        assert!(results.iter().all(|r| r.ok));
+        assert_eq!(std::fs::read_to_string(plain.path().join("tuesday.md")).unwrap(), "Rain\n");
+    }
+
+    #[tokio::test]
+    async fn test_clear_workspace_metadata_keeps_notes() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join(".miku")).unwrap();
+        std::fs::create_dir_all(root.join("projects/drafts")).unwrap();
+        std::fs::write(root.join(".miku/lock"), "{}").unwrap();
+        std::fs::write(root.join(FOLDER_ORDER_FILE), "[]").unwrap();
+        std::fs::write(root.join("projects/drafts").join(FOLDER_ORDER_FILE), "[]").unwrap();
+        std::fs::write(root.join("projects/plan.md"), "# Plan").unwrap();
+        std::fs::write(root.join("projects/staging.miku-env"), "KEY=1").unwrap();
+
+        let active = ActiveWorkspace::default();
+        let unknown = clear_workspace_metadata_in(data.path(), &active, root).await;
+        assert!(matches!(unknown, Err(MikuError::Path(_))));
+        assert!(root.join(".miku/lock").exists());
+
+        active.set(root);
+        let removed = clear_workspace_metadata_in(data.path(), &active, root).await.unwrap();
+        let expected: Vec<String> = [".miku", FOLDER_ORDER_FILE, "projects/drafts/.miku-order"]
+            .iter()
+            .map(|name| root.join(name).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(removed, expected);
+        assert!(!root.join(".miku").exists());
+        assert!(!root.join("projects/drafts").join(FOLDER_ORDER_FILE).exists());
+        assert!(root.join("projects/plan.md").exists());
+        assert!(root.join("projects/staging.miku-env").exists());
+
+        // The lock this instance holds survives; everything else goes
+        let locks = WorkspaceLocks::default();
+        locks.hold(root);
+        std::fs::write(root.join(".miku/cache.json"), "{}").unwrap();
+        let removed = clear_workspace_metadata_in(data.path(), &active, root).await.unwrap();
+        assert_eq!(removed, vec![root.join(".miku/cache.json").to_string_lossy().to_string()]);
+        assert!(workspace_lock_path(root).exists());
+        locks.release();
+        assert!(!workspace_lock_path(root).exists());
+    }
+
+    #[tokio::test]
+    async fn test_open_most_recent_picks_newest() {
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir(vault.path().join("journal")).unwrap();
+        let now = SystemTime::now();
+        for (name, age_secs) in [("old.md", 300), ("journal/today.md", 10), ("middle.md", 60), ("newer.txt", 0)] {
+            let path = vault.path().join(name);
+            std::fs::write(&path, name).unwrap();
+            let mtime = filetime::FileTime::from_system_time(now - Duration::from_secs(age_secs));
+            filetime::set_file_mtime(&path, mtime).unwrap();
+        }
+
+        let document = open_most_recent_in(data.path(), vault.path()).await.unwrap();
+        let today = vault.path().join("journal/today.md").to_string_lossy().to_string();
+        assert_eq!(document.path.as_deref(), Some(today.as_str()));
+        assert_eq!(document.content, "journal/today.md");
+        let recents = crate::commands::load_recent_files_in(data.path()).await.unwrap();
+        assert_eq!(recents[0].path, today);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            open_most_recent_in(data.path(), empty.path()).await,
+            Err(MikuError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_workspace_files_relative_paths() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(vault.path().join("projects")).unwrap();
+        std::fs::write(vault.path().join("projects/plan.md"), "").unwrap();
+
+        let files = list_workspace_files(vault.path().to_string_lossy().to_string(), None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(files[0].relative_path.as_deref(), Some("projects"));
+        let plan = &files[0].children.as_ref().unwrap()[0];
+        assert_eq!(plan.relative_path, Some(Path::new("projects").join("plan.md").to_string_lossy().to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_folder_order_is_applied() {
+        let vault = tempfile::tempdir().unwrap();
+        for name in ["alpha.md", "beta.md", "chapter-2.md", "chapter-10.md"] {
+            std::fs::write(vault.path().join(name), "").unwrap();
+        }
+        let vault_path = vault.path().to_string_lossy().to_string();
+
+        set_folder_order(
+            vault_path.clone(),
+            vec!["chapter-2.md".to_string(), "renamed.md".to_string(), "chapter-10.md".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let names: Vec<String> = list_workspace_files(vault_path, None, None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, vec!["chapter-2.md", "chapter-10.md", "alpha.md", "beta.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_repair_workspace_links_after_moves() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        let a = concat!(
+            "[B](b.md) and [C](../c.md#top) and [root](/notes/b.md)\n",
+            "[[notes/b]], [[c|see c]], [[a]], [site](https://example.com/b.md)\n",
+            "[C again][c], [[plan]]\n\n[c]: ../c.md\n",
+        );
+        std::fs::write(root.join("notes/a.md"), a).unwrap();
+        std::fs::write(root.join("notes/b.md"), "Back to [A](a.md \"title\")\n").unwrap();
+        std::fs::write(root.join("c.md"), "# Top\n").unwrap();
+        std::fs::write(root.join("untouched.md"), "[A](notes/a.md)\n").unwrap();
+        // `[[plan]]` finds notes/plan.md, so renaming the other plan leaves it
+        std::fs::write(root.join("notes/plan.md"), "").unwrap();
+        std::fs::create_dir_all(root.join("zz")).unwrap();
+        std::fs::write(root.join("zz/plan-v2.md"), "").unwrap();
+
+        // Move b into a new folder and rename c, then repair
+        std::fs::create_dir_all(root.join("archive")).unwrap();
+        std::fs::create_dir_all(root.join("ref")).unwrap();
+        std::fs::rename(root.join("notes/b.md"), root.join("archive/b.md")).unwrap();
+        std::fs::rename(root.join("c.md"), root.join("ref/see-c.md")).unwrap();
+        let path = |name: &str| root.join(name).to_string_lossy().to_string();
+        let moves = vec![
+            PathMove {
+                old: path("notes/b.md"),
+                new: path("archive/b.md"),
+            },
+            PathMove {
+                old: path("c.md"),
+                new: path("ref/see-c.md"),
+            },
+            PathMove {
+                old: path("zz/plan.md"),
+                new: path("zz/plan-v2.md"),
+            },
+        ];
+
+        let repairs = repair_workspace_links_in(root, &moves, &MarkdownOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            repairs,
+            vec![
+                LinkRepair {
+                    path: path("archive/b.md"),
+                    edits: 1,
+                },
+                LinkRepair {
+                    path: path("notes/a.md"),
+                    edits: 6,
+                },
+            ]
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(root.join("notes/a.md")).unwrap(),
+            concat!(
+                "[B](../archive/b.md) and [C](../ref/see-c.md#top) and [root](/archive/b.md)\n",
+                "[[archive/b]], [[see-c|see c]], [[a]], [site](https://example.com/b.md)\n",
+                "[C again][c], [[plan]]\n\n[c]: ../ref/see-c.md\n",
+            )
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("archive/b.md")).unwrap(),
+            "Back to [A](../notes/a.md \"title\")\n"
+        );
+        assert_eq!(std::fs::read_to_string(root.join("untouched.md")).unwrap(), "[A](notes/a.md)\n");
+
+        // Running it again finds nothing left to fix
+        let again = repair_workspace_links_in(root, &moves, &MarkdownOptions::default()).await.unwrap();
+        assert!(again.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_data_survives_rename() {
+        use crate::file_ids::{get_file_data_in, get_or_assign_file_id_in, set_file_data_in};
+
+        let data = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let folder = vault.path().join("drafts");
+        std::fs::create_dir(&folder).unwrap();
+        let note = folder.join("idea.md");
+        std::fs::write(&note, "").unwrap();
+        let note = note.to_string_lossy().to_string();
+
+        let id = get_or_assign_file_id_in(data.path(), &note).await.unwrap();
+        set_file_data_in(data.path(), &note, "goal", serde_json::json!(500)).await.unwrap();
+
+        let renamed = rename_file_in(data.path(), note.clone(), "plan.md".to_string(), None).await.unwrap();
+        assert_eq!(get_or_assign_file_id_in(data.path(), &renamed).await.unwrap(), id);
+        assert_eq!(get_file_data_in(data.path(), &renamed).await.unwrap()["goal"], 500);
+        assert!(get_file_data_in(data.path(), &note).await.unwrap().is_empty());
+
+        // Renaming the folder carries the files inside it along
+        let moved = rename_file_in(data.path(), folder.to_string_lossy().to_string(), "plans".to_string(), None)
+            .await
+            .unwrap();
+        let inside = Path::new(&moved).join("plan.md").to_string_lossy().to_string();
+        assert_eq!(get_or_assign_file_id_in(data.path(), &inside).await.unwrap(), id);
     }
 }
@@ -56,10 +56,12 @@ export default function AutoSaveManager() {
     savingDocsRef.current.add(docId);
 
     try {
-      await saveFile(path, content);
+      const saved = await saveFile(path, content);
       lastSavedContentRef.current.set(docId, content);
-      // Clear the modified indicator after successful save
-      markDocumentSaved(docId);
+      // Clear the modified indicator after successful save. The editor keeps
+      // any whitespace the save trimmed, so the user isn't interrupted
+      // mid-line, but the baseline follows the file.
+      markDocumentSaved(docId, saved);
     } catch (error) {
       console.error('[AutoSave] Failed to save document:', error);
     } finally {
@@ -1,7 +1,8 @@
 'use client';
 
 import { createContext, useContext, useState, useCallback, useRef, useEffect, ReactNode } from 'react';
-import { isTauri, safeTauriCall, openFile, saveFile, addRecentFile, getRecentFiles, saveSession, loadSession } from '@/lib/tauri';
+import { isTauri, safeTauriCall, openFile, saveFile, addRecentFile, getRecentFiles, saveSession, loadSession, markDirty, closeFile } from '@/lib/tauri';
+import type { RecentFileEntry } from '@/lib/tauri';
 import type { SessionState } from '@/lib/tauri/commands';
 
 interface OpenDocument {
@@ -16,7 +17,7 @@ interface DocumentState {
   path: string | null;
   content: string;
   isModified: boolean;
-  recentFiles: string[];
+  recentFiles: RecentFileEntry[];
 }
 
 interface DocumentContextType {
@@ -34,8 +35,11 @@ interface DocumentContextType {
   closeDocument: (id: string, currentContent?: string) => void;
   registerContentGetter: (getter: () => string) => void;
   getEditorContent: () => string;
-  /** Mark a document as saved (updates originalContent to match current content) */
-  markDocumentSaved: (docId: string) => void;
+  /**
+   * Mark a document as saved. `savedContent` is what was written to disk,
+   * when that differs from the editor content; it becomes originalContent.
+   */
+  markDocumentSaved: (docId: string, savedContent?: string) => void;
 }
 
 const DocumentContext = createContext<DocumentContextType | undefined>(undefined);
@@ -85,7 +89,7 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
     { id: generateDocId(), path: null, content: '', originalContent: '', isModified: false }
   ]);
   const [activeDocumentId, setActiveDocumentId] = useState<string | null>(openDocuments[0]?.id || null);
-  const [recentFiles, setRecentFiles] = useState<string[]>([]);
+  const [recentFiles, setRecentFiles] = useState<RecentFileEntry[]>([]);
 
   // Ref to hold the content getter function from BlockEditor
   const contentGetterRef = useRef<(() => string) | null>(null);
@@ -175,6 +179,31 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
     return () => window.removeEventListener('beforeunload', handleBeforeUnload);
   }, [openDocuments, activeDocumentId]);
 
+  // Keep the backend's dirty tracker (used for the quit prompt) in step with
+  // every edit, save and close. Untitled tabs are tracked by their id.
+  const reportedDirtyRef = useRef<Set<string>>(new Set());
+  useEffect(() => {
+    if (!isTauri()) return;
+
+    const dirty = new Set(
+      openDocuments
+        .filter(d => d.isModified)
+        .map(d => d.path ?? `untitled:${d.id}`)
+    );
+    const reported = reportedDirtyRef.current;
+    for (const key of dirty) {
+      if (!reported.has(key)) {
+        markDirty(key, true).catch(err => console.error('Failed to mark document dirty:', err));
+      }
+    }
+    for (const key of reported) {
+      if (!dirty.has(key)) {
+        markDirty(key, false).catch(err => console.error('Failed to mark document clean:', err));
+      }
+    }
+    reportedDirtyRef.current = dirty;
+  }, [openDocuments]);
+
   const getEditorContent = useCallback((): string => {
     if (contentGetterRef.current) {
       return contentGetterRef.current();
@@ -237,10 +266,10 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
 
   // Mark a document as saved (for auto-save)
   // This updates originalContent to match current content, clearing the modified flag
-  const markDocumentSaved = useCallback((docId: string) => {
+  const markDocumentSaved = useCallback((docId: string, savedContent?: string) => {
     setOpenDocuments(prev => prev.map(doc =>
       doc.id === docId
-        ? { ...doc, originalContent: doc.content, isModified: false }
+        ? { ...doc, originalContent: savedContent ?? doc.content, isModified: false }
         : doc
     ));
   }, []);
@@ -395,12 +424,14 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
         filePath = selected;
       }
 
-      await saveFile(filePath, activeDocument.content);
+      // The backend may trim trailing whitespace; take what it wrote so the
+      // editor matches the file and the document isn't left dirty
+      const saved = await saveFile(filePath, activeDocument.content);
       await addRecentFile(filePath);
 
       setOpenDocuments(prev => prev.map(doc =>
         doc.id === activeDocumentId
-          ? { ...doc, path: filePath, originalContent: doc.content, isModified: false }
+          ? { ...doc, path: filePath, content: saved, originalContent: saved, isModified: false }
           : doc
       ));
 
@@ -471,6 +502,11 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
       ));
     }
 
+    const closedPath = openDocuments.find(d => d.id === id)?.path;
+    if (closedPath && isTauri()) {
+      closeFile(closedPath).catch(err => console.error('Failed to close file:', err));
+    }
+
     setOpenDocuments(prev => {
       const newDocs = prev.filter(d => d.id !== id);
 
@@ -492,7 +528,7 @@ export function DocumentProvider({ children }: { children: ReactNode }) {
 
       return newDocs;
     });
-  }, [activeDocumentId]);
+  }, [openDocuments, activeDocumentId]);
 
   return (
     <DocumentContext.Provider
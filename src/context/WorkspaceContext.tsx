@@ -2,6 +2,7 @@
 
 import { createContext, useContext, useState, useCallback, useEffect, ReactNode } from 'react';
 import { isTauri, safeTauriCall } from '@/lib/tauri';
+import type { WorkspaceSwitchWarning } from '@/lib/tauri';
 import { invoke } from '@tauri-apps/api/core';
 
 export interface WorkspaceFile {
@@ -39,6 +40,30 @@ interface WorkspaceContextType {
 
 const WorkspaceContext = createContext<WorkspaceContextType | undefined>(undefined);
 
+/**
+ * Make `path` the current workspace, taking its lock. When the backend
+ * warns about unsaved changes or another window holding the workspace,
+ * ask before forcing the switch. Returns whether the switch happened.
+ */
+async function claimWorkspace(path: string): Promise<boolean> {
+  const warning = await invoke<WorkspaceSwitchWarning | null>('set_workspace', { path });
+  if (!warning) return true;
+
+  const reasons: string[] = [];
+  if (warning.already_locked_by) {
+    reasons.push(`This workspace is already open in another Miku window (process ${warning.already_locked_by.pid}).`);
+  }
+  if (warning.dirty_documents.length > 0) {
+    reasons.push(`${warning.dirty_documents.length} document(s) have unsaved changes that will be discarded.`);
+  }
+  if (!window.confirm(`${reasons.join('\n')}\n\nOpen ${path} anyway?`)) {
+    return false;
+  }
+
+  await invoke<WorkspaceSwitchWarning | null>('set_workspace', { path, force: true });
+  return true;
+}
+
 export function WorkspaceProvider({ children }: { children: ReactNode }) {
   const [workspace, setWorkspace] = useState<WorkspaceState>({
     currentWorkspace: null,
@@ -57,7 +82,8 @@ export function WorkspaceProvider({ children }: { children: ReactNode }) {
         const savedWorkspace = await invoke<Workspace | null>('get_current_workspace');
         const recentWorkspaces = await invoke<Workspace[]>('get_recent_workspaces');
 
-        if (savedWorkspace) {
+        // Restoring goes through `set_workspace` so this window takes the lock
+        if (savedWorkspace && await claimWorkspace(savedWorkspace.path)) {
           setWorkspace(prev => ({
             ...prev,
             currentWorkspace: savedWorkspace,
@@ -110,8 +136,11 @@ export function WorkspaceProvider({ children }: { children: ReactNode }) {
     setWorkspace(prev => ({ ...prev, isLoading: true }));
 
     try {
-      // Save workspace to backend
-      await invoke('set_workspace', { path: workspacePath });
+      // Save workspace to backend, unless the user backs out of a warning
+      if (!(await claimWorkspace(workspacePath))) {
+        setWorkspace(prev => ({ ...prev, isLoading: false }));
+        return;
+      }
 
       // Get workspace info
       const workspaceInfo = await invoke<Workspace>('get_workspace_info', { path: workspacePath });
@@ -178,16 +207,14 @@ export function WorkspaceProvider({ children }: { children: ReactNode }) {
 
     try {
       const basePath = parentPath || workspace.currentWorkspace.path;
-      // Add .md extension only if the name has no extension
-      // Dotfiles (like .miku-env, .gitignore) and files with extensions should not get .md added
-      const hasExtension = name.startsWith('.') || name.includes('.');
-      const fileName = hasExtension ? name : `${name}.md`;
+      // The backend adds .md when the name has no extension, so a title
+      // with a dot in it (e.g. "v1.2 notes") still becomes a note
       const filePath = await invoke<string>('create_file', {
         basePath,
-        name: fileName
+        name
       });
       // Refresh the appropriate file list based on file type
-      if (fileName.endsWith('.miku-env')) {
+      if (filePath.endsWith('.miku-env')) {
         await refreshEnvFiles();
       } else {
         await refreshFiles();
@@ -1,7 +1,7 @@
 'use client';
 
 import { useEffect, useRef, useCallback } from 'react';
-import { isTauri, saveFile } from '@/lib/tauri';
+import { errorMessage, isTauri, saveFile } from '@/lib/tauri';
 
 interface AutoSaveOptions {
   /** Content to save */
@@ -72,7 +72,7 @@ export function useAutoSave({
       onSaveSuccess?.();
     } catch (error) {
       console.error('[AutoSave] Failed to save:', error);
-      onSaveError?.(error instanceof Error ? error : new Error(String(error)));
+      onSaveError?.(error instanceof Error ? error : new Error(errorMessage(error)));
     } finally {
       isSavingRef.current = false;
 
@@ -9,7 +9,7 @@
  * Uses the user's existing Claude Code authentication — no API key needed.
  */
 
-import { isTauri } from './tauri';
+import { errorMessage, isTauri } from './tauri';
 
 // ============================================
 // Session Update Types for the UI
@@ -110,7 +110,7 @@ export class AcpClient {
       this.agentInfo = { name: 'Claude Code', version };
     } catch (err) {
       throw new Error(
-        `Could not find Claude Code.\n\n${err instanceof Error ? err.message : String(err)}\n\n` +
+        `Could not find Claude Code.\n\n${errorMessage(err)}\n\n` +
         'Make sure Claude Code is installed and in your PATH.\n' +
         'You can verify by running "claude --version" in your terminal.'
       );
@@ -218,7 +218,7 @@ export class AcpClient {
         unlistenExit();
         this.currentPromptId = null;
         rejected = true;
-        reject(new Error(`Failed to start Claude: ${err instanceof Error ? err.message : String(err)}`));
+        reject(new Error(`Failed to start Claude: ${errorMessage(err)}`));
       }
     });
   }
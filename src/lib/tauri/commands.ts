@@ -5,9 +5,54 @@
 
 import { invoke } from '@tauri-apps/api/core';
 
+// ============================================
+// Errors
+// ============================================
+
+export type MikuErrorCode =
+  | 'Io'
+  | 'Json'
+  | 'Path'
+  | 'ReadOnly'
+  | 'NotFound'
+  | 'PermissionDenied'
+  | 'AlreadyExists'
+  | 'Conflict'
+  | 'OutsideWorkspace'
+  | 'InvalidPattern'
+  | 'Cancelled'
+  | 'Timeout'
+  | 'DataDirUnavailable'
+  | 'Unsupported'
+  | 'NotARepository'
+  | 'Git';
+
 /**
- * Keyboard sound settings (snake_case for Rust backend)
+ * What a failed backend command rejects with
+ */
+export interface MikuError {
+  code: MikuErrorCode;
+  message: string;
+}
+
+export function isMikuError(err: unknown): err is MikuError {
+  return (
+    typeof err === 'object' &&
+    err !== null &&
+    typeof (err as MikuError).code === 'string' &&
+    typeof (err as MikuError).message === 'string'
+  );
+}
+
+/**
+ * Human-readable message for anything a command or other code threw
  */
+export function errorMessage(err: unknown): string {
+  if (isMikuError(err) || err instanceof Error) return err.message;
+  if (typeof err === 'string') return err;
+  return String(err);
+}
+
 /**
  * Keyboard sound settings (snake_case for Rust backend)
  */
@@ -28,6 +73,31 @@ export interface ThemePreferenceBackend {
   dark_fallback: string;
 }
 
+/**
+ * Limits on config backups and auto-save drafts; 0 turns a limit off
+ */
+export interface RetentionPolicy {
+  /** Backups kept per file, newest first (default 5) */
+  max_backups_per_file: number;
+  /** Backups and auto-save drafts older than this are removed (default 30) */
+  max_snapshot_age_days: number;
+  /** Combined size of all backups in bytes (default 50 MB) */
+  max_total_backup_bytes: number;
+}
+
+/**
+ * Markdown extensions shared by every backend command that parses markdown
+ */
+export interface MarkdownOptions {
+  tables: boolean;
+  footnotes: boolean;
+  tasklists: boolean;
+  strikethrough: boolean;
+  wikilinks: boolean;
+  /** Pass raw HTML through to the output; when off it's shown as text */
+  html: boolean;
+}
+
 export interface EditorSettingsBackend {
   /** @deprecated Use theme_preference instead */
   theme?: string | null;
@@ -41,14 +111,46 @@ export interface EditorSettingsBackend {
   writing_context: string;
   sound_enabled: boolean;
   keyboard_sounds: KeyboardSoundSettingsBackend;
+  /** Auto-save interval in seconds; null disables auto-save */
+  autosave_interval_secs?: number | null;
+  /** Overwrite the document, or write `<path>.autosave` until an explicit save */
+  autosave_mode?: 'in_place' | 'sidecar';
+  markdown_options?: MarkdownOptions;
+  /** File extensions treated as markdown, without the dot */
+  markdown_extensions?: string[];
+  /** Files bulk operations open at once (minimum 1, default 32) */
+  io_concurrency?: number;
+  /** Seconds a file read may take before failing with a Timeout error (0 waits forever) */
+  read_timeout_secs?: number;
+  /** Folder nesting below which the file tree stops listing (minimum 1, default 64) */
+  max_tree_depth?: number;
+  retention?: RetentionPolicy;
+  /** Recent files kept per workspace (1-100, default 10) */
+  max_recent_files?: number;
+  /** Unpinned recent workspaces kept (1-100, default 10) */
+  max_recent_workspaces?: number;
+  /** Strip trailing whitespace on save, leaving fenced code untouched */
+  trim_trailing_whitespace?: boolean;
+  /** When trimming, keep two-space markdown hard line breaks (default true) */
+  preserve_hard_breaks?: boolean;
 }
 
 export interface Document {
   path: string | null;
   content: string;
   is_modified: boolean;
+  /** Opened in preview mode; editing is disabled and saves are refused */
+  readonly?: boolean;
+  /** The file starts with a UTF-8 byte order mark, kept in `content` */
+  has_bom?: boolean;
+  /** Line endings the file was read with; `content` keeps them as-is */
+  line_ending?: LineEnding;
+  /** Hash of `content` as loaded or saved, in hex */
+  content_hash?: string;
 }
 
+export type LineEnding = 'lf' | 'crlf' | 'mixed';
+
 export interface Workspace {
   path: string;
   name: string;
@@ -59,6 +161,19 @@ export interface WorkspaceFile {
   path: string;
   isDirectory: boolean;
   children?: WorkspaceFile[];
+  /** Path relative to the workspace root, for display */
+  relativePath?: string;
+  /** Words in the file, or in all markdown files below the folder */
+  wordCount?: number;
+  /**
+   * Folders only: entries directly inside that the tree leaves out, i.e.
+   * unsupported files and pruned empty folders (dotfiles aren't counted)
+   */
+  hiddenCount?: number;
+  /** Folder nested too deeply to be listed; its children are left out */
+  truncated?: boolean;
+  /** Files only: the frontmatter `title`, or the name without extension */
+  displayTitle?: string;
 }
 
 /**
@@ -69,12 +184,21 @@ export async function loadSettings(): Promise<EditorSettingsBackend> {
 }
 
 /**
- * Save settings to the app data directory
+ * Save settings to the app data directory. Fields left out, including
+ * backend-only ones such as recent file limits, keep their saved values.
  */
-export async function saveSettings(settings: EditorSettingsBackend): Promise<void> {
+export async function saveSettings(settings: Partial<EditorSettingsBackend>): Promise<void> {
   return invoke('save_settings', { settings });
 }
 
+/**
+ * Set which file extensions are treated as markdown everywhere. Returns the
+ * cleaned-up list; an empty list restores the defaults.
+ */
+export async function setMarkdownExtensions(extensions: string[]): Promise<string[]> {
+  return invoke<string[]>('set_markdown_extensions', { extensions });
+}
+
 /**
  * Open a file and return its contents
  */
@@ -83,10 +207,81 @@ export async function openFile(path: string): Promise<Document> {
 }
 
 /**
- * Save content to a file
+ * Open a file, recreating it empty when it's missing and `createIfMissing`
+ * is set. Otherwise a missing file fails with a `NotFound` error.
+ */
+export async function openFileOrCreate(path: string, createIfMissing: boolean): Promise<Document> {
+  return invoke<Document>('open_file_or_create', { path, createIfMissing });
+}
+
+/**
+ * Open the most recently modified note in a workspace. Fails with a
+ * `NotFound` error when the workspace has no markdown files.
+ */
+export async function openMostRecent(workspacePath: string): Promise<Document> {
+  return invoke<Document>('open_most_recent', { workspacePath });
+}
+
+/**
+ * Open a file in read-only preview mode
+ */
+export async function openFileReadonly(path: string): Promise<Document> {
+  return invoke<Document>('open_file_readonly', { path });
+}
+
+/**
+ * Save content to a file. Fails with `NotFound` for the folder if it no
+ * longer exists, unless `createParents` is set to recreate it. Resolves
+ * with the content as written, which has trailing whitespace trimmed when
+ * that setting is on.
+ */
+export async function saveFile(
+  path: string,
+  content: string,
+  lineEnding?: LineEnding,
+  createParents = false
+): Promise<string> {
+  return invoke<string>('save_file', { path, content, lineEnding, createParents });
+}
+
+/**
+ * Save a document under a new path, add it to recent files and return it as
+ * the document to keep editing. Fails with `AlreadyExists` unless
+ * `overwrite` is set. Data kept by file id for `sourcePath`, the document
+ * it was saved from, moves to the new path.
+ */
+export async function saveFileAs(
+  newPath: string,
+  content: string,
+  lineEnding?: LineEnding,
+  overwrite = false,
+  sourcePath?: string
+): Promise<Document> {
+  return invoke<Document>('save_file_as', { newPath, content, lineEnding, overwrite, sourcePath });
+}
+
+export interface LineEndingCounts {
+  lf: number;
+  crlf: number;
+  /** Lone `\r`, as written by classic Mac OS */
+  cr: number;
+  /** The more common of `lf` and `crlf` */
+  dominant: LineEnding;
+}
+
+/**
+ * Count the line breaks of each kind in a document
+ */
+export async function detectLineEndings(content: string): Promise<LineEndingCounts> {
+  return invoke<LineEndingCounts>('detect_line_endings', { content });
+}
+
+/**
+ * Rewrite every line break in `content` in one style, e.g. to fix mixed
+ * endings in the open buffer. `mixed` uses whichever style dominates.
  */
-export async function saveFile(path: string, content: string): Promise<void> {
-  return invoke('save_file', { path, content });
+export async function normalizeContentLineEndings(content: string, style: LineEnding): Promise<string> {
+  return invoke<string>('normalize_content_line_endings', { content, style });
 }
 
 /**
@@ -96,11 +291,78 @@ export async function newDocument(): Promise<Document> {
   return invoke<Document>('new_document');
 }
 
+export interface RecentFileEntry {
+  path: string;
+  name: string;
+  last_opened_ms: number;
+  /** False when the file has been moved or deleted since it was opened */
+  exists: boolean;
+}
+
 /**
  * Get the list of recently opened files
  */
-export async function getRecentFiles(): Promise<string[]> {
-  return invoke<string[]>('get_recent_files');
+export async function getRecentFiles(): Promise<RecentFileEntry[]> {
+  return invoke<RecentFileEntry[]>('get_recent_files');
+}
+
+export interface PartitionedRecentFiles {
+  existing: RecentFileEntry[];
+  /** Moved or deleted since they were opened */
+  missing: RecentFileEntry[];
+}
+
+/**
+ * Recent files split into existing and missing ones, e.g. to offer
+ * relocating the missing ones. The stored list is not changed.
+ */
+export async function getRecentFilesPartitioned(): Promise<PartitionedRecentFiles> {
+  return invoke<PartitionedRecentFiles>('get_recent_files_partitioned');
+}
+
+/**
+ * Auto-save a document. Returns true when the file was actually written;
+ * unchanged content and calls within the configured interval are skipped.
+ */
+export async function autosave(path: string, content: string): Promise<boolean> {
+  return invoke<boolean>('autosave', { path, content });
+}
+
+export interface AutosaveSidecar {
+  /** The document the sidecar belongs to */
+  path: string;
+  sidecar_path: string;
+  modified_ms: number | null;
+}
+
+/**
+ * Sidecar auto-saves never followed by an explicit save, for recovery
+ */
+export async function listAutosaveSidecars(workspacePath: string): Promise<AutosaveSidecar[]> {
+  return invoke<AutosaveSidecar[]>('list_autosave_sidecars', { workspacePath });
+}
+
+export interface RetentionReport {
+  removed: string[];
+  freed_bytes: number;
+}
+
+/**
+ * Prune config backups and stale auto-save drafts per the retention policy.
+ * Drafts are pruned in `workspacePath`, or else the saved current workspace;
+ * drafts newer than their document are always kept.
+ */
+export async function applyRetention(workspacePath?: string): Promise<RetentionReport> {
+  return invoke<RetentionReport>('apply_retention', { workspacePath });
+}
+
+/**
+ * Remove config backups older than `maxAgeDays`, and the oldest ones beyond
+ * `maxTotalBytes` in total. The newest backup of every file is always kept;
+ * pass 0 to skip either limit.
+ */
+export async function pruneBackups(maxAgeDays: number, maxTotalBytes: number): Promise<RetentionReport> {
+  return invoke<RetentionReport>('prune_backups', { maxAgeDays, maxTotalBytes });
 }
 
 /**
@@ -110,6 +372,57 @@ export async function addRecentFile(path: string): Promise<void> {
   return invoke('add_recent_file', { path });
 }
 
+/**
+ * Remove a file from the recent files list
+ */
+export async function removeRecentFile(path: string): Promise<void> {
+  return invoke('remove_recent_file', { path });
+}
+
+/**
+ * Point a recent file that was moved outside the app at its new location,
+ * keeping its place in the list
+ */
+export async function relocateRecentFile(oldPath: string, newPath: string): Promise<void> {
+  return invoke('relocate_recent_file', { oldPath, newPath });
+}
+
+export interface TrashedItem {
+  original_path: string;
+  /** Milliseconds since the Unix epoch */
+  trashed_at: number;
+  /** Where the item sits in the trash; unknown on macOS, where it can't be restored from Miku */
+  trash_location: string | null;
+}
+
+/**
+ * Items moved to the trash from Miku that are still there, newest first
+ */
+export async function listTrashed(): Promise<TrashedItem[]> {
+  return invoke<TrashedItem[]>('list_trashed');
+}
+
+/**
+ * Put a trashed item back at its original path. Fails with `AlreadyExists`
+ * when something else exists there now.
+ */
+export async function restoreTrashed(originalPath: string): Promise<void> {
+  return invoke('restore_trashed', { originalPath });
+}
+
+/**
+ * Clear the recent files shown for the active workspace
+ */
+export async function clearRecentFiles(): Promise<void> {
+  return invoke('clear_recent_files');
+}
+
+/**
+ * Event emitted to every window with the updated recent files whenever
+ * they are added, removed or cleared
+ */
+export const RECENTS_CHANGED_EVENT = 'recents://changed';
+
 /**
  * Get the app version
  */
@@ -117,6 +430,59 @@ export async function getAppVersion(): Promise<string> {
   return invoke<string>('get_app_version');
 }
 
+export interface Diagnostics {
+  app_version: string;
+  os: string;
+  data_dir: string;
+  /** False when settings.json exists but doesn't parse */
+  settings_ok: boolean;
+  /** False when the workspace config exists but doesn't parse */
+  workspace_config_ok: boolean;
+  current_workspace: string | null;
+  recent_file_count: number;
+}
+
+/**
+ * Collect version, platform, and config health for support requests
+ */
+export async function getDiagnostics(): Promise<Diagnostics> {
+  return invoke<Diagnostics>('diagnostics');
+}
+
+export interface AppDataIssue {
+  /** The config file, or the data directory itself */
+  file: string;
+  problem: string;
+}
+
+export interface AppDataReport {
+  writable: boolean;
+  issues: AppDataIssue[];
+}
+
+/**
+ * Check that the data directory is writable and every config file in it
+ * parses. Nothing is repaired.
+ */
+export async function verifyAppData(): Promise<AppDataReport> {
+  return invoke<AppDataReport>('verify_app_data');
+}
+
+/**
+ * Path relative to `base` (usually the workspace root) for display.
+ * Paths outside `base` are returned unchanged.
+ */
+export async function relativizePath(path: string, base: string): Promise<string> {
+  return invoke<string>('relativize_path', { path, base });
+}
+
+/**
+ * Open a file the editor can't display with the system's default application
+ */
+export async function openExternal(path: string): Promise<void> {
+  return invoke('open_external', { path });
+}
+
 // ============================================
 // Session restore
 // ============================================
@@ -139,6 +505,44 @@ export async function loadSession(): Promise<SessionState | null> {
   return invoke<SessionState | null>('load_session');
 }
 
+// ============================================
+// Window layout
+// ============================================
+
+/**
+ * Save window and panel layout. The shape is up to the caller; it's stored
+ * as-is.
+ */
+export async function saveLayout<T extends object>(layout: T): Promise<void> {
+  return invoke('save_layout', { layout });
+}
+
+/**
+ * Load the saved layout, or `{}` when there is none or it was corrupt
+ */
+export async function loadLayout<T extends object>(): Promise<Partial<T>> {
+  return invoke<Partial<T>>('load_layout');
+}
+
+// ============================================
+// Preferences
+// ============================================
+
+/**
+ * Read a small preference stored with `setPreference`, or `null` when it
+ * was never set. Keys are namespaced as `namespace.name`.
+ */
+export async function getPreference<T = unknown>(key: string): Promise<T | null> {
+  return invoke<T | null>('get_preference', { key });
+}
+
+/**
+ * Store any JSON value under a `namespace.name` key; `null` removes it
+ */
+export async function setPreference(key: string, value: unknown): Promise<void> {
+  return invoke('set_preference', { key, value });
+}
+
 // ============================================
 // Image assets
 // ============================================
@@ -313,25 +717,302 @@ export async function getCurrentWorkspace(): Promise<Workspace | null> {
   return invoke<Workspace | null>('get_current_workspace');
 }
 
+/** Lock written by the instance that has a workspace open */
+export interface WorkspaceLock {
+  pid: number;
+  timestamp_ms: number;
+}
+
+export interface WorkspaceSwitchWarning {
+  dirty_documents: string[];
+  /** Another running instance has the workspace open */
+  already_locked_by: WorkspaceLock | null;
+}
+
 /**
- * Set the current workspace
+ * Set the current workspace. While documents have unsaved changes or another
+ * instance has the workspace open, this returns a warning instead of
+ * switching, unless `force` is set.
  */
-export async function setWorkspace(path: string): Promise<void> {
-  return invoke('set_workspace', { path });
+export async function setWorkspace(
+  path: string,
+  force = false
+): Promise<WorkspaceSwitchWarning | null> {
+  return invoke<WorkspaceSwitchWarning | null>('set_workspace', { path, force });
 }
 
 /**
- * Get recent workspaces
+ * Tell the backend whether an open document has unsaved changes
  */
-export async function getRecentWorkspaces(): Promise<Workspace[]> {
-  return invoke<Workspace[]>('get_recent_workspaces');
+export async function markDirty(path: string, dirty: boolean): Promise<void> {
+  return invoke('mark_dirty', { path, dirty });
 }
 
 /**
- * List files in a workspace
+ * Tell the backend a document was closed, clearing its read-only and
+ * unsaved state
  */
-export async function listWorkspaceFiles(workspacePath: string): Promise<WorkspaceFile[]> {
-  return invoke<WorkspaceFile[]>('list_workspace_files', { workspacePath });
+export async function closeFile(path: string): Promise<void> {
+  return invoke('close_file', { path });
+}
+
+export interface DirtySummary {
+  /** Files with unsaved changes, sorted */
+  dirty_paths: string[];
+  /** Untitled documents with unsaved content */
+  untitled_count: number;
+}
+
+/**
+ * Unsaved work across every window, e.g. for a confirmation before quitting.
+ * Untitled documents count when marked dirty under a non-path key.
+ */
+export async function dirtySummary(): Promise<DirtySummary> {
+  return invoke<DirtySummary>('dirty_summary');
+}
+
+export type WorkspaceSort = 'selection_order' | 'folder_modified';
+
+/**
+ * Get recent workspaces, pinned first. `sortBy` orders the rest by last
+ * selection (default) or by folder modification time.
+ */
+export async function getRecentWorkspaces(
+  sortBy: WorkspaceSort = 'selection_order'
+): Promise<Workspace[]> {
+  return invoke<Workspace[]>('get_recent_workspaces', { sortBy });
+}
+
+/**
+ * Whether a path belongs to the current workspace, or to `workspacePath`
+ * when given, e.g. to decide between copying and linking a dropped file
+ */
+export async function isInWorkspace(path: string, workspacePath?: string): Promise<boolean> {
+  return invoke<boolean>('is_in_workspace', { path, workspacePath });
+}
+
+/**
+ * Remove Miku's metadata (the `.miku` folder and `.miku-order` files) from
+ * the open or a recent workspace, leaving notes alone. The lock of a window
+ * that has the workspace open is kept. Returns the removed paths.
+ */
+export async function clearWorkspaceMetadata(workspacePath: string): Promise<string[]> {
+  return invoke<string[]>('clear_workspace_metadata', { workspacePath });
+}
+
+/**
+ * Names of the templates new workspaces can be scaffolded from
+ */
+export async function listWorkspaceTemplates(): Promise<string[]> {
+  return invoke<string[]>('list_workspace_templates');
+}
+
+export interface TemplateWorkspace {
+  workspace: Workspace;
+  /** Why the new workspace wasn't switched to; it stays scaffolded either way */
+  switch_warning: WorkspaceSwitchWarning | null;
+}
+
+/**
+ * Scaffold a workspace in an empty or missing folder from a template and
+ * make it the current workspace. The switch is held back with a warning
+ * while documents have unsaved changes, unless `force` is set.
+ */
+export async function createWorkspaceFromTemplate(
+  destDir: string,
+  templateName: string,
+  force?: boolean
+): Promise<TemplateWorkspace> {
+  return invoke<TemplateWorkspace>('create_workspace_from_template', { destDir, templateName, force });
+}
+
+/** Where folders go relative to files within a directory listing */
+export type TreeGrouping = 'dirs_first' | 'files_first' | 'interleaved';
+
+/**
+ * List files in a workspace. Empty folders below the top level are hidden
+ * unless `showEmptyDirs` is set. `wordCounts` fills in `wordCount` on every
+ * entry and `displayTitles` fills in `displayTitle` on files; both read
+ * every markdown file.
+ */
+export async function listWorkspaceFiles(
+  workspacePath: string,
+  showEmptyDirs = false,
+  wordCounts = false,
+  grouping: TreeGrouping = 'dirs_first',
+  displayTitles = false
+): Promise<WorkspaceFile[]> {
+  return invoke<WorkspaceFile[]>('list_workspace_files', {
+    workspacePath,
+    showEmptyDirs,
+    wordCounts,
+    grouping,
+    displayTitles,
+  });
+}
+
+export interface ListingBenchmark {
+  duration_ms: number;
+  file_count: number;
+  folder_count: number;
+  /** Bytes read from files, i.e. the markdown files when counting words */
+  bytes_read: number;
+}
+
+/**
+ * List a workspace the way the file tree does and report how long it took,
+ * for diagnosing slow vaults
+ */
+export async function benchmarkListing(workspacePath: string, wordCounts = false): Promise<ListingBenchmark> {
+  return invoke<ListingBenchmark>('benchmark_listing', { workspacePath, wordCounts });
+}
+
+/**
+ * A file or folder sent by `streamWorkspaceFiles`
+ */
+export interface WorkspaceEntryEvent {
+  operationId: string;
+  name: string;
+  path: string;
+  /** Folder the entry belongs in; the workspace root for top-level entries */
+  parentPath: string;
+  isDirectory: boolean;
+  relativePath: string;
+  /** Only sent when `displayTitles` is set */
+  displayTitle?: string;
+  /** Folder nested too deep to be looked into */
+  truncated: boolean;
+}
+
+export interface WorkspaceCompleteEvent {
+  operationId: string;
+  entryCount: number;
+  /** Why the walk stopped early, including cancellation */
+  error?: string;
+}
+
+export const WORKSPACE_ENTRY_EVENT = 'workspace://entry';
+export const WORKSPACE_COMPLETE_EVENT = 'workspace://complete';
+
+/**
+ * List a workspace incrementally: every entry arrives as a
+ * `workspace://entry` event (parents before children), then
+ * `workspace://complete`, which is also sent when the walk fails or is
+ * cancelled. The `cancel_operation` command stops it. The options match
+ * `listWorkspaceFiles`. Resolves with the number of entries sent.
+ */
+export async function streamWorkspaceFiles(
+  workspacePath: string,
+  operationId: string,
+  showEmptyDirs = false,
+  grouping: TreeGrouping = 'dirs_first',
+  displayTitles = false
+): Promise<number> {
+  return invoke<number>('stream_workspace_files', {
+    workspacePath,
+    operationId,
+    showEmptyDirs,
+    grouping,
+    displayTitles,
+  });
+}
+
+/**
+ * Write a note linking every note in the workspace as nested bullets that
+ * mirror the folder tree. `outPath` may be relative to the workspace and
+ * must lie inside it. Fails with `AlreadyExists` when the note exists,
+ * unless `overwrite` is set. Returns the path of the generated note.
+ */
+export async function generateIndexNote(
+  workspacePath: string,
+  outPath: string,
+  groupByFolder = false,
+  overwrite = false
+): Promise<string> {
+  return invoke<string>('generate_index_note', { workspacePath, outPath, groupByFolder, overwrite });
+}
+
+export type RecencyBucket = 'today' | 'yesterday' | 'this_week' | 'this_month' | 'older';
+
+export interface FlatFile {
+  name: string;
+  path: string;
+  relative_path: string;
+  /** Last modification time in milliseconds since the Unix epoch */
+  modified_ms: number;
+}
+
+/**
+ * Every markdown file in a workspace as a flat list, sorted by relative path
+ */
+export async function listWorkspaceFilesFlat(workspacePath: string): Promise<FlatFile[]> {
+  return invoke<FlatFile[]>('list_workspace_files_flat', { workspacePath });
+}
+
+/**
+ * Groups of paths in the same folder whose names differ only by case
+ * (`Readme.md` vs `README.md`), which collide when synced to a
+ * case-insensitive filesystem
+ */
+export async function findCaseConflicts(workspacePath: string): Promise<string[][]> {
+  return invoke<string[][]>('find_case_conflicts', { workspacePath });
+}
+
+export interface ModifiedFile {
+  path: string;
+  name: string;
+  /** Last modification time in milliseconds since the Unix epoch */
+  modified_ms: number;
+}
+
+export interface RecencyGroup {
+  bucket: RecencyBucket;
+  /** Newest first */
+  files: ModifiedFile[];
+}
+
+/**
+ * Group the files of a workspace by how recently they were modified, in
+ * local calendar days. Empty groups are left out.
+ */
+export async function groupFilesByRecency(workspacePath: string): Promise<RecencyGroup[]> {
+  return invoke<RecencyGroup[]>('group_files_by_recency', { workspacePath });
+}
+
+export interface PathMove {
+  old: string;
+  new: string;
+}
+
+export interface LinkRepair {
+  path: string;
+  edits: number;
+}
+
+/**
+ * After files or folders were moved, rewrite every link across the
+ * workspace that pointed at their old location. Returns how many links
+ * changed in each file.
+ */
+export async function repairWorkspaceLinks(workspacePath: string, moves: PathMove[]): Promise<LinkRepair[]> {
+  return invoke<LinkRepair[]>('repair_workspace_links', { workspacePath, moves });
+}
+
+export interface CreateConflict {
+  /** An entry with that name, or another spelling of it, already exists */
+  exists: boolean;
+  /** The existing entry is a folder, so it can't be overwritten with a file */
+  is_directory: boolean;
+  /** Free name to create instead; `name` itself when there's no conflict */
+  suggested_name: string;
+}
+
+/**
+ * Check whether `createFile` would collide with an existing entry, so the
+ * UI can offer overwrite / rename / cancel up front
+ */
+export async function checkCreateConflict(basePath: string, name: string): Promise<CreateConflict> {
+  return invoke<CreateConflict>('check_create_conflict', { basePath, name });
 }
 
 /**
@@ -341,6 +1022,40 @@ export async function createFile(basePath: string, name: string): Promise<string
   return invoke<string>('create_file', { basePath, name });
 }
 
+/**
+ * Outcome of one path in a batch operation
+ */
+export interface BatchItemResult {
+  path: string;
+  ok: boolean;
+  error?: string;
+}
+
+/**
+ * Create several files at once. `{{title}}` and `{{date}}` in the template
+ * are filled in per file; existing files are reported, not overwritten.
+ */
+export async function createFiles(
+  basePath: string,
+  names: string[],
+  template?: string
+): Promise<BatchItemResult[]> {
+  return invoke<BatchItemResult[]>('create_files', { basePath, names, template });
+}
+
+/**
+ * Copy the `.txt` files in `srcDir` into `destDir` as markdown, optionally
+ * adding frontmatter and turning the first line into a title. Name
+ * collisions are reported per file.
+ */
+export async function importTextFiles(
+  srcDir: string,
+  destDir: string,
+  addFrontmatter: boolean
+): Promise<BatchItemResult[]> {
+  return invoke<BatchItemResult[]>('import_text_files', { srcDir, destDir, addFrontmatter });
+}
+
 /**
  * Create a new folder
  */
@@ -348,6 +1063,18 @@ export async function createFolder(basePath: string, name: string): Promise<stri
   return invoke<string>('create_folder', { basePath, name });
 }
 
+/**
+ * Create a new folder and return it as a tree node. With `recursive`, `name`
+ * may be a nested path and missing parents are created.
+ */
+export async function createFolderDetailed(
+  basePath: string,
+  name: string,
+  recursive = false
+): Promise<WorkspaceFile> {
+  return invoke<WorkspaceFile>('create_folder_detailed', { basePath, name, recursive });
+}
+
 /**
  * Delete a file or folder
  */
@@ -355,11 +1082,116 @@ export async function deleteFile(path: string): Promise<void> {
   return invoke('delete_file', { path });
 }
 
+/**
+ * What to do when a rename target already exists
+ */
+export type ConflictPolicy = 'error' | 'overwrite' | 'rename';
+
 /**
  * Rename a file or folder
  */
-export async function renameFile(oldPath: string, newName: string): Promise<string> {
-  return invoke<string>('rename_file', { oldPath, newName });
+export async function renameFile(
+  oldPath: string,
+  newName: string,
+  onConflict: ConflictPolicy = 'error'
+): Promise<string> {
+  return invoke<string>('rename_file', { oldPath, newName, onConflict });
+}
+
+/**
+ * Stable id of the file at `path`, assigned on first use. The id follows
+ * the file through `renameFile`.
+ */
+export async function getOrAssignFileId(path: string): Promise<string> {
+  return invoke<string>('get_or_assign_file_id', { path });
+}
+
+/**
+ * Everything stored about the file at `path` with `setFileData`
+ */
+export async function getFileData(path: string): Promise<Record<string, unknown>> {
+  return invoke<Record<string, unknown>>('get_file_data', { path });
+}
+
+/**
+ * Store `value` under `key` for the file at `path`; survives renames.
+ * Passing `null` removes the key.
+ */
+export async function setFileData(path: string, key: string, value: unknown): Promise<void> {
+  return invoke<void>('set_file_data', { path, key, value });
+}
+
+// ============================================
+// Markdown
+// ============================================
+
+/** Where a top-level rendered block starts in the source */
+export interface SourceMapEntry {
+  /** 1-based line number */
+  source_line: number;
+  /** Position among the top-level blocks of the rendered preview */
+  block_index: number;
+}
+
+/**
+ * Map each top-level block of the preview to the editor line it starts on,
+ * for synchronized scrolling. `options` defaults to the saved settings.
+ */
+export async function sourceMap(content: string, options?: MarkdownOptions): Promise<SourceMapEntry[]> {
+  return invoke<SourceMapEntry[]>('source_map', { content, options });
+}
+
+export type DiagnosticKind = 'raw_html' | 'unbalanced_emphasis' | 'malformed_table' | 'unclosed_fence';
+
+/** A rendering problem found by `renderDiagnostics` */
+export interface RenderDiagnostic {
+  /** 1-based line number */
+  line: number;
+  kind: DiagnosticKind;
+  message: string;
+}
+
+/**
+ * Warn about markup that may render differently than intended, e.g. before
+ * exporting. `options` defaults to the saved settings.
+ */
+export async function renderDiagnostics(content: string, options?: MarkdownOptions): Promise<RenderDiagnostic[]> {
+  return invoke<RenderDiagnostic[]>('render_diagnostics', { content, options });
+}
+
+/**
+ * Shift every heading by `offset` levels (clamped to 1-6), skipping code
+ * fences, e.g. 1 to turn a note's H1 into an H2 before merging it
+ */
+export async function shiftHeadings(content: string, offset: number): Promise<string> {
+  return invoke<string>('shift_headings', { content, offset });
+}
+
+// ============================================
+// Workspace statistics
+// ============================================
+
+export interface WorkspaceStats {
+  file_count: number;
+  /** Words outside frontmatter and fenced code */
+  word_count: number;
+}
+
+/**
+ * File and word totals for a workspace, counted from scratch. With an
+ * operationId, reports `progress://<operationId>` and can be cancelled.
+ */
+export async function workspaceStats(workspacePath: string, operationId?: string): Promise<WorkspaceStats> {
+  return invoke<WorkspaceStats>('workspace_stats', { workspacePath, operationId });
+}
+
+/**
+ * File and word totals kept current by a file watcher. The first call for
+ * a workspace counts everything and starts watching it; an operationId
+ * reports progress of that count and lets it be cancelled.
+ */
+export async function getCachedStats(workspacePath: string, operationId?: string): Promise<WorkspaceStats> {
+  return invoke<WorkspaceStats>('get_cached_stats', { workspacePath, operationId });
 }
 
 // ============================================
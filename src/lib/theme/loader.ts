@@ -13,111 +13,20 @@ import {
 import { validateTheme, ThemeValidationError } from './validator';
 import { extractPreviewColors } from './engine';
 
+// The built-in and preset manifests are shared with the backend, which
+// embeds the same files
+import lightManifest from '@/themes/light.json';
+import darkManifest from '@/themes/dark.json';
+
 /**
  * Built-in Light Theme
  */
-export const LIGHT_THEME_MANIFEST: ThemeManifest = {
-  version: '1.0',
-  id: 'light',
-  name: 'Light',
-  description: 'Default light theme with warm stone tones',
-  author: { name: 'Miku Team' },
-  variant: 'light',
-  colors: {
-    background: {
-      primary: '#FAFAF9',
-      secondary: '#F5F5F4',
-      tertiary: '#E7E5E4',
-    },
-    text: {
-      primary: '#1C1917',
-      secondary: '#57534E',
-      tertiary: '#A8A29E',
-    },
-    accent: {
-      primary: '#D97757',
-      subtle: '#FDEBE6',
-    },
-    border: {
-      default: '#E7E5E4',
-      subtle: '#F0EFEE',
-      focus: '#D97757',
-    },
-    shadows: {
-      sm: '0 1px 2px rgba(28, 25, 23, 0.05)',
-      md: '0 4px 12px rgba(28, 25, 23, 0.08)',
-      lg: '0 8px 24px rgba(28, 25, 23, 0.12)',
-    },
-    highlights: {
-      clarity: 'rgba(251, 191, 36, 0.25)',
-      grammar: 'rgba(239, 68, 68, 0.2)',
-      style: 'rgba(59, 130, 246, 0.2)',
-      structure: 'rgba(168, 85, 247, 0.2)',
-      economy: 'rgba(34, 197, 94, 0.2)',
-    },
-    syntax: {
-      heading: '#D97757',
-      emphasis: '#57534E',
-      codeBackground: '#E7E5E4',
-      codeText: '#c7254e',
-      link: '#3b82f6',
-      listMarker: '#A8A29E',
-    },
-  },
-};
+export const LIGHT_THEME_MANIFEST = lightManifest as ThemeManifest;
 
 /**
  * Built-in Dark Theme
  */
-export const DARK_THEME_MANIFEST: ThemeManifest = {
-  version: '1.0',
-  id: 'dark',
-  name: 'Dark',
-  description: 'Default dark theme with warm stone tones',
-  author: { name: 'Miku Team' },
-  variant: 'dark',
-  colors: {
-    background: {
-      primary: '#1C1917',
-      secondary: '#292524',
-      tertiary: '#44403C',
-    },
-    text: {
-      primary: '#FAFAF9',
-      secondary: '#A8A29E',
-      tertiary: '#78716C',
-    },
-    accent: {
-      primary: '#E89B7D',
-      subtle: '#2D2420',
-    },
-    border: {
-      default: '#44403C',
-      subtle: '#3A3634',
-      focus: '#E89B7D',
-    },
-    shadows: {
-      sm: '0 1px 2px rgba(0, 0, 0, 0.2)',
-      md: '0 4px 12px rgba(0, 0, 0, 0.3)',
-      lg: '0 8px 24px rgba(0, 0, 0, 0.4)',
-    },
-    highlights: {
-      clarity: 'rgba(251, 191, 36, 0.3)',
-      grammar: 'rgba(248, 113, 113, 0.3)',
-      style: 'rgba(96, 165, 250, 0.3)',
-      structure: 'rgba(192, 132, 252, 0.3)',
-      economy: 'rgba(74, 222, 128, 0.3)',
-    },
-    syntax: {
-      heading: '#E89B7D',
-      emphasis: '#A8A29E',
-      codeBackground: '#44403C',
-      codeText: '#e83e8c',
-      link: '#60a5fa',
-      listMarker: '#78716C',
-    },
-  },
-};
+export const DARK_THEME_MANIFEST = darkManifest as ThemeManifest;
 
 /**
  * Create a Theme object from a manifest.